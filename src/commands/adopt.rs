@@ -0,0 +1,132 @@
+//! Adopt command implementation - register existing gcloud configurations as contexts.
+
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::path::Path;
+
+use crate::commands::save::{SaveOptions, save_context_with_options};
+use crate::config::{
+    adc_identity, adc_project, context_exists, gcloud_command, get_current_gcloud_config,
+    invalidate_gcloud_cache, list_gcloud_configs, validate_adc_json, validate_context_name,
+};
+use crate::error::GcpxError;
+
+/// Activates a gcloud configuration.
+fn activate(config: &str) -> Result<()> {
+    let output = gcloud_command()
+        .args(["config", "configurations", "activate", config])
+        .output()
+        .context("Failed to execute gcloud command")?;
+    invalidate_gcloud_cache();
+    if !output.status.success() {
+        let err = String::from_utf8_lossy(&output.stderr);
+        bail!(GcpxError::GcloudFailed(err.trim().to_string()));
+    }
+    Ok(())
+}
+
+/// Registers an existing gcloud configuration as a context of the same name.
+///
+/// The configuration is activated so its account and project can be read,
+/// and the current ADC is captured as the context's credentials. The
+/// previously active configuration and the tracked context are restored
+/// afterwards: adopting registers a context, it doesn't switch to it.
+pub fn adopt_gcloud_config(config: &str, quiet: bool) -> Result<()> {
+    validate_context_name(config)?;
+    if context_exists(config)? {
+        bail!(GcpxError::ContextExists(config.to_string()));
+    }
+    let original = get_current_gcloud_config()?;
+
+    activate(config)?;
+    let saved = save_context_with_options(
+        config,
+        &SaveOptions {
+            quiet,
+            gcloud_config: Some(config.to_string()),
+            no_track: true,
+            ..Default::default()
+        },
+    );
+
+    if original != config {
+        activate(&original)?;
+    }
+    saved
+}
+
+/// Adopts every gcloud configuration that isn't already a context.
+///
+/// Configurations whose names aren't valid context names are skipped. Every
+/// configuration is attempted; an error is returned if any failed.
+pub fn adopt_all_gcloud_configs(quiet: bool) -> Result<()> {
+    let mut adopted = 0;
+    let mut failed = 0;
+    for config in list_gcloud_configs()? {
+        if validate_context_name(&config).is_err() {
+            println!("Skipping '{}': not a valid context name.", config);
+            continue;
+        }
+        if context_exists(&config)? {
+            println!("Skipping '{}': context already exists.", config);
+            continue;
+        }
+        match adopt_gcloud_config(&config, quiet) {
+            Ok(()) => adopted += 1,
+            Err(e) => {
+                eprintln!("Failed to adopt '{}': {:#}", config, e);
+                failed += 1;
+            }
+        }
+    }
+    println!("Adopted {} gcloud configuration(s).", adopted);
+    if failed > 0 {
+        bail!(GcpxError::AdoptFailed(failed));
+    }
+    Ok(())
+}
+
+/// Options controlling `gcpx adopt-adc`.
+#[derive(Debug, Clone, Default)]
+pub struct AdoptAdcOptions {
+    /// Account to record instead of the one named in the credentials
+    pub account: Option<String>,
+    /// Project to record instead of the credentials' quota or key project
+    pub project: Option<String>,
+    /// gcloud configuration to record (defaults to `default`)
+    pub gcloud_config: Option<String>,
+    /// Hide sensitive details (account, project, etc.) in the output
+    pub quiet: bool,
+}
+
+/// Creates a context from an existing credential file, such as a hand-managed
+/// `adc-work.json` from before gcpx.
+///
+/// The file is validated, and the account and project are taken from the
+/// JSON (`client_email` or the impersonation target, and `quota_project_id`
+/// or `project_id`) unless given. Live gcloud and kubectl state is not
+/// consulted, and the tracked context is left alone.
+pub fn adopt_adc_file(file: &Path, name: &str, options: &AdoptAdcOptions) -> Result<()> {
+    validate_context_name(name)?;
+    if context_exists(name)? {
+        bail!(GcpxError::ContextExists(name.to_string()));
+    }
+    let content =
+        fs::read(file).with_context(|| format!("Failed to read credentials: {:?}", file))?;
+    validate_adc_json(&content)?;
+
+    let project = options.project.clone().or_else(|| adc_project(&content));
+    save_context_with_options(
+        name,
+        &SaveOptions {
+            quiet: options.quiet,
+            adc_path: Some(file.to_path_buf()),
+            account: options.account.clone().or_else(|| adc_identity(&content)),
+            project,
+            gcloud_config: options.gcloud_config.clone(),
+            adc_json: Some(content),
+            no_track: true,
+            ..Default::default()
+        },
+    )
+}
@@ -0,0 +1,241 @@
+//! Dynamic shell completion support.
+//!
+//! The scripts generated by `gcpx completions <shell> --dynamic` call back into
+//! the hidden `gcpx __complete <subcommand> <prefix>` command at completion time,
+//! so newly saved contexts are suggested without regenerating the script.
+//! After a flag, the script passes it with `--after <flag>` and the flag's
+//! values are completed instead.
+
+use anyhow::{Result, bail};
+use clap::Command;
+use clap_complete::Shell;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::{get_home, get_store_dir, list_gcloud_configs, write_atomic};
+use crate::error::GcpxError;
+use crate::groups::load_groups;
+
+/// Subcommands whose first positional argument is a context name.
+///
+/// `switch-group` (group names) and `adopt` (gcloud configurations) also
+/// complete their first positional argument.
+pub const NAME_SUBCOMMANDS: &[&str] = &[
+    "switch",
+    "run",
+    "delete",
+    "rename",
+    "touch",
+    "repair",
+    "login",
+    "rollback",
+    "env",
+    "show",
+    "reauth-if-needed",
+    "save",
+    "export",
+];
+
+/// Returns the context names matching `prefix` for the given subcommand.
+///
+/// This runs on every tab press, so it reads the store directory directly and
+/// never loads metadata.
+pub fn complete_names(subcommand: &str, prefix: &str) -> Result<Vec<String>> {
+    match subcommand {
+        "switch-group" => return Ok(with_prefix(load_groups()?.into_keys(), prefix)),
+        "adopt" => return Ok(with_prefix(list_gcloud_configs()?, prefix)),
+        _ if !NAME_SUBCOMMANDS.contains(&subcommand) => return Ok(Vec::new()),
+        _ => {}
+    }
+
+    let mut names = Vec::new();
+    for entry in fs::read_dir(get_store_dir()?)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        if let Some(name) = entry.file_name().to_str() {
+            if !name.starts_with('.') && name.starts_with(prefix) {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Returns the values of `flag` (e.g. `--format`) matching `prefix`.
+///
+/// `cli` is the full command tree. The flag is looked up on `subcommand`, then
+/// on the top-level command, so global flags such as `--context` resolve too.
+/// Value-enum flags offer their possible values; `save --config` and
+/// `login --config` offer gcloud configurations and `--context` saved contexts. Flags that take no
+/// value, or free-form values, complete nothing.
+pub fn complete_flag_value(
+    cli: &Command,
+    subcommand: &str,
+    flag: &str,
+    prefix: &str,
+) -> Result<Vec<String>> {
+    let matches_flag = |arg: &&clap::Arg| match flag.strip_prefix("--") {
+        Some(long) => arg.get_long() == Some(long),
+        None => flag
+            .strip_prefix('-')
+            .and_then(|s| s.chars().next())
+            .is_some_and(|short| arg.get_short() == Some(short)),
+    };
+    let arg = cli
+        .find_subcommand(subcommand)
+        .and_then(|sub| sub.get_arguments().find(matches_flag))
+        .or_else(|| cli.get_arguments().find(matches_flag));
+    let Some(arg) = arg.filter(|arg| arg.get_action().takes_values()) else {
+        return Ok(Vec::new());
+    };
+
+    match arg.get_id().as_str() {
+        "gcloud_config" => Ok(with_prefix(list_gcloud_configs()?, prefix)),
+        "context" => complete_names("switch", prefix),
+        _ => Ok(with_prefix(
+            arg.get_possible_values()
+                .into_iter()
+                .filter(|value| !value.is_hide_set())
+                .map(|value| value.get_name().to_string()),
+            prefix,
+        )),
+    }
+}
+
+/// Keeps the candidates starting with `prefix`, sorted.
+fn with_prefix(candidates: impl IntoIterator<Item = String>, prefix: &str) -> Vec<String> {
+    let mut names: Vec<String> = candidates
+        .into_iter()
+        .filter(|name| name.starts_with(prefix))
+        .collect();
+    names.sort();
+    names
+}
+
+/// Generates a completion script that completes context names dynamically.
+///
+/// `subcommands` is the list of visible subcommands offered in first position.
+pub fn dynamic_completion_script(shell: Shell, subcommands: &[String]) -> Result<String> {
+    let subcommands = subcommands.join(" ");
+    let name_subcommands = [NAME_SUBCOMMANDS, &["switch-group", "adopt"]]
+        .concat()
+        .join(" ");
+
+    let script = match shell {
+        Shell::Bash => format!(
+            r#"_gcpx_dynamic() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}"
+    local prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    if [[ $COMP_CWORD -eq 1 ]]; then
+        COMPREPLY=($(compgen -W "{subcommands}" -- "$cur"))
+    elif [[ "$prev" == -* ]]; then
+        COMPREPLY=($(gcpx __complete "${{COMP_WORDS[1]}}" "$cur" --after "$prev" 2>/dev/null))
+    elif [[ $COMP_CWORD -eq 2 ]]; then
+        COMPREPLY=($(gcpx __complete "${{COMP_WORDS[1]}}" "$cur" 2>/dev/null))
+    fi
+}}
+complete -o default -F _gcpx_dynamic gcpx
+"#
+        ),
+        Shell::Zsh => format!(
+            r#"#compdef gcpx
+
+_gcpx() {{
+    local -a values
+    if (( CURRENT == 2 )); then
+        compadd -- {subcommands}
+    elif [[ "${{words[CURRENT-1]}}" == -* ]]; then
+        values=(${{(f)"$(gcpx __complete "${{words[2]}}" "${{words[CURRENT]}}" --after "${{words[CURRENT-1]}}" 2>/dev/null)"}})
+        if (( ${{#values}} )); then
+            compadd -- $values
+        else
+            _files
+        fi
+    elif (( CURRENT == 3 )); then
+        compadd -- ${{(f)"$(gcpx __complete "${{words[2]}}" "${{words[3]}}" 2>/dev/null)"}}
+    else
+        _files
+    fi
+}}
+
+if [ "$funcstack[1]" = "_gcpx" ]; then
+    _gcpx "$@"
+else
+    compdef _gcpx gcpx
+fi
+"#
+        ),
+        Shell::Fish => format!(
+            r#"complete -c gcpx -f
+complete -c gcpx -n "__fish_use_subcommand" -a "{subcommands}"
+complete -c gcpx -n "__fish_seen_subcommand_from {name_subcommands}; and test (count (commandline -opc)) -eq 2" -a "(gcpx __complete (commandline -opc)[2] (commandline -ct))"
+complete -c gcpx -n "string match -q -- '-*' (commandline -opc)[-1]" -a "(gcpx __complete (commandline -opc)[2] (commandline -ct) --after (commandline -opc)[-1])"
+"#
+        ),
+        _ => bail!(GcpxError::DynamicCompletionsUnsupported(shell.to_string())),
+    };
+    Ok(script)
+}
+
+/// Returns where `gcpx completions install` puts the script for a shell: the
+/// per-user directory the shell (or bash-completion) loads completions from.
+///
+/// That is `$XDG_DATA_HOME/bash-completion/completions/gcpx` for bash,
+/// `${ZDOTDIR:-$HOME}/.zfunc/_gcpx` for zsh (which must be in `fpath`) and
+/// `$XDG_CONFIG_HOME/fish/completions/gcpx.fish` for fish. Other shells have
+/// no such directory.
+pub fn completion_install_path(shell: Shell) -> Result<PathBuf> {
+    let home = get_home()?;
+    let env_dir = |var: &str| {
+        env::var_os(var)
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+    };
+    Ok(match shell {
+        Shell::Bash => env_dir("XDG_DATA_HOME")
+            .unwrap_or_else(|| home.join(".local").join("share"))
+            .join("bash-completion")
+            .join("completions")
+            .join("gcpx"),
+        Shell::Zsh => env_dir("ZDOTDIR")
+            .unwrap_or(home)
+            .join(".zfunc")
+            .join("_gcpx"),
+        Shell::Fish => env_dir("XDG_CONFIG_HOME")
+            .unwrap_or_else(|| home.join(".config"))
+            .join("fish")
+            .join("completions")
+            .join("gcpx.fish"),
+        other => bail!(GcpxError::NoCompletionDir(other.to_string())),
+    })
+}
+
+/// Writes a completion script to [`completion_install_path`], creating the
+/// directory if needed, and prints where it went and how to load it.
+pub fn install_completions(shell: Shell, script: &str) -> Result<PathBuf> {
+    let path = completion_install_path(shell)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    write_atomic(&path, script.as_bytes())?;
+    println!("Installed {} completions to {}", shell, path.display());
+    match shell {
+        Shell::Zsh => {
+            let dir = path.parent().unwrap_or(&path);
+            println!(
+                "Make sure ~/.zshrc adds this directory to fpath before running compinit:\n  \
+                fpath=({} $fpath)\nthen open a new shell.",
+                dir.display()
+            );
+        }
+        Shell::Bash => {
+            println!("Open a new shell to load them (requires the bash-completion package).")
+        }
+        _ => println!("Open a new shell to load them."),
+    }
+    Ok(path)
+}
@@ -1,22 +1,45 @@
 //! Delete command implementation.
 
 use anyhow::{Context, Result, bail};
-use std::fs;
-use std::process::Command;
 
-use crate::config::{context_exists, get_context_dir, get_current_tracking, validate_context_name};
+use crate::config::{
+    clear_previous_tracking, context_exists, gcloud_command, get_current_gcloud_config,
+    get_current_tracking, get_gcloud_config_file, get_previous_tracking, invalidate_gcloud_cache,
+    list_contexts_with_metadata, load_context_metadata, validate_context_name,
+};
+use crate::error::GcpxError;
+use crate::trash::{LastOp, OpKind, copy_file_to_trash, move_to_trash, record_last_op};
 
 /// Deletes a saved context.
 ///
 /// This function:
-/// 1. Removes the stored ADC credentials
-/// 2. Optionally deletes the gcloud configuration as well
+/// 1. Moves the stored context to the trash (see `gcpx undo`)
+/// 2. Clears the previous context pointer if it referenced this context
+/// 3. Optionally deletes the context's gcloud configuration as well, after
+///    copying its properties file to the trash so `gcpx undo` can recreate it
+///
+/// The gcloud configuration is the one recorded in the context's metadata
+/// (falling back to the context name). Deletion is refused up front, before
+/// anything is removed, if that configuration is active or used by another
+/// context.
 pub fn delete_context(name: &str, delete_gcloud_config: bool) -> Result<()> {
     validate_context_name(name)?;
     if !context_exists(name)? {
-        bail!("Context '{}' not found.", name);
+        bail!(GcpxError::ContextNotFound(name.to_string()));
     }
 
+    let gcloud_config = if delete_gcloud_config {
+        let config = load_context_metadata(name)
+            .ok()
+            .flatten()
+            .map(|m| m.gcloud_config)
+            .unwrap_or_else(|| name.to_string());
+        check_gcloud_config_deletable(name, &config)?;
+        Some(config)
+    } else {
+        None
+    };
+
     let current = get_current_tracking();
     if current == name {
         println!(
@@ -26,31 +49,72 @@ pub fn delete_context(name: &str, delete_gcloud_config: bool) -> Result<()> {
         );
     }
 
-    // Delete the stored context directory
-    let context_dir = get_context_dir(name)?;
-    fs::remove_dir_all(&context_dir)
-        .with_context(|| format!("Failed to delete context directory: {:?}", context_dir))?;
+    // Move the stored context directory to the trash so it can be undone
+    let previous = get_previous_tracking();
+    let trash_entry = move_to_trash(name)?;
+    let gcloud_config_entry = match &gcloud_config {
+        Some(config) => {
+            let file = get_gcloud_config_file(config)?;
+            if file.is_file() {
+                Some(copy_file_to_trash(&file, &format!("config_{}", config))?)
+            } else {
+                None
+            }
+        }
+        None => None,
+    };
+    record_last_op(&LastOp {
+        op: OpKind::Delete,
+        name: name.to_string(),
+        trash_entry,
+        current,
+        previous: previous.clone(),
+        gcloud_config: gcloud_config.clone(),
+        gcloud_config_entry,
+    })?;
+
+    // Don't leave `switch -` pointing at a context that no longer exists
+    if previous == name {
+        clear_previous_tracking()?;
+    }
 
-    println!("Deleted context '{}'.", name);
+    println!("Deleted context '{}'. Run 'gcpx undo' to restore it.", name);
 
     // Optionally delete the gcloud configuration
-    if delete_gcloud_config {
-        println!("Deleting gcloud configuration '{}'...", name);
-        let status = Command::new("gcloud")
-            .args(["config", "configurations", "delete", name, "--quiet"])
+    if let Some(config) = gcloud_config {
+        println!("Deleting gcloud configuration '{}'...", config);
+        let status = gcloud_command()
+            .args(["config", "configurations", "delete", &config, "--quiet"])
             .output()
             .context("Failed to execute gcloud command")?;
+        invalidate_gcloud_cache();
 
         if !status.status.success() {
             let err_msg = String::from_utf8_lossy(&status.stderr);
             // Don't fail if config doesn't exist
             if !err_msg.contains("does not exist") {
-                bail!("gcloud error: {}", err_msg);
+                bail!(GcpxError::GcloudFailed(err_msg.trim().to_string()));
             }
         } else {
-            println!("Deleted gcloud configuration '{}'.", name);
+            println!("Deleted gcloud configuration '{}'.", config);
         }
     }
 
     Ok(())
 }
+
+/// Refuses to delete a gcloud configuration that is active or shared.
+fn check_gcloud_config_deletable(name: &str, config: &str) -> Result<()> {
+    if get_current_gcloud_config()? == config {
+        bail!(GcpxError::GcloudConfigActive(config.to_string()));
+    }
+    let sharing: Vec<String> = list_contexts_with_metadata()?
+        .into_iter()
+        .filter(|(other, m)| other != name && m.as_ref().is_some_and(|m| m.gcloud_config == config))
+        .map(|(other, _)| other)
+        .collect();
+    if !sharing.is_empty() {
+        bail!(GcpxError::GcloudConfigShared(config.to_string(), sharing));
+    }
+    Ok(())
+}
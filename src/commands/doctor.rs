@@ -0,0 +1,324 @@
+//! Doctor command implementation - find (and fix) common problems in the store.
+
+use anyhow::{Result, bail};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::commands::repair::{RepairOptions, rebuild_metadata};
+use crate::config::{
+    ContextProblem, context_exists, context_problems, get_context_metadata_path,
+    get_current_tracking, get_store_dir, list_contexts, set_current_tracking,
+};
+use crate::credstore::private_temp_dir_path;
+use crate::error::GcpxError;
+use crate::trash::{get_trash_dir, move_file_to_trash};
+
+/// Temp files younger than this may belong to a write still in progress.
+const TEMP_FILE_MIN_AGE: Duration = Duration::from_secs(60);
+
+/// Prefixes of what gcpx creates in the system temp directory: credential
+/// copies for `run` (`gcpx-adc-`, in the private temp directory), token
+/// checks (`gcpx-check-`) and texts being edited (`gcpx-edit-`).
+const STRAY_TEMP_PREFIXES: [&str; 3] = ["gcpx-adc-", "gcpx-check-", "gcpx-edit-"];
+
+/// Temp directory entries younger than this may belong to a command still
+/// running: `gcpx run` keeps its credential copy for as long as the program.
+const STRAY_TEMP_MIN_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Options controlling `gcpx doctor`.
+#[derive(Debug, Clone, Default)]
+pub struct DoctorOptions {
+    /// Remediate the problems that can be fixed without the user
+    pub fix: bool,
+}
+
+/// What `--fix` does about a problem.
+#[derive(Debug)]
+enum Fix {
+    /// Make a credential file readable by the current user only
+    Permissions(PathBuf),
+    /// Remove a temp file or directory a killed gcpx command left behind
+    StrayTemp(PathBuf),
+    /// Reset `.current`, which names a context that no longer exists
+    ClearCurrent,
+    /// Rebuild a context's metadata from its credentials
+    Metadata(String),
+    /// Move a temp file left by an interrupted write to the trash
+    TempFile(PathBuf),
+}
+
+/// A problem found in the store.
+#[derive(Debug)]
+struct Finding {
+    problem: String,
+    /// How to fix it, or `None` if it needs the user (e.g. a new login)
+    fix: Option<Fix>,
+    /// What to tell the user when there is no automatic fix
+    advice: Option<String>,
+}
+
+/// Returns true for temp files written by [`crate::config::write_atomic`]
+/// (`<file>.tmp<pid>`).
+fn is_temp_file(name: &str) -> bool {
+    name.rsplit_once(".tmp").is_some_and(|(file, pid)| {
+        !file.is_empty() && !pid.is_empty() && pid.bytes().all(|b| b.is_ascii_digit())
+    })
+}
+
+/// Collects temp files under `dir` that are old enough not to belong to a
+/// running write. The trash is skipped.
+fn find_temp_files(dir: &Path, trash_dir: &Path, found: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if path != trash_dir {
+                find_temp_files(&path, trash_dir, found)?;
+            }
+            continue;
+        }
+        let stale = entry
+            .metadata()?
+            .modified()
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age >= TEMP_FILE_MIN_AGE);
+        if stale && is_temp_file(&entry.file_name().to_string_lossy()) {
+            found.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Returns true if a temp directory entry's modification time is at least
+/// `min_age` ago.
+fn older_than(metadata: &fs::Metadata, min_age: Duration) -> bool {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age >= min_age)
+}
+
+/// Collects the current user's files and directories with a
+/// [`STRAY_TEMP_PREFIXES`] name in the system and private temp directories
+/// that are older than [`STRAY_TEMP_MIN_AGE`]. Normally gcpx removes them
+/// itself, so these were left by a command that was killed.
+fn find_stray_temp_files() -> Vec<PathBuf> {
+    let mut dirs = vec![env::temp_dir()];
+    let private = private_temp_dir_path();
+    if !dirs.contains(&private) {
+        dirs.push(private);
+    }
+    let mut found = Vec::new();
+    for dir in dirs {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !STRAY_TEMP_PREFIXES.iter().any(|p| name.starts_with(p)) {
+                continue;
+            }
+            let Ok(metadata) = fs::symlink_metadata(entry.path()) else {
+                continue;
+            };
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                // SAFETY: getuid has no preconditions and cannot fail
+                if metadata.uid() != unsafe { libc::getuid() } {
+                    continue;
+                }
+            }
+            if older_than(&metadata, STRAY_TEMP_MIN_AGE) {
+                found.push(entry.path());
+            }
+        }
+    }
+    found.sort();
+    found
+}
+
+/// Checks the store for broken contexts, stale tracking and leftover temp
+/// files, and the temp directories for credential copies gcpx left behind.
+fn diagnose() -> Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+    let store_dir = get_store_dir()?;
+    if !store_dir.exists() {
+        return Ok(findings);
+    }
+
+    for name in list_contexts()? {
+        for problem in context_problems(&name)? {
+            let (fix, advice) = match &problem {
+                ContextProblem::ReadableByOthers(path) => {
+                    (Some(Fix::Permissions(path.clone())), None)
+                }
+                ContextProblem::MissingMetadata | ContextProblem::UnreadableMetadata
+                    if context_exists(&name)? =>
+                {
+                    (Some(Fix::Metadata(name.clone())), None)
+                }
+                _ => (
+                    None,
+                    Some(format!(
+                        "run 'gcpx login {}' to re-authenticate, or delete it",
+                        name
+                    )),
+                ),
+            };
+            findings.push(Finding {
+                problem: format!("context '{}': {}", name, problem),
+                fix,
+                advice,
+            });
+        }
+    }
+
+    let current = get_current_tracking();
+    if current != "none" && !context_exists(&current)? {
+        findings.push(Finding {
+            problem: format!("current context '{}' no longer exists", current),
+            fix: Some(Fix::ClearCurrent),
+            advice: None,
+        });
+    }
+
+    let mut temp_files = Vec::new();
+    find_temp_files(&store_dir, &get_trash_dir()?, &mut temp_files)?;
+    for path in temp_files {
+        findings.push(Finding {
+            problem: format!("leftover temp file {}", path.display()),
+            fix: Some(Fix::TempFile(path)),
+            advice: None,
+        });
+    }
+    for path in find_stray_temp_files() {
+        findings.push(Finding {
+            problem: format!("leftover gcpx temp file {}", path.display()),
+            fix: Some(Fix::StrayTemp(path)),
+            advice: None,
+        });
+    }
+    Ok(findings)
+}
+
+/// Applies a fix, returning a short description of what was done.
+fn apply(fix: &Fix) -> Result<String> {
+    match fix {
+        Fix::Permissions(path) => {
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let old = fs::metadata(path)?.permissions().mode() & 0o777;
+                fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+                Ok(format!("mode {:04o} -> 0600", old))
+            }
+            #[cfg(not(unix))]
+            {
+                let _ = path;
+                bail!(GcpxError::ModesUnsupported)
+            }
+        }
+        Fix::StrayTemp(path) => {
+            if fs::symlink_metadata(path)?.is_dir() {
+                fs::remove_dir_all(path)?;
+            } else {
+                fs::remove_file(path)?;
+            }
+            Ok("removed".to_string())
+        }
+        Fix::ClearCurrent => {
+            set_current_tracking("none")?;
+            Ok("cleared".to_string())
+        }
+        Fix::Metadata(name) => {
+            // Keep unparseable metadata around in case it held anything useful
+            let path = get_context_metadata_path(name)?;
+            let moved = if path.exists() {
+                Some(move_file_to_trash(
+                    &path,
+                    &format!("{}-metadata.json", name),
+                )?)
+            } else {
+                None
+            };
+            let metadata = rebuild_metadata(
+                name,
+                &RepairOptions {
+                    infer_only: true,
+                    ..Default::default()
+                },
+            )?;
+            let mut done = format!("rebuilt (gcloud config '{}')", metadata.gcloud_config);
+            if let Some(entry) = moved {
+                done.push_str(&format!(", old file in .trash/{}", entry));
+            }
+            Ok(done)
+        }
+        Fix::TempFile(path) => {
+            let label = path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let entry = move_file_to_trash(path, &label)?;
+            Ok(format!("moved to .trash/{}", entry))
+        }
+    }
+}
+
+/// Checks the store for common problems and prints one line per problem.
+///
+/// With `fix`, problems gcpx can remediate on its own are fixed as they are
+/// reported: loose credential permissions are tightened, a `.current` naming
+/// a deleted context is cleared, missing or unreadable metadata is rebuilt
+/// (as `gcpx repair` would, without prompting) and leftover temp files are
+/// moved to the trash. Files that are replaced or removed go to `.trash`,
+/// except credential copies and edit files a killed gcpx command left in the
+/// temp directory, which are deleted. Problems that need new credentials are
+/// only reported.
+///
+/// Returns an error if any problem remains.
+pub fn doctor(options: &DoctorOptions) -> Result<()> {
+    let findings = diagnose()?;
+    if findings.is_empty() {
+        println!("No problems found.");
+        return Ok(());
+    }
+
+    let mut remaining = 0;
+    for finding in &findings {
+        let outcome = match (&finding.fix, options.fix) {
+            (Some(fix), true) => match apply(fix) {
+                Ok(done) => format!("fixed: {}", done),
+                Err(e) => {
+                    remaining += 1;
+                    format!("fix failed: {:#}", e)
+                }
+            },
+            (Some(_), false) => {
+                remaining += 1;
+                "fixable with --fix".to_string()
+            }
+            (None, _) => {
+                remaining += 1;
+                finding.advice.clone().unwrap_or_default()
+            }
+        };
+        println!("  {} - {}", finding.problem, outcome);
+    }
+
+    if remaining > 0 {
+        bail!(GcpxError::ProblemsRemain(
+            remaining,
+            findings.len(),
+            options.fix
+        ));
+    }
+    println!("Fixed {} problem(s).", findings.len());
+    Ok(())
+}
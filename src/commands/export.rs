@@ -0,0 +1,306 @@
+//! Export command implementation - pack a context into a `.tar.gz` archive.
+//!
+//! The archive holds a single `<name>/` directory laid out like the store:
+//! `adc.json`, `metadata.json` and, if bundled, `credential_source`. A
+//! portable export also carries `portable.json`, recording the absolute paths
+//! it replaced with [`CONTEXT_DIR_PLACEHOLDER`].
+//!
+//! `export --all` writes one such directory per context, plus the store's
+//! control files (`.current`, `.previous`, `groups.json`), the settings file
+//! as `config.toml` and a [`BACKUP_MANIFEST`] that marks the archive as a
+//! full backup.
+
+use anyhow::{Context, Result, bail};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::config::{
+    context_exists, get_context_credential_source_path, get_context_metadata_path, get_store_dir,
+    list_contexts, now_unix, write_atomic_private,
+};
+use crate::credstore::credential_store;
+use crate::error::GcpxError;
+use crate::settings::get_settings_path;
+
+/// Stands for the importing machine's context directory in portable exports.
+pub const CONTEXT_DIR_PLACEHOLDER: &str = "${GCPX_CONTEXT_DIR}";
+
+/// Name of the manifest written next to the credentials by portable exports.
+pub const PORTABLE_MANIFEST: &str = "portable.json";
+
+/// Name of the manifest at the top of a full store backup.
+pub const BACKUP_MANIFEST: &str = "gcpx-backup.json";
+
+/// Store files carried by a full backup, besides the contexts.
+pub const BACKUP_CONTROL_FILES: &[&str] = &[".current", ".previous", "groups.json"];
+
+/// Name of the settings file inside a full backup.
+pub const BACKUP_SETTINGS_FILE: &str = "config.toml";
+
+/// Manifest of a full store backup.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BackupManifest {
+    /// Contexts in the archive
+    pub contexts: Vec<String>,
+    /// When the backup was made (Unix seconds)
+    pub created: u64,
+}
+
+/// Options controlling `gcpx export`.
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    /// Replace machine-specific paths in the credentials with placeholders
+    pub portable: bool,
+    /// With a full backup, leave out contexts whose credentials can't be read
+    /// instead of failing
+    pub skip_unreadable: bool,
+}
+
+/// Paths a portable export rewrote, keyed by their JSON field
+/// (e.g. `credential_source.file`), with the original value.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PortableManifest {
+    pub paths: BTreeMap<String, String>,
+}
+
+/// Credentials with machine-specific paths replaced by placeholders.
+struct ScrubbedAdc {
+    adc: Vec<u8>,
+    manifest: PortableManifest,
+    credential_source: Option<Vec<u8>>,
+}
+
+/// Rewrites the absolute paths in `external_account` credentials to point
+/// into the context directory.
+///
+/// The `credential_source.file` token file is carried along (the bundled copy
+/// if there is one, otherwise the original); `credential_source.executable`
+/// output files are only relocated. Other credential types are returned
+/// unchanged.
+fn scrub_paths(name: &str, adc: &[u8]) -> Result<ScrubbedAdc> {
+    let bundled = get_context_credential_source_path(name)?;
+    let mut scrubbed = ScrubbedAdc {
+        adc: adc.to_vec(),
+        manifest: PortableManifest::default(),
+        credential_source: fs::read(&bundled).ok(),
+    };
+    let Ok(mut value) = serde_json::from_slice::<serde_json::Value>(adc) else {
+        return Ok(scrubbed);
+    };
+    if value.get("type").and_then(|t| t.as_str()) != Some("external_account") {
+        return Ok(scrubbed);
+    }
+    let Some(source) = value
+        .get_mut("credential_source")
+        .and_then(|s| s.as_object_mut())
+    else {
+        return Ok(scrubbed);
+    };
+
+    if let Some(file) = source
+        .get("file")
+        .and_then(|f| f.as_str())
+        .map(String::from)
+    {
+        if scrubbed.credential_source.is_none() {
+            scrubbed.credential_source = fs::read(&file).ok();
+        }
+        if scrubbed.credential_source.is_some() {
+            source.insert(
+                "file".to_string(),
+                format!("{}/credential_source", CONTEXT_DIR_PLACEHOLDER).into(),
+            );
+            scrubbed
+                .manifest
+                .paths
+                .insert("credential_source.file".to_string(), file);
+        } else {
+            eprintln!(
+                "Warning: credential source {} is not readable; left as is.",
+                file
+            );
+        }
+    }
+    if let Some(executable) = source.get_mut("executable").and_then(|e| e.as_object_mut()) {
+        if let Some(output) = executable
+            .get("output_file")
+            .and_then(|f| f.as_str())
+            .map(String::from)
+        {
+            executable.insert(
+                "output_file".to_string(),
+                format!("{}/executable_output.json", CONTEXT_DIR_PLACEHOLDER).into(),
+            );
+            scrubbed.manifest.paths.insert(
+                "credential_source.executable.output_file".to_string(),
+                output,
+            );
+        }
+    }
+
+    if !scrubbed.manifest.paths.is_empty() {
+        scrubbed.adc = serde_json::to_vec_pretty(&value)?;
+    }
+    Ok(scrubbed)
+}
+
+/// Appends a private (0600) file to the archive.
+fn append_file<W: std::io::Write>(
+    builder: &mut tar::Builder<W>,
+    path: &str,
+    content: &[u8],
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o600);
+    header.set_mtime(now_unix());
+    header.set_cksum();
+    builder.append_data(&mut header, path, content)?;
+    Ok(())
+}
+
+/// Appends a context's `<name>/` directory to the archive, returning the
+/// paths a portable export replaced.
+fn append_context<W: std::io::Write>(
+    builder: &mut tar::Builder<W>,
+    name: &str,
+    options: &ExportOptions,
+) -> Result<PortableManifest> {
+    let adc = credential_store()?.read(name)?;
+    let scrubbed = if options.portable {
+        scrub_paths(name, &adc)?
+    } else {
+        ScrubbedAdc {
+            adc,
+            manifest: PortableManifest::default(),
+            credential_source: fs::read(get_context_credential_source_path(name)?).ok(),
+        }
+    };
+
+    append_file(builder, &format!("{}/adc.json", name), &scrubbed.adc)?;
+    let metadata_path = get_context_metadata_path(name)?;
+    if metadata_path.exists() {
+        append_file(
+            builder,
+            &format!("{}/metadata.json", name),
+            &fs::read(&metadata_path)?,
+        )?;
+    }
+    if let Some(credential_source) = &scrubbed.credential_source {
+        append_file(
+            builder,
+            &format!("{}/credential_source", name),
+            credential_source,
+        )?;
+    }
+    if options.portable {
+        append_file(
+            builder,
+            &format!("{}/{}", name, PORTABLE_MANIFEST),
+            &serde_json::to_vec_pretty(&scrubbed.manifest)?,
+        )?;
+    }
+    Ok(scrubbed.manifest)
+}
+
+/// Writes a context to a `.tar.gz` archive that `gcpx import` can restore.
+///
+/// With `portable`, absolute paths in `external_account` credentials are
+/// replaced by placeholders (recorded in `portable.json`) and the token file
+/// is included, so the context works wherever the store lives on the
+/// importing machine. For other credential types it makes no difference.
+/// The archive contains credentials, so it is created readable only by the
+/// current user.
+pub fn export_context(name: &str, file: &Path, options: &ExportOptions) -> Result<()> {
+    if !context_exists(name)? {
+        return Err(GcpxError::ContextNotFound(name.to_string()).into());
+    }
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    let manifest = append_context(&mut builder, name, options)?;
+    let archive = builder.into_inner()?.finish()?;
+    write_atomic_private(file, &archive)
+        .with_context(|| format!("Failed to write archive: {:?}", file))?;
+
+    println!("Exported context '{}' to {}", name, file.display());
+    if !manifest.paths.is_empty() {
+        println!(
+            "  Replaced {} machine-specific path(s) with placeholders",
+            manifest.paths.len()
+        );
+    }
+    Ok(())
+}
+
+/// Backs up the whole store to a `.tar.gz` archive that `gcpx import --all`
+/// can restore: every context (credentials, metadata and any bundled
+/// credential source), the tracking files, `groups.json` and the settings
+/// file.
+///
+/// If any context's credentials can't be read, nothing is written and an
+/// error is returned, unless `skip_unreadable` is set: then those contexts are
+/// left out with a warning. `portable` applies to every context, as for a
+/// single export. The archive contains credentials, so it is created readable
+/// only by the current user.
+pub fn export_store(file: &Path, options: &ExportOptions) -> Result<()> {
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    let mut manifest = BackupManifest {
+        created: now_unix(),
+        ..Default::default()
+    };
+    let mut skipped = 0;
+    for name in list_contexts()? {
+        match append_context(&mut builder, &name, options) {
+            Ok(_) => manifest.contexts.push(name),
+            Err(e) => {
+                skipped += 1;
+                eprintln!("Warning: skipping context '{}': {:#}", name, e);
+            }
+        }
+    }
+
+    if skipped > 0 && !options.skip_unreadable {
+        bail!(GcpxError::UnreadableContexts(skipped));
+    }
+
+    let store_dir = get_store_dir()?;
+    let mut control_files = 0;
+    for file_name in BACKUP_CONTROL_FILES {
+        if let Ok(content) = fs::read(store_dir.join(file_name)) {
+            append_file(&mut builder, file_name, &content)?;
+            control_files += 1;
+        }
+    }
+    let settings = fs::read(get_settings_path()?).ok();
+    if let Some(settings) = &settings {
+        append_file(&mut builder, BACKUP_SETTINGS_FILE, settings)?;
+    }
+    append_file(
+        &mut builder,
+        BACKUP_MANIFEST,
+        &serde_json::to_vec_pretty(&manifest)?,
+    )?;
+    let archive = builder.into_inner()?.finish()?;
+    write_atomic_private(file, &archive)
+        .with_context(|| format!("Failed to write archive: {:?}", file))?;
+
+    println!(
+        "Backed up {} context(s){} to {}",
+        manifest.contexts.len(),
+        if skipped > 0 {
+            format!(" ({} skipped)", skipped)
+        } else {
+            String::new()
+        },
+        file.display()
+    );
+    println!(
+        "  Also included: {} control file(s){}",
+        control_files,
+        if settings.is_some() { ", settings" } else { "" }
+    );
+    Ok(())
+}
@@ -0,0 +1,129 @@
+//! Groups command implementations - manage and switch context groups.
+
+use anyhow::{Result, bail};
+
+use crate::commands::run::{ExportShell, export_line};
+use crate::commands::switch::{SwitchOptions, switch_context_with_options};
+use crate::config::{
+    context_exists, switch_kubectl_context, validate_context_name, validate_env_key,
+};
+use crate::error::GcpxError;
+use crate::groups::{Group, load_groups, save_groups};
+
+/// Lists saved groups with their context and overrides.
+pub fn list_groups() -> Result<()> {
+    let groups = load_groups()?;
+    if groups.is_empty() {
+        println!("No groups found. Create one with 'gcpx groups add <name> <context>'");
+    }
+    for (name, group) in &groups {
+        println!("{} -> {}", name, group.context);
+        if let Some(kctx) = &group.kubectl_context {
+            println!("  kubectl: {}", kctx);
+        }
+        if !group.env.is_empty() {
+            let keys: Vec<&str> = group.env.keys().map(String::as_str).collect();
+            println!("  env: {}", keys.join(", "));
+        }
+    }
+    Ok(())
+}
+
+/// Creates or replaces a group.
+///
+/// `env` entries are `KEY=VALUE` pairs.
+pub fn add_group(
+    name: &str,
+    context: &str,
+    kubectl_context: Option<String>,
+    env: &[String],
+) -> Result<()> {
+    validate_context_name(name)?;
+    validate_context_name(context)?;
+    if !context_exists(context)? {
+        bail!(GcpxError::ContextNotFound(context.to_string()));
+    }
+    let mut group = Group {
+        context: context.to_string(),
+        kubectl_context,
+        ..Default::default()
+    };
+    for pair in env {
+        let Some((key, value)) = pair.split_once('=') else {
+            bail!(GcpxError::InvalidEnvAssignment(
+                "env".to_string(),
+                pair.to_string()
+            ));
+        };
+        validate_env_key(key)?;
+        group.env.insert(key.to_string(), value.to_string());
+    }
+
+    let mut groups = load_groups()?;
+    let replaced = groups.insert(name.to_string(), group).is_some();
+    save_groups(&groups)?;
+    println!(
+        "Group '{}' {}.",
+        name,
+        if replaced { "updated" } else { "saved" }
+    );
+    Ok(())
+}
+
+/// Deletes a group (the context it refers to is left alone).
+pub fn remove_group(name: &str) -> Result<()> {
+    let mut groups = load_groups()?;
+    if groups.remove(name).is_none() {
+        bail!(GcpxError::GroupNotFound(name.to_string()));
+    }
+    save_groups(&groups)?;
+    println!("Group '{}' deleted.", name);
+    Ok(())
+}
+
+/// Prints `export` statements for a group's environment variables, for use
+/// with `eval "$(gcpx groups env <name>)"`.
+pub fn print_group_env(name: &str, shell: ExportShell) -> Result<()> {
+    let groups = load_groups()?;
+    let Some(group) = groups.get(name) else {
+        bail!(GcpxError::GroupNotFound(name.to_string()));
+    };
+    for (key, value) in &group.env {
+        println!("{}", export_line(shell, key, value)?);
+    }
+    Ok(())
+}
+
+/// Switches to a group: its context, then its kubectl override.
+///
+/// gcpx can't change the calling shell's environment, so the group's
+/// environment variables are applied with `eval "$(gcpx groups env <name>)"`.
+pub fn switch_group(name: &str, quiet: bool) -> Result<()> {
+    let groups = load_groups()?;
+    let Some(group) = groups.get(name) else {
+        bail!(GcpxError::GroupNotFound(name.to_string()));
+    };
+
+    switch_context_with_options(
+        &group.context,
+        &SwitchOptions {
+            quiet,
+            ..Default::default()
+        },
+    )?;
+    if let Some(kctx) = &group.kubectl_context {
+        let shown = if quiet { "(hidden)" } else { kctx };
+        match switch_kubectl_context(kctx).failure_message(shown) {
+            None if !quiet => println!("  kubectl (group override): {}", kctx),
+            None => {}
+            Some(message) => eprintln!("Warning: {}.", message),
+        }
+    }
+    if !group.env.is_empty() {
+        println!(
+            "  Run 'eval \"$(gcpx groups env {})\"' to set the group's environment.",
+            name
+        );
+    }
+    Ok(())
+}
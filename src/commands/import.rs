@@ -0,0 +1,267 @@
+//! Import command implementation - restore a context from a `gcpx export`
+//! archive, or the whole store from a `gcpx export --all` backup.
+
+use anyhow::{Context, Result, bail};
+use flate2::read::GzDecoder;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path};
+
+use crate::commands::export::{
+    BACKUP_CONTROL_FILES, BACKUP_MANIFEST, BACKUP_SETTINGS_FILE, BackupManifest,
+    CONTEXT_DIR_PLACEHOLDER, PORTABLE_MANIFEST, PortableManifest,
+};
+use crate::commands::import_store::install_context;
+use crate::config::{
+    ContextMetadata, context_exists, get_context_dir, get_store_dir, set_current_tracking,
+    validate_adc_json, validate_context_name, write_atomic,
+};
+use crate::error::GcpxError;
+use crate::settings::get_settings_path;
+
+/// Options controlling `gcpx import`.
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    /// Name to import the context as (defaults to its name in the archive)
+    pub name: Option<String>,
+    /// Replace an existing context of the same name
+    pub overwrite: bool,
+}
+
+/// Reads the files of an export archive, keyed by their path inside it.
+///
+/// Entries with absolute paths or `..` components are rejected.
+fn read_archive(file: &Path) -> Result<BTreeMap<String, Vec<u8>>> {
+    let reader =
+        fs::File::open(file).with_context(|| format!("Failed to open archive: {:?}", file))?;
+    let mut archive = tar::Archive::new(GzDecoder::new(reader));
+    let mut files = BTreeMap::new();
+    for entry in archive
+        .entries()
+        .with_context(|| format!("Not a gcpx export archive: {:?}", file))?
+    {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.into_owned();
+        if !path.components().all(|c| matches!(c, Component::Normal(_))) {
+            bail!(GcpxError::UnsafeArchivePath(path));
+        }
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content)?;
+        files.insert(path.to_string_lossy().into_owned(), content);
+    }
+    Ok(files)
+}
+
+/// Replaces [`CONTEXT_DIR_PLACEHOLDER`] in every string of the credentials
+/// with the context's directory on this machine.
+fn resolve_placeholders(value: &mut serde_json::Value, context_dir: &str) {
+    match value {
+        serde_json::Value::String(s) if s.contains(CONTEXT_DIR_PLACEHOLDER) => {
+            *s = s.replace(CONTEXT_DIR_PLACEHOLDER, context_dir);
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                resolve_placeholders(item, context_dir);
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for item in map.values_mut() {
+                resolve_placeholders(item, context_dir);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Installs the context stored under `archived/` in the archive as `name`,
+/// returning how many portable paths were resolved.
+///
+/// The credentials and metadata are validated before anything is written.
+fn install_archived(
+    files: &BTreeMap<String, Vec<u8>>,
+    archived: &str,
+    name: &str,
+    file: &Path,
+) -> Result<usize> {
+    let entry = |file_name: &str| files.get(&format!("{}/{}", archived, file_name));
+    let Some(adc) = entry("adc.json") else {
+        bail!(GcpxError::ArchiveMissingCredentials(
+            file.to_path_buf(),
+            archived.to_string()
+        ));
+    };
+    validate_adc_json(adc)?;
+    let metadata = match entry("metadata.json") {
+        Some(content) => serde_json::from_slice::<ContextMetadata>(content)
+            .with_context(|| format!("Invalid metadata for '{}' in archive", archived))?,
+        None => ContextMetadata {
+            gcloud_config: archived.to_string(),
+            ..Default::default()
+        },
+    };
+
+    let mut adc = adc.clone();
+    let mut resolved = 0;
+    if let Some(manifest) = entry(PORTABLE_MANIFEST) {
+        let manifest: PortableManifest = serde_json::from_slice(manifest)
+            .with_context(|| format!("Invalid {} in archive", PORTABLE_MANIFEST))?;
+        if !manifest.paths.is_empty() {
+            let mut value: serde_json::Value = serde_json::from_slice(&adc)?;
+            resolve_placeholders(&mut value, &get_context_dir(name)?.to_string_lossy());
+            adc = serde_json::to_vec_pretty(&value)?;
+            resolved = manifest.paths.len();
+        }
+    }
+
+    install_context(
+        name,
+        &adc,
+        &metadata,
+        entry("credential_source").map(Vec::as_slice),
+    )?;
+    Ok(resolved)
+}
+
+/// Restores a context from an archive written by `gcpx export`.
+///
+/// Paths a portable export replaced with placeholders are re-resolved
+/// against the context's directory in this store. The credentials and
+/// metadata are validated before anything is written. Returns the name the
+/// context was imported as.
+pub fn import_context(file: &Path, options: &ImportOptions) -> Result<String> {
+    let files = read_archive(file)?;
+    if files.contains_key(BACKUP_MANIFEST) {
+        bail!(GcpxError::ArchiveIsBackup(file.to_path_buf()));
+    }
+    let archived_names: BTreeSet<&str> = files
+        .keys()
+        .filter_map(|path| path.split('/').next())
+        .collect();
+    let archived = match Vec::from_iter(archived_names).as_slice() {
+        [name] => name.to_string(),
+        [] => bail!(GcpxError::EmptyArchive(file.to_path_buf())),
+        _ => bail!(GcpxError::ArchiveHasSeveralContexts(file.to_path_buf())),
+    };
+    let name = options.name.clone().unwrap_or_else(|| archived.clone());
+    validate_context_name(&name)?;
+    if !options.overwrite && context_exists(&name)? {
+        return Err(GcpxError::ContextExists(name).into());
+    }
+
+    let resolved = install_archived(&files, &archived, &name, file)?;
+
+    println!("Imported context '{}' from {}", name, file.display());
+    if resolved > 0 {
+        println!(
+            "  Resolved {} portable path(s) to {}",
+            resolved,
+            get_context_dir(&name)?.display()
+        );
+    }
+    Ok(name)
+}
+
+/// Restores a full store backup written by `gcpx export --all`.
+///
+/// Every context in the backup is restored under its own name; existing
+/// contexts are skipped unless `overwrite` is set. The same goes for the
+/// tracking files, `groups.json` and the settings file: they are only
+/// written if missing here, or with `overwrite`. `.current` is only restored
+/// if that context was imported, and doesn't activate it: the user is told to
+/// `gcpx switch`. Every context is attempted; an error is returned at the end
+/// if any failed. `name` is ignored.
+pub fn import_backup(file: &Path, options: &ImportOptions) -> Result<()> {
+    let files = read_archive(file)?;
+    let Some(manifest) = files.get(BACKUP_MANIFEST) else {
+        bail!(GcpxError::ArchiveNotBackup(file.to_path_buf()));
+    };
+    let manifest: BackupManifest = serde_json::from_slice(manifest)
+        .with_context(|| format!("Invalid {} in archive", BACKUP_MANIFEST))?;
+
+    let (mut imported, mut skipped, mut failed) = (Vec::new(), 0, 0);
+    for name in &manifest.contexts {
+        if let Err(e) = validate_context_name(name) {
+            failed += 1;
+            eprintln!("  {}: {:#}", name, e);
+            continue;
+        }
+        if !options.overwrite && context_exists(name)? {
+            skipped += 1;
+            println!(
+                "  {}: already exists, skipped (use --overwrite to replace)",
+                name
+            );
+            continue;
+        }
+        match install_archived(&files, name, name, file) {
+            Ok(_) => {
+                imported.push(name.as_str());
+                println!("  {}: imported", name);
+            }
+            Err(e) => {
+                failed += 1;
+                eprintln!("  {}: {:#}", name, e);
+            }
+        }
+    }
+
+    let store_dir = get_store_dir()?;
+    fs::create_dir_all(&store_dir)?;
+    for file_name in BACKUP_CONTROL_FILES {
+        let Some(content) = files.get(*file_name) else {
+            continue;
+        };
+        let dest = store_dir.join(file_name);
+        if dest.exists() && !options.overwrite {
+            println!("  {}: already exists, kept", file_name);
+            continue;
+        }
+        if *file_name == ".current" {
+            // Tracking must name a context that is here, and the live gcloud
+            // config and ADC only follow with a switch
+            let current = String::from_utf8_lossy(content).trim().to_string();
+            if !imported.contains(&current.as_str()) {
+                println!(
+                    "  {}: not restored, '{}' wasn't imported",
+                    file_name, current
+                );
+                continue;
+            }
+            set_current_tracking(&current)?;
+            println!(
+                "  {}: restored; run 'gcpx switch {}' to activate it",
+                file_name, current
+            );
+            continue;
+        }
+        write_atomic(&dest, content)?;
+        println!("  {}: restored", file_name);
+    }
+    if let Some(content) = files.get(BACKUP_SETTINGS_FILE) {
+        let dest = get_settings_path()?;
+        if dest.exists() && !options.overwrite {
+            println!("  settings: already exist at {}, kept", dest.display());
+        } else {
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            write_atomic(&dest, content)?;
+            println!("  settings: restored to {}", dest.display());
+        }
+    }
+
+    println!(
+        "Imported {} context(s), skipped {}, failed {}.",
+        imported.len(),
+        skipped,
+        failed
+    );
+    if failed > 0 {
+        bail!(GcpxError::ImportFailed(failed, manifest.contexts.len()));
+    }
+    Ok(())
+}
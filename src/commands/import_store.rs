@@ -0,0 +1,219 @@
+//! Import-store command implementation - copy contexts from another gcpx store.
+
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::path::Path;
+
+use crate::config::{
+    ContextMetadata, get_context_dir, get_current_tracking, get_previous_tracking, get_store_dir,
+    seed_context_gcloud_dir, validate_adc_json, validate_context_name, write_atomic_private,
+};
+use crate::credstore::{CredentialMode, StoreBackend, credential_store};
+use crate::error::GcpxError;
+use crate::settings::load_settings;
+use crate::trash::{LastOp, OpKind, get_trash_dir, move_to_trash, record_last_op};
+
+/// Options controlling `gcpx import-store`.
+#[derive(Debug, Clone, Default)]
+pub struct ImportStoreOptions {
+    /// Contexts to import
+    pub names: Vec<String>,
+    /// Import every context in the source store
+    pub all: bool,
+    /// Replace contexts that already exist instead of skipping them
+    pub overwrite: bool,
+}
+
+/// Lists the contexts in another store directory: visible subdirectories
+/// holding credentials or metadata.
+fn list_store_contexts(source: &Path) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in
+        fs::read_dir(source).with_context(|| format!("Failed to read store: {:?}", source))?
+    {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if name.starts_with('.') || !path.is_dir() {
+            continue;
+        }
+        if path.join("adc.json").exists() || path.join("metadata.json").exists() {
+            names.push(name.to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Copies one context from `source` into the current store.
+///
+/// The credentials and metadata are validated before anything is written.
+fn import_one(source: &Path, name: &str) -> Result<()> {
+    let dir = source.join(name);
+
+    let adc_path = dir.join("adc.json");
+    let adc = fs::read(&adc_path)
+        .with_context(|| format!("No readable credentials at {:?}", adc_path))?;
+    validate_adc_json(&adc)?;
+
+    let metadata_path = dir.join("metadata.json");
+    let metadata = if metadata_path.exists() {
+        let content = fs::read_to_string(&metadata_path)?;
+        serde_json::from_str::<ContextMetadata>(&content)
+            .with_context(|| format!("Invalid metadata: {:?}", metadata_path))?
+    } else {
+        // Legacy context: its gcloud config was named after it
+        ContextMetadata {
+            gcloud_config: name.to_string(),
+            ..Default::default()
+        }
+    };
+
+    let source = dir.join("credential_source");
+    let credential_source = if source.exists() {
+        Some(fs::read(&source)?)
+    } else {
+        None
+    };
+    install_context(name, &adc, &metadata, credential_source.as_deref())
+}
+
+/// Writes an imported context into the current store, replacing any existing
+/// directory of that name.
+///
+/// The new directory is staged next to the store's contexts and renamed into
+/// place, so a failure leaves the existing context untouched. A replaced
+/// context goes to the trash and can be brought back with `gcpx undo`.
+///
+/// The credentials go through the configured credential store (0600 for the
+/// default file backend); a bundled credential source is kept next to them.
+pub(crate) fn install_context(
+    name: &str,
+    adc: &[u8],
+    metadata: &ContextMetadata,
+    credential_source: Option<&[u8]>,
+) -> Result<()> {
+    let store_dir = get_store_dir()?;
+    fs::create_dir_all(&store_dir)?;
+    // Hidden, so it's never listed as a context; removed if anything fails
+    let staging = tempfile::Builder::new()
+        .prefix(&format!(".import-{}-", name))
+        .tempdir_in(&store_dir)
+        .context("Failed to create a staging directory")?;
+    let file_backend = load_settings()?.store_backend == StoreBackend::File;
+    if file_backend {
+        CredentialMode::from_settings()?.write(&staging.path().join("adc.json"), adc)?;
+    }
+    if let Some(credential_source) = credential_source {
+        write_atomic_private(&staging.path().join("credential_source"), credential_source)?;
+    }
+    fs::write(
+        staging.path().join("metadata.json"),
+        serde_json::to_string_pretty(metadata)?,
+    )?;
+
+    let dest = get_context_dir(name)?;
+    let trash_entry = if dest.exists() {
+        Some(move_to_trash(name)?)
+    } else {
+        None
+    };
+    let installed = fs::rename(staging.path(), &dest)
+        .with_context(|| format!("Failed to move the imported context to {:?}", dest))
+        .and_then(|()| {
+            if file_backend {
+                Ok(())
+            } else {
+                credential_store()?.write(name, adc)
+            }
+        });
+    if let Err(e) = installed {
+        let _ = fs::remove_dir_all(&dest);
+        if let Some(entry) = &trash_entry {
+            let _ = fs::rename(get_trash_dir()?.join(entry), &dest);
+        }
+        return Err(e);
+    }
+    if let Some(trash_entry) = trash_entry {
+        record_last_op(&LastOp {
+            op: OpKind::Import,
+            name: name.to_string(),
+            trash_entry,
+            current: get_current_tracking(),
+            previous: get_previous_tracking(),
+            gcloud_config: None,
+            gcloud_config_entry: None,
+        })?;
+    }
+    if load_settings()?.isolated_configs {
+        seed_context_gcloud_dir(name, metadata, true)?;
+    }
+    Ok(())
+}
+
+/// Copies contexts from another gcpx store directory (e.g. a synced copy of
+/// an old machine's `~/.gcpx`) into the current store.
+///
+/// Contexts that already exist are skipped unless `overwrite` is set. Every
+/// selected context is attempted; an error is returned at the end if any
+/// failed. Tracking files are not imported.
+pub fn import_store(source: &Path, options: &ImportStoreOptions) -> Result<()> {
+    if !source.is_dir() {
+        bail!(GcpxError::StoreNotFound(source.to_path_buf()));
+    }
+    let store_dir = get_store_dir()?;
+    if store_dir.exists() && fs::canonicalize(source)? == fs::canonicalize(&store_dir)? {
+        bail!(GcpxError::SameStore(source.to_path_buf()));
+    }
+
+    let available = list_store_contexts(source)?;
+    let names = if options.all {
+        available.clone()
+    } else if options.names.is_empty() {
+        bail!(GcpxError::NoContextsSelected(
+            "Pass context names or --all.".to_string()
+        ));
+    } else {
+        options.names.clone()
+    };
+    for name in &names {
+        validate_context_name(name)?;
+    }
+
+    let (mut imported, mut skipped, mut failed) = (0, 0, 0);
+    for name in &names {
+        if !available.contains(name) {
+            failed += 1;
+            eprintln!("  {}: not found in {:?}", name, source);
+            continue;
+        }
+        if !options.overwrite && get_context_dir(name)?.exists() {
+            skipped += 1;
+            println!(
+                "  {}: already exists, skipped (use --overwrite to replace)",
+                name
+            );
+            continue;
+        }
+        match import_one(source, name) {
+            Ok(()) => {
+                imported += 1;
+                println!("  {}: imported", name);
+            }
+            Err(e) => {
+                failed += 1;
+                eprintln!("  {}: {:#}", name, e);
+            }
+        }
+    }
+
+    println!(
+        "Imported {} context(s), skipped {}, failed {}.",
+        imported, skipped, failed
+    );
+    if failed > 0 {
+        bail!(GcpxError::ImportFailed(failed, names.len()));
+    }
+    Ok(())
+}
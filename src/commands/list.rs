@@ -0,0 +1,502 @@
+//! List command implementation.
+
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::config::{
+    ContextMetadata, context_problems, get_current_tracking, list_contexts,
+    list_contexts_with_metadata, list_gcloud_configs,
+};
+use crate::credstore::credential_store;
+use crate::error::GcpxError;
+use crate::json::{ContextEntry, JsonEnvelope};
+use crate::settings::load_settings;
+use crate::verify::{
+    DEFAULT_CHECK_CONCURRENCY, DEFAULT_CHECK_TIMEOUT, TokenStatus, check_context_tokens,
+};
+
+/// Output format for `gcpx list`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// One name per line with an active marker (stable, for prompts and scripts)
+    #[default]
+    Plain,
+    /// Columnar view with account, project and gcloud config
+    Table,
+    /// Bare JSON array (prefer `--json` for the versioned object)
+    Json,
+}
+
+/// Sort order for `gcpx list`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SortOrder {
+    /// Alphabetical by context name
+    #[default]
+    Name,
+    /// Most recently used first
+    Recent,
+    /// Alphabetical by project
+    Project,
+    /// Alphabetical by account
+    Account,
+}
+
+/// Field to group contexts by in `gcpx list --group-by`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GroupBy {
+    /// The account's organization (email domain)
+    Org,
+}
+
+impl GroupBy {
+    /// Returns the group a context belongs to, if the field is set.
+    fn key(self, metadata: Option<&ContextMetadata>) -> Option<String> {
+        match self {
+            GroupBy::Org => metadata.and_then(|m| m.org.clone()),
+        }
+    }
+}
+
+/// Options controlling `gcpx list` output.
+#[derive(Debug, Clone, Default)]
+pub struct ListOptions {
+    pub format: OutputFormat,
+    pub sort: SortOrder,
+    /// `key=value` filters (tag, account, project, config, org); all must match
+    pub filters: Vec<String>,
+    /// Check each context's saved credentials against Google (network access)
+    pub stale: bool,
+    /// Print only the number of (matching) contexts
+    pub count: bool,
+    /// Append a footer with the total and the active context
+    pub summary: bool,
+    /// Print a versioned JSON object (`{"version", "current", "contexts"}`)
+    pub json: bool,
+    /// Cluster plain and table output under a heading per group
+    pub group_by: Option<GroupBy>,
+    /// Show only contexts with problems, with a reason each (fails if any)
+    pub broken: bool,
+    /// Only contexts last used at or after this Unix time
+    pub since: Option<u64>,
+    /// Only contexts last used before this Unix time (or never)
+    pub before: Option<u64>,
+    /// Only the tracked context, if it still exists
+    pub active_only: bool,
+    /// Every context except the tracked one
+    pub inactive_only: bool,
+    /// Include each context's `adc.json` path (`name<TAB>path` in plain format)
+    pub paths: bool,
+    /// Mark contexts whose gcloud configuration no longer exists (one gcloud call)
+    pub check_config: bool,
+    /// With `check_config`, fail if any context's configuration is missing
+    pub strict: bool,
+}
+
+/// Returns the gcloud configuration a context uses; contexts without metadata
+/// are assumed to use one named after them.
+fn gcloud_config_of<'a>(name: &'a str, metadata: Option<&'a ContextMetadata>) -> &'a str {
+    metadata.map_or(name, |m| m.gcloud_config.as_str())
+}
+
+/// Returns the contexts whose gcloud configuration doesn't exist, asking
+/// gcloud for its configurations once.
+fn contexts_missing_config(
+    contexts: &[(String, Option<ContextMetadata>)],
+) -> Result<BTreeSet<String>> {
+    if load_settings()?.isolated_configs {
+        bail!(GcpxError::CheckConfigIsolated);
+    }
+    let existing: BTreeSet<String> = list_gcloud_configs()?.into_iter().collect();
+    Ok(contexts
+        .iter()
+        .filter(|(name, m)| !existing.contains(gcloud_config_of(name, m.as_ref())))
+        .map(|(name, _)| name.clone())
+        .collect())
+}
+
+/// Explains how to fix contexts whose gcloud configuration is missing, and
+/// with `strict` fails if there are any.
+fn report_missing_configs(missing: &BTreeSet<String>, strict: bool) -> Result<()> {
+    if missing.is_empty() {
+        return Ok(());
+    }
+    eprintln!(
+        "{} context(s) use a gcloud configuration that no longer exists. Recreate it with \
+        'gcloud config configurations create <config>', or point the context at another \
+        one with 'gcpx repair <name> --config <config> --force'.",
+        missing.len()
+    );
+    if strict {
+        bail!(GcpxError::MissingGcloudConfigs(missing.len()));
+    }
+    Ok(())
+}
+
+/// Body of `gcpx list --json`.
+#[derive(Serialize)]
+struct ListJson<'a> {
+    contexts: Vec<ContextEntry<'a>>,
+}
+
+/// Returns true if the context matches a single `key=value` filter.
+fn matches_filter(metadata: Option<&ContextMetadata>, key: &str, value: &str) -> Result<bool> {
+    let Some(m) = metadata else {
+        return Ok(false);
+    };
+    Ok(match key {
+        "tag" => m.tags.iter().any(|t| t == value),
+        "account" => m.account.as_deref() == Some(value),
+        "project" => m.project.as_deref() == Some(value),
+        "config" => m.gcloud_config == value,
+        "org" => m.org.as_deref() == Some(value),
+        _ => bail!(GcpxError::UnknownFilterKey(key.to_string())),
+    })
+}
+
+/// Lists saved contexts in the requested format.
+pub fn list_contexts_cmd(options: &ListOptions) -> Result<()> {
+    if options.broken {
+        return list_broken();
+    }
+    let current = get_current_tracking();
+    let mut contexts = list_contexts_with_metadata()?;
+
+    for filter in &options.filters {
+        let Some((key, value)) = filter.split_once('=') else {
+            bail!(GcpxError::InvalidFilter(filter.to_string()));
+        };
+        let mut kept = Vec::new();
+        for (name, metadata) in contexts {
+            if matches_filter(metadata.as_ref(), key, value)? {
+                kept.push((name, metadata));
+            }
+        }
+        contexts = kept;
+    }
+
+    // Contexts never used count as older than any date
+    contexts.retain(|(_, metadata)| {
+        let last_used = metadata.as_ref().and_then(|m| m.last_used);
+        options
+            .since
+            .is_none_or(|since| last_used.is_some_and(|t| t >= since))
+            && options
+                .before
+                .is_none_or(|before| last_used.is_none_or(|t| t < before))
+    });
+    if options.active_only {
+        contexts.retain(|(name, _)| *name == current);
+    } else if options.inactive_only {
+        contexts.retain(|(name, _)| *name != current);
+    }
+
+    // Stable sorts keep name order for ties; contexts without the field go last
+    match options.sort {
+        SortOrder::Name => {}
+        SortOrder::Recent => {
+            contexts.sort_by_key(|(_, m)| std::cmp::Reverse(m.as_ref().and_then(|m| m.last_used)))
+        }
+        SortOrder::Project => contexts.sort_by_cached_key(|(_, m)| {
+            let project = m.as_ref().and_then(|m| m.project.clone());
+            (project.is_none(), project)
+        }),
+        SortOrder::Account => contexts.sort_by_cached_key(|(_, m)| {
+            let account = m.as_ref().and_then(|m| m.account.clone());
+            (account.is_none(), account)
+        }),
+    }
+
+    let missing = if options.check_config {
+        contexts_missing_config(&contexts)?
+    } else {
+        BTreeSet::new()
+    };
+
+    if options.count {
+        println!("{}", contexts.len());
+        return Ok(());
+    }
+
+    if options.stale {
+        return print_stale_report(options.format, &contexts);
+    }
+
+    let store = credential_store()?;
+    let entries = || -> Result<Vec<ContextEntry>> {
+        contexts
+            .iter()
+            .map(|(name, metadata)| {
+                Ok(ContextEntry {
+                    name,
+                    active: *name == current,
+                    adc_path: if options.paths {
+                        store.stored_path(name)?
+                    } else {
+                        None
+                    },
+                    gcloud_config_missing: options.check_config.then(|| missing.contains(name)),
+                    metadata: metadata.as_ref(),
+                })
+            })
+            .collect()
+    };
+    if options.json {
+        JsonEnvelope::new(ListJson {
+            contexts: entries()?,
+        })
+        .print()?;
+        return report_missing_configs(&missing, options.strict);
+    }
+
+    match (options.format, options.group_by) {
+        (OutputFormat::Plain, None) if options.paths => {
+            // Contexts whose credentials aren't kept in a file get a `-`
+            for (name, _) in &contexts {
+                match store.stored_path(name)? {
+                    Some(path) => println!("{}\t{}", name, path.display()),
+                    None => println!("{}\t-", name),
+                }
+            }
+        }
+        // Bare names for scripts; the marker would be redundant
+        (OutputFormat::Plain, None) if options.active_only || options.inactive_only => {
+            for (name, _) in &contexts {
+                println!("{}", name);
+            }
+        }
+        (OutputFormat::Json, _) => println!("{}", serde_json::to_string_pretty(&entries()?)?),
+        (format, None) => print_contexts(format, &contexts, &current, &missing),
+        (format, Some(group_by)) => {
+            // Named groups alphabetically, ungrouped contexts last
+            let mut groups: BTreeMap<Option<String>, Vec<(String, Option<ContextMetadata>)>> =
+                BTreeMap::new();
+            for (name, metadata) in &contexts {
+                groups
+                    .entry(group_by.key(metadata.as_ref()))
+                    .or_default()
+                    .push((name.clone(), metadata.clone()));
+            }
+            let ungrouped = groups.remove(&None);
+            let groups = groups
+                .into_iter()
+                .map(|(key, group)| (key.unwrap_or_default(), group))
+                .chain(ungrouped.map(|group| ("(none)".to_string(), group)));
+            for (i, (heading, group)) in groups.enumerate() {
+                if i > 0 {
+                    println!();
+                }
+                println!("{}:", heading);
+                print_contexts(format, &group, &current, &missing);
+            }
+        }
+    }
+
+    if options.summary && options.format != OutputFormat::Json {
+        let active = if contexts.iter().any(|(name, _)| *name == current) {
+            current.as_str()
+        } else {
+            "none"
+        };
+        let noun = if contexts.len() == 1 {
+            "context"
+        } else {
+            "contexts"
+        };
+        println!("\n{} {}, active: {}", contexts.len(), noun, active);
+    }
+    report_missing_configs(&missing, options.strict)
+}
+
+/// Prints contexts in the plain or table format, marking those in `missing`
+/// as using a gcloud configuration that doesn't exist.
+fn print_contexts(
+    format: OutputFormat,
+    contexts: &[(String, Option<ContextMetadata>)],
+    current: &str,
+    missing: &BTreeSet<String>,
+) {
+    match format {
+        OutputFormat::Table => print_table(contexts, current, missing),
+        _ => {
+            if contexts.is_empty() {
+                println!("No contexts found. Create one with 'gcpx save <name>'");
+            }
+            for (name, m) in contexts {
+                let note = if missing.contains(name) {
+                    format!(
+                        " (gcloud config '{}' missing)",
+                        gcloud_config_of(name, m.as_ref())
+                    )
+                } else {
+                    String::new()
+                };
+                if name == current {
+                    println!("* {} (active){}", name, note);
+                } else {
+                    println!("  {}{}", name, note);
+                }
+            }
+        }
+    }
+}
+
+/// Prints only the contexts with problems (see [`context_problems`]), one
+/// line each, and returns an error if there are any.
+fn list_broken() -> Result<()> {
+    let mut broken = 0;
+    for name in list_contexts()? {
+        let problems = context_problems(&name)?;
+        if !problems.is_empty() {
+            broken += 1;
+            let problems: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+            println!("{}: {}", name, problems.join("; "));
+        }
+    }
+    if broken > 0 {
+        bail!(GcpxError::BrokenContexts(broken));
+    }
+    println!("No broken contexts.");
+    Ok(())
+}
+
+/// One context's outcome in `gcpx list --stale --format json`.
+#[derive(Serialize)]
+struct StaleEntry<'a> {
+    name: &'a str,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'a str>,
+}
+
+/// Checks every context's credentials and prints them grouped by outcome
+/// (plain), one row per context (table) or as a JSON array.
+fn print_stale_report(
+    format: OutputFormat,
+    contexts: &[(String, Option<ContextMetadata>)],
+) -> Result<()> {
+    let names: Vec<String> = contexts.iter().map(|(name, _)| name.clone()).collect();
+    let statuses = check_context_tokens(&names, DEFAULT_CHECK_CONCURRENCY, DEFAULT_CHECK_TIMEOUT);
+    let rows: Vec<StaleEntry> = names
+        .iter()
+        .zip(&statuses)
+        .map(|(name, status)| {
+            let (status, reason) = match status {
+                TokenStatus::Valid => ("valid", None),
+                TokenStatus::Revoked => ("revoked", None),
+                TokenStatus::Unchecked(reason) => ("unchecked", Some(reason.as_str())),
+            };
+            StaleEntry {
+                name,
+                status,
+                reason,
+            }
+        })
+        .collect();
+
+    match format {
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&rows)?);
+            return Ok(());
+        }
+        OutputFormat::Table => {
+            let width = rows.iter().map(|r| r.name.len()).max().unwrap_or(0).max(4);
+            println!("{:width$}  {:9}  REASON", "NAME", "STATUS");
+            for row in &rows {
+                println!(
+                    "{:width$}  {:9}  {}",
+                    row.name,
+                    row.status,
+                    row.reason.unwrap_or("-")
+                );
+            }
+            return Ok(());
+        }
+        OutputFormat::Plain => {}
+    }
+
+    let mut valid = Vec::new();
+    let mut revoked = Vec::new();
+    let mut unchecked = Vec::new();
+    for (name, status) in names.iter().zip(&statuses) {
+        match status {
+            TokenStatus::Valid => valid.push(name.clone()),
+            TokenStatus::Revoked => revoked.push(name.clone()),
+            TokenStatus::Unchecked(reason) => unchecked.push(format!("{} ({})", name, reason)),
+        }
+    }
+
+    for (heading, entries) in [
+        ("Valid", valid),
+        ("Revoked or expired - run 'gcpx login <name>'", revoked),
+        ("Couldn't check", unchecked),
+    ] {
+        if entries.is_empty() {
+            continue;
+        }
+        println!("{}:", heading);
+        for entry in entries {
+            println!("  {}", entry);
+        }
+    }
+    Ok(())
+}
+
+/// Prints contexts as an aligned table.
+fn print_table(
+    contexts: &[(String, Option<ContextMetadata>)],
+    current: &str,
+    missing: &BTreeSet<String>,
+) {
+    let header = ["NAME", "ACCOUNT", "PROJECT", "GCLOUD CONFIG"];
+    let rows: Vec<[String; 4]> = contexts
+        .iter()
+        .map(|(name, m)| {
+            let field = |f: Option<&str>| f.unwrap_or("-").to_string();
+            let m = m.as_ref();
+            [
+                name.clone(),
+                field(m.and_then(|m| m.account.as_deref())),
+                field(m.and_then(|m| m.project.as_deref())),
+                if missing.contains(name) {
+                    format!("{} (missing)", gcloud_config_of(name, m))
+                } else {
+                    field(m.map(|m| m.gcloud_config.as_str()))
+                },
+            ]
+        })
+        .collect();
+
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    println!(
+        "  {:w0$}  {:w1$}  {:w2$}  {}",
+        header[0],
+        header[1],
+        header[2],
+        header[3],
+        w0 = widths[0],
+        w1 = widths[1],
+        w2 = widths[2]
+    );
+    for row in rows {
+        let marker = if row[0] == current { "*" } else { " " };
+        println!(
+            "{} {:w0$}  {:w1$}  {:w2$}  {}",
+            marker,
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2]
+        );
+    }
+}
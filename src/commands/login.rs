@@ -3,58 +3,138 @@
 use anyhow::{Context, Result};
 use std::process::Command;
 
-use crate::commands::save::save_context;
-use crate::config::validate_context_name;
+use crate::commands::save::{SaveOptions, save_context_with_options};
+use crate::config::{
+    copy_gcloud_config, gcloud_command, get_current_gcloud_config, invalidate_gcloud_cache,
+    load_context_metadata, validate_context_name,
+};
+use crate::prompt::require_input;
+
+/// Options controlling how a context is logged in.
+#[derive(Debug, Clone, Default)]
+pub struct LoginOptions {
+    /// Hide sensitive details (account, project, etc.) after saving
+    pub quiet: bool,
+    /// Service accounts to impersonate (a delegation chain if more than one).
+    /// Defaults to the chain already saved with the context, if any.
+    pub impersonate: Vec<String>,
+    /// Keep up to this many previous `adc.json` versions (0 keeps none)
+    pub keep_versions: usize,
+    /// Fork the active gcloud configuration into a new one with this name and
+    /// log in there, instead of using a configuration named after the context
+    pub copy_current_config_as: Option<String>,
+    /// Authenticate without launching a browser (`--no-launch-browser`): gcloud
+    /// prints a URL to open on any machine and reads back the code, for
+    /// headless servers and SSH sessions
+    pub no_browser: bool,
+}
+
+/// Re-authenticates an existing context or creates a new one.
+///
+/// If `quiet` is true, sensitive details are hidden after save.
+/// See [`login_context_with_options`] for the full set of options.
+pub fn login_context(name: &str, quiet: bool) -> Result<()> {
+    login_context_with_options(
+        name,
+        &LoginOptions {
+            quiet,
+            ..Default::default()
+        },
+    )
+}
 
 /// Re-authenticates an existing context or creates a new one.
 ///
 /// This function:
-/// 1. Activates or creates the gcloud configuration
+/// 1. Activates or creates the gcloud configuration (optionally forked from
+///    the active one with `copy_current_config_as`)
 /// 2. Runs `gcloud auth login` for browser-based authentication
 /// 3. Runs `gcloud auth application-default login` for ADC
 /// 4. Auto-saves the credentials to the context
 ///
-/// If `quiet` is true, sensitive details are hidden after save.
-pub fn login_context(name: &str, quiet: bool) -> Result<()> {
+/// With `no_browser`, both logins use gcloud's `--no-launch-browser` flow.
+pub fn login_context_with_options(name: &str, options: &LoginOptions) -> Result<()> {
     validate_context_name(name)?;
-    // First, try to activate or create the gcloud configuration
-    println!("Setting up gcloud configuration '{}'...", name);
+    require_input(
+        "gcloud login needs a browser and a terminal; use 'gcpx save --stdin' in automation",
+    )?;
+    // Keep the existing impersonation target unless a new one was given
+    let impersonate = if options.impersonate.is_empty() {
+        load_context_metadata(name)
+            .ok()
+            .flatten()
+            .map(|m| m.impersonate)
+            .unwrap_or_default()
+    } else {
+        options.impersonate.clone()
+    };
+
+    // Fork the active configuration if asked, then activate or create it
+    let config_name = match &options.copy_current_config_as {
+        Some(new_config) => {
+            let active = get_current_gcloud_config()?;
+            copy_gcloud_config(&active, new_config)?;
+            println!(
+                "Copied gcloud configuration '{}' to '{}'.",
+                active, new_config
+            );
+            new_config.as_str()
+        }
+        None => name,
+    };
+    println!("Setting up gcloud configuration '{}'...", config_name);
 
     // Check if config exists
-    let check = Command::new("gcloud")
-        .args(["config", "configurations", "describe", name])
+    let check = gcloud_command()
+        .args(["config", "configurations", "describe", config_name])
         .output()
         .context("Failed to execute gcloud command")?;
 
     if check.status.success() {
         // Config exists, activate it
-        let status = Command::new("gcloud")
-            .args(["config", "configurations", "activate", name])
+        let status = gcloud_command()
+            .args(["config", "configurations", "activate", config_name])
             .status()
             .context("Failed to activate gcloud configuration")?;
 
         if !status.success() {
-            println!("Warning: Could not activate configuration '{}'", name);
+            println!(
+                "Warning: Could not activate configuration '{}'",
+                config_name
+            );
         }
     } else {
         // Config doesn't exist, create it
-        println!("Creating new gcloud configuration '{}'...", name);
-        let status = Command::new("gcloud")
-            .args(["config", "configurations", "create", name])
+        println!("Creating new gcloud configuration '{}'...", config_name);
+        let status = gcloud_command()
+            .args(["config", "configurations", "create", config_name])
             .status()
             .context("Failed to create gcloud configuration")?;
 
         if !status.success() {
-            println!("Warning: Could not create configuration '{}'", name);
+            println!("Warning: Could not create configuration '{}'", config_name);
         }
     }
 
-    // Run gcloud auth login (interactive, opens browser)
+    // Run gcloud auth login (interactive, opens browser). Unlike gcloud_command(),
+    // this keeps gcloud's own prompts, as does the ADC login below.
+    let browser_args: &[&str] = if options.no_browser {
+        &["--no-launch-browser"]
+    } else {
+        &[]
+    };
     println!("\nStarting gcloud authentication...");
-    println!("A browser window will open for you to sign in.\n");
+    if options.no_browser {
+        println!(
+            "Open the URL gcloud prints in a browser on any machine and paste the code here.\n"
+        );
+    } else {
+        println!("A browser window will open for you to sign in.\n");
+    }
 
     let auth_status = Command::new("gcloud")
         .args(["auth", "login"])
+        .args(browser_args)
         .status()
         .context("Failed to run gcloud auth login")?;
 
@@ -64,10 +144,15 @@ pub fn login_context(name: &str, quiet: bool) -> Result<()> {
 
     // Run gcloud auth application-default login
     println!("\nStarting Application Default Credentials authentication...");
-    println!("Another browser window will open.\n");
+    if options.no_browser {
+        println!("gcloud will print another URL; paste its code here as well.\n");
+    } else {
+        println!("Another browser window will open.\n");
+    }
 
     let adc_status = Command::new("gcloud")
         .args(["auth", "application-default", "login"])
+        .args(browser_args)
         .status()
         .context("Failed to run gcloud auth application-default login")?;
 
@@ -75,9 +160,20 @@ pub fn login_context(name: &str, quiet: bool) -> Result<()> {
         println!("Warning: ADC authentication may not have completed successfully.");
     }
 
+    // The configuration and its account changed behind gcloud's back
+    invalidate_gcloud_cache();
+
     // Save the context
     println!("\nSaving credentials to context '{}'...", name);
-    save_context(name, quiet)?;
+    save_context_with_options(
+        name,
+        &SaveOptions {
+            quiet: options.quiet,
+            impersonate,
+            keep_versions: options.keep_versions,
+            ..Default::default()
+        },
+    )?;
 
     println!("\nLogin complete! Context '{}' is now ready to use.", name);
     Ok(())
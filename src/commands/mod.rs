@@ -1,13 +1,69 @@
 //! Command implementations for gcpx.
 
+pub mod adopt;
+pub mod complete;
 pub mod delete;
+pub mod doctor;
+pub mod export;
+pub mod groups;
+pub mod import;
+pub mod import_store;
+pub mod list;
 pub mod login;
+pub mod prune;
+pub mod reauth;
+pub mod rename;
+pub mod rename_config;
+pub mod repair;
+pub mod rollback;
 pub mod run;
 pub mod save;
+pub mod self_test;
+pub mod settings;
+pub mod show;
+pub mod status;
 pub mod switch;
+pub mod touch;
+pub mod tree;
+pub mod undo;
+pub mod whoami;
 
+pub use adopt::{AdoptAdcOptions, adopt_adc_file, adopt_all_gcloud_configs, adopt_gcloud_config};
 pub use delete::delete_context;
-pub use login::login_context;
-pub use run::run_with_context;
-pub use save::save_context;
-pub use switch::{interactive_switch, switch_context};
+pub use doctor::{DoctorOptions, doctor};
+pub use export::{ExportOptions, export_context, export_store};
+pub use groups::{add_group, list_groups, print_group_env, remove_group, switch_group};
+pub use import::{ImportOptions, import_backup, import_context};
+pub use import_store::{ImportStoreOptions, import_store};
+pub use list::{GroupBy, ListOptions, OutputFormat, SortOrder, list_contexts_cmd};
+pub use login::{LoginOptions, login_context, login_context_with_options};
+pub use prune::{PruneOptions, orphaned_gcloud_configs, prune_gcloud_configs};
+pub use reauth::reauth_if_needed;
+pub use rename::rename_context;
+pub use rename_config::rename_gcloud_config;
+pub use repair::{RepairOptions, repair_context};
+pub use rollback::rollback_context;
+pub use run::{
+    ExportShell, RunEachOptions, RunOptions, export_line, print_env_exports, print_run_env,
+    resolve_run_env, run_each, run_policy_violation, run_with_context, run_with_context_file,
+    run_with_context_options,
+};
+pub use save::{SaveOptions, save_context, save_context_with_options};
+pub use self_test::self_test;
+pub use settings::{config_get, config_list, config_set, config_unset};
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+))]
+pub use show::hold_clipboard;
+pub use show::{CopyField, copy_context_field, show_context};
+pub use status::show_status;
+pub use switch::{
+    InteractiveOptions, SwitchOptions, context_for_project, interactive_candidates,
+    interactive_switch, interactive_switch_with_options, most_recent, switch_context,
+    switch_context_with_options,
+};
+pub use touch::touch_context;
+pub use tree::show_tree;
+pub use undo::undo_last_op;
+pub use whoami::whoami;
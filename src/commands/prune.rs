@@ -0,0 +1,109 @@
+//! Prune command implementation - remove gcloud configurations no context uses.
+
+use anyhow::{Context, Result, bail};
+use dialoguer::{Confirm, theme::ColorfulTheme};
+use std::collections::BTreeSet;
+
+use crate::config::{
+    gcloud_command, get_active_gcloud_config, invalidate_gcloud_cache, list_contexts,
+    list_gcloud_configs, load_context_metadata,
+};
+use crate::error::GcpxError;
+use crate::prompt::require_input;
+
+/// Options controlling `gcpx prune`.
+#[derive(Debug, Clone, Default)]
+pub struct PruneOptions {
+    /// Only list what would be deleted
+    pub dry_run: bool,
+    /// Delete without asking for confirmation
+    pub yes: bool,
+}
+
+/// Returns the gcloud configurations that no context references, except the
+/// active one and `default` (which gcloud recreates anyway).
+///
+/// A context without metadata is taken to use the configuration named after it.
+/// Contexts whose metadata can't be read are skipped with a warning, since the
+/// configuration they use is unknown.
+pub fn orphaned_gcloud_configs() -> Result<Vec<String>> {
+    let mut referenced = BTreeSet::new();
+    for name in list_contexts()? {
+        match load_context_metadata(&name) {
+            Ok(Some(metadata)) => referenced.insert(metadata.gcloud_config),
+            Ok(None) => referenced.insert(name),
+            Err(e) => {
+                eprintln!(
+                    "Warning: skipping context '{}': {:#}. Fix it with 'gcpx repair {}' \
+                    so the configuration it uses is kept.",
+                    name, e, name
+                );
+                continue;
+            }
+        };
+    }
+    let active = get_active_gcloud_config()?;
+    Ok(list_gcloud_configs()?
+        .into_iter()
+        .filter(|config| {
+            config != "default" && !referenced.contains(config) && active.as_ref() != Some(config)
+        })
+        .collect())
+}
+
+/// Deletes gcloud configurations that no context references (see
+/// [`orphaned_gcloud_configs`]), after listing them and asking for
+/// confirmation unless `yes` is set.
+///
+/// Every configuration is attempted; an error is returned if any failed.
+pub fn prune_gcloud_configs(options: &PruneOptions) -> Result<()> {
+    let orphans = orphaned_gcloud_configs()?;
+    if orphans.is_empty() {
+        println!("No unused gcloud configurations.");
+        return Ok(());
+    }
+
+    println!("gcloud configurations not used by any context:");
+    for config in &orphans {
+        println!("  {}", config);
+    }
+    if options.dry_run {
+        println!("Would delete {} configuration(s) (dry run).", orphans.len());
+        return Ok(());
+    }
+    if !options.yes {
+        require_input("pass --yes to delete them, or --dry-run to only list them")?;
+        let confirmed = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!("Delete {} configuration(s)?", orphans.len()))
+            .default(false)
+            .interact()?;
+        if !confirmed {
+            println!("Nothing deleted.");
+            return Ok(());
+        }
+    }
+
+    let mut failed = 0;
+    for config in &orphans {
+        let output = gcloud_command()
+            .args(["config", "configurations", "delete", config, "--quiet"])
+            .output()
+            .context("Failed to execute gcloud command")?;
+        invalidate_gcloud_cache();
+        if output.status.success() {
+            println!("  {}: deleted", config);
+        } else {
+            failed += 1;
+            eprintln!(
+                "  {}: {}",
+                config,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+    }
+    if failed > 0 {
+        bail!(GcpxError::PruneFailed(failed, orphans.len()));
+    }
+    println!("Deleted {} configuration(s).", orphans.len());
+    Ok(())
+}
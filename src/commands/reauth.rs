@@ -0,0 +1,34 @@
+//! Reauth-if-needed command implementation - switch, and log in again only if
+//! the saved credentials no longer work.
+
+use anyhow::Result;
+
+use crate::commands::login::login_context;
+use crate::commands::switch::switch_context;
+use crate::verify::{DEFAULT_CHECK_TIMEOUT, TokenStatus, check_context_token};
+
+/// Switches to a context, then re-authenticates it only if its credentials
+/// were revoked or expired.
+///
+/// Credentials that can't be checked (service account keys, no network, ...)
+/// are left alone, so scripts only pay for the interactive login when the
+/// token is known to be dead.
+pub fn reauth_if_needed(name: &str, quiet: bool) -> Result<()> {
+    switch_context(name, quiet)?;
+
+    match check_context_token(name, DEFAULT_CHECK_TIMEOUT) {
+        TokenStatus::Valid => println!("Credentials for '{}' are valid.", name),
+        TokenStatus::Unchecked(reason) => println!(
+            "Couldn't check credentials for '{}' ({}); not re-authenticating.",
+            name, reason
+        ),
+        TokenStatus::Revoked => {
+            println!(
+                "Credentials for '{}' were revoked or expired; logging in again...",
+                name
+            );
+            login_context(name, quiet)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,82 @@
+//! Rename command implementation.
+
+use anyhow::{Context, Result, bail};
+use std::fs;
+
+use crate::config::{
+    ContextMetadata, context_exists, get_context_dir, get_current_tracking, get_previous_tracking,
+    load_context_metadata, save_context_metadata, set_current_tracking, set_previous_tracking,
+    validate_context_name,
+};
+use crate::credstore::credential_store;
+use crate::error::GcpxError;
+
+/// Renames a saved context.
+///
+/// This function:
+/// 1. Moves the context directory to the new name. With a credential store
+///    that keeps credentials outside it (the command backend), they are read
+///    under the old name and written under the new one first; the old entry
+///    is left in place
+/// 2. Pins the gcloud config name in metadata for legacy contexts (which
+///    previously fell back to using the context name)
+/// 3. Updates the current and previous context tracking if they point at the old name
+pub fn rename_context(old: &str, new: &str) -> Result<()> {
+    validate_context_name(old)?;
+    validate_context_name(new)?;
+    if !context_exists(old)? {
+        bail!(GcpxError::ContextNotFound(old.to_string()));
+    }
+    if get_context_dir(new)?.exists() {
+        bail!(GcpxError::ContextExists(new.to_string()));
+    }
+
+    // Legacy contexts without metadata resolve their gcloud config from the
+    // context name, so record it explicitly before the name changes.
+    if load_context_metadata(old)?.is_none() {
+        let metadata = ContextMetadata {
+            gcloud_config: old.to_string(),
+            ..Default::default()
+        };
+        save_context_metadata(old, &metadata)?;
+    }
+
+    // Credentials kept outside the context directory (the command backend)
+    // are stored under the context name, so they don't move with it
+    let store = credential_store()?;
+    let external_adc = match store.stored_path(old)? {
+        Some(_) => None,
+        None => Some(store.read(old)?),
+    };
+
+    let old_dir = get_context_dir(old)?;
+    let new_dir = get_context_dir(new)?;
+    fs::rename(&old_dir, &new_dir)
+        .with_context(|| format!("Failed to rename context directory: {:?}", old_dir))?;
+
+    if let Some(adc) = &external_adc {
+        if let Err(e) = store.write(new, adc) {
+            let _ = fs::rename(&new_dir, &old_dir);
+            return Err(e.context(format!(
+                "Failed to store the credentials of '{}' under '{}'; the rename was undone",
+                old, new
+            )));
+        }
+    }
+
+    if get_current_tracking() == old {
+        set_current_tracking(new)?;
+    }
+    if get_previous_tracking() == old {
+        set_previous_tracking(new)?;
+    }
+
+    println!("Renamed context '{}' to '{}'.", old, new);
+    if external_adc.is_some() {
+        println!(
+            "  Note: the credentials stored under '{}' were copied, not removed; delete them from your secret manager if no longer needed.",
+            old
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,56 @@
+//! Rename-config command implementation - rename an underlying gcloud configuration.
+
+use anyhow::{Context, Result, bail};
+
+use crate::config::{
+    copy_gcloud_config, gcloud_command, get_current_gcloud_config, invalidate_gcloud_cache,
+    list_contexts_with_metadata, save_context_metadata,
+};
+use crate::error::GcpxError;
+
+/// Runs a gcloud command, failing with its stderr if it doesn't succeed.
+fn gcloud(args: &[&str]) -> Result<()> {
+    let output = gcloud_command()
+        .args(args)
+        .output()
+        .context("Failed to execute gcloud command")?;
+    invalidate_gcloud_cache();
+    if !output.status.success() {
+        let err = String::from_utf8_lossy(&output.stderr);
+        bail!(GcpxError::GcloudFailed(err.trim().to_string()));
+    }
+    Ok(())
+}
+
+/// Renames a gcloud configuration and updates every context that uses it.
+///
+/// gcloud has no native rename, so this:
+/// 1. Creates the new configuration and copies the old one's properties
+/// 2. Points the metadata of every context using the old configuration at the new one
+/// 3. Activates the new configuration if the old one was active
+/// 4. Deletes the old configuration
+pub fn rename_gcloud_config(old: &str, new: &str) -> Result<()> {
+    let was_active = get_current_gcloud_config()? == old;
+    let copied = copy_gcloud_config(old, new)?;
+
+    let mut updated = Vec::new();
+    for (name, metadata) in list_contexts_with_metadata()? {
+        if let Some(mut m) = metadata.filter(|m| m.gcloud_config == old) {
+            m.gcloud_config = new.to_string();
+            save_context_metadata(&name, &m)?;
+            updated.push(name);
+        }
+    }
+
+    if was_active {
+        gcloud(&["config", "configurations", "activate", new])?;
+    }
+    gcloud(&["config", "configurations", "delete", old, "--quiet"])?;
+
+    println!("Renamed gcloud configuration '{}' to '{}'.", old, new);
+    println!("  copied {} properties", copied);
+    if !updated.is_empty() {
+        println!("  updated contexts: {}", updated.join(", "));
+    }
+    Ok(())
+}
@@ -0,0 +1,116 @@
+//! Repair command implementation - rebuild a context's metadata from its credentials.
+
+use anyhow::{Result, bail};
+use dialoguer::{Input, theme::ColorfulTheme};
+
+use crate::config::{
+    ContextMetadata, account_domain, adc_identity, adc_project, adc_type_of, context_exists,
+    load_context_metadata, save_context_metadata, validate_context_name,
+};
+use crate::credstore::credential_store;
+use crate::error::GcpxError;
+use crate::prompt::input_allowed;
+
+/// Options controlling `gcpx repair`.
+#[derive(Debug, Clone, Default)]
+pub struct RepairOptions {
+    /// Rebuild metadata that is already valid
+    pub force: bool,
+    /// Account to record instead of inferring or prompting for it
+    pub account: Option<String>,
+    /// Project to record instead of inferring or prompting for it
+    pub project: Option<String>,
+    /// gcloud configuration to record (defaults to the context name)
+    pub gcloud_config: Option<String>,
+    /// Leave values that can't be inferred unset instead of prompting
+    pub infer_only: bool,
+}
+
+/// Asks for a value that couldn't be inferred; empty input leaves it unset.
+/// Without a terminal (or with `--no-input`) nothing is asked.
+fn prompt_optional(prompt: &str, options: &RepairOptions) -> Result<Option<String>> {
+    if options.infer_only || !input_allowed() {
+        return Ok(None);
+    }
+    let value: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!("{} (leave empty to skip)", prompt))
+        .allow_empty(true)
+        .interact_text()?;
+    let value = value.trim();
+    Ok((!value.is_empty()).then(|| value.to_string()))
+}
+
+/// Rebuilds `metadata.json` for a context whose metadata is missing or
+/// unparseable, so it no longer relies on the context name as its gcloud
+/// config.
+///
+/// The credential type, account and project are read from the saved ADC
+/// where it records them, and the gcloud config defaults to the context name.
+/// Anything still unknown is prompted for when input is allowed, otherwise
+/// left unset. Valid metadata is only rebuilt with `force`; its other fields
+/// (tags, description, last use, ...) are kept.
+pub fn repair_context(name: &str, options: &RepairOptions) -> Result<()> {
+    let metadata = rebuild_metadata(name, options)?;
+    println!("Repaired metadata for context '{}'", name);
+    println!("  gcloud config: {}", metadata.gcloud_config);
+    println!(
+        "  account: {}",
+        metadata.account.as_deref().unwrap_or("(unknown)")
+    );
+    println!(
+        "  project: {}",
+        metadata.project.as_deref().unwrap_or("(unknown)")
+    );
+    if let Some(adc_type) = &metadata.adc_type {
+        println!("  type: {}", adc_type);
+    }
+    Ok(())
+}
+
+/// Rebuilds and saves a context's metadata as described in
+/// [`repair_context`], without printing anything.
+pub(crate) fn rebuild_metadata(name: &str, options: &RepairOptions) -> Result<ContextMetadata> {
+    validate_context_name(name)?;
+    if !context_exists(name)? {
+        bail!(GcpxError::ContextNotFound(name.to_string()));
+    }
+    let existing = match load_context_metadata(name) {
+        Ok(Some(_)) if !options.force => bail!(GcpxError::MetadataValid(name.to_string())),
+        Ok(metadata) => metadata,
+        Err(_) => None,
+    };
+    let adc = credential_store()?.read(name)?;
+
+    let mut metadata = existing.unwrap_or_else(|| ContextMetadata {
+        gcloud_config: name.to_string(),
+        ..Default::default()
+    });
+    if let Some(config) = &options.gcloud_config {
+        metadata.gcloud_config = config.clone();
+    }
+    metadata.adc_type = adc_type_of(&adc).or(metadata.adc_type);
+    metadata.adc_identity = adc_identity(&adc);
+    // An org other than the one derived from the account was set by hand
+    let derived_org = metadata.account.as_deref().and_then(account_domain);
+    let explicit_org = metadata
+        .org
+        .clone()
+        .filter(|org| Some(org) != derived_org.as_ref());
+    metadata.account = match options.account.clone().or_else(|| adc_identity(&adc)) {
+        Some(account) => Some(account),
+        None => match metadata.account {
+            Some(account) => Some(account),
+            None => prompt_optional(&format!("Account for '{}'", name), options)?,
+        },
+    };
+    metadata.project = match options.project.clone().or_else(|| adc_project(&adc)) {
+        Some(project) => Some(project),
+        None => match metadata.project {
+            Some(project) => Some(project),
+            None => prompt_optional(&format!("Project for '{}'", name), options)?,
+        },
+    };
+    metadata.org = explicit_org.or_else(|| metadata.account.as_deref().and_then(account_domain));
+    save_context_metadata(name, &metadata)?;
+    Ok(metadata)
+}
@@ -0,0 +1,67 @@
+//! Rollback command implementation - restore a context's previous ADC version.
+
+use anyhow::{Result, bail};
+use std::fs;
+use std::path::Path;
+
+use crate::config::{
+    adc_identity, context_exists, get_adc_path, get_context_adc_path, get_context_adc_version_path,
+    get_context_credential_source_path, get_context_credential_source_version_path,
+    get_current_tracking, load_context_metadata, move_if_exists, read_adc_type,
+    save_context_metadata, validate_context_name,
+};
+use crate::error::GcpxError;
+
+/// Restores the most recent previous ADC version (`adc.json.1`) of a context.
+///
+/// The current `adc.json` takes its place as `adc.json.1`, so running this
+/// again undoes it. A bundled `credential_source` is swapped along with it.
+/// If the context is the active one, the live ADC is updated as well.
+pub fn rollback_context(name: &str) -> Result<()> {
+    validate_context_name(name)?;
+    if !context_exists(name)? {
+        bail!(GcpxError::ContextNotFound(name.to_string()));
+    }
+
+    let previous = get_context_adc_version_path(name, 1)?;
+    if !previous.exists() {
+        bail!(GcpxError::NoPreviousVersion(name.to_string()));
+    }
+
+    let adc_path = get_context_adc_path(name)?;
+    swap_files(&adc_path, &previous)?;
+    swap_files(
+        &get_context_credential_source_path(name)?,
+        &get_context_credential_source_version_path(name, 1)?,
+    )?;
+
+    if let Some(mut metadata) = load_context_metadata(name)? {
+        metadata.adc_type = read_adc_type(&adc_path);
+        metadata.adc_identity = fs::read(&adc_path).ok().and_then(|adc| adc_identity(&adc));
+        save_context_metadata(name, &metadata)?;
+    }
+
+    println!(
+        "Rolled back '{}' to its previous ADC version. Run 'gcpx rollback {}' again to undo.",
+        name, name
+    );
+    if get_current_tracking() == name {
+        fs::write(get_adc_path()?, fs::read(&adc_path)?)?;
+        println!(
+            "  Live ADC updated, since '{}' is the active context.",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// Exchanges two files, either of which may be missing.
+fn swap_files(a: &Path, b: &Path) -> Result<()> {
+    let mut tmp_name = a.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".swap");
+    let tmp = a.with_file_name(tmp_name);
+    move_if_exists(a, &tmp)?;
+    move_if_exists(b, a)?;
+    move_if_exists(&tmp, b)?;
+    Ok(())
+}
@@ -1,64 +1,651 @@
 //! Run command implementation - execute commands with a specific context.
 
 use anyhow::{Context, Result, bail};
-use std::process::Command;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fs;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Instant;
 
 use crate::config::{
-    context_exists, get_context_adc_path, load_context_metadata, validate_context_name,
+    ContextMetadata, context_exists, get_context_credential_source_path, get_context_gcloud_dir,
+    get_context_run_adc_path, get_current_context_checked, list_contexts_with_metadata,
+    load_context_metadata, restored_adc, validate_adc_json, validate_context_name,
+    validate_env_key, warn_if_incomplete, write_atomic, write_atomic_private,
 };
+use crate::credstore::{MaterializedAdc, credential_store};
+use crate::error::GcpxError;
+use crate::settings::load_settings;
+use crate::style::print_danger_banner;
+
+/// Resolves the environment variables `gcpx run` sets for a context.
+///
+/// [`run_with_context`] and [`print_run_env`] share this resolution, so what
+/// is printed is exactly what the child receives.
+///
+/// With a non-file credential backend, the credentials are written to a
+/// file in the per-user [`private_temp_dir`](crate::credstore::private_temp_dir)
+/// that is kept (for printing and `gcpx env`); `run` itself removes its copy
+/// once the command exits.
+pub fn resolve_run_env(context_name: &str) -> Result<Vec<(String, String)>> {
+    let (env, adc) = prepare_run_env(context_name)?;
+    adc.keep();
+    Ok(env)
+}
+
+/// Resolves the run environment along with the credential file it points at,
+/// which is removed when dropped if it was a temporary copy.
+fn prepare_run_env(context_name: &str) -> Result<(Vec<(String, String)>, MaterializedAdc)> {
+    validate_context_name(context_name)?;
+    if !context_exists(context_name)? {
+        bail!(GcpxError::ContextNotFound(context_name.to_string()));
+    }
+
+    warn_if_incomplete(context_name)?;
+
+    // A bundled credential source means the saved JSON needs its path
+    // rewritten; the result is kept next to it, private like adc.json
+    let adc = if get_context_credential_source_path(context_name)?.exists() {
+        let path = get_context_run_adc_path(context_name)?;
+        write_atomic_private(&path, &restored_adc(context_name)?)?;
+        MaterializedAdc::stored(path)
+    } else {
+        credential_store()?.materialize(context_name)?
+    };
+
+    // Load metadata to get the actual gcloud config name
+    let metadata = load_context_metadata(context_name)?;
+    let gcloud_config = metadata
+        .as_ref()
+        .map(|m| m.gcloud_config.as_str())
+        .unwrap_or(context_name);
+
+    let mut env = vec![(
+        "GOOGLE_APPLICATION_CREDENTIALS".to_string(),
+        adc.path().to_string_lossy().into_owned(),
+    )];
+    if load_settings()?.isolated_configs {
+        env.push((
+            "CLOUDSDK_CONFIG".to_string(),
+            get_context_gcloud_dir(context_name)?
+                .to_string_lossy()
+                .into_owned(),
+        ));
+    } else {
+        env.push((
+            "CLOUDSDK_ACTIVE_CONFIG_NAME".to_string(),
+            gcloud_config.to_string(),
+        ));
+    }
+    if let Some(chain) = metadata.as_ref().and_then(|m| m.impersonation()) {
+        env.push((
+            "CLOUDSDK_AUTH_IMPERSONATE_SERVICE_ACCOUNT".to_string(),
+            chain,
+        ));
+    }
+    // Client libraries don't read the gcloud configuration, so the project and
+    // quota project are passed to them directly
+    if let Some(metadata) = &metadata {
+        if let Some(project) = &metadata.project {
+            env.push(("GOOGLE_CLOUD_PROJECT".to_string(), project.clone()));
+        }
+        if let Some(quota) = metadata.gcloud_properties.get("billing/quota_project") {
+            env.push(("GOOGLE_CLOUD_QUOTA_PROJECT".to_string(), quota.clone()));
+        }
+    }
+    Ok((env, adc))
+}
+
+/// Prints the production warning banner if the context is flagged as dangerous.
+fn banner_if_dangerous(context_name: &str) {
+    if load_context_metadata(context_name)
+        .ok()
+        .flatten()
+        .is_some_and(|m| m.is_dangerous())
+    {
+        print_danger_banner(context_name);
+    }
+}
+
+/// Prints the environment `gcpx run` would set for a context, without running anything.
+pub fn print_run_env(context_name: &str) -> Result<()> {
+    for (key, value) in resolve_run_env(context_name)? {
+        println!("{}={}", key, value);
+    }
+    Ok(())
+}
+
+/// Shell syntax for `gcpx env` output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ExportShell {
+    /// `export KEY='value'`
+    #[default]
+    Bash,
+    /// Same syntax as bash
+    Zsh,
+    /// `set -gx KEY 'value'`
+    Fish,
+}
+
+/// Returns a statement that sets and exports an environment variable in the
+/// given shell, quoted so that any value evaluates back to itself.
+///
+/// POSIX shells take everything literally inside single quotes, so only `'`
+/// needs escaping (by closing, escaping and reopening the quote). Inside fish
+/// single quotes, `\\` and `\'` are escape sequences. The key can't be
+/// quoted, so it must be a valid variable name (see [`validate_env_key`]).
+pub fn export_line(shell: ExportShell, key: &str, value: &str) -> Result<String> {
+    validate_env_key(key)?;
+    Ok(match shell {
+        ExportShell::Bash | ExportShell::Zsh => {
+            format!("export {}='{}'", key, value.replace('\'', r"'\''"))
+        }
+        ExportShell::Fish => format!(
+            "set -gx {} '{}'",
+            key,
+            value.replace('\\', r"\\").replace('\'', r"\'")
+        ),
+    })
+}
+
+/// Prints `export` statements for a context's environment, for use with
+/// `eval "$(gcpx env)"` (or `gcpx env --shell fish | source`). Defaults to the
+/// current context.
+pub fn print_env_exports(context_name: Option<&str>, shell: ExportShell) -> Result<()> {
+    let name = match context_name {
+        Some(name) => name.to_string(),
+        None => match get_current_context_checked() {
+            Some(name) => name,
+            None => bail!(GcpxError::NoCurrentContext),
+        },
+    };
+    for (key, value) in resolve_run_env(&name)? {
+        println!("{}", export_line(shell, &key, &value)?);
+    }
+    Ok(())
+}
+
+/// Returns true if `cmd` is matched by a run policy entry. The entry's first
+/// word names the program (its file name, so `/usr/bin/terraform` matches
+/// `terraform`); how its further words are matched depends on the list:
+///
+/// - a denylist entry matches if the words appear in the arguments in order,
+///   anywhere, so global flags in between (`terraform -chdir=x apply`) don't
+///   get around it
+/// - an allowlist entry matches if the words are the leading arguments once
+///   flags (anything starting with `-`) are skipped
+fn policy_entry_matches(entry: &str, cmd: &[String], deny: bool) -> bool {
+    let mut words = entry.split_whitespace();
+    let Some(program) = words.next() else {
+        return false;
+    };
+    let name = Path::new(&cmd[0])
+        .file_name()
+        .map(|n| n.to_string_lossy())
+        .unwrap_or_default();
+    if program != cmd[0] && program != name {
+        return false;
+    }
+    if deny {
+        let mut args = cmd[1..].iter();
+        words.all(|word| args.any(|arg| arg == word))
+    } else {
+        let mut args = cmd[1..].iter().filter(|arg| !arg.starts_with('-'));
+        words.all(|word| args.next().is_some_and(|arg| arg == word))
+    }
+}
+
+/// Explains why the context's run policy forbids `cmd`, or returns `None` if
+/// it is allowed. Denylist entries win over allowlist entries; a non-empty
+/// allowlist forbids everything it doesn't match.
+pub fn run_policy_violation(metadata: &ContextMetadata, cmd: &[String]) -> Option<String> {
+    if cmd.is_empty() {
+        return None;
+    }
+    if let Some(entry) = metadata
+        .run_denylist
+        .iter()
+        .find(|entry| policy_entry_matches(entry, cmd, true))
+    {
+        return Some(format!("'{}' is denied", entry.trim()));
+    }
+    if !metadata.run_allowlist.is_empty()
+        && !metadata
+            .run_allowlist
+            .iter()
+            .any(|entry| policy_entry_matches(entry, cmd, false))
+    {
+        return Some(format!(
+            "'{}' is not allowed (allowed: {})",
+            cmd[0],
+            metadata.run_allowlist.join(", ")
+        ));
+    }
+    None
+}
+
+/// Fails if the context's run policy forbids `cmd`, unless `override_policy`
+/// is set, in which case a warning is printed instead.
+///
+/// Called before the context's credentials are materialized, so a refused
+/// command never causes a credential copy to be written.
+fn check_run_policy(context_name: &str, cmd: &[String], override_policy: bool) -> Result<()> {
+    let Some(metadata) = load_context_metadata(context_name)? else {
+        return Ok(());
+    };
+    let Some(reason) = run_policy_violation(&metadata, cmd) else {
+        return Ok(());
+    };
+    if !override_policy {
+        bail!(GcpxError::RunPolicy(context_name.to_string(), reason));
+    }
+    eprintln!(
+        "Warning: overriding the run policy of context '{}': {}",
+        context_name, reason
+    );
+    Ok(())
+}
+
+/// Options controlling `gcpx run`.
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Write a JSON summary of the run to this file once the command exits
+    pub report: Option<PathBuf>,
+    /// Start the command with an empty environment instead of inheriting ours
+    pub clean_env: bool,
+    /// With `clean_env`, variables to carry over anyway: names or globs
+    /// (`*` and `?`), e.g. `PATH` or `AWS_*`
+    pub env_passthrough: Vec<String>,
+    /// `KEY=VALUE` variables to set, overriding everything else
+    pub env: Vec<String>,
+    /// Run the command even if the context's run policy forbids it
+    pub override_policy: bool,
+}
+
+/// Matches `text` against a glob where `*` matches any run of characters and
+/// `?` any single one.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text): (Vec<char>, Vec<char>) =
+        (pattern.chars().collect(), text.chars().collect());
+    let (mut p, mut t) = (0, 0);
+    // Position after the last `*` and the text position it was tried at
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, t));
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bt)) => {
+                    p = bp;
+                    t = bt + 1;
+                    backtrack = Some((bp, bt + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Builds the child's environment changes on top of gcpx's `managed` variables.
+///
+/// Precedence, lowest first: inherited (or, with `clean_env`, passed-through)
+/// variables, the managed ones, then explicit `env` entries.
+fn child_env(
+    managed: Vec<(String, String)>,
+    options: &RunOptions,
+) -> Result<Vec<(String, String)>> {
+    let mut env = Vec::new();
+    if options.clean_env {
+        env.extend(std::env::vars().filter(|(key, _)| {
+            options
+                .env_passthrough
+                .iter()
+                .any(|pattern| glob_match(pattern, key))
+        }));
+    }
+    env.extend(managed);
+    for entry in &options.env {
+        let Some((key, value)) = entry.split_once('=').filter(|(key, _)| !key.is_empty()) else {
+            bail!(GcpxError::InvalidEnvAssignment(
+                "--env".to_string(),
+                entry.to_string()
+            ));
+        };
+        env.push((key.to_string(), value.to_string()));
+    }
+    Ok(env)
+}
+
+/// Summary written by `gcpx run --report`.
+#[derive(Debug, Serialize)]
+struct RunReport<'a> {
+    context: &'a str,
+    /// Exit code, or null if the command was killed by a signal
+    exit_code: Option<i32>,
+    duration_ms: u128,
+}
 
 /// Runs a command with a specific context without switching globally.
 ///
 /// This function sets environment variables to temporarily use the specified
 /// context for the subprocess only:
 /// - `GOOGLE_APPLICATION_CREDENTIALS`: Points to the context's ADC file
-/// - `CLOUDSDK_ACTIVE_CONFIG_NAME`: Sets the gcloud configuration name
+/// - `CLOUDSDK_ACTIVE_CONFIG_NAME`: Sets the gcloud configuration name (or
+///   `CLOUDSDK_CONFIG` with the `isolated_configs` setting)
+/// - `CLOUDSDK_AUTH_IMPERSONATE_SERVICE_ACCOUNT`: Set if the context impersonates
+///   a service account
 ///
-/// The current shell's context is not affected.
+/// The current shell's context is not affected. The command inherits stdin,
+/// stdout and stderr as they are (so interactive programs get the terminal),
+/// and gcpx's own messages go to stderr, leaving stdout to the command.
 pub fn run_with_context(context_name: &str, cmd: &[String]) -> Result<()> {
+    run_with_context_options(context_name, cmd, &RunOptions::default())
+}
+
+/// Runs a command with a specific context, with options.
+///
+/// The command's output is streamed as usual; with `report` set, a JSON
+/// summary (context, exit code, duration) is written after it exits, whether
+/// or not it succeeded. With `clean_env`, the command only sees the
+/// `env_passthrough` variables, the context's variables and `env`.
+///
+/// A command forbidden by the context's `run_denylist` or `run_allowlist` is
+/// refused with [`GcpxError::RunPolicy`] unless `override_policy` is set.
+pub fn run_with_context_options(
+    context_name: &str,
+    cmd: &[String],
+    options: &RunOptions,
+) -> Result<()> {
     validate_context_name(context_name)?;
     if cmd.is_empty() {
-        bail!("No command specified. Usage: gcpx run <context> -- <command>");
+        bail!(GcpxError::NoCommand);
     }
 
-    if !context_exists(context_name)? {
-        bail!(
-            "Context '{}' not found. Run 'gcpx save {}' first.",
-            context_name,
-            context_name
-        );
+    check_run_policy(context_name, cmd, options.override_policy)?;
+    let (managed, _adc) = prepare_run_env(context_name)?;
+    banner_if_dangerous(context_name);
+    run_command(
+        context_name,
+        &format!("context '{}'", context_name),
+        managed,
+        cmd,
+        options,
+    )
+}
+
+/// Runs a command with an ad-hoc credential file instead of a saved context.
+///
+/// Only `GOOGLE_APPLICATION_CREDENTIALS` is set (to the file's absolute
+/// path); nothing is looked up in the store, so the variables derived from a
+/// context's metadata (gcloud configuration, impersonation) are not set and
+/// gcloud keeps using its active configuration. The file must be credential
+/// JSON with a `type`. With `report`, the file's path is recorded as the
+/// context.
+pub fn run_with_context_file(file: &Path, cmd: &[String], options: &RunOptions) -> Result<()> {
+    if cmd.is_empty() {
+        bail!(GcpxError::NoCommand);
     }
+    let content =
+        fs::read(file).with_context(|| format!("Failed to read credentials: {:?}", file))?;
+    validate_adc_json(&content).with_context(|| format!("Invalid credentials in {:?}", file))?;
+    let path = file
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {:?}", file))?;
+    let path = path.to_string_lossy();
+    let managed = vec![(
+        "GOOGLE_APPLICATION_CREDENTIALS".to_string(),
+        path.clone().into_owned(),
+    )];
+    run_command(
+        &path,
+        &format!("credentials {}", path),
+        managed,
+        cmd,
+        options,
+    )
+}
 
-    let adc_path = get_context_adc_path(context_name)?;
+/// Ignores SIGINT and SIGQUIT in gcpx until dropped, as `system(3)` does
+/// while its command runs.
+///
+/// The terminal sends Ctrl-C to the whole foreground process group, so the
+/// command gets it either way and decides what it means; an interactive
+/// program that only cancels the current line must not lose gcpx (and with
+/// it the terminal) underneath. Installed after the command is spawned, so
+/// the command itself keeps the default handlers.
+struct IgnoreInterrupts {
+    #[cfg(unix)]
+    previous: [(libc::c_int, libc::sighandler_t); 2],
+}
 
-    // Load metadata to get the actual gcloud config name
-    let metadata = load_context_metadata(context_name)?;
-    let gcloud_config = metadata
-        .as_ref()
-        .map(|m| m.gcloud_config.as_str())
-        .unwrap_or(context_name);
+impl IgnoreInterrupts {
+    fn new() -> Self {
+        #[cfg(unix)]
+        {
+            // SAFETY: `signal` only swaps the process's disposition for these
+            // signals; SIG_IGN needs no handler code
+            let previous = [libc::SIGINT, libc::SIGQUIT]
+                .map(|sig| (sig, unsafe { libc::signal(sig, libc::SIG_IGN) }));
+            IgnoreInterrupts { previous }
+        }
+        #[cfg(not(unix))]
+        IgnoreInterrupts {}
+    }
+}
 
+impl Drop for IgnoreInterrupts {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        for (sig, handler) in self.previous {
+            // SAFETY: restores the disposition returned by `signal` above
+            unsafe { libc::signal(sig, handler) };
+        }
+    }
+}
+
+/// Runs `cmd` with the `managed` variables on top of the environment built
+/// from `options`, announcing it with `label` and reporting it as `context`.
+fn run_command(
+    context: &str,
+    label: &str,
+    managed: Vec<(String, String)>,
+    cmd: &[String],
+    options: &RunOptions,
+) -> Result<()> {
+    let env = child_env(managed, options)?;
     let program = &cmd[0];
     let args = &cmd[1..];
 
-    println!(
-        "Running with context '{}': {} {}",
-        context_name,
-        program,
-        args.join(" ")
-    );
+    // On stderr, so the command's stdout is exactly what it would print on its own
+    eprintln!("Running with {}: {} {}", label, program, args.join(" "));
 
-    let status = Command::new(program)
+    // stdin, stdout and stderr are inherited untouched, so a terminal stays a
+    // terminal for the command and pipes stay pipes
+    let started = Instant::now();
+    let mut command = Command::new(program);
+    if options.clean_env {
+        command.env_clear();
+    }
+    let mut child = command
         .args(args)
-        .env("GOOGLE_APPLICATION_CREDENTIALS", &adc_path)
-        .env("CLOUDSDK_ACTIVE_CONFIG_NAME", gcloud_config)
-        .status()
-        .with_context(|| format!("Failed to execute command: {}", program))?;
+        .envs(env)
+        .spawn()
+        .map_err(|e| spawn_error(program, e))?;
+    let status = {
+        let _interrupts = IgnoreInterrupts::new();
+        child.wait()?
+    };
+
+    if let Some(path) = &options.report {
+        let report = RunReport {
+            context,
+            exit_code: status.code(),
+            duration_ms: started.elapsed().as_millis(),
+        };
+        let json = serde_json::to_string_pretty(&report)?;
+        write_atomic(path, json.as_bytes())
+            .with_context(|| format!("Failed to write report: {:?}", path))?;
+    }
 
     if !status.success() {
         let code = status.code().unwrap_or(-1);
-        bail!("Command exited with code {}", code);
+        bail!(GcpxError::CommandFailed(code));
+    }
+
+    Ok(())
+}
+
+/// Options controlling `gcpx run-each`.
+#[derive(Debug, Clone, Default)]
+pub struct RunEachOptions {
+    /// Contexts to run in, in order
+    pub contexts: Vec<String>,
+    /// Also run in every context with this tag
+    pub tag: Option<String>,
+    /// How many contexts to run at once (0 or 1 runs sequentially)
+    pub parallel: usize,
+    /// Run the command even in contexts whose run policy forbids it
+    pub override_policy: bool,
+}
+
+/// Runs a command in each selected context, labeling output with the context name.
+///
+/// Every context is attempted even if an earlier one fails. A summary of
+/// per-context results is printed at the end, and an error is returned if
+/// any context failed. A context whose run policy forbids the command counts
+/// as failed, unless `override_policy` is set.
+pub fn run_each(options: &RunEachOptions, cmd: &[String]) -> Result<()> {
+    if cmd.is_empty() {
+        bail!(GcpxError::NoCommand);
+    }
+
+    let mut names = options.contexts.clone();
+    if let Some(tag) = &options.tag {
+        for (name, metadata) in list_contexts_with_metadata()? {
+            if metadata.is_some_and(|m| m.tags.contains(tag)) && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    if names.is_empty() {
+        bail!(GcpxError::NoContextsSelected(
+            "Use --contexts a,b,c or --tag <tag>.".to_string()
+        ));
+    }
+    for name in &names {
+        validate_context_name(name)?;
+    }
+
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<Result<i32, String>>>> = Mutex::new(vec![None; names.len()]);
+    thread::scope(|scope| {
+        for _ in 0..options.parallel.clamp(1, names.len()) {
+            scope.spawn(|| {
+                loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(name) = names.get(i) else {
+                        break;
+                    };
+                    let result =
+                        run_labeled(name, cmd, options.override_policy).map_err(|e| e.to_string());
+                    results.lock().unwrap_or_else(|e| e.into_inner())[i] = Some(result);
+                }
+            });
+        }
+    });
+    let results = results.into_inner().unwrap_or_else(|e| e.into_inner());
+
+    println!("\nSummary:");
+    let mut failed = 0;
+    for (name, result) in names.iter().zip(results) {
+        match result {
+            Some(Ok(0)) => println!("  {}: ok", name),
+            Some(Ok(code)) => {
+                failed += 1;
+                println!("  {}: exit {}", name, code);
+            }
+            Some(Err(e)) => {
+                failed += 1;
+                println!("  {}: error: {}", name, e);
+            }
+            None => {
+                failed += 1;
+                println!("  {}: not run", name);
+            }
+        }
     }
 
+    if failed > 0 {
+        bail!(GcpxError::RunEachFailed(failed, names.len()));
+    }
     Ok(())
 }
+
+/// Explains why a child program could not be started.
+///
+/// A missing program is reported as not on PATH, or as a nonexistent file
+/// when it was given as a path; an existing but non-executable one as such.
+fn spawn_error(program: &str, err: io::Error) -> anyhow::Error {
+    match err.kind() {
+        io::ErrorKind::NotFound if program.contains(std::path::is_separator) => {
+            GcpxError::ProgramMissing(program.to_string()).into()
+        }
+        io::ErrorKind::NotFound => GcpxError::ProgramNotFound(program.to_string()).into(),
+        io::ErrorKind::PermissionDenied => {
+            GcpxError::ProgramNotExecutable(program.to_string()).into()
+        }
+        _ => anyhow::Error::new(err).context(format!("Failed to execute command: {}", program)),
+    }
+}
+
+/// Runs a command in one context, prefixing each output line with `[name]`.
+///
+/// Returns the exit code (-1 if the process was killed by a signal).
+fn run_labeled(context_name: &str, cmd: &[String], override_policy: bool) -> Result<i32> {
+    check_run_policy(context_name, cmd, override_policy)?;
+    let (env, _adc) = prepare_run_env(context_name)?;
+    banner_if_dangerous(context_name);
+
+    let mut child = Command::new(&cmd[0])
+        .args(&cmd[1..])
+        .envs(env)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| spawn_error(&cmd[0], e))?;
+
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    thread::scope(|scope| {
+        if let Some(out) = stdout {
+            scope.spawn(move || {
+                for line in BufReader::new(out).lines().map_while(Result::ok) {
+                    println!("[{}] {}", context_name, line);
+                }
+            });
+        }
+        if let Some(err) = stderr {
+            scope.spawn(move || {
+                for line in BufReader::new(err).lines().map_while(Result::ok) {
+                    eprintln!("[{}] {}", context_name, line);
+                }
+            });
+        }
+    });
+
+    let status = child.wait()?;
+    Ok(status.code().unwrap_or(-1))
+}
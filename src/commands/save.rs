@@ -1,70 +1,589 @@
 //! Save command implementation.
 
-use anyhow::{Result, bail};
+use anyhow::{Context, Result, bail};
 use std::fs;
+use std::path::{Path, PathBuf};
 
 use crate::config::{
-    ContextMetadata, get_adc_path, get_context_dir, get_current_gcloud_account,
-    get_current_gcloud_config, get_current_gcloud_project, get_current_kubectl_context,
-    save_context_metadata, set_current_tracking, validate_context_name,
+    ADC_SOURCE_ENV, ADC_SOURCE_GCLOUD, ADC_SOURCE_PATH, ADC_SOURCE_STDIN, ContextMetadata,
+    DEFAULT_CAPTURED_PROPERTIES, account_domain, adc_equivalent, adc_identity, adc_type_of,
+    context_exists, copy_gcloud_config, delete_gcloud_config, env_adc_path,
+    external_credential_source_file, gcloud_project_accessible, get_active_gcloud_config,
+    get_context_adc_path, get_context_config_snapshot_path, get_context_credential_source_path,
+    get_context_dir, get_context_metadata_path, get_current_kubectl_context, get_current_tracking,
+    get_gcloud_config_file, get_gcloud_properties, get_previous_tracking, list_kubectl_contexts,
+    load_context_metadata, now_unix, resolve_adc_source, rotate_adc_versions,
+    seed_context_gcloud_dir, set_current_tracking, stash_adc_version, validate_adc_json,
+    validate_context_name, validate_service_account, write_atomic,
 };
+use crate::credstore::{
+    CredentialMode, StoreBackend, credential_store, credential_store_with_mode,
+};
+use crate::editor::edit_text;
+use crate::error::GcpxError;
+use crate::gcloud_cache::gcloud_account_and_project;
+use crate::settings::load_settings;
+use crate::trash::{LastOp, OpKind, copy_to_trash, get_trash_dir, record_last_op};
 
-/// Saves the current gcloud ADC credentials as a named context.
+/// Options controlling how a context is saved.
+#[derive(Debug, Clone, Default)]
+pub struct SaveOptions {
+    /// Hide sensitive details (account, project, etc.) in the output
+    pub quiet: bool,
+    /// Free-form description stored in the context metadata
+    pub description: Option<String>,
+    /// Compose the description in `$EDITOR` instead
+    pub edit_description: bool,
+    /// Capture the allowlisted gcloud config properties for replay on switch
+    pub all_properties: bool,
+    /// Tags to attach to the context
+    pub tags: Vec<String>,
+    /// Service accounts to impersonate when using this context; more than one
+    /// is a delegation chain ending at the target
+    pub impersonate: Vec<String>,
+    /// Read credentials from this file instead of the gcloud ADC path
+    pub adc_path: Option<PathBuf>,
+    /// Read credentials from the file named by `GOOGLE_APPLICATION_CREDENTIALS`
+    /// even if gcloud has written an ADC file (the variable must be set)
+    pub from_env: bool,
+    /// Require confirmation when switching to this context
+    pub danger: bool,
+    /// Keep up to this many previous `adc.json` versions (0 keeps none)
+    pub keep_versions: usize,
+    /// Permissions for the saved credential files, instead of the
+    /// `credential_mode` setting (0600 by default)
+    pub credential_mode: Option<CredentialMode>,
+    /// Credential JSON supplied directly (e.g. read from stdin). gcloud and
+    /// kubectl are not queried; metadata comes from the fields below. If
+    /// `adc_path` is also set, it is recorded as where the JSON came from.
+    pub adc_json: Option<Vec<u8>>,
+    /// Account to record instead of the live gcloud account
+    pub account: Option<String>,
+    /// Project to record instead of the live gcloud project
+    pub project: Option<String>,
+    /// Check with gcloud that the active account can see `project` (network
+    /// access); warns if it can't
+    pub check_project: bool,
+    /// Fail instead of warning when `check_project` finds the project inaccessible
+    pub strict: bool,
+    /// gcloud configuration to record instead of the active one
+    pub gcloud_config: Option<String>,
+    /// Fork the active gcloud configuration into a new one with this name and
+    /// record that instead (the active configuration is left untouched)
+    pub copy_current_config_as: Option<String>,
+    /// Keep user-set fields (description, tags, impersonation, danger flag, run
+    /// policy and properties not re-captured) from the existing metadata, only replacing
+    /// what was captured or explicitly given
+    pub merge_metadata: bool,
+    /// Only report what would be saved and where; nothing is written (the
+    /// gcloud configuration isn't forked and tracking is left alone)
+    pub dry_run: bool,
+    /// Refuse to save when gcloud reports no active configuration, instead of
+    /// recording `default`
+    pub require_active_config: bool,
+    /// For `external_account` credentials, copy the token file named by
+    /// `credential_source.file` into the context, so it is self-contained
+    pub bundle_credential_source: bool,
+    /// kubectl context to record instead of the active one
+    pub kube_context: Option<String>,
+    /// Record no kubectl context
+    pub no_kube: bool,
+    /// Keep a verbatim copy of the gcloud configuration's file, written back
+    /// over the configuration on every switch (including properties gcpx
+    /// doesn't capture itself)
+    pub snapshot_config: bool,
+    /// Write nothing if the credentials and metadata match what is stored
+    pub if_changed: bool,
+    /// Ask gcloud for the account and project even if a recent answer for the
+    /// active configuration is cached (see [`crate::gcloud_cache`])
+    pub force_account_query: bool,
+    /// Commands `run` refuses in this context (see [`ContextMetadata::run_denylist`])
+    pub run_deny: Vec<String>,
+    /// The only commands `run` allows in this context (see
+    /// [`ContextMetadata::run_allowlist`])
+    pub run_allow: Vec<String>,
+    /// Leave `.current` tracking alone, registering the context without
+    /// marking it as the current one
+    pub no_track: bool,
+}
+
+/// Credential types whose named identity is the principal they authenticate
+/// as. Impersonated and workload-identity credentials name a service account
+/// that is expected to differ from the gcloud account.
+const SELF_IDENTIFYING_ADC_TYPES: &[&str] = &["authorized_user", "service_account"];
+
+/// Warns if the saved credentials belong to someone other than the recorded
+/// account: code using ADC would then act as a different identity than
+/// gcloud. Identities are only hidden in quiet mode.
+fn warn_if_identity_mismatch(metadata: &ContextMetadata, quiet: bool) {
+    let (Some(account), Some(identity)) = (&metadata.account, &metadata.adc_identity) else {
+        return;
+    };
+    if account.eq_ignore_ascii_case(identity)
+        || !metadata
+            .adc_type
+            .as_deref()
+            .is_some_and(|t| SELF_IDENTIFYING_ADC_TYPES.contains(&t))
+    {
+        return;
+    }
+    if quiet {
+        eprintln!(
+            "Warning: the credentials belong to a different identity than the gcloud account."
+        );
+    } else {
+        eprintln!(
+            "Warning: the credentials belong to {}, but the gcloud account is {}.",
+            identity, account
+        );
+    }
+    eprintln!(
+        "  Both are recorded; re-run 'gcloud auth application-default login' as the \
+        right user if this is unintended."
+    );
+}
+
+/// Returns the active gcloud configuration, or `default` if there is none.
 ///
-/// This function:
-/// 1. Checks if ADC credentials exist
-/// 2. Captures current gcloud config, account, project, and kubectl context
-/// 3. Copies credentials to the context storage directory
-/// 4. Saves metadata (gcloud config name, account, project, kubectl context)
-/// 5. Sets secure file permissions (Unix only)
-/// 6. Updates the current context tracking
+/// With `require_active_config`, having no active configuration is an error.
+fn active_gcloud_config(options: &SaveOptions) -> Result<String> {
+    match get_active_gcloud_config()? {
+        Some(config) => Ok(config),
+        None if options.require_active_config => bail!(GcpxError::NoActiveGcloudConfig),
+        None => Ok("default".to_string()),
+    }
+}
+
+/// Returns true if `name` already stores exactly these files: equivalent
+/// credentials (see [`adc_equivalent`]), the same metadata apart from
+/// `last_used`, and the same bundled credential source and config snapshot.
+fn stored_unchanged(
+    name: &str,
+    adc: &[u8],
+    metadata: &ContextMetadata,
+    credential_source: Option<&[u8]>,
+    config_snapshot: Option<&[u8]>,
+) -> Result<bool> {
+    if !context_exists(name)? {
+        return Ok(false);
+    }
+    let Ok(Some(stored)) = load_context_metadata(name) else {
+        return Ok(false);
+    };
+    let comparable = |metadata: &ContextMetadata| {
+        serde_json::to_value(ContextMetadata {
+            last_used: None,
+            ..metadata.clone()
+        })
+    };
+    Ok(comparable(&stored)? == comparable(metadata)?
+        && adc_equivalent(&credential_store()?.read(name)?, adc)
+        && fs::read(get_context_credential_source_path(name)?)
+            .ok()
+            .as_deref()
+            == credential_source
+        && fs::read(get_context_config_snapshot_path(name)?)
+            .ok()
+            .as_deref()
+            == config_snapshot)
+}
+
+/// Checks that the context's account (through its gcloud configuration) can
+/// see `project`, failing if `strict` and warning otherwise. Not being able to
+/// run gcloud at all is treated the same way.
+fn check_project(
+    project: &str,
+    account: Option<&str>,
+    configuration: Option<&str>,
+    strict: bool,
+) -> Result<()> {
+    let problem = match gcloud_project_accessible(project, account, configuration) {
+        Ok(true) => return Ok(()),
+        Ok(false) => format!(
+            "project '{}' doesn't exist or isn't accessible to {}",
+            project,
+            account.unwrap_or("the gcloud account")
+        ),
+        Err(e) => format!("couldn't check project '{}': {:#}", project, e),
+    };
+    if strict {
+        bail!(GcpxError::ProjectCheck(problem));
+    }
+    eprintln!("Warning: {}; saving anyway.", problem);
+    Ok(())
+}
+
+/// Returns the kubectl context given with `kube_context` (None with
+/// `no_kube`), warning if kubectl doesn't know it.
+fn kube_context_override(options: &SaveOptions) -> Option<String> {
+    let kube_context = options.kube_context.clone().filter(|_| !options.no_kube)?;
+    if let Some(known) = list_kubectl_contexts() {
+        if !known.contains(&kube_context) {
+            eprintln!(
+                "Warning: kubectl has no context '{}'; switching will leave kubectl unchanged \
+                until it exists.",
+                kube_context
+            );
+        }
+    }
+    Some(kube_context)
+}
+
+/// Saves the current gcloud ADC credentials as a named context.
 ///
 /// If `quiet` is true, sensitive details (account, project, etc.) are hidden.
+/// See [`save_context_with_options`] for the full set of options.
 pub fn save_context(name: &str, quiet: bool) -> Result<()> {
+    save_context_with_options(
+        name,
+        &SaveOptions {
+            quiet,
+            ..Default::default()
+        },
+    )
+}
+
+/// Saves the current gcloud ADC credentials as a named context.
+///
+/// This function:
+/// 1. Locates ADC credentials (the gcloud ADC file, falling back to
+///    `GOOGLE_APPLICATION_CREDENTIALS`, unless `adc_path` or `adc_json` is given;
+///    `from_env` reads `GOOGLE_APPLICATION_CREDENTIALS` only)
+/// 2. Captures current gcloud config, account, project, and kubectl context
+///    (plus allowlisted gcloud properties with `all_properties`); explicit
+///    `account`/`project`/`gcloud_config` values take precedence
+/// 3. Builds and serializes the metadata (gcloud config name, account,
+///    project, kubectl context); with `merge_metadata`, user-set fields of the
+///    existing metadata are kept unless given again
+/// 4. Copies credentials to the context storage directory with secure file
+///    permissions (Unix only) and writes the metadata. An overwritten context
+///    is kept in the trash for `gcpx undo`, and previous `adc.json` versions
+///    are rotated with `keep_versions`. If any write fails, the context is
+///    restored to its previous state.
+/// 5. Updates the current context tracking
+pub fn save_context_with_options(name: &str, options: &SaveOptions) -> Result<()> {
     validate_context_name(name)?;
-    let adc_path = get_adc_path()?;
+    for service_account in &options.impersonate {
+        validate_service_account(service_account)?;
+    }
+    let quiet = options.quiet;
 
-    // Check if credentials exist
-    if !adc_path.exists() {
-        bail!(
-            "No credentials found at {:?}.\nRun 'gcloud auth application-default login' first!",
-            adc_path
-        );
+    // Find the credentials (supplied directly, --adc-path, --from-env, gcloud
+    // ADC, or GOOGLE_APPLICATION_CREDENTIALS)
+    let (content, adc_path, adc_source) = match &options.adc_json {
+        Some(json) => {
+            validate_adc_json(json)?;
+            match &options.adc_path {
+                Some(path) => (json.clone(), Some(path.clone()), ADC_SOURCE_PATH),
+                None => (json.clone(), None, ADC_SOURCE_STDIN),
+            }
+        }
+        None if options.from_env => {
+            let path = env_adc_path()?;
+            let content = fs::read(&path)?;
+            validate_adc_json(&content)
+                .with_context(|| format!("Invalid credentials in {:?}", path))?;
+            (content, Some(path), ADC_SOURCE_ENV)
+        }
+        None => {
+            let (path, source) = resolve_adc_source(options.adc_path.as_deref())?;
+            (fs::read(&path)?, Some(path), source)
+        }
+    };
+
+    // Fork the active gcloud configuration so the new context gets its own
+    let forked_config = match &options.copy_current_config_as {
+        Some(new_config) => {
+            let active = active_gcloud_config(options)?;
+            if options.dry_run {
+                println!(
+                    "Would copy gcloud configuration '{}' to '{}'.",
+                    active, new_config
+                );
+            } else {
+                copy_gcloud_config(&active, new_config)?;
+            }
+            if !quiet && !options.dry_run {
+                println!(
+                    "Copied gcloud configuration '{}' to '{}'.",
+                    active, new_config
+                );
+            }
+            Some(new_config.clone())
+        }
+        None => None,
+    };
+    let explicit_config = options.gcloud_config.clone().or(forked_config.clone());
+
+    let saved = save_captured(
+        name,
+        options,
+        content,
+        adc_path,
+        adc_source,
+        explicit_config,
+    );
+    if let Some(config) = forked_config.filter(|_| saved.is_err() && !options.dry_run) {
+        // Don't leave behind a configuration no context refers to
+        let recorded = load_context_metadata(name).ok().flatten();
+        if recorded.is_none_or(|m| m.gcloud_config != config) {
+            if let Err(e) = delete_gcloud_config(&config) {
+                eprintln!(
+                    "Warning: could not remove the copied gcloud configuration '{}': {:#}",
+                    config, e
+                );
+            }
+        }
     }
+    saved
+}
 
-    // Capture current gcloud state
-    let gcloud_config = get_current_gcloud_config()?;
-    let account = get_current_gcloud_account()?;
-    let project = get_current_gcloud_project()?;
+/// Steps 2 to 5 of [`save_context_with_options`], once the credentials are
+/// read and the gcloud configuration to record (if given or forked) is known.
+fn save_captured(
+    name: &str,
+    options: &SaveOptions,
+    content: Vec<u8>,
+    adc_path: Option<PathBuf>,
+    adc_source: &str,
+    explicit_config: Option<String>,
+) -> Result<()> {
+    let quiet = options.quiet;
 
-    // Capture current kubectl context (if available)
-    let kubectl_context = get_current_kubectl_context();
+    // Capture current gcloud and kubectl state, unless credentials were supplied
+    // directly (the live state is unrelated to them then)
+    let (gcloud_config, account, project, kubectl_context) = if options.adc_json.is_some() {
+        (
+            explicit_config.unwrap_or_else(|| "default".to_string()),
+            options.account.clone(),
+            options.project.clone(),
+            kube_context_override(options),
+        )
+    } else {
+        let gcloud_config = match &explicit_config {
+            Some(config) => config.clone(),
+            None => active_gcloud_config(options)?,
+        };
+        let (live_account, live_project) = if options.account.is_some() && options.project.is_some()
+        {
+            (None, None)
+        } else {
+            // An explicit configuration is asked about (and cached) by name
+            gcloud_account_and_project(
+                &gcloud_config,
+                explicit_config.is_none(),
+                !options.force_account_query,
+            )?
+        };
+        (
+            gcloud_config,
+            options.account.clone().or(live_account),
+            options.project.clone().or(live_project),
+            if options.kube_context.is_some() || options.no_kube {
+                kube_context_override(options)
+            } else {
+                get_current_kubectl_context()
+            },
+        )
+    };
 
-    let store_path = get_context_dir(name)?;
-    fs::create_dir_all(&store_path)?;
+    if options.check_project {
+        if let Some(project) = &options.project {
+            // Credentials supplied directly may name a configuration that
+            // doesn't exist (yet)
+            let configuration = options.adc_json.is_none().then_some(gcloud_config.as_str());
+            check_project(project, account.as_deref(), configuration, options.strict)?;
+        }
+    }
 
-    let dest_adc = store_path.join("adc.json");
+    // Capture allowlisted gcloud properties (opt-in)
+    let gcloud_properties = if options.all_properties {
+        let settings = load_settings()?;
+        let mut properties = get_gcloud_properties(None)?;
+        properties.retain(|key, _| {
+            DEFAULT_CAPTURED_PROPERTIES.contains(&key.as_str())
+                || settings.capture_properties.iter().any(|p| p == key)
+        });
+        properties
+    } else {
+        Default::default()
+    };
 
-    // Read and save credentials
-    let content = fs::read(&adc_path)?;
-    fs::write(&dest_adc, content)?;
+    let description = if options.edit_description {
+        let existing = load_context_metadata(name)
+            .ok()
+            .flatten()
+            .and_then(|m| m.description)
+            .unwrap_or_default();
+        let template = format!(
+            "{}\n# Describe context '{}'. Lines starting with '#' are ignored.\n",
+            existing, name
+        );
+        Some(edit_text(&template)?).filter(|d| !d.is_empty())
+    } else {
+        options.description.clone()
+    };
 
-    // Save metadata
-    let metadata = ContextMetadata {
+    // Build and serialize metadata up front, so a failure here can't leave
+    // credentials saved without metadata
+    let fresh = ContextMetadata {
         gcloud_config: gcloud_config.clone(),
         account: account.clone(),
         project: project.clone(),
         kubectl_context: kubectl_context.clone(),
+        description: description.clone(),
+        gcloud_properties: gcloud_properties.clone(),
+        last_used: Some(now_unix()),
+        adc_type: adc_type_of(&content),
+        tags: options.tags.clone(),
+        impersonate: options.impersonate.clone(),
+        adc_source: Some(adc_source.to_string()),
+        danger: options.danger,
+        org: account.as_deref().and_then(account_domain),
+        adc_identity: adc_identity(&content),
+        run_denylist: options.run_deny.clone(),
+        run_allowlist: options.run_allow.clone(),
+    };
+    let existing = if options.merge_metadata {
+        load_context_metadata(name).ok().flatten()
+    } else {
+        None
+    };
+    let metadata = match existing {
+        Some(existing) => ContextMetadata {
+            description: fresh.description.clone().or(existing.description),
+            gcloud_properties: if options.all_properties {
+                fresh.gcloud_properties.clone()
+            } else {
+                existing.gcloud_properties
+            },
+            tags: if options.tags.is_empty() {
+                existing.tags
+            } else {
+                fresh.tags.clone()
+            },
+            impersonate: if fresh.impersonate.is_empty() {
+                existing.impersonate
+            } else {
+                fresh.impersonate.clone()
+            },
+            danger: fresh.danger || existing.danger,
+            run_denylist: if fresh.run_denylist.is_empty() {
+                existing.run_denylist
+            } else {
+                fresh.run_denylist.clone()
+            },
+            run_allowlist: if fresh.run_allowlist.is_empty() {
+                existing.run_allowlist
+            } else {
+                fresh.run_allowlist.clone()
+            },
+            ..fresh
+        },
+        None => fresh,
+    };
+    let metadata_json = serde_json::to_string_pretty(&metadata)?;
+    warn_if_identity_mismatch(&metadata, quiet);
+
+    // Workload identity federation reads a token file on every request
+    let source_file = external_credential_source_file(&content);
+    let bundled_source = match &source_file {
+        Some(path) if options.bundle_credential_source => Some(
+            fs::read(path)
+                .with_context(|| format!("Failed to read credential source file {:?}", path))?,
+        ),
+        None if options.bundle_credential_source => bail!(GcpxError::NoCredentialSource),
+        _ => None,
     };
-    save_context_metadata(name, &metadata)?;
 
-    // Set secure permissions (Unix only)
-    #[cfg(unix)]
+    // Snapshot the whole gcloud configuration file (opt-in); a merge keeps
+    // an existing snapshot
+    let config_file = get_gcloud_config_file(&gcloud_config)?;
+    let config_snapshot = if options.snapshot_config {
+        Some(fs::read(&config_file).with_context(|| {
+            format!(
+                "Failed to read gcloud configuration '{}' for the snapshot",
+                gcloud_config
+            )
+        })?)
+    } else if options.merge_metadata {
+        fs::read(get_context_config_snapshot_path(name)?).ok()
+    } else {
+        None
+    };
+
+    if options.if_changed
+        && stored_unchanged(
+            name,
+            &content,
+            &metadata,
+            bundled_source.as_deref(),
+            config_snapshot.as_deref(),
+        )?
     {
-        use std::os::unix::fs::PermissionsExt;
-        let mut perms = fs::metadata(&dest_adc)?.permissions();
-        perms.set_mode(0o600);
-        fs::set_permissions(&dest_adc, perms)?;
+        println!("No changes to context '{}'; nothing saved.", name);
+        return Ok(());
+    }
+
+    if options.dry_run {
+        return print_dry_run(
+            name,
+            &metadata,
+            &metadata_json,
+            adc_path.as_deref(),
+            source_file
+                .as_deref()
+                .map(|path| (path, bundled_source.is_some())),
+            options.snapshot_config.then_some(config_file.as_path()),
+            quiet,
+        );
+    }
+
+    // Keep the context being overwritten in the trash, both for `gcpx undo`
+    // and to roll back if writing fails part-way
+    let store_path = get_context_dir(name)?;
+    let trash_entry = if store_path.exists() {
+        Some(copy_to_trash(name)?)
+    } else {
+        None
+    };
+    // Credentials kept outside the context directory (the command backend)
+    // aren't in that copy, so fetch them too, to put them back on failure
+    let store = credential_store()?;
+    let previous_adc = if trash_entry.is_some() && store.stored_path(name)?.is_none() {
+        store.read(name).ok()
+    } else {
+        None
+    };
+
+    if let Err(e) = write_context_files(
+        name,
+        &content,
+        &metadata_json,
+        bundled_source.as_deref(),
+        config_snapshot.as_deref(),
+        options.keep_versions,
+        options.credential_mode,
+    ) {
+        restore_after_failed_save(name, trash_entry.as_deref(), previous_adc.as_deref());
+        return Err(e.context(format!(
+            "Failed to save context '{}'; nothing was changed",
+            name
+        )));
+    }
+    if let Some(trash_entry) = trash_entry {
+        record_last_op(&LastOp {
+            op: OpKind::Save,
+            name: name.to_string(),
+            trash_entry,
+            current: get_current_tracking(),
+            previous: get_previous_tracking(),
+            gcloud_config: None,
+            gcloud_config_entry: None,
+        })?;
+    }
+    if load_settings()?.isolated_configs {
+        seed_context_gcloud_dir(name, &metadata, true)?;
     }
 
     println!("Context '{}' saved.", name);
@@ -73,13 +592,168 @@ pub fn save_context(name: &str, quiet: bool) -> Result<()> {
         if let Some(acc) = &account {
             println!("  account: {}", acc);
         }
+        if let Some(org) = &metadata.org {
+            println!("  org: {}", org);
+        }
         if let Some(proj) = &project {
             println!("  project: {}", proj);
         }
         if let Some(kctx) = &kubectl_context {
             println!("  kubectl: {}", kctx);
         }
+        if let Some(desc) = &metadata.description {
+            println!("  description: {}", desc);
+        }
+        if options.snapshot_config {
+            println!("  gcloud config snapshot: {}", config_file.display());
+        }
+        match &adc_path {
+            Some(path) if adc_source != ADC_SOURCE_GCLOUD => {
+                println!("  credentials: {} ({})", path.display(), adc_source)
+            }
+            None => println!("  credentials: {}", adc_source),
+            _ => {}
+        }
+        if let Some(chain) = metadata.impersonation() {
+            println!("  impersonate: {}", chain);
+        }
+        if let Some(path) = &source_file {
+            if bundled_source.is_some() {
+                println!("  credential source: {} (bundled)", path.display());
+            } else {
+                println!(
+                    "  credential source: {} (not copied; pass --bundle-credential-source \
+                    to keep a copy with the context)",
+                    path.display()
+                );
+            }
+        }
+        if metadata.danger {
+            println!("  danger: switching will require confirmation");
+        }
+        if !metadata.tags.is_empty() {
+            println!("  tags: {}", metadata.tags.join(", "));
+        }
+        if !metadata.run_denylist.is_empty() {
+            println!("  run denylist: {}", metadata.run_denylist.join(", "));
+        }
+        if !metadata.run_allowlist.is_empty() {
+            println!("  run allowlist: {}", metadata.run_allowlist.join(", "));
+        }
+        for (key, value) in &metadata.gcloud_properties {
+            println!("  {}: {}", key, value);
+        }
+    }
+    if !options.no_track {
+        set_current_tracking(name)?;
     }
-    set_current_tracking(name)?;
     Ok(())
 }
+
+/// Reports what `save` would write, without writing anything.
+fn print_dry_run(
+    name: &str,
+    metadata: &ContextMetadata,
+    metadata_json: &str,
+    adc_path: Option<&Path>,
+    credential_source: Option<(&Path, bool)>,
+    config_snapshot: Option<&Path>,
+    quiet: bool,
+) -> Result<()> {
+    let exists = context_exists(name)?;
+    println!(
+        "Dry run: would {} context '{}' (nothing was written).",
+        if exists { "overwrite" } else { "create" },
+        name
+    );
+    match adc_path {
+        Some(path) => println!("  credentials from: {}", path.display()),
+        None => println!("  credentials from: stdin"),
+    }
+    if let Some(adc_type) = &metadata.adc_type {
+        println!("  credential type: {}", adc_type);
+    }
+    if let Some((path, bundled)) = credential_source {
+        println!(
+            "  credential source file: {}{}",
+            path.display(),
+            if bundled { " (would be bundled)" } else { "" }
+        );
+    }
+    if let Some(path) = config_snapshot {
+        println!("  gcloud config snapshot from: {}", path.display());
+    }
+    let credentials_dest = match load_settings()?.store_backend {
+        StoreBackend::File => get_context_adc_path(name)?.display().to_string(),
+        StoreBackend::Command => "the store_command put_cmd".to_string(),
+    };
+    println!("  credentials to: {}", credentials_dest);
+    println!(
+        "  metadata to: {}",
+        get_context_metadata_path(name)?.display()
+    );
+    if quiet {
+        println!("  gcloud config: {}", metadata.gcloud_config);
+    } else {
+        println!("  metadata:");
+        for line in metadata_json.lines() {
+            println!("    {}", line);
+        }
+    }
+    Ok(())
+}
+
+/// Writes a context's credentials (through the credential store), bundled
+/// credential source and gcloud config snapshot (each replacing or removing
+/// any previous one) and metadata, then rotates the replaced credentials into
+/// the kept versions.
+fn write_context_files(
+    name: &str,
+    adc: &[u8],
+    metadata_json: &str,
+    credential_source: Option<&[u8]>,
+    config_snapshot: Option<&[u8]>,
+    keep_versions: usize,
+    mode: Option<CredentialMode>,
+) -> Result<()> {
+    fs::create_dir_all(get_context_dir(name)?)?;
+    stash_adc_version(name, keep_versions)?;
+
+    let mode = match mode {
+        Some(mode) => mode,
+        None => CredentialMode::from_settings()?,
+    };
+    credential_store_with_mode(Some(mode))?.write(name, adc)?;
+    let source_path = get_context_credential_source_path(name)?;
+    match credential_source {
+        Some(source) => mode.write(&source_path, source)?,
+        None if source_path.exists() => fs::remove_file(&source_path)?,
+        None => {}
+    }
+    let snapshot_path = get_context_config_snapshot_path(name)?;
+    match config_snapshot {
+        Some(snapshot) => write_atomic(&snapshot_path, snapshot)?,
+        None if snapshot_path.exists() => fs::remove_file(&snapshot_path)?,
+        None => {}
+    }
+    fs::write(get_context_metadata_path(name)?, metadata_json)?;
+    rotate_adc_versions(name, keep_versions)?;
+    Ok(())
+}
+
+/// Puts a context back the way it was before a failed save: restored from its
+/// trash copy, or removed if it didn't exist. `previous_adc` is written back
+/// through the credential store, for backends that keep credentials outside
+/// the context directory.
+fn restore_after_failed_save(name: &str, trash_entry: Option<&str>, previous_adc: Option<&[u8]>) {
+    let Ok(context_dir) = get_context_dir(name) else {
+        return;
+    };
+    let _ = fs::remove_dir_all(&context_dir);
+    if let (Some(entry), Ok(trash_dir)) = (trash_entry, get_trash_dir()) {
+        let _ = fs::rename(trash_dir.join(entry), &context_dir);
+    }
+    if let (Some(adc), Ok(store)) = (previous_adc, credential_store()) {
+        let _ = store.write(name, adc);
+    }
+}
@@ -0,0 +1,193 @@
+//! Self-test command implementation - exercise gcpx in a throwaway store.
+
+use anyhow::{Context, Result, bail};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::commands::{
+    SaveOptions, SwitchOptions, delete_context, save_context_with_options,
+    switch_context_with_options,
+};
+use crate::config::{
+    context_exists, get_current_tracking, list_contexts, set_isolated_gcloud_cli,
+    set_store_dir_override,
+};
+use crate::error::GcpxError;
+
+/// Environment variables pointed at the temporary directories.
+const ISOLATED_VARS: &[&str] = &["GCPX_HOME", "GCPX_GCLOUD_DIR", "GCPX_CONFIG"];
+
+/// Fake user credentials saved by the self-test.
+const FAKE_ADC: &str = r#"{
+  "type": "authorized_user",
+  "client_id": "gcpx-self-test",
+  "client_secret": "gcpx-self-test",
+  "refresh_token": "gcpx-self-test"
+}"#;
+
+/// A temporary store, gcloud directory and settings file in use by the
+/// process. Dropping it restores the environment and removes the directory.
+struct Sandbox {
+    root: tempfile::TempDir,
+    saved_vars: Vec<(&'static str, Option<std::ffi::OsString>)>,
+}
+
+impl Sandbox {
+    fn new() -> Result<Self> {
+        let root = tempfile::Builder::new()
+            .prefix("gcpx-self-test-")
+            .tempdir()
+            .context("Failed to create a temporary directory")?;
+        for dir in ["store", "gcloud"] {
+            let path = root.path().join(dir);
+            fs::create_dir_all(&path).with_context(|| format!("Failed to create {:?}", path))?;
+        }
+        // Isolated configs (without handing anything to the gcloud CLI) keep
+        // switching away from gcloud
+        fs::write(root.path().join("config.toml"), "isolated_configs = true\n")?;
+
+        let sandbox = Sandbox {
+            root,
+            saved_vars: ISOLATED_VARS
+                .iter()
+                .map(|var| (*var, env::var_os(var)))
+                .collect(),
+        };
+        let values = [
+            sandbox.path("store"),
+            sandbox.path("gcloud"),
+            sandbox.path("config.toml"),
+        ];
+        for (var, value) in ISOLATED_VARS.iter().zip(values) {
+            // SAFETY: the self-test is a standalone command that runs on the main
+            // thread; nothing else reads the environment concurrently
+            unsafe { env::set_var(var, value) };
+        }
+        set_store_dir_override(Some(&sandbox.path("store")));
+        set_isolated_gcloud_cli(false);
+        Ok(sandbox)
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.root.path().join(name)
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        set_store_dir_override(None);
+        set_isolated_gcloud_cli(true);
+        for (var, value) in &self.saved_vars {
+            // SAFETY: see `Sandbox::new`
+            unsafe {
+                match value {
+                    Some(value) => env::set_var(var, value),
+                    None => env::remove_var(var),
+                }
+            }
+        }
+        // The temporary directory itself is removed when `root` drops
+    }
+}
+
+/// Saves a context from the fake credentials, as `save --stdin` would.
+fn save(name: &str) -> Result<()> {
+    save_context_with_options(
+        name,
+        &SaveOptions {
+            quiet: true,
+            adc_json: Some(FAKE_ADC.as_bytes().to_vec()),
+            account: Some(format!("{}@self-test.invalid", name)),
+            project: Some("gcpx-self-test".to_string()),
+            ..Default::default()
+        },
+    )
+}
+
+/// Switches to a context (`-` for the previous one), writing its credentials
+/// to `adc_dest`, and checks that `expected` became current.
+fn switch(name: &str, expected: &str, adc_dest: &Path) -> Result<()> {
+    switch_context_with_options(
+        name,
+        &SwitchOptions {
+            print: true,
+            force: true,
+            adc_dest: Some(adc_dest.to_path_buf()),
+            ..Default::default()
+        },
+    )?;
+    if get_current_tracking() != expected {
+        bail!(GcpxError::SelfTestCheck(format!(
+            "current context is '{}'",
+            get_current_tracking()
+        )));
+    }
+    let restored = fs::read_to_string(adc_dest)?;
+    if restored != FAKE_ADC {
+        bail!(GcpxError::SelfTestCheck(
+            "restored credentials differ from the saved ones".to_string()
+        ));
+    }
+    Ok(())
+}
+
+/// Runs save, list, switch and delete end to end against a temporary store
+/// and reports each step.
+///
+/// The real store, gcloud directory and settings are never touched: the
+/// store, `GCPX_GCLOUD_DIR` and `GCPX_CONFIG` point into a temporary
+/// directory for the duration of the test, and contexts use isolated gcloud
+/// configs so no gcloud CLI is needed. Returns an error if any step failed.
+pub fn self_test() -> Result<()> {
+    let sandbox = Sandbox::new()?;
+    let adc_dest = sandbox.path("adc.json");
+
+    let steps: [(&str, &dyn Fn() -> Result<()>); 6] = [
+        ("save", &|| {
+            save("self-test-a")?;
+            save("self-test-b")
+        }),
+        ("list", &|| {
+            let contexts = list_contexts()?;
+            if contexts != ["self-test-a", "self-test-b"] {
+                bail!(GcpxError::SelfTestCheck(format!("listed {:?}", contexts)));
+            }
+            Ok(())
+        }),
+        ("switch", &|| {
+            switch("self-test-a", "self-test-a", &adc_dest)
+        }),
+        ("switch again", &|| {
+            switch("self-test-b", "self-test-b", &adc_dest)
+        }),
+        ("switch back", &|| switch("-", "self-test-a", &adc_dest)),
+        ("delete", &|| {
+            delete_context("self-test-b", false)?;
+            if context_exists("self-test-b")? {
+                bail!(GcpxError::SelfTestCheck(
+                    "context still exists after delete".to_string()
+                ));
+            }
+            Ok(())
+        }),
+    ];
+
+    let mut failed = 0;
+    for (step, run) in &steps {
+        match run() {
+            Ok(()) => println!("  ok    {}", step),
+            Err(e) => {
+                failed += 1;
+                println!("  FAIL  {}: {:#}", step, e);
+            }
+        }
+    }
+    drop(sandbox);
+
+    if failed > 0 {
+        bail!(GcpxError::SelfTestFailed(failed, steps.len()));
+    }
+    println!("Self-test passed ({} steps).", steps.len());
+    Ok(())
+}
@@ -0,0 +1,57 @@
+//! Config command implementation - view and edit gcpx's own settings.
+
+use anyhow::Result;
+
+use crate::settings::{
+    SETTING_KEYS, get_setting, get_settings_path, list_settings, set_setting, unset_setting,
+};
+
+/// Prints a setting's value, or `(unset)`.
+pub fn config_get(key: &str) -> Result<()> {
+    match get_setting(key)? {
+        Some(value) => println!("{}", value),
+        None => println!("(unset)"),
+    }
+    Ok(())
+}
+
+/// Sets a setting in the settings file.
+pub fn config_set(key: &str, value: &str) -> Result<()> {
+    set_setting(key, value)?;
+    println!("Set {} = {}", key, get_setting(key)?.unwrap_or_default());
+    Ok(())
+}
+
+/// Removes a setting from the settings file, reverting it to its default.
+pub fn config_unset(key: &str) -> Result<()> {
+    if unset_setting(key)? {
+        println!("Unset {}.", key);
+    } else {
+        println!("{} was not set.", key);
+    }
+    Ok(())
+}
+
+/// Prints the settings file location, the settings that are set, and every
+/// available key.
+pub fn config_list() -> Result<()> {
+    println!("# {}", get_settings_path()?.display());
+    let set = list_settings()?;
+    if set.is_empty() {
+        println!("(no settings set)");
+    }
+    for (key, value) in &set {
+        println!("{} = {}", key, value);
+    }
+
+    println!("\nAvailable settings:");
+    let width = SETTING_KEYS
+        .iter()
+        .map(|(k, _, _)| k.len())
+        .max()
+        .unwrap_or(0);
+    for (key, _, description) in SETTING_KEYS {
+        println!("  {:width$}  {}", key, description, width = width);
+    }
+    Ok(())
+}
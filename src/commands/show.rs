@@ -0,0 +1,228 @@
+//! Show command implementation - print the details of one context.
+
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::config::{
+    context_exists, get_context_adc_path, get_current_tracking, load_context_metadata,
+    validate_context_name,
+};
+use crate::credstore::StoreBackend;
+use crate::error::GcpxError;
+use crate::json::{ContextEntry, JsonEnvelope};
+use crate::settings::load_settings;
+
+/// Field copied by `gcpx show --copy`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum CopyField {
+    /// The saved account email
+    #[default]
+    Account,
+    /// The saved project ID
+    Project,
+    /// Path of the saved credential file
+    AdcPath,
+}
+
+/// Body of `gcpx show --json`.
+#[derive(Serialize)]
+struct ShowJson<'a> {
+    context: ContextEntry<'a>,
+}
+
+/// Prints everything gcpx knows about a context (never the credentials).
+///
+/// With `json`, prints a versioned JSON object with the context under `context`.
+pub fn show_context(name: &str, json: bool) -> Result<()> {
+    validate_context_name(name)?;
+    if !context_exists(name)? {
+        bail!(GcpxError::ContextNotFound(name.to_string()));
+    }
+    let metadata = load_context_metadata(name)?;
+    let active = get_current_tracking() == name;
+
+    if json {
+        return JsonEnvelope::new(ShowJson {
+            context: ContextEntry {
+                name,
+                active,
+                adc_path: None,
+                gcloud_config_missing: None,
+                metadata: metadata.as_ref(),
+            },
+        })
+        .print();
+    }
+
+    println!("{}{}", name, if active { " (active)" } else { "" });
+    let Some(m) = metadata else {
+        println!("  (no metadata; gcloud config '{}' is assumed)", name);
+        return Ok(());
+    };
+    println!("  gcloud config: {}", m.gcloud_config);
+    let impersonation = m.impersonation();
+    let fields = [
+        ("account", m.account.as_deref()),
+        ("org", m.org.as_deref()),
+        ("project", m.project.as_deref()),
+        ("kubectl", m.kubectl_context.as_deref()),
+        ("impersonate", impersonation.as_deref()),
+        ("credentials", m.adc_type.as_deref()),
+        ("credentials identity", m.adc_identity.as_deref()),
+        ("source", m.adc_source.as_deref()),
+        ("description", m.description.as_deref()),
+    ];
+    for (label, value) in fields {
+        if let Some(value) = value {
+            println!("  {}: {}", label, value);
+        }
+    }
+    if !m.tags.is_empty() {
+        println!("  tags: {}", m.tags.join(", "));
+    }
+    if m.is_dangerous() {
+        println!("  danger: switching requires confirmation");
+    }
+    if !m.run_denylist.is_empty() {
+        println!("  run denylist: {}", m.run_denylist.join(", "));
+    }
+    if !m.run_allowlist.is_empty() {
+        println!("  run allowlist: {}", m.run_allowlist.join(", "));
+    }
+    for (key, value) in &m.gcloud_properties {
+        println!("  {}: {}", key, value);
+    }
+    Ok(())
+}
+
+/// Copies one detail of a context to the system clipboard.
+///
+/// Fails with a message (rather than a panic) when no clipboard is available,
+/// e.g. over SSH or in CI.
+///
+/// On X11 the copied text only stays available while its owner is running,
+/// so it is handed to a background `gcpx __hold-clipboard` process that serves
+/// it until something else is copied.
+pub fn copy_context_field(name: &str, field: CopyField) -> Result<()> {
+    validate_context_name(name)?;
+    if !context_exists(name)? {
+        bail!(GcpxError::ContextNotFound(name.to_string()));
+    }
+    let metadata = load_context_metadata(name)?.unwrap_or_default();
+    let (label, value) = match field {
+        CopyField::Account => ("account", metadata.account),
+        CopyField::Project => ("project", metadata.project),
+        CopyField::AdcPath => {
+            if load_settings()?.store_backend != StoreBackend::File {
+                bail!(GcpxError::NoCredentialFile(name.to_string()));
+            }
+            (
+                "credential path",
+                Some(get_context_adc_path(name)?.to_string_lossy().into_owned()),
+            )
+        }
+    };
+    let Some(value) = value else {
+        bail!(GcpxError::FieldNotRecorded(
+            name.to_string(),
+            label.to_string()
+        ));
+    };
+
+    set_clipboard_text(&value)
+        .map_err(|e| GcpxError::Clipboard(e, name.to_string(), label.to_string()))?;
+    println!("Copied the {} of '{}' to the clipboard.", label, name);
+    Ok(())
+}
+
+/// Puts text on the clipboard of a platform where the clipboard keeps it after
+/// gcpx exits. Returns the reason the text couldn't be copied on failure.
+#[cfg(not(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+)))]
+fn set_clipboard_text(text: &str) -> Result<(), String> {
+    let mut clipboard =
+        arboard::Clipboard::new().map_err(|e| format!("No clipboard available ({}).", e))?;
+    clipboard
+        .set_text(text)
+        .map_err(|e| format!("Failed to copy to the clipboard ({}).", e))
+}
+
+/// Puts text on the X11 clipboard by starting `gcpx __hold-clipboard` in the
+/// background and waiting for it to report that it owns the selection.
+///
+/// Returns the reason the text couldn't be copied on failure.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+))]
+fn set_clipboard_text(text: &str) -> Result<(), String> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    let mut child = std::env::current_exe()
+        .and_then(|exe| {
+            Command::new(exe)
+                .arg("__hold-clipboard")
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                // Keep Ctrl-C in this terminal from clearing the clipboard
+                .process_group(0)
+                .spawn()
+        })
+        .map_err(|e| format!("Failed to start the clipboard helper ({}).", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A helper that failed early may not read its input
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    let mut status = String::new();
+    if let Some(stdout) = child.stdout.take() {
+        let _ = BufReader::new(stdout).read_line(&mut status);
+    }
+    match status.trim_end() {
+        "ok" => Ok(()),
+        error => {
+            let _ = child.wait();
+            if error.is_empty() {
+                Err("The clipboard helper exited without copying anything.".to_string())
+            } else {
+                Err(error.to_string())
+            }
+        }
+    }
+}
+
+/// Runs `gcpx __hold-clipboard`: copies standard input to the clipboard,
+/// prints `ok` (or the reason it couldn't) and then keeps serving the text
+/// until something else is copied.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+))]
+pub fn hold_clipboard() -> Result<()> {
+    use arboard::SetExtLinux;
+    use std::io::{Read, Write};
+
+    let mut text = String::new();
+    std::io::stdin().read_to_string(&mut text)?;
+    let mut clipboard = match arboard::Clipboard::new() {
+        Ok(clipboard) => clipboard,
+        Err(e) => {
+            println!("No clipboard available ({}).", e);
+            return Ok(());
+        }
+    };
+    if let Err(e) = clipboard.set_text(text.as_str()) {
+        println!("Failed to copy to the clipboard ({}).", e);
+        return Ok(());
+    }
+    println!("ok");
+    std::io::stdout().flush()?;
+    // Owning the selection already, this returns once another program takes it
+    let _ = clipboard.set().wait().text(text);
+    Ok(())
+}
@@ -0,0 +1,125 @@
+//! Status command implementation - compare tracked context with live state.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::fs;
+
+use crate::config::{
+    adc_equivalent, get_adc_path, get_current_context_checked, get_current_gcloud_config,
+    get_current_tracking, load_context_metadata, restored_adc,
+};
+use crate::json::JsonEnvelope;
+
+/// Tracked context compared with the live gcloud state (body of `status --json`).
+#[derive(Debug, Serialize)]
+struct StatusReport {
+    /// Raw tracking, even if the context no longer exists (null if none)
+    tracked: Option<String>,
+    /// gcloud configuration the current context expects (null without a context)
+    gcloud_config: Option<String>,
+    live_gcloud_config: String,
+    /// Whether the live ADC matches the saved credentials (null if unknown)
+    adc_matches: Option<bool>,
+    /// True if there is a current context and the live state matches it
+    in_sync: bool,
+}
+
+/// A context's gcloud configuration and credentials compared with the live ones.
+pub(crate) struct LiveComparison {
+    /// gcloud configuration the context expects
+    pub expected_config: String,
+    /// Whether the live gcloud configuration is the expected one
+    pub config_matches: bool,
+    /// Whether the live ADC matches the saved credentials (None if unknown)
+    pub adc_matches: Option<bool>,
+}
+
+impl LiveComparison {
+    /// Returns true if the live state matches the context.
+    pub fn in_sync(&self) -> bool {
+        self.config_matches && self.adc_matches != Some(false)
+    }
+}
+
+/// Compares a context with the live gcloud configuration `live_config` and
+/// the live ADC file.
+pub(crate) fn compare_live_state(context: &str, live_config: &str) -> Result<LiveComparison> {
+    let expected_config = load_context_metadata(context)?
+        .map(|m| m.gcloud_config)
+        .unwrap_or_else(|| context.to_string());
+    let stored_adc = restored_adc(context).ok();
+    let live_adc = fs::read(get_adc_path()?).ok();
+    Ok(LiveComparison {
+        config_matches: live_config == expected_config,
+        adc_matches: stored_adc
+            .map(|stored| live_adc.is_some_and(|live| adc_equivalent(&stored, &live))),
+        expected_config,
+    })
+}
+
+/// Prints the tracked context and whether the live gcloud state matches it.
+///
+/// The live state can diverge from tracking after `gcpx switch --no-track`, or
+/// when gcloud/ADC are changed outside of gcpx. Only non-sensitive details
+/// (context and gcloud config names) are printed. With `json`, the report is
+/// printed as a versioned JSON object instead.
+pub fn show_status(json: bool) -> Result<()> {
+    let live_config = get_current_gcloud_config()?;
+    let tracked = get_current_tracking();
+    let current = get_current_context_checked();
+
+    let mut report = StatusReport {
+        tracked: Some(tracked.clone()).filter(|t| t != "none"),
+        gcloud_config: None,
+        live_gcloud_config: live_config.clone(),
+        adc_matches: None,
+        in_sync: false,
+    };
+    if let Some(current) = &current {
+        let live = compare_live_state(current, &live_config)?;
+        report.in_sync = live.in_sync();
+        report.adc_matches = live.adc_matches;
+        report.gcloud_config = Some(live.expected_config);
+    }
+
+    if json {
+        return JsonEnvelope::new(report).print();
+    }
+
+    let (Some(current), Some(expected_config)) = (current, &report.gcloud_config) else {
+        if tracked == "none" {
+            println!("No active context.");
+        } else {
+            println!(
+                "Tracked context '{}' no longer exists. Switch to another context to fix tracking.",
+                tracked
+            );
+        }
+        println!("  live gcloud config: {}", live_config);
+        return Ok(());
+    };
+
+    println!("Current context: {}", current);
+    println!("  gcloud config: {}", expected_config);
+
+    if live_config != *expected_config {
+        println!(
+            "  Warning: live gcloud config is '{}', not '{}'.",
+            live_config, expected_config
+        );
+    }
+    if report.adc_matches == Some(false) {
+        println!("  Warning: live ADC differs from the credentials saved for this context.");
+    }
+
+    if report.in_sync {
+        println!("  Live gcloud config and ADC match this context.");
+    } else {
+        println!(
+            "\nLive state is out of sync with tracking (expected after 'switch --no-track'). \
+            Run 'gcpx switch {}' to realign.",
+            current
+        );
+    }
+    Ok(())
+}
@@ -1,41 +1,168 @@
 //! Switch command implementation.
 
 use anyhow::{Context, Result, bail};
-use dialoguer::{Select, theme::ColorfulTheme};
+use dialoguer::{Input, Select, theme::ColorfulTheme};
+use std::collections::HashMap;
 use std::fs;
-use std::process::Command;
+use std::path::{Path, PathBuf};
 
+use crate::commands::login::login_context;
+use crate::commands::save::save_context;
+use crate::commands::status::compare_live_state;
 use crate::config::{
-    get_adc_path, get_context_adc_path, get_current_tracking, list_contexts, load_context_metadata,
-    set_current_tracking, switch_kubectl_context, validate_context_name,
+    ContextMetadata, adc_type_expires, context_exists, file_age_days, gcloud_command, get_adc_path,
+    get_context_config_snapshot_path, get_context_gcloud_dir, get_current_gcloud_config,
+    get_current_kubectl_context, get_current_tracking, get_gcloud_config_file,
+    get_previous_tracking, get_store_dir, invalidate_gcloud_cache, list_contexts,
+    list_contexts_with_metadata, load_context_metadata, restored_adc, seed_context_gcloud_dir,
+    set_current_tracking, set_gcloud_property, set_previous_tracking, switch_kubectl_context,
+    touch_context_metadata, unset_gcloud_property, validate_context_name, warn_if_incomplete,
+    write_atomic, write_atomic_private,
 };
+use crate::credstore::credential_store;
+use crate::error::GcpxError;
+use crate::log::debug;
+use crate::prompt::{input_allowed, require_input};
+use crate::settings::{DEFAULT_ADC_MAX_AGE_DAYS, load_settings};
+use crate::style::{print_danger_banner, print_leaving_danger_notice};
+
+/// Options controlling how a context is switched.
+#[derive(Debug, Clone, Default)]
+pub struct SwitchOptions {
+    /// Hide sensitive details (account, project, etc.) in the output
+    pub quiet: bool,
+    /// Leave the live ADC file untouched and only switch the gcloud configuration
+    pub no_adc: bool,
+    /// Don't update `.current`/`.previous` tracking. This deliberately leaves
+    /// tracking out of sync with the live gcloud state (see `gcpx status`).
+    pub no_track: bool,
+    /// Skip the typed confirmation for contexts flagged as dangerous
+    pub force: bool,
+    /// Replace the usual output with a single log-safe line naming the
+    /// previous and new context and the gcloud configuration (never the
+    /// account or project)
+    pub print: bool,
+    /// Write the context's credentials to this file instead of the standard
+    /// ADC location, which is then left untouched
+    pub adc_dest: Option<PathBuf>,
+    /// Only activate the gcloud configuration and update tracking: like
+    /// `no_adc`, and kubectl is left alone as well
+    pub activate_only: bool,
+    /// Warn and return successfully, without switching, if the context
+    /// doesn't exist. Any other failure is still an error.
+    pub if_exists: bool,
+    /// Write the context's config snapshot (see `save --snapshot-config`)
+    /// over its gcloud configuration even if the configuration exists,
+    /// discarding changes made to it since the snapshot was saved
+    pub restore_config: bool,
+}
+
+impl SwitchOptions {
+    /// Returns true if no credentials should be written.
+    fn skip_adc(&self) -> bool {
+        self.no_adc || self.activate_only
+    }
+}
 
 /// Switches to a saved context.
 ///
-/// This function:
-/// 1. Checks if already on the requested context (skips if so)
-/// 2. Reads context metadata to get the correct gcloud config name
-/// 3. Activates the gcloud configuration
-/// 4. Restores the saved ADC credentials
-/// 5. Switches kubectl context if saved
-/// 6. Updates the current context tracking
-///
 /// If `quiet` is true, sensitive details (account, project, etc.) are hidden.
+/// See [`switch_context_with_options`] for the full set of options.
 pub fn switch_context(name: &str, quiet: bool) -> Result<()> {
-    validate_context_name(name)?;
-    let stored_adc = get_context_adc_path(name)?;
+    switch_context_with_options(
+        name,
+        &SwitchOptions {
+            quiet,
+            ..Default::default()
+        },
+    )
+}
 
-    if !stored_adc.exists() {
-        bail!(
-            "Context '{}' not found. Run 'gcpx save {}' first.",
-            name,
-            name
-        );
+/// Returns true if the live gcloud configuration and (unless the switch
+/// leaves the ADC alone) the live ADC already match the context, as compared
+/// by `gcpx status`. With isolated configs the live state isn't switched, so
+/// it always matches.
+fn live_state_matches(name: &str, options: &SwitchOptions) -> Result<bool> {
+    if load_settings()?.isolated_configs {
+        return Ok(true);
+    }
+    let live = compare_live_state(name, &get_current_gcloud_config()?)?;
+    Ok(live.config_matches && (options.skip_adc() || live.adc_matches != Some(false)))
+}
+
+/// Switches to a saved context.
+///
+/// Passing `-` as the name switches back to the previous context.
+///
+/// This function:
+/// 1. Checks if already on the requested context (skips if so, unless the
+///    live gcloud config or ADC no longer match it, or `adc_dest` or
+///    `restore_config` is set)
+/// 2. Reads context metadata to get the correct gcloud config name, asking for
+///    confirmation if the context is dangerous (unless `force` is set). A
+///    notice is printed when leaving a dangerous context, without asking
+/// 3. Recreates the gcloud configuration from the context's config snapshot if
+///    it is missing (or overwrites it, with `restore_config`), activates it and
+///    replays any captured properties
+///    (including the impersonated service account, which is unset for
+///    contexts that don't impersonate)
+/// 4. Restores the saved ADC credentials (unless `no_adc` is set), to
+///    `adc_dest` if given (atomically, 0600) instead of the standard location
+///
+///    With the `isolated_configs` setting, steps 3 and 4 are skipped: the
+///    context's own gcloud config directory is used via `CLOUDSDK_CONFIG`
+/// 5. Switches kubectl context if saved (unless `activate_only` is set, which
+///    also implies `no_adc`)
+/// 6. Updates the current and previous context tracking (unless `no_track` is set)
+///
+/// Steps 3 to 6 are transactional: if any of them fails, the previously active
+/// gcloud configuration, ADC file, kubectl context and tracking are restored
+/// before the error is returned, so a failed switch leaves you where you started.
+///
+/// With `print`, the only output is one line such as
+/// `switch: dev -> prod (gcloud config 'prod')`, which is safe for CI logs.
+///
+/// With `if_exists`, a missing context only prints a warning to stderr and
+/// `Ok(())` is returned with nothing changed.
+pub fn switch_context_with_options(name: &str, options: &SwitchOptions) -> Result<()> {
+    let quiet = options.quiet;
+    let previous;
+    let name = if name == "-" {
+        previous = get_previous_tracking();
+        if previous == "none" {
+            bail!(GcpxError::NoPreviousContext);
+        }
+        previous.as_str()
+    } else {
+        name
+    };
+    validate_context_name(name)?;
+    if !context_exists(name)? {
+        if options.if_exists {
+            eprintln!("Warning: context '{}' not found; not switching.", name);
+            return Ok(());
+        }
+        bail!(GcpxError::ContextNotFound(name.to_string()));
     }
 
-    // Check if already on this context (fast file read)
+    // Nothing to do if already on this context, unless the live state has
+    // drifted from it (e.g. after `switch --no-track`) or an option asks for
+    // something to be written
     let current = get_current_tracking();
-    if current == name {
+    if current == name
+        && options.adc_dest.is_none()
+        && !options.restore_config
+        && live_state_matches(name, options)?
+    {
+        if options.print {
+            let metadata = load_context_metadata(name).ok().flatten();
+            println!(
+                "switch: {} (unchanged, gcloud config '{}')",
+                name,
+                metadata.as_ref().map_or(name, |m| m.gcloud_config.as_str())
+            );
+            return Ok(());
+        }
         println!("Already on context '{}'.", name);
         // Still show the context info (unless quiet)
         if !quiet {
@@ -54,49 +181,72 @@ pub fn switch_context(name: &str, quiet: bool) -> Result<()> {
         return Ok(());
     }
 
+    warn_if_incomplete(name)?;
+
     // Load metadata to get the actual gcloud config name
     let metadata = load_context_metadata(name)?;
-    let gcloud_config = metadata
-        .as_ref()
-        .map(|m| m.gcloud_config.as_str())
-        .unwrap_or(name); // Fall back to context name for backward compatibility
-
-    // Activate gcloud configuration
-    println!("Switching to context '{}'...", name);
-    let status = Command::new("gcloud")
-        .args(["config", "configurations", "activate", gcloud_config])
-        .output()
-        .context("Failed to execute gcloud command")?;
-
-    if !status.status.success() {
-        let err_msg = String::from_utf8_lossy(&status.stderr);
-        bail!(
-            "gcloud error: {}\n\nHint: The gcloud config '{}' may not exist. \
-            Try running 'gcpx login {}' to re-authenticate.",
-            err_msg.trim(),
-            gcloud_config,
-            name
-        );
-    }
-
-    // Restore ADC credentials
-    let target_adc = get_adc_path()?;
-    let content = fs::read(&stored_adc)?;
-    fs::write(&target_adc, content)?;
 
-    // Switch kubectl context if saved
-    if let Some(ref m) = metadata {
-        if let Some(kctx) = &m.kubectl_context {
-            if switch_kubectl_context(kctx)? {
-                // kubectl switched successfully, will print below
-            }
+    if metadata.as_ref().is_some_and(|m| m.is_dangerous()) {
+        print_danger_banner(name);
+        if !options.force {
+            confirm_dangerous_switch(name)?;
         }
     }
 
-    // Update tracking
-    set_current_tracking(name)?;
+    if !options.print {
+        // A reminder only; leaving needs no confirmation
+        if current != "none"
+            && current != name
+            && load_context_metadata(&current)
+                .ok()
+                .flatten()
+                .is_some_and(|m| m.is_dangerous())
+        {
+            print_leaving_danger_notice(&current);
+        }
+        println!("Switching to context '{}'...", name);
+    }
+    let isolated = load_settings()?.isolated_configs;
+    let before = LiveState::capture(name, isolated, metadata.as_ref(), options)?;
+    if let Err(e) = apply_switch(name, metadata.as_ref(), options, &before, &current) {
+        before.restore();
+        return Err(e.context(format!(
+            "Failed to switch to '{}'; the previous gcloud config, ADC and tracking were restored",
+            name
+        )));
+    }
+    touch_context_metadata(name)?;
 
-    println!("Switched to '{}' successfully!", name);
+    if options.print {
+        println!(
+            "switch: {} -> {} (gcloud config '{}')",
+            current,
+            name,
+            metadata.as_ref().map_or(name, |m| m.gcloud_config.as_str())
+        );
+        return Ok(());
+    }
+    if quiet || current == "none" || current == name {
+        println!("Switched to '{}' successfully!", name);
+    } else {
+        println!("Switched from '{}' to '{}'.", current, name);
+    }
+    if isolated {
+        println!("  Run 'eval \"$(gcpx env)\"' to point this shell's gcloud at it.");
+    }
+    if options.activate_only {
+        println!("  Note: ADC and kubectl were left unchanged (--activate-only).");
+    } else if options.no_adc {
+        println!("  Note: ADC was left unchanged (--no-adc).");
+    } else if let Some(dest) = &options.adc_dest {
+        println!("  Credentials written to {}", dest.display());
+    }
+    if options.no_track {
+        println!(
+            "  Note: tracking still points at '{}' (--no-track).",
+            current
+        );
+    }
     if !quiet {
         if let Some(m) = &metadata {
             if let Some(acc) = &m.account {
@@ -108,24 +258,567 @@ pub fn switch_context(name: &str, quiet: bool) -> Result<()> {
             if let Some(kctx) = &m.kubectl_context {
                 println!("  kubectl: {}", kctx);
             }
+            if let Some(chain) = m.impersonation() {
+                println!("  impersonate: {}", chain);
+            }
+        }
+    }
+
+    // Heuristic nudge: old user credentials may have been revoked by org policy.
+    // Based on file age only, and never blocks the switch.
+    if let Some(adc_type) = metadata.as_ref().and_then(|m| m.adc_type.as_deref()) {
+        if adc_type_expires(adc_type) {
+            let max_age = load_settings()
+                .ok()
+                .and_then(|s| s.adc_max_age_days)
+                .unwrap_or(DEFAULT_ADC_MAX_AGE_DAYS);
+            let stored = credential_store()?.stored_path(name)?;
+            if let Some(age) = stored.as_deref().and_then(file_age_days) {
+                if age > max_age {
+                    println!(
+                        "  Tip: these credentials were saved {} days ago. If they stop working, \
+                        run 'gcpx login {}'.",
+                        age, name
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Performs the state-changing steps of a switch: gcloud config and ADC (or the
+/// isolated config directory), kubectl, then tracking.
+fn apply_switch(
+    name: &str,
+    metadata: Option<&ContextMetadata>,
+    options: &SwitchOptions,
+    before: &LiveState,
+    current: &str,
+) -> Result<()> {
+    // The live gcloud config is only captured when using the shared config directory
+    let restore_standard_adc = !options.skip_adc() && options.adc_dest.is_none();
+    match &before.gcloud_config {
+        Some(active_config) => {
+            activate_gcloud_config(
+                name,
+                metadata,
+                !restore_standard_adc,
+                options.restore_config,
+                active_config,
+            )?;
+        }
+        None => {
+            // Each context owns its gcloud config directory; the shell picks it up
+            // through CLOUDSDK_CONFIG (see `gcpx env`), so shared state is untouched
+            let gcloud_dir = get_context_gcloud_dir(name)?;
+            if !gcloud_dir.exists() {
+                seed_context_gcloud_dir(
+                    name,
+                    &metadata.cloned().unwrap_or_default(),
+                    !options.skip_adc(),
+                )?;
+            }
+        }
+    }
+
+    if let Some(dest) = options.adc_dest.as_deref().filter(|_| !options.skip_adc()) {
+        write_atomic_private(dest, &restored_adc(name)?)
+            .with_context(|| format!("Failed to write credentials to {:?}", dest))?;
+    }
+
+    // Switch kubectl context if saved
+    if let Some(kctx) = metadata
+        .and_then(|m| m.kubectl_context.as_deref())
+        .filter(|_| !options.activate_only)
+    {
+        // Hidden in quiet and print modes, like the rest of the context details
+        let shown = if options.quiet || options.print {
+            "(hidden)"
+        } else {
+            kctx
+        };
+        if let Some(message) = switch_kubectl_context(kctx).failure_message(shown) {
+            eprintln!("Warning: {}.", message);
+        }
+    }
+
+    // Update tracking, remembering where we came from for `switch -`
+    if !options.no_track {
+        if current != "none" && current != name {
+            set_previous_tracking(current)?;
         }
+        set_current_tracking(name)?;
     }
     Ok(())
 }
 
+/// The live state a switch changes, captured so a failed switch can put it back.
+struct LiveState {
+    /// Active gcloud configuration (not captured with isolated configs)
+    gcloud_config: Option<String>,
+    /// The ADC file the switch writes (the standard location or `adc_dest`)
+    adc_path: PathBuf,
+    /// Contents of that file, if there was one
+    adc: Option<Vec<u8>>,
+    /// Only captured when the switch changes the kubectl context
+    kubectl_context: Option<String>,
+    /// The gcloud configuration file a config snapshot may be written over,
+    /// and its contents, if any (only captured when the context has a snapshot)
+    gcloud_config_file: Option<(PathBuf, Option<Vec<u8>>)>,
+    /// The `.current` and `.previous` tracking files and their contents, if any
+    tracking: Vec<(PathBuf, Option<Vec<u8>>)>,
+}
+
+impl LiveState {
+    fn capture(
+        name: &str,
+        isolated: bool,
+        metadata: Option<&ContextMetadata>,
+        options: &SwitchOptions,
+    ) -> Result<Self> {
+        let adc_path = match options.adc_dest.as_deref() {
+            Some(dest) => dest.to_path_buf(),
+            None => get_adc_path()?,
+        };
+        let store_dir = get_store_dir()?;
+        let tracking = [".current", ".previous"]
+            .iter()
+            .map(|file| {
+                let path = store_dir.join(file);
+                let content = fs::read(&path).ok();
+                (path, content)
+            })
+            .collect();
+        let gcloud_config_file = if !isolated && get_context_config_snapshot_path(name)?.is_file() {
+            let config = metadata.map_or(name, |m| m.gcloud_config.as_str());
+            let path = get_gcloud_config_file(config)?;
+            let content = fs::read(&path).ok();
+            Some((path, content))
+        } else {
+            None
+        };
+        let switches_kubectl =
+            !options.activate_only && metadata.is_some_and(|m| m.kubectl_context.is_some());
+        Ok(LiveState {
+            gcloud_config: if isolated {
+                None
+            } else {
+                Some(get_current_gcloud_config()?)
+            },
+            adc: fs::read(&adc_path).ok(),
+            adc_path,
+            kubectl_context: if switches_kubectl {
+                get_current_kubectl_context()
+            } else {
+                None
+            },
+            gcloud_config_file,
+            tracking,
+        })
+    }
+
+    /// Best-effort restore; errors are ignored so the original failure is reported.
+    ///
+    /// Properties replayed into the target configuration are not undone, but
+    /// that configuration is no longer active.
+    fn restore(&self) {
+        if let Some(config) = &self.gcloud_config {
+            let _ = gcloud_command()
+                .args(["config", "configurations", "activate", config])
+                .output();
+            invalidate_gcloud_cache();
+        }
+        if let Some((path, content)) = &self.gcloud_config_file {
+            restore_file(path, content.as_deref());
+        }
+        restore_file(&self.adc_path, self.adc.as_deref());
+        if let Some(kctx) = &self.kubectl_context {
+            let _ = switch_kubectl_context(kctx);
+        }
+        for (path, content) in &self.tracking {
+            restore_file(path, content.as_deref());
+        }
+    }
+}
+
+/// Puts back a file's captured contents, or removes it if there was none.
+/// Errors are ignored, as in [`LiveState::restore`].
+fn restore_file(path: &Path, content: Option<&[u8]>) {
+    let _ = match content {
+        Some(content) => fs::write(path, content),
+        None if path.is_file() => fs::remove_file(path),
+        None => Ok(()),
+    };
+}
+
+/// Activates a context's gcloud configuration in the shared gcloud config
+/// directory (unless it is already `active_config`), replays its captured
+/// properties and restores its ADC (unless `no_adc` is set).
+///
+/// A config snapshot saved with the context recreates the configuration if it
+/// is missing. An existing configuration is only overwritten with
+/// `restore_config`; otherwise a note says when it differs from the snapshot.
+fn activate_gcloud_config(
+    name: &str,
+    metadata: Option<&ContextMetadata>,
+    no_adc: bool,
+    restore_config: bool,
+    active_config: &str,
+) -> Result<()> {
+    let gcloud_config = metadata.map(|m| m.gcloud_config.as_str()).unwrap_or(name); // Fall back to context name for backward compatibility
+    invalidate_gcloud_cache();
+
+    let snapshot_path = get_context_config_snapshot_path(name)?;
+    if snapshot_path.is_file() {
+        let snapshot = fs::read(&snapshot_path)?;
+        let config_file = get_gcloud_config_file(gcloud_config)?;
+        match fs::read(&config_file) {
+            Ok(live) if live == snapshot => {}
+            Ok(_) if !restore_config => eprintln!(
+                "Note: gcloud config '{}' has changed since its snapshot was saved; \
+                pass --restore-config to put the snapshot back.",
+                gcloud_config
+            ),
+            _ => {
+                if let Some(parent) = config_file.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                write_atomic(&config_file, &snapshot)?;
+                debug(format_args!(
+                    "restored gcloud config '{}' from its snapshot",
+                    gcloud_config
+                ));
+            }
+        }
+    }
+
+    if gcloud_config == active_config {
+        debug(format_args!(
+            "gcloud config '{}' is already active; skipping activation",
+            gcloud_config
+        ));
+    } else {
+        let status = gcloud_command()
+            .args(["config", "configurations", "activate", gcloud_config])
+            .output()
+            .context("Failed to execute gcloud command")?;
+
+        if !status.status.success() {
+            let err_msg = String::from_utf8_lossy(&status.stderr);
+            bail!(GcpxError::GcloudFailed(format!(
+                "{}\n\nHint: The gcloud config '{}' may not exist. \
+                Try running 'gcpx login {}' to re-authenticate.",
+                err_msg.trim(),
+                gcloud_config,
+                name
+            )));
+        }
+    }
+
+    // Replay captured gcloud properties into the activated configuration
+    if let Some(m) = metadata {
+        for (key, value) in &m.gcloud_properties {
+            set_gcloud_property(key, value, None)?;
+        }
+        match m.impersonation() {
+            Some(chain) => set_gcloud_property("auth/impersonate_service_account", &chain, None)?,
+            // Don't keep impersonating for a context that doesn't. The file
+            // check saves a gcloud call in the common case of nothing to unset.
+            None => {
+                let config_file = get_gcloud_config_file(gcloud_config)?;
+                if fs::read_to_string(&config_file)
+                    .is_ok_and(|ini| ini.contains("impersonate_service_account"))
+                {
+                    unset_gcloud_property("auth/impersonate_service_account", None)?;
+                }
+            }
+        }
+    }
+
+    // Restore ADC credentials
+    if !no_adc {
+        let target_adc = get_adc_path()?;
+        let content = restored_adc(name)?;
+        fs::write(&target_adc, content)?;
+    }
+    Ok(())
+}
+
+/// Asks the user to type the context name before switching to a dangerous context.
+fn confirm_dangerous_switch(name: &str) -> Result<()> {
+    require_input(&format!(
+        "confirm the switch to '{}' by re-running with --force",
+        name
+    ))?;
+    let typed: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!("Type '{}' to confirm", name))
+        .allow_empty(true)
+        .interact_text()?;
+    if typed.trim() != name {
+        bail!(GcpxError::ConfirmationMismatch(name.to_string()));
+    }
+    Ok(())
+}
+
+/// Options controlling the interactive context menu.
+#[derive(Debug, Clone, Default)]
+pub struct InteractiveOptions {
+    /// Hide sensitive details (account, project, etc.) after switching
+    pub quiet: bool,
+    /// Only offer contexts whose saved account contains this substring
+    pub account: Option<String>,
+    /// With more candidates than this, first offer only the most recently
+    /// used ones plus a "show all" entry
+    pub limit: Option<usize>,
+    /// How the picked context is switched (`quiet` above takes precedence)
+    pub switch: SwitchOptions,
+}
+
 /// Shows an interactive menu to select and switch contexts.
 pub fn interactive_switch(quiet: bool) -> Result<()> {
-    let contexts = list_contexts()?;
-    if contexts.is_empty() {
-        println!("No contexts found. Create one with 'gcpx save <name>'");
-        return Ok(());
+    interactive_switch_with_options(&InteractiveOptions {
+        quiet,
+        ..Default::default()
+    })
+}
+
+/// Returns the context whose saved project is `project`.
+///
+/// Fails if no context or more than one context has that project, listing
+/// the candidates in the latter case.
+pub fn context_for_project(project: &str) -> Result<String> {
+    let matches: Vec<String> = list_contexts_with_metadata()?
+        .into_iter()
+        .filter(|(_, m)| m.as_ref().and_then(|m| m.project.as_deref()) == Some(project))
+        .map(|(name, _)| name)
+        .collect();
+    match matches.as_slice() {
+        [name] => Ok(name.clone()),
+        [] => bail!(GcpxError::NoContextForProject(project.to_string())),
+        _ => bail!(GcpxError::AmbiguousProject(project.to_string(), matches)),
     }
+}
 
+/// Returns the contexts the interactive menu offers for `options`.
+///
+/// With `account` set, contexts without metadata are left out.
+pub fn interactive_candidates(options: &InteractiveOptions) -> Result<Vec<String>> {
+    Ok(match &options.account {
+        Some(filter) => list_contexts_with_metadata()?
+            .into_iter()
+            .filter(|(_, m)| {
+                m.as_ref()
+                    .and_then(|m| m.account.as_deref())
+                    .is_some_and(|a| a.contains(filter.as_str()))
+            })
+            .map(|(name, _)| name)
+            .collect(),
+        None => list_contexts()?,
+    })
+}
+
+/// Returns the `limit` most recently used of `contexts`, most recent first.
+///
+/// Contexts that were never used (or have no metadata) come last, by name.
+pub fn most_recent(contexts: &[String], limit: usize) -> Result<Vec<String>> {
+    let mut recent: Vec<(String, Option<u64>)> = list_contexts_with_metadata()?
+        .into_iter()
+        .filter(|(name, _)| contexts.contains(name))
+        .map(|(name, m)| (name, m.and_then(|m| m.last_used)))
+        .collect();
+    recent.sort_by_key(|(_, last_used)| std::cmp::Reverse(*last_used));
+    Ok(recent
+        .into_iter()
+        .take(limit)
+        .map(|(name, _)| name)
+        .collect())
+}
+
+/// Shows an interactive menu to select and switch contexts.
+///
+/// With `limit` set and more candidates than that, the menu first lists the
+/// most recently used contexts and a final entry that shows all of them.
+///
+/// The selection is re-checked before switching: if the context was deleted
+/// (e.g. from another terminal) while the menu was open, the menu is shown
+/// again with the refreshed list.
+pub fn interactive_switch_with_options(options: &InteractiveOptions) -> Result<()> {
+    loop {
+        let contexts = interactive_candidates(options)?;
+        // With an empty store the menu holds just the save and login actions
+        if contexts.is_empty() {
+            match &options.account {
+                Some(filter) => println!("No contexts with an account matching '{}'.", filter),
+                None if !input_allowed() => {
+                    println!("No contexts found. Create one with 'gcpx save <name>'")
+                }
+                None => {}
+            }
+            if !input_allowed() {
+                return Ok(());
+            }
+        }
+        require_input("pass a context name, e.g. 'gcpx switch <name>'")?;
+
+        let selected = match select_context(&contexts, options.limit, options.quiet)? {
+            MenuChoice::Context(name) => name,
+            MenuChoice::SaveNew => {
+                return save_context(&prompt_new_context_name()?, options.quiet);
+            }
+            MenuChoice::LoginNew => {
+                return login_context(&prompt_new_context_name()?, options.quiet);
+            }
+        };
+        if !context_exists(&selected)? {
+            println!(
+                "Context '{}' no longer exists (it was removed while the menu was open). \
+                Showing the updated list.",
+                selected
+            );
+            continue;
+        }
+        return switch_context_with_options(
+            &selected,
+            &SwitchOptions {
+                quiet: options.quiet,
+                ..options.switch.clone()
+            },
+        );
+    }
+}
+
+/// What was picked in the interactive menu.
+enum MenuChoice {
+    Context(String),
+    /// Save the current gcloud/ADC state as a new context
+    SaveNew,
+    /// Log in and save the result as a new context
+    LoginNew,
+}
+
+/// Entries listed after the contexts in every menu.
+const MENU_ACTIONS: [&str; 2] = [
+    "+ Save current as new context...",
+    "\u{21bb} Login to new context...",
+];
+
+/// Maps a selection past the first `listed` entries to a menu action.
+fn menu_action(selection: usize, listed: usize) -> Option<MenuChoice> {
+    match selection.checked_sub(listed)? {
+        0 => Some(MenuChoice::SaveNew),
+        1 => Some(MenuChoice::LoginNew),
+        _ => None,
+    }
+}
+
+/// Renders a context's menu entry from a `menu_format` template.
+///
+/// `{name}`, `{account}` and `{project}` are replaced with the context's
+/// values (empty if unknown), and `{active}` with `*` for the current context
+/// (empty otherwise). Without metadata the entry is just the name.
+pub fn render_menu_item(
+    template: &str,
+    name: &str,
+    metadata: Option<&ContextMetadata>,
+    active: bool,
+) -> String {
+    let Some(metadata) = metadata else {
+        return name.to_string();
+    };
+    template
+        .replace("{name}", name)
+        .replace("{account}", metadata.account.as_deref().unwrap_or(""))
+        .replace("{project}", metadata.project.as_deref().unwrap_or(""))
+        .replace("{active}", if active { "*" } else { "" })
+        .trim_end()
+        .to_string()
+}
+
+/// Returns the menu entries for `names`, rendered with the `menu_format`
+/// setting if one is set. With `quiet`, entries are the plain names so no
+/// account or project is shown.
+fn menu_labels(names: &[String], quiet: bool) -> Result<Vec<String>> {
+    let template = match load_settings()?.menu_format {
+        Some(template) if !quiet => template,
+        _ => return Ok(names.to_vec()),
+    };
+    let metadata: HashMap<String, Option<ContextMetadata>> =
+        list_contexts_with_metadata()?.into_iter().collect();
+    let current = get_current_tracking();
+    Ok(names
+        .iter()
+        .map(|name| {
+            let context = metadata.get(name).and_then(Option::as_ref);
+            render_menu_item(&template, name, context, *name == current)
+        })
+        .collect())
+}
+
+/// Shows the menu (limited to recent contexts first, if `limit` applies),
+/// followed by the save and login actions, and returns the choice.
+fn select_context(contexts: &[String], limit: Option<usize>, quiet: bool) -> Result<MenuChoice> {
+    if let Some(limit) = limit.filter(|&limit| contexts.len() > limit) {
+        let mut recent = most_recent(contexts, limit)?;
+        let mut items = menu_labels(&recent, quiet)?;
+        items.push(format!("Show all ({} contexts)...", contexts.len()));
+        items.extend(MENU_ACTIONS.map(String::from));
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Select GCP Context (most recent)")
+            .default(0)
+            .items(&items)
+            .interact()?;
+        if selection < recent.len() {
+            return Ok(MenuChoice::Context(recent.swap_remove(selection)));
+        }
+        if let Some(action) = menu_action(selection, recent.len() + 1) {
+            return Ok(action);
+        }
+    }
+
+    let mut items = menu_labels(contexts, quiet)?;
+    items.extend(MENU_ACTIONS.map(String::from));
     let selection = Select::with_theme(&ColorfulTheme::default())
         .with_prompt("Select GCP Context")
         .default(0)
-        .items(&contexts)
+        .items(&items)
         .interact()?;
+    Ok(match menu_action(selection, contexts.len()) {
+        Some(action) => action,
+        None => MenuChoice::Context(contexts[selection].clone()),
+    })
+}
 
-    switch_context(&contexts[selection], quiet)
+/// Asks for the name of a context to create, rejecting invalid and taken names.
+fn prompt_new_context_name() -> Result<String> {
+    let name: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("New context name")
+        .validate_with(|input: &String| -> Result<(), String> {
+            validate_context_name(input).map_err(|e| e.to_string())?;
+            match context_exists(input) {
+                Ok(false) => Ok(()),
+                Ok(true) => Err(format!("Context '{}' already exists.", input)),
+                Err(e) => Err(e.to_string()),
+            }
+        })
+        .interact_text()?;
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn menu_actions_follow_listed_contexts() {
+        assert!(menu_action(0, 2).is_none());
+        assert!(menu_action(1, 2).is_none());
+        assert!(matches!(menu_action(2, 2), Some(MenuChoice::SaveNew)));
+        assert!(matches!(menu_action(3, 2), Some(MenuChoice::LoginNew)));
+        assert!(menu_action(4, 2).is_none());
+        // An empty store lists only the actions
+        assert!(matches!(menu_action(0, 0), Some(MenuChoice::SaveNew)));
+        assert!(matches!(menu_action(1, 0), Some(MenuChoice::LoginNew)));
+        assert_eq!(MENU_ACTIONS.len(), 2);
+    }
 }
@@ -0,0 +1,18 @@
+//! Touch command implementation - mark a context as recently used.
+
+use anyhow::{Result, bail};
+
+use crate::config::{context_exists, touch_context_metadata, validate_context_name};
+use crate::error::GcpxError;
+
+/// Updates a context's `last_used` timestamp without switching to it.
+///
+/// Useful when a context was used indirectly (e.g. via `gcpx run`) and should
+/// still count as recent.
+pub fn touch_context(name: &str) -> Result<()> {
+    validate_context_name(name)?;
+    if !context_exists(name)? {
+        bail!(GcpxError::ContextNotFound(name.to_string()));
+    }
+    touch_context_metadata(name)
+}
@@ -0,0 +1,84 @@
+//! Tree command implementation - show what gcpx stores where.
+
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+
+use crate::config::{context_problems, get_store_dir};
+
+/// Prints the store directory layout.
+pub fn show_tree() -> Result<()> {
+    print!("{}", render_tree()?);
+    Ok(())
+}
+
+/// Renders the store directory as a tree, with each file's size and permissions.
+///
+/// Top-level directories are contexts, and are annotated with anomalies such
+/// as missing credentials or unreadable metadata.
+pub fn render_tree() -> Result<String> {
+    let store_dir = get_store_dir()?;
+    let mut out = format!("{}\n", store_dir.display());
+    if !store_dir.exists() {
+        out.push_str("  (does not exist yet)\n");
+        return Ok(out);
+    }
+    walk(&store_dir, "", true, &mut out)?;
+    Ok(out)
+}
+
+/// Appends the entries of `dir` to `out`, one line each.
+fn walk(dir: &Path, prefix: &str, top_level: bool, out: &mut String) -> Result<()> {
+    let mut entries: Vec<_> = fs::read_dir(dir)?.collect::<Result<_, _>>()?;
+    entries.sort_by_key(|e| e.file_name());
+
+    for (i, entry) in entries.iter().enumerate() {
+        let last = i + 1 == entries.len();
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let path = entry.path();
+        let meta = fs::symlink_metadata(&path)?;
+
+        if meta.is_dir() {
+            let mut line = format!("{}{}{}/", prefix, branch, name);
+            if top_level && !name.starts_with('.') {
+                for anomaly in context_problems(&name).unwrap_or_default() {
+                    line.push_str(&format!("  [{}]", anomaly));
+                }
+            }
+            out.push_str(&line);
+            out.push('\n');
+            walk(&path, &format!("{}{}", prefix, indent), false, out)?;
+        } else {
+            out.push_str(&format!(
+                "{}{}{} ({})\n",
+                prefix,
+                branch,
+                name,
+                describe_file(&meta)
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Returns a short description of a file's size and (on Unix) permissions.
+fn describe_file(meta: &fs::Metadata) -> String {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        format!(
+            "{} B, {:04o}",
+            meta.len(),
+            meta.permissions().mode() & 0o7777
+        )
+    }
+    #[cfg(not(unix))]
+    {
+        format!("{} B", meta.len())
+    }
+}
@@ -0,0 +1,108 @@
+//! Undo command implementation - restore the last deleted or overwritten context.
+
+use anyhow::{Context, Result, bail};
+use std::fs;
+
+use crate::config::{
+    gcloud_config_exists, get_context_dir, get_current_tracking, get_gcloud_config_file,
+    get_previous_tracking, get_store_dir, invalidate_gcloud_cache, set_current_tracking,
+    set_previous_tracking,
+};
+use crate::error::GcpxError;
+use crate::trash::{OpKind, clear_last_op, get_trash_dir, load_last_op};
+
+/// Undoes the most recent destructive operation (`delete`, or an overwriting
+/// `save` or import).
+///
+/// The context directory is restored from the trash, and tracking is rewritten
+/// where the operation changed it:
+/// - after a delete, the previous context pointer is restored if it was cleared,
+///   and a gcloud configuration removed with `--gcloud-config` is recreated from
+///   its saved properties file unless gcloud already has one by that name
+/// - after an overwriting save or import, the current context is restored if
+///   it still points at the replaced context
+pub fn undo_last_op() -> Result<()> {
+    let Some(op) = load_last_op()? else {
+        bail!(GcpxError::NothingToUndo);
+    };
+
+    let trash_path = get_trash_dir()?.join(&op.trash_entry);
+    if !trash_path.exists() {
+        clear_last_op()?;
+        bail!(GcpxError::TrashEntryMissing(trash_path, op.name));
+    }
+
+    let context_dir = get_context_dir(&op.name)?;
+    match op.op {
+        OpKind::Delete => {
+            if context_dir.exists() {
+                bail!(GcpxError::ContextExists(op.name.clone()));
+            }
+            fs::rename(&trash_path, &context_dir)
+                .with_context(|| format!("Failed to restore {:?}", context_dir))?;
+            if op.previous != "none" && get_previous_tracking() == "none" {
+                set_previous_tracking(&op.previous)?;
+            }
+            println!("Restored deleted context '{}'.", op.name);
+            if let Some(config) = &op.gcloud_config {
+                restore_gcloud_config(config, op.gcloud_config_entry.as_deref())?;
+            }
+        }
+        OpKind::Save | OpKind::Import => {
+            if context_dir.exists() {
+                fs::remove_dir_all(&context_dir).with_context(|| {
+                    format!("Failed to remove context directory: {:?}", context_dir)
+                })?;
+            }
+            fs::rename(&trash_path, &context_dir)
+                .with_context(|| format!("Failed to restore {:?}", context_dir))?;
+            if get_current_tracking() == op.name && op.current != op.name {
+                if op.current == "none" {
+                    fs::remove_file(get_store_dir()?.join(".current"))?;
+                } else {
+                    set_current_tracking(&op.current)?;
+                }
+            }
+            println!(
+                "Restored context '{}' to its state before the last {}.",
+                op.name,
+                if op.op == OpKind::Save {
+                    "save"
+                } else {
+                    "import"
+                }
+            );
+        }
+    }
+
+    clear_last_op()
+}
+
+/// Recreates a gcloud configuration deleted along with its context from the
+/// copy of its properties file in the trash.
+fn restore_gcloud_config(config: &str, entry: Option<&str>) -> Result<()> {
+    if gcloud_config_exists(config)? {
+        println!(
+            "gcloud configuration '{}' already exists; left as is.",
+            config
+        );
+        return Ok(());
+    }
+    let Some(entry) = entry else {
+        println!(
+            "gcloud configuration '{}' was not restored (it had no properties file). \
+            Recreate it with 'gcloud config configurations create {}'.",
+            config, config
+        );
+        return Ok(());
+    };
+    let file = get_gcloud_config_file(config)?;
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(get_trash_dir()?.join(entry), &file)
+        .with_context(|| format!("Failed to restore {:?}", file))?;
+    invalidate_gcloud_cache();
+    println!("Restored gcloud configuration '{}'.", config);
+    Ok(())
+}
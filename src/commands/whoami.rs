@@ -0,0 +1,128 @@
+//! Whoami command implementation - report the identity actually in effect.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::fs;
+
+use crate::config::{
+    adc_equivalent, adc_identity, adc_type_of, get_adc_path, get_current_context_checked,
+    get_current_gcloud_account, get_current_gcloud_config, get_current_gcloud_project,
+    load_context_metadata, restored_adc,
+};
+use crate::json::JsonEnvelope;
+
+/// The live identity, compared with the current context (body of `whoami --json`).
+#[derive(Debug, Default, Serialize)]
+struct WhoamiReport {
+    gcloud_config: String,
+    /// Account of the active gcloud configuration (null if unset)
+    account: Option<String>,
+    /// Project of the active gcloud configuration (null if unset)
+    project: Option<String>,
+    /// `type` of the live ADC file (null if there is none)
+    adc_type: Option<String>,
+    /// Identity named in the live ADC file (null if it doesn't name one)
+    adc_identity: Option<String>,
+    /// `quota_project_id` of the live ADC file
+    adc_quota_project: Option<String>,
+    /// Ways the live state differs from the current context's metadata
+    mismatches: Vec<String>,
+}
+
+/// Prints the account and project in effect right now, read from gcloud and
+/// the live ADC file rather than from gcpx's tracking.
+///
+/// This is the ground-truth counterpart to `gcpx current`: any difference from
+/// the current context's saved metadata is flagged. With `json`, the report is
+/// printed as a versioned JSON object instead.
+pub fn whoami(json: bool) -> Result<()> {
+    let mut report = WhoamiReport {
+        gcloud_config: get_current_gcloud_config()?,
+        account: get_current_gcloud_account()?,
+        project: get_current_gcloud_project()?,
+        ..Default::default()
+    };
+
+    let live_adc = fs::read(get_adc_path()?).ok();
+    if let Some(content) = &live_adc {
+        report.adc_type = adc_type_of(content);
+        report.adc_identity = adc_identity(content);
+        report.adc_quota_project = serde_json::from_slice::<serde_json::Value>(content)
+            .ok()
+            .and_then(|v| v.get("quota_project_id")?.as_str().map(str::to_string));
+    }
+
+    let current = get_current_context_checked();
+    if let Some(current) = &current {
+        let metadata = load_context_metadata(current)?.unwrap_or_default();
+        if metadata.account.is_some() && metadata.account != report.account {
+            report.mismatches.push(format!(
+                "gcloud account is {}, context '{}' expects {}",
+                report.account.as_deref().unwrap_or("(unset)"),
+                current,
+                metadata.account.as_deref().unwrap_or_default()
+            ));
+        }
+        if metadata.project.is_some() && metadata.project != report.project {
+            report.mismatches.push(format!(
+                "gcloud project is {}, context '{}' expects {}",
+                report.project.as_deref().unwrap_or("(unset)"),
+                current,
+                metadata.project.as_deref().unwrap_or_default()
+            ));
+        }
+        if let Ok(stored) = restored_adc(current) {
+            match &live_adc {
+                Some(live) if adc_equivalent(&stored, live) => {}
+                Some(_) => report.mismatches.push(format!(
+                    "live ADC differs from the credentials saved for '{}'",
+                    current
+                )),
+                None => report.mismatches.push("no live ADC file".to_string()),
+            }
+        }
+    }
+
+    if json {
+        return JsonEnvelope::new(report).print();
+    }
+
+    println!("gcloud config: {}", report.gcloud_config);
+    println!(
+        "account: {}",
+        report.account.as_deref().unwrap_or("(unset)")
+    );
+    println!(
+        "project: {}",
+        report.project.as_deref().unwrap_or("(unset)")
+    );
+    match &report.adc_type {
+        Some(adc_type) => {
+            print!("ADC: {}", adc_type);
+            if let Some(identity) = &report.adc_identity {
+                print!(" as {}", identity);
+            }
+            if let Some(quota) = &report.adc_quota_project {
+                print!(" (quota project {})", quota);
+            }
+            println!();
+        }
+        None if live_adc.is_some() => println!("ADC: (unrecognized file)"),
+        None => println!("ADC: (none)"),
+    }
+
+    match &current {
+        None => println!("\nNo current context to compare against."),
+        Some(current) if report.mismatches.is_empty() => {
+            println!("\nMatches context '{}'.", current)
+        }
+        Some(current) => {
+            println!();
+            for mismatch in &report.mismatches {
+                println!("Warning: {}", mismatch);
+            }
+            println!("Run 'gcpx switch {}' to realign.", current);
+        }
+    }
+    Ok(())
+}
@@ -9,9 +9,29 @@
 
 use anyhow::{Context, Result, bail};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::credstore::credential_store;
+use crate::error::GcpxError;
+use crate::settings::load_settings;
+
+/// Process-wide store directory override (set by the `--store-dir` flag).
+static STORE_DIR_OVERRIDE: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Set while seeding isolated gcloud config directories must not run the
+/// gcloud CLI (see [`set_isolated_gcloud_cli`]).
+static ISOLATED_WITHOUT_GCLOUD_CLI: AtomicBool = AtomicBool::new(false);
+
+/// The store and gcloud directories last checked by [`check_store_dir`], so
+/// the check runs once per process rather than on every path lookup.
+static CHECKED_STORE_DIR: Mutex<Option<(PathBuf, PathBuf)>> = Mutex::new(None);
 
 /// Validates a context name to prevent directory traversal and invalid names.
 ///
@@ -23,25 +43,52 @@ use std::path::PathBuf;
 /// - Start with a dot (reserved for internal files like `.current`)
 pub fn validate_context_name(name: &str) -> Result<()> {
     if name.is_empty() {
-        bail!("Context name cannot be empty.");
+        bail!(GcpxError::InvalidName(
+            "Context name cannot be empty.".to_string()
+        ));
     }
     if name == "." || name == ".." {
-        bail!("Context name cannot be '.' or '..'.");
+        bail!(GcpxError::InvalidName(
+            "Context name cannot be '.' or '..'.".to_string()
+        ));
     }
     if name.starts_with('.') {
-        bail!("Context name cannot start with a dot.");
+        bail!(GcpxError::InvalidName(
+            "Context name cannot start with a dot.".to_string()
+        ));
     }
     if name.contains('/') || name.contains('\\') {
-        bail!("Context name cannot contain path separators ('/' or '\\').");
+        bail!(GcpxError::InvalidName(
+            "Context name cannot contain path separators ('/' or '\\').".to_string()
+        ));
     }
     if name.chars().any(|c| c.is_ascii_control()) {
-        bail!("Context name cannot contain control characters.");
+        bail!(GcpxError::InvalidName(
+            "Context name cannot contain control characters.".to_string()
+        ));
+    }
+    Ok(())
+}
+
+/// Validates an environment variable name: ASCII letters, digits and
+/// underscores, not starting with a digit (`[A-Za-z_][A-Za-z0-9_]*`).
+///
+/// Names are written unquoted into shell `export` lines, so anything else
+/// could inject shell syntax.
+pub fn validate_env_key(key: &str) -> Result<()> {
+    let mut chars = key.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        bail!(GcpxError::InvalidEnvKey(key.to_string()));
     }
     Ok(())
 }
 
 /// Metadata stored alongside each context's credentials.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ContextMetadata {
     /// The gcloud configuration name that was active when saved
     pub gcloud_config: String,
@@ -52,8 +99,137 @@ pub struct ContextMetadata {
     /// The kubectl context that was active when saved (optional)
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub kubectl_context: Option<String>,
+    /// Free-form notes about the context (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Additional gcloud config properties (`section/key` -> value) replayed on switch
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub gcloud_properties: BTreeMap<String, String>,
+    /// When the context was last used, as seconds since the Unix epoch (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<u64>,
+    /// The `type` field of the saved ADC (e.g. `authorized_user`, `service_account`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adc_type: Option<String>,
+    /// User-defined labels for grouping and filtering (optional)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Service accounts to impersonate with this context's credentials, as a
+    /// delegation chain: the last one is the target, the others delegates
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        with = "impersonation_chain"
+    )]
+    pub impersonate: Vec<String>,
+    /// Where the saved credentials were read from (see `ADC_SOURCE_*`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adc_source: Option<String>,
+    /// Whether switching to this context requires confirmation
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub danger: bool,
+    /// Organization the account belongs to, derived from its email domain (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org: Option<String>,
+    /// Identity the saved credentials name (see [`adc_identity`]), which can
+    /// differ from `account` (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adc_identity: Option<String>,
+    /// Commands `run` refuses in this context without `--override-policy`:
+    /// a program name, optionally followed by words its arguments contain in
+    /// order (e.g. `terraform apply`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub run_denylist: Vec<String>,
+    /// If non-empty, the only commands `run` allows in this context without
+    /// `--override-policy`: a program name, optionally followed by its leading
+    /// arguments with flags skipped
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub run_allowlist: Vec<String>,
+}
+
+/// Tag that marks a context as dangerous without setting `danger` explicitly.
+pub const DANGER_TAG: &str = "prod";
+
+impl ContextMetadata {
+    /// Returns true if the context is flagged `danger` or tagged [`DANGER_TAG`].
+    pub fn is_dangerous(&self) -> bool {
+        self.danger || self.tags.iter().any(|t| t == DANGER_TAG)
+    }
+
+    /// Returns the impersonation chain in gcloud's comma-separated form
+    /// (`auth/impersonate_service_account`), or None if nothing is impersonated.
+    pub fn impersonation(&self) -> Option<String> {
+        (!self.impersonate.is_empty()).then(|| self.impersonate.join(","))
+    }
 }
 
+/// Stores a single impersonated service account as a plain string, as older
+/// versions did, and a delegation chain as a list. Both forms are read, as is
+/// a comma-separated string.
+mod impersonation_chain {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Chain {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    pub fn serialize<S: Serializer>(chain: &[String], serializer: S) -> Result<S::Ok, S::Error> {
+        match chain {
+            [one] => one.serialize(serializer),
+            _ => chain.serialize(serializer),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<String>, D::Error> {
+        Ok(match Option::<Chain>::deserialize(deserializer)? {
+            Some(Chain::One(s)) => s
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+                .collect(),
+            Some(Chain::Many(chain)) => chain,
+            None => Vec::new(),
+        })
+    }
+}
+
+/// Checks that `email` looks like a service account
+/// (`name@project.iam.gserviceaccount.com` and the like).
+pub fn validate_service_account(email: &str) -> Result<()> {
+    let valid = email.split_once('@').is_some_and(|(name, domain)| {
+        !name.is_empty()
+            && !name.contains(char::is_whitespace)
+            && domain.ends_with(".gserviceaccount.com")
+    });
+    if !valid {
+        bail!(GcpxError::InvalidServiceAccount(email.to_string()));
+    }
+    Ok(())
+}
+
+/// Returns the domain of an account email (`alice@example.com` -> `example.com`).
+pub fn account_domain(account: &str) -> Option<String> {
+    account
+        .rsplit_once('@')
+        .map(|(_, domain)| domain.to_ascii_lowercase())
+        .filter(|domain| !domain.is_empty())
+}
+
+/// gcloud config properties captured by `save --all-properties` by default.
+/// The `capture_properties` setting adds to this list.
+pub const DEFAULT_CAPTURED_PROPERTIES: &[&str] = &[
+    "compute/region",
+    "compute/zone",
+    "core/account",
+    "core/project",
+];
+
 /// Returns the user's home directory.
 pub fn get_home() -> Result<PathBuf> {
     dirs::home_dir().context("Could not find home directory")
@@ -68,24 +244,159 @@ pub fn get_gcloud_dir() -> Result<PathBuf> {
     Ok(get_home()?.join(".config").join("gcloud"))
 }
 
+/// Overrides the store directory for the rest of the process.
+///
+/// Takes precedence over `GCPX_HOME` and the `store_dir` setting.
+/// Passing `None` removes the override.
+pub fn set_store_dir_override(path: Option<&Path>) {
+    let mut guard = STORE_DIR_OVERRIDE
+        .write()
+        .unwrap_or_else(|e| e.into_inner());
+    *guard = path.map(Path::to_path_buf);
+}
+
+/// Controls whether [`seed_context_gcloud_dir`] hands credentials and
+/// properties to the gcloud CLI (the default). The self-test turns this off,
+/// so it runs without gcloud.
+pub fn set_isolated_gcloud_cli(enabled: bool) {
+    ISOLATED_WITHOUT_GCLOUD_CLI.store(!enabled, Ordering::Relaxed);
+}
+
 /// Returns the gcpx storage directory (~/.config/gcpx).
 /// Creates the directory if it doesn't exist.
-/// Can be overridden with GCPX_HOME environment variable for testing.
+///
+/// Resolution order: `--store-dir` override, then the GCPX_HOME environment
+/// variable, then the `store_dir` setting, then the default location.
+///
+/// Fails with [`GcpxError::StoreIsGcloudDir`] if the directory is, or
+/// contains, gcloud's configuration directory, or holds gcloud's ADC file,
+/// since gcloud's directories would then be treated (and deleted) as contexts.
 pub fn get_store_dir() -> Result<PathBuf> {
-    let path = if let Ok(dir) = env::var("GCPX_HOME") {
+    let overridden = STORE_DIR_OVERRIDE
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    let path = if let Some(dir) = overridden {
+        dir
+    } else if let Ok(dir) = env::var("GCPX_HOME") {
         PathBuf::from(dir)
+    } else if let Some(dir) = load_settings()?.store_dir {
+        dir
     } else {
         get_home()?.join(".config").join("gcpx")
     };
     if !path.exists() {
         fs::create_dir_all(&path)?;
     }
+    let checked = (path.clone(), get_gcloud_dir()?);
+    let mut last_checked = CHECKED_STORE_DIR.lock().unwrap_or_else(|e| e.into_inner());
+    if last_checked.as_ref() != Some(&checked) {
+        check_store_dir(&checked.0, &checked.1)?;
+        *last_checked = Some(checked);
+    }
     Ok(path)
 }
 
+/// Refuses a store directory that is or contains `gcloud_dir`, or that holds
+/// gcloud's ADC file.
+fn check_store_dir(store_dir: &Path, gcloud_dir: &Path) -> Result<()> {
+    let holds_gcloud_dir = match (store_dir.canonicalize(), gcloud_dir.canonicalize()) {
+        (Ok(store), Ok(gcloud)) => gcloud.starts_with(store),
+        _ => false,
+    };
+    let holds_adc = get_adc_path()?
+        .file_name()
+        .is_some_and(|adc| store_dir.join(adc).exists());
+    if holds_gcloud_dir || holds_adc {
+        bail!(GcpxError::StoreIsGcloudDir(store_dir.to_path_buf()));
+    }
+    Ok(())
+}
+
+/// The file name gcloud uses for Application Default Credentials.
+pub const DEFAULT_ADC_FILENAME: &str = "application_default_credentials.json";
+
 /// Returns the path to the Application Default Credentials file.
+///
+/// The file name can be changed for nonstandard gcloud layouts with the
+/// `GCPX_ADC_FILENAME` environment variable or the `adc_filename` setting
+/// (in that order); it defaults to [`DEFAULT_ADC_FILENAME`]. Either must be
+/// a plain file name, so the path can't leave the gcloud directory.
 pub fn get_adc_path() -> Result<PathBuf> {
-    Ok(get_gcloud_dir()?.join("application_default_credentials.json"))
+    let filename = match env::var("GCPX_ADC_FILENAME") {
+        Ok(name) if !name.is_empty() => name,
+        _ => load_settings()?
+            .adc_filename
+            .unwrap_or_else(|| DEFAULT_ADC_FILENAME.to_string()),
+    };
+    let mut components = Path::new(&filename).components();
+    let plain = matches!(components.next(), Some(std::path::Component::Normal(_)))
+        && components.next().is_none()
+        && !filename.contains(std::path::is_separator);
+    if !plain {
+        bail!(GcpxError::InvalidAdcFilename(filename));
+    }
+    Ok(get_gcloud_dir()?.join(filename))
+}
+
+/// Where `save` found the credentials it stored.
+pub const ADC_SOURCE_GCLOUD: &str = "gcloud";
+/// Credentials were read from the file named by `GOOGLE_APPLICATION_CREDENTIALS`.
+pub const ADC_SOURCE_ENV: &str = "GOOGLE_APPLICATION_CREDENTIALS";
+/// Credentials were read from an explicit `--adc-path`.
+pub const ADC_SOURCE_PATH: &str = "adc-path";
+/// Credentials were supplied directly (`save --stdin`).
+pub const ADC_SOURCE_STDIN: &str = "stdin";
+
+/// Checks that `content` looks like an ADC file: a JSON object with a `type`.
+pub fn validate_adc_json(content: &[u8]) -> Result<()> {
+    let value: serde_json::Value =
+        serde_json::from_slice(content).context("Credentials are not valid JSON")?;
+    if value.get("type").and_then(|t| t.as_str()).is_none() {
+        bail!(GcpxError::UntypedCredentials);
+    }
+    Ok(())
+}
+
+/// Finds the credentials file to save, mirroring how Google client libraries
+/// resolve ADC.
+///
+/// An explicit `adc_path` always wins. Otherwise the gcloud ADC file is
+/// preferred, falling back to `GOOGLE_APPLICATION_CREDENTIALS` when gcloud has
+/// not written one. Returns the path together with one of the `ADC_SOURCE_*`
+/// constants.
+pub fn resolve_adc_source(adc_path: Option<&Path>) -> Result<(PathBuf, &'static str)> {
+    if let Some(path) = adc_path {
+        if !path.exists() {
+            bail!(GcpxError::NoCredentials(path.to_path_buf()));
+        }
+        return Ok((path.to_path_buf(), ADC_SOURCE_PATH));
+    }
+
+    let default = get_adc_path()?;
+    if default.exists() {
+        return Ok((default, ADC_SOURCE_GCLOUD));
+    }
+
+    if let Ok(env_path) = env_adc_path() {
+        return Ok((env_path, ADC_SOURCE_ENV));
+    }
+
+    bail!(GcpxError::NoCredentials(default))
+}
+
+/// Returns the credentials file named by `GOOGLE_APPLICATION_CREDENTIALS`.
+///
+/// Fails if the variable is unset or empty, or doesn't name an existing file.
+pub fn env_adc_path() -> Result<PathBuf> {
+    let Some(path) = env::var_os("GOOGLE_APPLICATION_CREDENTIALS").filter(|p| !p.is_empty()) else {
+        bail!(GcpxError::NoCredentialsEnv);
+    };
+    let path = PathBuf::from(path);
+    if !path.is_file() {
+        bail!(GcpxError::NoCredentials(path));
+    }
+    Ok(path)
 }
 
 /// Returns the path to a context's stored ADC file.
@@ -93,11 +404,281 @@ pub fn get_context_adc_path(name: &str) -> Result<PathBuf> {
     Ok(get_store_dir()?.join(name).join("adc.json"))
 }
 
+/// Returns the path to a context's bundled external-account credential source
+/// (see `save --bundle-credential-source`).
+pub fn get_context_credential_source_path(name: &str) -> Result<PathBuf> {
+    Ok(get_context_dir(name)?.join("credential_source"))
+}
+
+/// Returns the path to the `n`th previous version of a context's bundled
+/// credential source (`credential_source.<n>`), kept alongside `adc.json.<n>`.
+pub fn get_context_credential_source_version_path(name: &str, n: usize) -> Result<PathBuf> {
+    Ok(get_context_dir(name)?.join(format!("credential_source.{}", n)))
+}
+
+/// Returns the path of the credentials handed to `gcpx run` for a context with
+/// a bundled credential source: [`restored_adc`] written out next to it.
+pub fn get_context_run_adc_path(name: &str) -> Result<PathBuf> {
+    Ok(get_context_dir(name)?.join("adc.run.json"))
+}
+
+/// Returns the path to the cache of gcloud answers kept by
+/// [`crate::gcloud_cache`].
+pub fn get_gcloud_cache_path() -> Result<PathBuf> {
+    Ok(get_store_dir()?.join(".gcloud-cache.json"))
+}
+
+/// Forgets every answer cached by [`crate::gcloud_cache`]. Called after gcpx
+/// changes gcloud state (activating, creating or deleting configurations,
+/// setting properties, logging in).
+pub fn invalidate_gcloud_cache() {
+    if let Ok(path) = get_gcloud_cache_path() {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Returns the path to a context's snapshot of its whole gcloud configuration
+/// file (see `save --snapshot-config`).
+pub fn get_context_config_snapshot_path(name: &str) -> Result<PathBuf> {
+    Ok(get_context_dir(name)?.join("config-snapshot.ini"))
+}
+
+/// Returns the path of a named configuration's properties file in the gcloud
+/// config directory (`configurations/config_<name>`).
+pub fn get_gcloud_config_file(config: &str) -> Result<PathBuf> {
+    Ok(get_gcloud_dir()?
+        .join("configurations")
+        .join(format!("config_{}", config)))
+}
+
+/// Returns the `credential_source.file` of `external_account` ADC content:
+/// the token file workload identity federation reads on every request.
+pub fn external_credential_source_file(content: &[u8]) -> Option<PathBuf> {
+    let value: serde_json::Value = serde_json::from_slice(content).ok()?;
+    if value.get("type")?.as_str()? != "external_account" {
+        return None;
+    }
+    value
+        .get("credential_source")?
+        .get("file")?
+        .as_str()
+        .map(PathBuf::from)
+}
+
+/// Returns the credentials to restore for a context.
+///
+/// If the context bundles its external-account credential source, the
+/// `credential_source.file` path in the JSON is pointed at the bundled copy,
+/// wherever the store currently lives.
+pub fn restored_adc(name: &str) -> Result<Vec<u8>> {
+    let content = credential_store()?.read(name)?;
+    let bundled = get_context_credential_source_path(name)?;
+    if !bundled.exists() {
+        return Ok(content);
+    }
+    let mut value: serde_json::Value = serde_json::from_slice(&content)
+        .with_context(|| format!("Credentials of '{}' are not valid JSON", name))?;
+    if let Some(source) = value
+        .get_mut("credential_source")
+        .and_then(|s| s.as_object_mut())
+    {
+        source.insert(
+            "file".to_string(),
+            serde_json::Value::String(bundled.to_string_lossy().into_owned()),
+        );
+    }
+    Ok(serde_json::to_vec_pretty(&value)?)
+}
+
+/// Returns the path to the `n`th previous ADC version of a context (`adc.json.<n>`).
+pub fn get_context_adc_version_path(name: &str, n: usize) -> Result<PathBuf> {
+    Ok(get_context_dir(name)?.join(format!("adc.json.{}", n)))
+}
+
+/// Copies a context's `adc.json` (and bundled `credential_source`) to version
+/// 0, for [`rotate_adc_versions`] to shift in once the new credentials are
+/// written. Does nothing if `keep` is zero or the context has no `adc.json` yet.
+///
+/// Copies keep the original's permissions.
+pub fn stash_adc_version(name: &str, keep: usize) -> Result<()> {
+    let current = get_context_adc_path(name)?;
+    if keep == 0 || !current.exists() {
+        return Ok(());
+    }
+    fs::copy(&current, get_context_adc_version_path(name, 0)?)?;
+    let source = get_context_credential_source_path(name)?;
+    let stashed_source = get_context_credential_source_version_path(name, 0)?;
+    if source.exists() {
+        fs::copy(&source, &stashed_source)?;
+    } else {
+        remove_if_exists(&stashed_source)?;
+    }
+    Ok(())
+}
+
+/// Moves the version stashed by [`stash_adc_version`] to `adc.json.1`,
+/// shifting older versions up and keeping at most `keep` of them.
+///
+/// A bundled `credential_source` moves with its `adc.json`, so each version
+/// keeps the token file it was saved with. Files are renamed, so their
+/// permissions are preserved. Does nothing if `keep` is zero or nothing was
+/// stashed.
+pub fn rotate_adc_versions(name: &str, keep: usize) -> Result<()> {
+    let stashed = get_context_adc_version_path(name, 0)?;
+    if keep == 0 || !stashed.exists() {
+        return Ok(());
+    }
+
+    // Drop versions that would fall off the end (including any beyond a
+    // previously larger `keep`)
+    let mut n = keep;
+    loop {
+        let path = get_context_adc_version_path(name, n)?;
+        let source = get_context_credential_source_version_path(name, n)?;
+        if !path.exists() && !source.exists() {
+            break;
+        }
+        remove_if_exists(&path)?;
+        remove_if_exists(&source)?;
+        n += 1;
+    }
+
+    for n in (0..keep).rev() {
+        move_if_exists(
+            &get_context_adc_version_path(name, n)?,
+            &get_context_adc_version_path(name, n + 1)?,
+        )?;
+        move_if_exists(
+            &get_context_credential_source_version_path(name, n)?,
+            &get_context_credential_source_version_path(name, n + 1)?,
+        )?;
+    }
+    Ok(())
+}
+
+/// Removes a file, doing nothing if it doesn't exist.
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Renames `from` to `to` if `from` exists; otherwise makes sure `to` doesn't.
+pub fn move_if_exists(from: &Path, to: &Path) -> Result<()> {
+    if from.exists() {
+        fs::rename(from, to)?;
+    } else {
+        remove_if_exists(to)?;
+    }
+    Ok(())
+}
+
 /// Returns the path to the context directory.
 pub fn get_context_dir(name: &str) -> Result<PathBuf> {
     Ok(get_store_dir()?.join(name))
 }
 
+/// Returns the path to a context's own gcloud config directory, used when the
+/// `isolated_configs` setting is enabled.
+pub fn get_context_gcloud_dir(name: &str) -> Result<PathBuf> {
+    Ok(get_context_dir(name)?.join("gcloud"))
+}
+
+/// Creates or refreshes a context's isolated gcloud config directory.
+///
+/// With `credentials`, the saved credentials are copied in as the directory's
+/// ADC file and, when they changed, also given to the gcloud CLI (`gcloud auth
+/// login --cred-file`), so `gcloud` commands are authenticated too. The
+/// `default` configuration then gets the context's account, project, captured
+/// properties and impersonation, set through `gcloud config set` so gcloud
+/// does the INI quoting. A config snapshot instead replaces the configuration
+/// verbatim.
+///
+/// Failures to configure gcloud are reported as warnings: the ADC file alone
+/// is enough for client libraries.
+pub fn seed_context_gcloud_dir(
+    name: &str,
+    metadata: &ContextMetadata,
+    credentials: bool,
+) -> Result<()> {
+    let dir = get_context_gcloud_dir(name)?;
+    let configurations = dir.join("configurations");
+    if !configurations.exists() {
+        fs::create_dir_all(&configurations)?;
+        fs::write(configurations.join("config_default"), "")?;
+        fs::write(dir.join("active_config"), "default")?;
+    }
+    let use_cli = !ISOLATED_WITHOUT_GCLOUD_CLI.load(Ordering::Relaxed);
+
+    let isolated_gcloud = |args: &[&str]| -> Result<()> {
+        let output = gcloud_command()
+            .env("CLOUDSDK_CONFIG", &dir)
+            .env_remove("CLOUDSDK_ACTIVE_CONFIG_NAME")
+            .args(args)
+            .output()
+            .context("Failed to execute gcloud command")?;
+        if !output.status.success() {
+            let err = String::from_utf8_lossy(&output.stderr);
+            bail!(GcpxError::GcloudFailed(err.trim().to_string()));
+        }
+        Ok(())
+    };
+
+    if credentials {
+        let adc_path = dir.join(DEFAULT_ADC_FILENAME);
+        let adc = restored_adc(name)?;
+        let changed = fs::read(&adc_path).ok().as_deref() != Some(adc.as_slice());
+        write_atomic_private(&adc_path, &adc)?;
+        let supported = matches!(
+            adc_type_of(&adc).as_deref(),
+            Some("authorized_user" | "service_account" | "external_account")
+        );
+        if use_cli && changed && supported {
+            let cred_file = format!("--cred-file={}", adc_path.display());
+            if let Err(e) = isolated_gcloud(&["auth", "login", &cred_file, "--quiet"]) {
+                eprintln!(
+                    "Warning: gcloud couldn't load the credentials of '{}' into its isolated \
+                    config, so gcloud commands there may be unauthenticated: {:#}",
+                    name, e
+                );
+            }
+        }
+    }
+
+    if let Ok(snapshot) = fs::read(get_context_config_snapshot_path(name)?) {
+        write_atomic(&configurations.join("config_default"), &snapshot)?;
+        return Ok(());
+    }
+
+    if !use_cli {
+        return Ok(());
+    }
+    let mut properties: Vec<(&str, String)> = Vec::new();
+    if let Some(account) = &metadata.account {
+        properties.push(("core/account", account.clone()));
+    }
+    if let Some(project) = &metadata.project {
+        properties.push(("core/project", project.clone()));
+    }
+    for (key, value) in &metadata.gcloud_properties {
+        properties.push((key, value.clone()));
+    }
+    if let Some(chain) = metadata.impersonation() {
+        properties.push(("auth/impersonate_service_account", chain));
+    }
+    for (key, value) in &properties {
+        if let Err(e) = isolated_gcloud(&["config", "set", key, value]) {
+            eprintln!(
+                "Warning: couldn't set {} in the isolated gcloud config of '{}': {:#}",
+                key, name, e
+            );
+            break;
+        }
+    }
+    Ok(())
+}
+
 /// Returns the path to a context's metadata file.
 pub fn get_context_metadata_path(name: &str) -> Result<PathBuf> {
     Ok(get_store_dir()?.join(name).join("metadata.json"))
@@ -122,10 +703,226 @@ pub fn load_context_metadata(name: &str) -> Result<Option<ContextMetadata>> {
     Ok(Some(metadata))
 }
 
+/// Reads the `type` field from an ADC file, if it parses as JSON.
+pub fn read_adc_type(path: &Path) -> Option<String> {
+    adc_type_of(&fs::read(path).ok()?)
+}
+
+/// Returns the `type` field of ADC JSON content, if it parses.
+pub fn adc_type_of(content: &[u8]) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_slice(content).ok()?;
+    value.get("type")?.as_str().map(str::to_string)
+}
+
+/// Returns the identity ADC content authenticates as, when the file names it.
+///
+/// Service account keys carry `client_email`; impersonated and
+/// workload-identity credentials name the target service account in
+/// `service_account_impersonation_url`. User credentials usually don't
+/// record the account, except in the optional `account` field.
+pub fn adc_identity(content: &[u8]) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_slice(content).ok()?;
+    let field = |key: &str| {
+        value
+            .get(key)
+            .and_then(|v| v.as_str())
+            .filter(|s| !s.is_empty())
+    };
+    if let Some(email) = field("client_email") {
+        return Some(email.to_string());
+    }
+    if let Some(url) = field("service_account_impersonation_url") {
+        let email = url.rsplit_once("/serviceAccounts/")?.1;
+        return Some(email.split(':').next()?.to_string());
+    }
+    field("account").map(str::to_string)
+}
+
+/// Returns the project ADC content names: the quota project, or for service
+/// account keys the project the key belongs to.
+pub fn adc_project(content: &[u8]) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_slice(content).ok()?;
+    ["quota_project_id", "project_id"]
+        .iter()
+        .find_map(|key| value.get(key)?.as_str().filter(|s| !s.is_empty()))
+        .map(str::to_string)
+}
+
+/// Returns a canonical form of ADC content for comparisons: the JSON
+/// re-serialized with sorted keys and no whitespace. Content that isn't JSON
+/// is returned unchanged.
+///
+/// gcloud may rewrite the ADC file with a different key order or formatting,
+/// so comparing raw bytes would report changes that aren't there.
+pub fn canonical_adc(content: &[u8]) -> Vec<u8> {
+    serde_json::from_slice::<serde_json::Value>(content)
+        .ok()
+        .and_then(|value| serde_json::to_vec(&value).ok())
+        .unwrap_or_else(|| content.to_vec())
+}
+
+/// Returns true if two ADC files hold the same credentials (see [`canonical_adc`]).
+pub fn adc_equivalent(a: &[u8], b: &[u8]) -> bool {
+    a == b || canonical_adc(a) == canonical_adc(b)
+}
+
+/// Returns true for ADC types backed by user refresh tokens, which can be
+/// revoked by org policy after inactivity.
+pub fn adc_type_expires(adc_type: &str) -> bool {
+    matches!(
+        adc_type,
+        "authorized_user" | "external_account_authorized_user"
+    )
+}
+
+/// Returns the age in days of a file, based on its modification time.
+pub fn file_age_days(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    let age = SystemTime::now().duration_since(modified).ok()?;
+    Some(age.as_secs() / 86_400)
+}
+
+/// Returns the current time as seconds since the Unix epoch.
+pub fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Returns the Unix time of midnight (UTC) on a calendar date, or None if the
+/// date doesn't exist or is before the epoch.
+fn unix_from_date(year: i64, month: u32, day: u32) -> Option<u64> {
+    let days_in_month = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        _ => return None,
+    };
+    if day == 0 || day > days_in_month {
+        return None;
+    }
+    // Days since 1970-01-01 in the proleptic Gregorian calendar
+    let (y, m) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * m as i64 + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    u64::try_from(days * 86_400).ok()
+}
+
+/// Parses a point in time given on the command line into seconds since the
+/// Unix epoch.
+///
+/// Accepts `YYYY-MM-DD`, `YYYY-MM-DD HH:MM[:SS]` (or with `T`), all in UTC;
+/// a Unix timestamp; `now`, `today` and `yesterday`; and ages such as `90d`,
+/// `2w`, `12h` or `30 minutes ago`, counted back from now.
+pub fn parse_time_spec(input: &str) -> Result<u64> {
+    let input = input.trim();
+    let now = now_unix();
+    match input {
+        "now" => return Ok(now),
+        "today" => return Ok(now - now % 86_400),
+        "yesterday" => return Ok((now - now % 86_400).saturating_sub(86_400)),
+        _ => {}
+    }
+    if !input.is_empty() && input.bytes().all(|b| b.is_ascii_digit()) {
+        return input
+            .parse()
+            .with_context(|| format!("Invalid timestamp '{}'", input));
+    }
+
+    // Relative age: a number and a unit, optionally followed by "ago"
+    let age = input.strip_suffix("ago").unwrap_or(input).trim_end();
+    let split = age.find(|c: char| !c.is_ascii_digit()).unwrap_or(age.len());
+    if split > 0 {
+        let (count, unit) = age.split_at(split);
+        let seconds = match unit.trim_start() {
+            "s" | "sec" | "secs" | "second" | "seconds" => Some(1),
+            "m" | "min" | "mins" | "minute" | "minutes" => Some(60),
+            "h" | "hour" | "hours" => Some(3_600),
+            "d" | "day" | "days" => Some(86_400),
+            "w" | "week" | "weeks" => Some(604_800),
+            _ => None,
+        };
+        if let (Some(seconds), Ok(count)) = (seconds, count.parse::<u64>()) {
+            return Ok(now.saturating_sub(count.saturating_mul(seconds)));
+        }
+    }
+
+    // Calendar date, optionally with a time of day
+    let (date, time) = match input.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (input, None),
+    };
+    let parse = || -> Option<u64> {
+        let mut parts = date.splitn(3, '-');
+        let year = parts.next()?.parse().ok()?;
+        let month = parts.next()?.parse().ok()?;
+        let day = parts.next()?.parse().ok()?;
+        let mut seconds = unix_from_date(year, month, day)?;
+        if let Some(time) = time {
+            let fields: Vec<u64> = time
+                .trim_end_matches('Z')
+                .split(':')
+                .map(|f| f.parse().ok())
+                .collect::<Option<_>>()?;
+            let (hours, minutes, secs) = match fields.as_slice() {
+                [h, m] => (*h, *m, 0),
+                [h, m, s] => (*h, *m, *s),
+                _ => return None,
+            };
+            if hours > 23 || minutes > 59 || secs > 59 {
+                return None;
+            }
+            seconds += hours * 3_600 + minutes * 60 + secs;
+        }
+        Some(seconds)
+    };
+    parse().ok_or_else(|| GcpxError::InvalidDate(input.to_string()).into())
+}
+
+/// Records that a context was just used by updating its `last_used` timestamp.
+///
+/// Legacy contexts without metadata get metadata created, with the gcloud
+/// config defaulting to the context name.
+pub fn touch_context_metadata(name: &str) -> Result<()> {
+    let mut metadata = load_context_metadata(name)?.unwrap_or_else(|| ContextMetadata {
+        gcloud_config: name.to_string(),
+        ..Default::default()
+    });
+    metadata.last_used = Some(now_unix());
+    save_context_metadata(name, &metadata)
+}
+
+/// Returns a `gcloud` command with interactive prompts and the component
+/// update check turned off, so its output can be parsed reliably.
+///
+/// Every gcloud command gcpx runs for itself goes through this; commands run
+/// with `gcpx run` and the interactive `gcloud auth` logins are not affected.
+pub fn gcloud_command() -> std::process::Command {
+    let mut cmd = std::process::Command::new("gcloud");
+    cmd.env("CLOUDSDK_CORE_DISABLE_PROMPTS", "1")
+        .env("CLOUDSDK_COMPONENT_MANAGER_DISABLE_UPDATE_CHECK", "1");
+    cmd
+}
+
 /// Gets the current active gcloud configuration name.
 /// Returns "default" if gcloud is not installed or no active config is found.
 pub fn get_current_gcloud_config() -> Result<String> {
-    let output = match std::process::Command::new("gcloud")
+    Ok(get_active_gcloud_config()?.unwrap_or_else(|| "default".to_string()))
+}
+
+/// Gets the active gcloud configuration name, without assuming `default`.
+/// Returns None if gcloud is not installed or reports no active config.
+pub fn get_active_gcloud_config() -> Result<Option<String>> {
+    let output = match gcloud_command()
         .args([
             "config",
             "configurations",
@@ -136,53 +933,218 @@ pub fn get_current_gcloud_config() -> Result<String> {
         .output()
     {
         Ok(output) => output,
-        Err(_) => return Ok("default".to_string()),
+        Err(_) => return Ok(None),
     };
 
     let config = String::from_utf8_lossy(&output.stdout).trim().to_string();
     if config.is_empty() {
-        Ok("default".to_string())
+        Ok(None)
     } else {
-        Ok(config)
+        Ok(Some(config))
     }
 }
 
-/// Gets the current gcloud account.
-/// Returns None if gcloud is not installed or no account is set.
-pub fn get_current_gcloud_account() -> Result<Option<String>> {
-    let output = match std::process::Command::new("gcloud")
-        .args(["config", "get-value", "account"])
+/// Lists the names of all gcloud configurations.
+pub fn list_gcloud_configs() -> Result<Vec<String>> {
+    let output = gcloud_command()
+        .args(["config", "configurations", "list", "--format=value(name)"])
         .output()
-    {
+        .context("Failed to execute gcloud command")?;
+    if !output.status.success() {
+        let err = String::from_utf8_lossy(&output.stderr);
+        bail!(GcpxError::GcloudFailed(err.trim().to_string()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Reads one property (`account`, `project`, ...) of a gcloud configuration:
+/// the active one unless `configuration` is given. Returns None if gcloud is
+/// not installed or the property is unset.
+pub fn get_gcloud_value(property: &str, configuration: Option<&str>) -> Result<Option<String>> {
+    let mut cmd = gcloud_command();
+    cmd.args(["config", "get-value", property]);
+    if let Some(config) = configuration {
+        cmd.arg(format!("--configuration={}", config));
+    }
+    let output = match cmd.output() {
         Ok(output) => output,
         Err(_) => return Ok(None),
     };
 
-    let account = String::from_utf8_lossy(&output.stdout).trim().to_string();
-    if account.is_empty() || account == "(unset)" {
+    let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if value.is_empty() || value == "(unset)" {
         Ok(None)
     } else {
-        Ok(Some(account))
+        Ok(Some(value))
     }
 }
 
+/// Gets the current gcloud account.
+/// Returns None if gcloud is not installed or no account is set.
+pub fn get_current_gcloud_account() -> Result<Option<String>> {
+    get_gcloud_value("account", None)
+}
+
 /// Gets the current gcloud project.
 /// Returns None if gcloud is not installed or no project is set.
 pub fn get_current_gcloud_project() -> Result<Option<String>> {
-    let output = match std::process::Command::new("gcloud")
-        .args(["config", "get-value", "project"])
-        .output()
-    {
+    get_gcloud_value("project", None)
+}
+
+/// Gets all properties set in a gcloud configuration as `section/key` -> value.
+///
+/// Reads the active configuration unless `configuration` is given.
+/// Returns an empty map if gcloud is not installed.
+pub fn get_gcloud_properties(configuration: Option<&str>) -> Result<BTreeMap<String, String>> {
+    let mut cmd = gcloud_command();
+    cmd.args(["config", "list", "--format=json"]);
+    if let Some(config) = configuration {
+        cmd.arg(format!("--configuration={}", config));
+    }
+    let output = match cmd.output() {
         Ok(output) => output,
-        Err(_) => return Ok(None),
+        Err(_) => return Ok(BTreeMap::new()),
     };
+    if !output.status.success() {
+        let err = String::from_utf8_lossy(&output.stderr);
+        bail!(GcpxError::GcloudFailed(err.trim().to_string()));
+    }
 
-    let project = String::from_utf8_lossy(&output.stdout).trim().to_string();
-    if project.is_empty() || project == "(unset)" {
-        Ok(None)
-    } else {
-        Ok(Some(project))
+    let sections: BTreeMap<String, BTreeMap<String, serde_json::Value>> =
+        serde_json::from_slice(&output.stdout).context("Failed to parse gcloud config list")?;
+    let mut properties = BTreeMap::new();
+    for (section, values) in sections {
+        for (key, value) in values {
+            let value = match value {
+                serde_json::Value::String(s) => s,
+                other => other.to_string(),
+            };
+            properties.insert(format!("{}/{}", section, key), value);
+        }
     }
+    Ok(properties)
+}
+
+/// Sets a gcloud config property (`section/key`).
+///
+/// Writes to the active configuration unless `configuration` is given.
+pub fn set_gcloud_property(key: &str, value: &str, configuration: Option<&str>) -> Result<()> {
+    let mut cmd = gcloud_command();
+    cmd.args(["config", "set", key, value]);
+    if let Some(config) = configuration {
+        cmd.arg(format!("--configuration={}", config));
+    }
+    let output = cmd.output().context("Failed to execute gcloud command")?;
+    invalidate_gcloud_cache();
+    if !output.status.success() {
+        let err = String::from_utf8_lossy(&output.stderr);
+        bail!(GcpxError::GcloudFailed(err.trim().to_string()));
+    }
+    Ok(())
+}
+
+/// Unsets a gcloud config property (`section/key`).
+///
+/// Writes to the active configuration unless `configuration` is given.
+pub fn unset_gcloud_property(key: &str, configuration: Option<&str>) -> Result<()> {
+    let mut cmd = gcloud_command();
+    cmd.args(["config", "unset", key]);
+    if let Some(config) = configuration {
+        cmd.arg(format!("--configuration={}", config));
+    }
+    let output = cmd.output().context("Failed to execute gcloud command")?;
+    invalidate_gcloud_cache();
+    if !output.status.success() {
+        let err = String::from_utf8_lossy(&output.stderr);
+        bail!(GcpxError::GcloudFailed(err.trim().to_string()));
+    }
+    Ok(())
+}
+
+/// Returns true if a gcloud configuration exists.
+pub fn gcloud_config_exists(name: &str) -> Result<bool> {
+    let output = gcloud_command()
+        .args(["config", "configurations", "describe", name])
+        .output()
+        .context("Failed to execute gcloud command")?;
+    Ok(output.status.success())
+}
+
+/// Returns true if a gcloud account can see a project (`gcloud projects
+/// describe`). This makes a network call.
+///
+/// `account` and `configuration` select whose credentials gcloud uses; by
+/// default it is the active configuration's account.
+pub fn gcloud_project_accessible(
+    project: &str,
+    account: Option<&str>,
+    configuration: Option<&str>,
+) -> Result<bool> {
+    let mut cmd = gcloud_command();
+    cmd.args(["projects", "describe", project, "--format=value(projectId)"]);
+    if let Some(account) = account {
+        cmd.arg(format!("--account={}", account));
+    }
+    if let Some(config) = configuration {
+        cmd.arg(format!("--configuration={}", config));
+    }
+    let output = cmd.output().context("Failed to execute gcloud command")?;
+    Ok(output.status.success())
+}
+
+/// Creates gcloud configuration `to` (without activating it) as a copy of
+/// `from`, replaying all of its properties. Returns the number of properties copied.
+pub fn copy_gcloud_config(from: &str, to: &str) -> Result<usize> {
+    if !gcloud_config_exists(from)? {
+        bail!(GcpxError::GcloudFailed(format!(
+            "configuration '{}' does not exist",
+            from
+        )));
+    }
+    if gcloud_config_exists(to)? {
+        bail!(GcpxError::GcloudFailed(format!(
+            "configuration '{}' already exists",
+            to
+        )));
+    }
+
+    let properties = get_gcloud_properties(Some(from))?;
+    let output = gcloud_command()
+        .args(["config", "configurations", "create", to, "--no-activate"])
+        .output()
+        .context("Failed to execute gcloud command")?;
+    invalidate_gcloud_cache();
+    if !output.status.success() {
+        let err = String::from_utf8_lossy(&output.stderr);
+        bail!(GcpxError::GcloudFailed(err.trim().to_string()));
+    }
+    for (key, value) in &properties {
+        if let Err(e) = set_gcloud_property(key, value, Some(to)) {
+            // Don't leave a partial copy behind
+            let _ = delete_gcloud_config(to);
+            return Err(e);
+        }
+    }
+    Ok(properties.len())
+}
+
+/// Deletes a gcloud configuration (which must not be the active one).
+pub fn delete_gcloud_config(name: &str) -> Result<()> {
+    let output = gcloud_command()
+        .args(["config", "configurations", "delete", name, "--quiet"])
+        .output()
+        .context("Failed to execute gcloud command")?;
+    invalidate_gcloud_cache();
+    if !output.status.success() {
+        let err = String::from_utf8_lossy(&output.stderr);
+        bail!(GcpxError::GcloudFailed(err.trim().to_string()));
+    }
+    Ok(())
 }
 
 /// Gets the current kubectl context (if kubectl is available).
@@ -204,47 +1166,222 @@ pub fn get_current_kubectl_context() -> Option<String> {
     }
 }
 
-/// Switches kubectl context. Returns Ok(true) if switched, Ok(false) if kubectl not available.
-pub fn switch_kubectl_context(context: &str) -> Result<bool> {
-    let status = std::process::Command::new("kubectl")
-        .args(["config", "use-context", context])
-        .output();
+/// Lists the kubectl context names.
+/// Returns None if kubectl is not installed or fails.
+pub fn list_kubectl_contexts() -> Option<Vec<String>> {
+    let output = std::process::Command::new("kubectl")
+        .args(["config", "get-contexts", "-o", "name"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect(),
+    )
+}
 
-    match status {
-        Ok(output) => {
-            if output.status.success() {
-                Ok(true)
-            } else {
-                // Context might not exist, log but don't fail
-                let err = String::from_utf8_lossy(&output.stderr);
-                if !err.is_empty() {
-                    eprintln!("  kubectl warning: {}", err.trim());
-                }
-                Ok(false)
+/// Outcome of [`switch_kubectl_context`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KubeSwitch {
+    /// The context is now kubectl's current context
+    Switched,
+    /// kubectl is not installed
+    KubectlMissing,
+    /// kubectl has no context by that name
+    ContextNotFound,
+    /// kubectl failed for another reason (its error output)
+    Failed(String),
+}
+
+impl KubeSwitch {
+    /// Returns a one-line explanation for an unsuccessful switch to `context`,
+    /// or None if it switched.
+    pub fn failure_message(&self, context: &str) -> Option<String> {
+        match self {
+            KubeSwitch::Switched => None,
+            KubeSwitch::KubectlMissing => Some(format!(
+                "kubectl not found; kube context '{}' was not switched",
+                context
+            )),
+            KubeSwitch::ContextNotFound => Some(format!(
+                "saved kube context '{}' no longer exists - run 'kubectl config get-contexts' \
+                to see the available ones",
+                context
+            )),
+            KubeSwitch::Failed(err) if err.is_empty() => {
+                Some(format!("could not switch kube context to '{}'", context))
             }
+            KubeSwitch::Failed(err) => Some(format!(
+                "could not switch kube context to '{}': {}",
+                context, err
+            )),
         }
-        Err(_) => {
-            // kubectl not installed, that's fine
-            Ok(false)
+    }
+}
+
+/// Makes `context` kubectl's current context.
+///
+/// A failure other than a missing kubectl or an unknown context is retried
+/// once after a short pause, since kubeconfig writes can race with other
+/// tools. Nothing here is an error; callers decide how to report the outcome.
+pub fn switch_kubectl_context(context: &str) -> KubeSwitch {
+    let attempt = || {
+        let output = match std::process::Command::new("kubectl")
+            .args(["config", "use-context", context])
+            .output()
+        {
+            Ok(output) => output,
+            Err(_) => return KubeSwitch::KubectlMissing,
+        };
+        if output.status.success() {
+            return KubeSwitch::Switched;
+        }
+        let err = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        if err.contains("no context exists") {
+            KubeSwitch::ContextNotFound
+        } else {
+            KubeSwitch::Failed(err)
+        }
+    };
+    match attempt() {
+        KubeSwitch::Failed(_) => {
+            std::thread::sleep(std::time::Duration::from_millis(300));
+            attempt()
         }
+        outcome => outcome,
+    }
+}
+
+/// Writes a file atomically by writing a sibling temp file and renaming it
+/// into place, so readers never observe a partial write.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    write_atomic_with_mode(path, contents, None)
+}
+
+/// Like [`write_atomic`], but the file is readable only by the current user
+/// (0600 on Unix) from the moment it appears. Used for credentials.
+pub fn write_atomic_private(path: &Path, contents: &[u8]) -> Result<()> {
+    write_atomic_with_mode(path, contents, Some(0o600))
+}
+
+/// Like [`write_atomic`], but on Unix the temp file is created with `mode`
+/// (further restricted by the umask), so the contents are never readable by
+/// anyone `mode` excludes.
+pub fn write_atomic_with_mode(path: &Path, contents: &[u8], mode: Option<u32>) -> Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(format!(".tmp{}", std::process::id()));
+    let tmp = path.with_file_name(tmp_name);
+    // A leftover from an earlier process with the same pid would keep its
+    // old permissions
+    remove_if_exists(&tmp)?;
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    if let Some(mode) = mode {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(mode);
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    let written = options
+        .open(&tmp)
+        .and_then(|mut file| file.write_all(contents))
+        .and_then(|()| fs::rename(&tmp, path));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Reads a tracking file, returning "none" if it is missing, empty or unreadable.
+fn read_tracking_file(file: &str) -> String {
+    let path = match get_store_dir() {
+        Ok(p) => p.join(file),
+        Err(_) => return "none".to_string(),
+    };
+    match fs::read_to_string(path) {
+        Ok(s) if !s.trim().is_empty() => s.trim().to_string(),
+        _ => "none".to_string(),
     }
 }
 
 /// Sets the current active context in the tracking file.
+///
+/// The file is replaced atomically, since shell prompts read it constantly.
+/// The switch signal is bumped as well (see [`current_generation`]).
 pub fn set_current_tracking(name: &str) -> Result<()> {
-    let path = get_store_dir()?.join(".current");
-    fs::write(path, name)?;
+    let store_dir = get_store_dir()?;
+    write_atomic(&store_dir.join(".current"), name.as_bytes())?;
+    fs::write(store_dir.join(".switch-signal"), name)?;
     Ok(())
 }
 
+/// Returns a value that changes every time the current context changes.
+///
+/// This is the modification time (in nanoseconds since the Unix epoch) of the
+/// `.switch-signal` file, which is rewritten whenever tracking is updated.
+/// Prompt integrations can cache what they display and only re-read context
+/// data when this changes. Returns `None` if no switch has happened yet.
+pub fn current_generation() -> Option<u128> {
+    let path = get_store_dir().ok()?.join(".switch-signal");
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_nanos())
+}
+
 /// Gets the current active context from the tracking file.
-/// Returns "none" if no context is set or on error.
+/// Returns "none" if no context is set, the file is empty, or on error.
+/// Surrounding whitespace is trimmed.
+///
+/// This is the tracked name as stored; see [`get_current_context_checked`]
+/// for a variant that verifies the context still exists.
 pub fn get_current_tracking() -> String {
-    let path = match get_store_dir() {
-        Ok(p) => p.join(".current"),
-        Err(_) => return "none".to_string(),
-    };
-    fs::read_to_string(path).unwrap_or_else(|_| "none".to_string())
+    read_tracking_file(".current")
+}
+
+/// Gets the current active context, but only if it still exists.
+///
+/// Unlike [`get_current_tracking`], which returns the raw contents of the
+/// tracking file, this returns `None` when nothing is tracked or when the
+/// tracked context was removed out-of-band.
+pub fn get_current_context_checked() -> Option<String> {
+    let current = get_current_tracking();
+    if current == "none" {
+        return None;
+    }
+    match context_exists(&current) {
+        Ok(true) => Some(current),
+        _ => None,
+    }
+}
+
+/// Sets the previous context in the tracking file.
+///
+/// The previous context is what `gcpx switch -` switches back to.
+pub fn set_previous_tracking(name: &str) -> Result<()> {
+    let path = get_store_dir()?.join(".previous");
+    write_atomic(&path, name.as_bytes())
+}
+
+/// Gets the previous context from the tracking file.
+/// Returns "none" if no previous context is set, the file is empty, or on error.
+pub fn get_previous_tracking() -> String {
+    read_tracking_file(".previous")
+}
+
+/// Clears the previous context tracking file.
+pub fn clear_previous_tracking() -> Result<()> {
+    let path = get_store_dir()?.join(".previous");
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
 }
 
 /// Lists all saved context names.
@@ -269,16 +1406,181 @@ pub fn list_contexts() -> Result<Vec<String>> {
     Ok(contexts)
 }
 
+/// Lists all saved contexts together with their metadata (if any).
+///
+/// Contexts whose metadata is missing or unreadable are returned with `None`.
+pub fn list_contexts_with_metadata() -> Result<Vec<(String, Option<ContextMetadata>)>> {
+    Ok(list_contexts()?
+        .into_iter()
+        .map(|name| {
+            let metadata = load_context_metadata(&name).ok().flatten();
+            (name, metadata)
+        })
+        .collect())
+}
+
 /// Checks if a context exists.
+///
+/// This is a lenient check that only asks the credential store (by default,
+/// looks for the ADC file); see [`context_is_complete`] for a stricter check.
 pub fn context_exists(name: &str) -> Result<bool> {
-    let adc_path = get_context_adc_path(name)?;
-    Ok(adc_path.exists())
+    credential_store()?.exists(name)
+}
+
+/// Checks that a context has both its ADC file and parseable metadata.
+///
+/// Contexts without metadata fall back to using the context name as the
+/// gcloud config, which may activate the wrong configuration.
+pub fn context_is_complete(name: &str) -> Result<bool> {
+    if !context_exists(name)? {
+        return Ok(false);
+    }
+    Ok(matches!(load_context_metadata(name), Ok(Some(_))))
+}
+
+/// Something wrong with a saved context, found by [`context_problems`].
+///
+/// Displays as the short reason `list --broken`, `tree` and `doctor` print.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContextProblem {
+    /// The credential store keeps credentials on disk, but `adc.json` is missing
+    MissingAdc,
+    /// `adc.json` is not an ADC file
+    InvalidAdc,
+    /// A credential file is readable by group or others
+    ReadableByOthers(PathBuf),
+    /// `metadata.json` is missing
+    MissingMetadata,
+    /// `metadata.json` can't be read or parsed
+    UnreadableMetadata,
+}
+
+impl std::fmt::Display for ContextProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContextProblem::MissingAdc => write!(f, "missing adc.json"),
+            ContextProblem::InvalidAdc => write!(f, "invalid adc.json"),
+            ContextProblem::ReadableByOthers(path) => write!(
+                f,
+                "{} readable by others",
+                path.file_name().unwrap_or_default().to_string_lossy()
+            ),
+            ContextProblem::MissingMetadata => write!(f, "missing metadata.json"),
+            ContextProblem::UnreadableMetadata => write!(f, "unreadable metadata.json"),
+        }
+    }
+}
+
+/// Returns true for the files in a context directory that hold credentials:
+/// `adc.json`, a bundled `credential_source`, their kept versions (`.<n>`) and
+/// the `adc.run.json` handed to `gcpx run`.
+fn is_credential_file(file_name: &str) -> bool {
+    let base = match file_name.rsplit_once('.') {
+        Some((base, n)) if !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()) => base,
+        _ => file_name,
+    };
+    matches!(base, "adc.json" | "credential_source") || file_name == "adc.run.json"
+}
+
+/// Lists anything wrong with a saved context: missing or unparseable metadata,
+/// a missing or invalid `adc.json` when the credential store keeps credentials
+/// on disk, and credential files (see [`is_credential_file`]) that group or
+/// others can read. Returns an empty list for a healthy context.
+pub fn context_problems(name: &str) -> Result<Vec<ContextProblem>> {
+    let mut problems = Vec::new();
+    if let Some(adc) = credential_store()?.stored_path(name)? {
+        match fs::read(&adc) {
+            Err(_) => problems.push(ContextProblem::MissingAdc),
+            Ok(content) => {
+                if validate_adc_json(&content).is_err() {
+                    problems.push(ContextProblem::InvalidAdc);
+                }
+            }
+        }
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut readable = Vec::new();
+        for entry in fs::read_dir(get_context_dir(name)?)? {
+            let entry = entry?;
+            if !is_credential_file(&entry.file_name().to_string_lossy()) {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            if metadata.is_file() && metadata.permissions().mode() & 0o077 != 0 {
+                readable.push(entry.path());
+            }
+        }
+        readable.sort();
+        problems.extend(readable.into_iter().map(ContextProblem::ReadableByOthers));
+    }
+    match load_context_metadata(name) {
+        Ok(Some(_)) => {}
+        Ok(None) => problems.push(ContextProblem::MissingMetadata),
+        Err(_) => problems.push(ContextProblem::UnreadableMetadata),
+    }
+    Ok(problems)
+}
+
+/// Prints a warning to stderr if a context is missing or has unreadable metadata.
+pub fn warn_if_incomplete(name: &str) -> Result<()> {
+    if !context_is_complete(name)? {
+        eprintln!(
+            "Warning: context '{}' has missing or unreadable metadata; \
+            gcloud config '{}' will be assumed. Run 'gcpx repair {}' to fix.",
+            name, name, name
+        );
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn adc_identity_from_key_or_impersonation_url() {
+        assert_eq!(
+            adc_identity(
+                br#"{"type":"service_account","client_email":"ci@p.iam.gserviceaccount.com"}"#
+            )
+            .as_deref(),
+            Some("ci@p.iam.gserviceaccount.com")
+        );
+        let impersonated = br#"{"type":"impersonated_service_account","service_account_impersonation_url":"https://iamcredentials.googleapis.com/v1/projects/-/serviceAccounts/deploy@p.iam.gserviceaccount.com:generateAccessToken"}"#;
+        assert_eq!(
+            adc_identity(impersonated).as_deref(),
+            Some("deploy@p.iam.gserviceaccount.com")
+        );
+        assert_eq!(adc_identity(br#"{"type":"authorized_user"}"#), None);
+    }
+
+    #[test]
+    fn parse_time_spec_formats() {
+        assert_eq!(parse_time_spec("1970-01-02").unwrap(), 86_400);
+        assert_eq!(parse_time_spec("2024-03-01").unwrap(), 1_709_251_200);
+        assert_eq!(
+            parse_time_spec("2024-03-01T12:30").unwrap(),
+            1_709_251_200 + 45_000
+        );
+        assert_eq!(
+            parse_time_spec("2024-03-01 00:00:07").unwrap(),
+            1_709_251_207
+        );
+        assert_eq!(parse_time_spec("1700000000").unwrap(), 1_700_000_000);
+
+        let now = now_unix();
+        let week_ago = parse_time_spec("1w").unwrap();
+        assert!(week_ago <= now - 604_800 && week_ago + 5 >= now - 604_800);
+        let two_days_ago = parse_time_spec("2 days ago").unwrap();
+        assert!(two_days_ago <= now - 172_800 && two_days_ago + 5 >= now - 172_800);
+
+        assert!(parse_time_spec("2023-02-29").is_err());
+        assert!(parse_time_spec("last tuesday").is_err());
+        assert!(parse_time_spec("5 fortnights").is_err());
+    }
+
     #[test]
     fn test_get_home_returns_path() {
         let home = get_home();
@@ -286,6 +1588,20 @@ mod tests {
         assert!(home.unwrap().exists());
     }
 
+    #[test]
+    fn test_adc_equivalent_ignores_formatting() {
+        let saved = br#"{"type": "authorized_user", "client_id": "a", "refresh_token": "r"}"#;
+        let rewritten = b"{\n  \"refresh_token\": \"r\",\n  \"client_id\": \"a\",\n  \"type\": \"authorized_user\"\n}\n";
+        assert!(adc_equivalent(saved, rewritten));
+        assert!(!adc_equivalent(
+            saved,
+            br#"{"type": "authorized_user", "client_id": "a", "refresh_token": "other"}"#
+        ));
+        // Non-JSON content falls back to a byte comparison
+        assert!(adc_equivalent(b"not json", b"not json"));
+        assert!(!adc_equivalent(b"not json", b"not json "));
+    }
+
     #[test]
     fn test_gcloud_dir_is_under_home() {
         let home = get_home().unwrap();
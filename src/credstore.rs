@@ -0,0 +1,394 @@
+//! Where a context's credentials (its ADC blob) live.
+//!
+//! By default credentials are kept next to the metadata as `adc.json`
+//! ([`FileStore`]). With `store_backend = "command"` in the settings file they
+//! are fetched from and stored in an external secret manager through shell
+//! commands ([`CommandStore`]); only the metadata stays on disk.
+//!
+//! Tools that need credentials as a file (`gcpx run`) get them through
+//! [`CredentialStore::materialize`], which for non-file backends writes a
+//! private temporary copy that is removed once the command finishes.
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use crate::config::{get_context_adc_path, get_context_metadata_path, write_atomic_with_mode};
+use crate::error::GcpxError;
+use crate::settings::load_settings;
+
+/// Selects the credential backend (`store_backend` setting).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreBackend {
+    /// `adc.json` in the context directory
+    #[default]
+    File,
+    /// Shell commands from the `[store_command]` settings table
+    Command,
+}
+
+/// Shell command templates for [`StoreBackend::Command`].
+///
+/// `{name}` is replaced with the shell-quoted context name, which is also
+/// exported as `GCPX_CONTEXT`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StoreCommandSettings {
+    /// Prints the context's credential JSON on stdout
+    pub get_cmd: String,
+    /// Reads the context's credential JSON from stdin and stores it
+    pub put_cmd: String,
+}
+
+/// Permissions given to credential files the file backend writes
+/// (`credential_mode` setting, `save --mode` / `--no-permissions`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CredentialMode {
+    /// Set this Unix mode (0600 by default)
+    Mode(u32),
+    /// Leave the permissions the filesystem gives new files
+    Skip,
+}
+
+impl Default for CredentialMode {
+    fn default() -> Self {
+        CredentialMode::Mode(0o600)
+    }
+}
+
+impl CredentialMode {
+    /// Parses a setting value: an octal mode (`600`, `0640`, `0o600`) or `none`.
+    pub fn parse(value: &str) -> Result<Self> {
+        if value == "none" {
+            return Ok(CredentialMode::Skip);
+        }
+        Ok(CredentialMode::Mode(parse_mode(value)?))
+    }
+
+    /// Returns the mode from the settings file, or the default.
+    pub fn from_settings() -> Result<Self> {
+        match load_settings()?.credential_mode {
+            Some(value) => Self::parse(&value)
+                .with_context(|| format!("Invalid credential_mode setting '{}'", value)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Atomically writes a credential file.
+    ///
+    /// With a mode, the file is created with it, so the credentials are never
+    /// more widely readable than asked for, and then set to it exactly (the
+    /// umask may have removed bits) as [`apply`](Self::apply) does. Without
+    /// one, the file is written in place, keeping an existing file's permissions.
+    pub fn write(self, path: &Path, contents: &[u8]) -> Result<()> {
+        match self {
+            CredentialMode::Mode(mode) => {
+                write_atomic_with_mode(path, contents, Some(mode))?;
+                self.apply(path);
+            }
+            CredentialMode::Skip => fs::write(path, contents)?,
+        }
+        Ok(())
+    }
+
+    /// Sets the permissions of a credential file.
+    ///
+    /// A failure (e.g. on network mounts that don't support Unix modes) is
+    /// reported as a warning rather than an error, so credentials can still
+    /// be saved there.
+    pub fn apply(self, path: &Path) {
+        #[cfg(unix)]
+        if let CredentialMode::Mode(mode) = self {
+            use std::os::unix::fs::PermissionsExt;
+            if let Err(e) = fs::set_permissions(path, fs::Permissions::from_mode(mode)) {
+                eprintln!(
+                    "Warning: could not set mode {:o} on {:?}: {}. Set credential_mode = \"none\" \
+                    to skip this step.",
+                    mode, path, e
+                );
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = path;
+    }
+}
+
+/// Parses an octal file mode (`600`, `0640`, `0o600`).
+pub fn parse_mode(value: &str) -> Result<u32> {
+    let digits = value.strip_prefix("0o").unwrap_or(value);
+    match u32::from_str_radix(digits, 8) {
+        Ok(mode) if !digits.is_empty() && mode <= 0o777 => Ok(mode),
+        _ => bail!(GcpxError::InvalidMode(value.to_string())),
+    }
+}
+
+/// Reads and writes the credentials of saved contexts.
+pub trait CredentialStore {
+    /// Returns a context's credential JSON.
+    fn read(&self, name: &str) -> Result<Vec<u8>>;
+
+    /// Stores a context's credential JSON, replacing any previous value.
+    fn write(&self, name: &str, adc: &[u8]) -> Result<()>;
+
+    /// Returns true if credentials are stored for the context.
+    fn exists(&self, name: &str) -> Result<bool>;
+
+    /// Returns the credentials as a file, for tools that only take a path.
+    fn materialize(&self, name: &str) -> Result<MaterializedAdc>;
+
+    /// Returns the file the credentials are kept in, or None if the backend
+    /// doesn't keep them on disk.
+    fn stored_path(&self, name: &str) -> Result<Option<PathBuf>>;
+}
+
+/// Returns the credential store selected in the settings file.
+pub fn credential_store() -> Result<Box<dyn CredentialStore>> {
+    credential_store_with_mode(None)
+}
+
+/// Like [`credential_store`], but the file backend uses `mode` instead of the
+/// `credential_mode` setting when given.
+pub fn credential_store_with_mode(
+    mode: Option<CredentialMode>,
+) -> Result<Box<dyn CredentialStore>> {
+    let settings = load_settings()?;
+    Ok(match settings.store_backend {
+        StoreBackend::File => Box::new(FileStore {
+            mode: match mode {
+                Some(mode) => mode,
+                None => CredentialMode::from_settings()?,
+            },
+        }),
+        StoreBackend::Command => match settings.store_command {
+            Some(commands) if !commands.get_cmd.is_empty() && !commands.put_cmd.is_empty() => {
+                Box::new(CommandStore { commands })
+            }
+            _ => bail!(GcpxError::StoreCommandMissing),
+        },
+    })
+}
+
+/// Credentials available as a file on disk.
+///
+/// Temporary copies are deleted when this is dropped, unless [`keep`](Self::keep)
+/// is called.
+#[derive(Debug)]
+pub struct MaterializedAdc {
+    path: PathBuf,
+    temporary: bool,
+}
+
+impl MaterializedAdc {
+    /// Writes credentials to a new file in [`private_temp_dir`], removed when
+    /// dropped.
+    pub fn temporary(name: &str, content: &[u8]) -> Result<Self> {
+        let dir = private_temp_dir()?;
+        // O_EXCL with a random name, 0600 on Unix
+        let mut file = tempfile::Builder::new()
+            .prefix(&format!("gcpx-adc-{}-", name))
+            .suffix(".json")
+            .tempfile_in(&dir)
+            .with_context(|| format!("Failed to create a credential file in {:?}", dir))?;
+        file.write_all(content)?;
+        let path = file
+            .into_temp_path()
+            .keep()
+            .context("Failed to keep temporary credential file")?;
+        Ok(MaterializedAdc {
+            path,
+            temporary: true,
+        })
+    }
+
+    /// Credentials already kept in a file, which is left in place.
+    pub fn stored(path: PathBuf) -> Self {
+        MaterializedAdc {
+            path,
+            temporary: false,
+        }
+    }
+
+    /// Path to the credential file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Leaves the file in place after this is dropped and returns its path.
+    pub fn keep(mut self) -> PathBuf {
+        self.temporary = false;
+        self.path.clone()
+    }
+}
+
+impl Drop for MaterializedAdc {
+    fn drop(&mut self) {
+        if self.temporary {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// The default backend: `adc.json` in the context directory (0600 on Unix
+/// unless [`CredentialMode`] says otherwise).
+pub struct FileStore {
+    pub mode: CredentialMode,
+}
+
+impl CredentialStore for FileStore {
+    fn read(&self, name: &str) -> Result<Vec<u8>> {
+        let path = get_context_adc_path(name)?;
+        fs::read(&path).with_context(|| format!("Failed to read credentials: {:?}", path))
+    }
+
+    fn write(&self, name: &str, adc: &[u8]) -> Result<()> {
+        self.mode.write(&get_context_adc_path(name)?, adc)
+    }
+
+    fn exists(&self, name: &str) -> Result<bool> {
+        Ok(get_context_adc_path(name)?.exists())
+    }
+
+    fn materialize(&self, name: &str) -> Result<MaterializedAdc> {
+        Ok(MaterializedAdc::stored(get_context_adc_path(name)?))
+    }
+
+    fn stored_path(&self, name: &str) -> Result<Option<PathBuf>> {
+        Ok(Some(get_context_adc_path(name)?))
+    }
+}
+
+/// Keeps credentials in an external secret manager, reached through the
+/// `get_cmd`/`put_cmd` shell commands.
+///
+/// A context exists as soon as its metadata does; credentials are only
+/// fetched when needed.
+pub struct CommandStore {
+    pub commands: StoreCommandSettings,
+}
+
+impl CommandStore {
+    /// Runs a command template for a context, feeding `input` on stdin.
+    fn run(&self, template: &str, name: &str, input: Option<&[u8]>) -> Result<Vec<u8>> {
+        let script = template.replace("{name}", &shell_quote(name));
+        let mut child = Command::new("sh")
+            .args(["-c", &script])
+            .env("GCPX_CONTEXT", name)
+            .stdin(if input.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to execute credential command: {}", script))?;
+        // Feed stdin from another thread while the output is drained, so a
+        // helper that fills its stdout pipe before reading all its input
+        // can't deadlock us
+        let stdin = child.stdin.take();
+        let (output, written) = thread::scope(|scope| {
+            let writer = scope.spawn(move || match (input, stdin) {
+                // Dropping stdin closes it, so the helper sees EOF
+                (Some(input), Some(mut stdin)) => stdin.write_all(input),
+                _ => Ok(()),
+            });
+            let output = child.wait_with_output();
+            let written = writer.join().unwrap_or(Ok(()));
+            (output, written)
+        });
+        let output = output?;
+        if !output.status.success() {
+            bail!(GcpxError::CredentialCommandFailed(
+                name.to_string(),
+                String::from_utf8_lossy(&output.stderr).trim().to_string()
+            ));
+        }
+        written.with_context(|| format!("Failed to write to credential command: {}", script))?;
+        Ok(output.stdout)
+    }
+}
+
+impl CredentialStore for CommandStore {
+    fn read(&self, name: &str) -> Result<Vec<u8>> {
+        self.run(&self.commands.get_cmd, name, None)
+    }
+
+    fn write(&self, name: &str, adc: &[u8]) -> Result<()> {
+        self.run(&self.commands.put_cmd, name, Some(adc))?;
+        Ok(())
+    }
+
+    fn exists(&self, name: &str) -> Result<bool> {
+        Ok(get_context_metadata_path(name)?.exists())
+    }
+
+    fn materialize(&self, name: &str) -> Result<MaterializedAdc> {
+        MaterializedAdc::temporary(name, &self.read(name)?)
+    }
+
+    fn stored_path(&self, _name: &str) -> Result<Option<PathBuf>> {
+        Ok(None)
+    }
+}
+
+/// Returns where [`private_temp_dir`] lives, without creating or checking it.
+pub fn private_temp_dir_path() -> PathBuf {
+    #[cfg(unix)]
+    {
+        // SAFETY: getuid has no preconditions and cannot fail
+        let uid = unsafe { libc::getuid() };
+        match env::var_os("XDG_RUNTIME_DIR") {
+            Some(runtime) if !runtime.is_empty() => PathBuf::from(runtime).join("gcpx"),
+            _ => env::temp_dir().join(format!("gcpx-{}", uid)),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        env::temp_dir()
+    }
+}
+
+/// Returns a directory only the current user can use, for temporary copies of
+/// credentials: `$XDG_RUNTIME_DIR/gcpx` if set, otherwise `gcpx-<uid>` in the
+/// system temp directory.
+///
+/// The directory is created with mode 0700. An existing one that is not a
+/// directory owned by the current user, or that others can access, is refused
+/// rather than trusted.
+pub fn private_temp_dir() -> Result<PathBuf> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+        // SAFETY: getuid has no preconditions and cannot fail
+        let uid = unsafe { libc::getuid() };
+        let dir = private_temp_dir_path();
+        match fs::DirBuilder::new().mode(0o700).create(&dir) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to create {:?}", dir));
+            }
+        }
+        let meta = fs::symlink_metadata(&dir)?;
+        if !meta.is_dir() || meta.uid() != uid || meta.permissions().mode() & 0o077 != 0 {
+            bail!(GcpxError::UnsafeTempDir(dir));
+        }
+        Ok(dir)
+    }
+    #[cfg(not(unix))]
+    {
+        Ok(private_temp_dir_path())
+    }
+}
+
+/// Quotes a string for use as a single POSIX shell word.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
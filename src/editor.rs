@@ -0,0 +1,60 @@
+//! Helpers for composing text in the user's editor.
+
+use anyhow::{Context, Result, bail};
+use std::env;
+use std::fs;
+use std::process::Command;
+
+use crate::error::GcpxError;
+use crate::prompt::require_input;
+
+/// Returns the user's preferred editor command from `$VISUAL` or `$EDITOR`.
+fn editor_command() -> String {
+    env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| {
+            if cfg!(windows) {
+                "notepad".to_string()
+            } else {
+                "vi".to_string()
+            }
+        })
+}
+
+/// Opens `initial` in the user's editor and returns the edited text.
+///
+/// Lines starting with `#` are treated as comments and removed, and the
+/// result is trimmed.
+pub fn edit_text(initial: &str) -> Result<String> {
+    require_input("pass the text directly instead of opening an editor")?;
+    let file = tempfile::Builder::new()
+        .prefix("gcpx-edit-")
+        .suffix(".txt")
+        .tempfile()
+        .context("Failed to create a temporary file for the editor")?;
+    fs::write(file.path(), initial)?;
+    let path = file.into_temp_path();
+
+    let editor = editor_command();
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or("vi");
+    let status = Command::new(program)
+        .args(parts)
+        .arg(&path)
+        .status()
+        .with_context(|| format!("Failed to launch editor: {}", editor));
+
+    let content = fs::read_to_string(&path);
+    drop(path);
+
+    if !status?.success() {
+        bail!(GcpxError::EditorFailed);
+    }
+
+    let content = content?;
+    let text: Vec<&str> = content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect();
+    Ok(text.join("\n").trim().to_string())
+}
@@ -0,0 +1,345 @@
+//! Error types for gcpx.
+//!
+//! Public functions return [`anyhow::Result`], but failures callers may want to
+//! handle are raised as [`GcpxError`] and can be recovered with
+//! `err.downcast_ref::<GcpxError>()`.
+
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Distinct failure kinds surfaced by gcpx operations.
+#[derive(Debug, Error)]
+pub enum GcpxError {
+    /// The named context does not exist in the store
+    #[error("Context '{0}' not found. Run 'gcpx save {0}' first.")]
+    ContextNotFound(String),
+
+    /// A context with this name already exists
+    #[error("Context '{0}' already exists.")]
+    ContextExists(String),
+
+    /// The named group does not exist in `groups.json`
+    #[error("Group '{0}' not found. Run 'gcpx groups' to see saved groups.")]
+    GroupNotFound(String),
+
+    /// No ADC credentials were found to save
+    #[error("No credentials found at {0:?}.\nRun 'gcloud auth application-default login' first!")]
+    NoCredentials(PathBuf),
+
+    /// gcloud reports no active configuration (`save --require-active-config`)
+    #[error(
+        "No active gcloud configuration.\nCreate or activate one first: \
+        'gcloud config configurations create <name>' or \
+        'gcloud config configurations activate <name>'."
+    )]
+    NoActiveGcloudConfig,
+
+    /// The context name failed validation
+    #[error("{0}")]
+    InvalidName(String),
+
+    /// An environment variable name is not `[A-Za-z_][A-Za-z0-9_]*`
+    #[error(
+        "Invalid environment variable name '{0}'. \
+        Use letters, digits and underscores, not starting with a digit."
+    )]
+    InvalidEnvKey(String),
+
+    /// A gcloud invocation failed
+    #[error("gcloud error: {0}")]
+    GcloudFailed(String),
+
+    /// `switch -` was used without a previous context
+    #[error("No previous context to switch back to.")]
+    NoPreviousContext,
+
+    /// `run` was called without a command
+    #[error("No command specified. Usage: gcpx run <context> -- <command>")]
+    NoCommand,
+
+    /// `undo` was used with no recorded operation
+    #[error("Nothing to undo.")]
+    NothingToUndo,
+
+    /// A prompt was needed but input is disabled (`--no-input` or no terminal)
+    #[error("Input required, but running non-interactively: {0}")]
+    InputRequired(String),
+
+    /// The store directory is, or contains, gcloud's own configuration directory
+    #[error(
+        "The context store {0:?} is or contains gcloud's configuration directory. \
+        Point GCPX_HOME, --store-dir or the store_dir setting at a separate directory."
+    )]
+    StoreIsGcloudDir(PathBuf),
+
+    /// The context's run policy forbids the command
+    #[error(
+        "Blocked by the run policy of context '{0}': {1}. Pass --override-policy to run it anyway."
+    )]
+    RunPolicy(String, String),
+
+    /// The command launched by `run` exited unsuccessfully
+    #[error("Command exited with code {0}")]
+    CommandFailed(i32),
+
+    /// The program given to `run` is a path that doesn't exist
+    #[error("'{0}' does not exist")]
+    ProgramMissing(String),
+
+    /// The program given to `run` is not on `PATH`
+    #[error("'{0}' not found on PATH")]
+    ProgramNotFound(String),
+
+    /// The program given to `run` exists but can't be executed
+    #[error("'{0}' is not executable (permission denied)")]
+    ProgramNotExecutable(String),
+
+    /// `run` was called without a context while none is current
+    #[error("No current context. Pass a context name or run 'gcpx switch' first.")]
+    NoCurrentContext,
+
+    /// An `--env`-style assignment is not `KEY=VALUE` (flag, value)
+    #[error("Invalid {0} '{1}'. Expected KEY=VALUE.")]
+    InvalidEnvAssignment(String, String),
+
+    /// A command working on several contexts was given none (with a hint)
+    #[error("No contexts selected. {0}")]
+    NoContextsSelected(String),
+
+    /// `run-each` ran in some contexts where the command failed (failed, total)
+    #[error("{0} of {1} contexts failed")]
+    RunEachFailed(usize, usize),
+
+    /// A service account email is malformed
+    #[error(
+        "'{0}' doesn't look like a service account email \
+        (name@project.iam.gserviceaccount.com)"
+    )]
+    InvalidServiceAccount(String),
+
+    /// The `adc_filename` setting is not a plain file name
+    #[error("Invalid ADC file name '{0}': expected a plain file name without directories")]
+    InvalidAdcFilename(String),
+
+    /// Credentials JSON has no `type` field
+    #[error("Credentials JSON has no \"type\" field; expected an ADC or service account key.")]
+    UntypedCredentials,
+
+    /// Credentials were requested from `GOOGLE_APPLICATION_CREDENTIALS`, which is unset
+    #[error("GOOGLE_APPLICATION_CREDENTIALS is not set")]
+    NoCredentialsEnv,
+
+    /// `save --bundle-credential-source` without a credential source file
+    #[error(
+        "--bundle-credential-source needs external_account credentials with a \
+        credential_source file."
+    )]
+    NoCredentialSource,
+
+    /// `save --check-project` found the project inaccessible (the reason)
+    #[error("Not saving: {0}.")]
+    ProjectCheck(String),
+
+    /// A date or age given on the command line can't be parsed
+    #[error(
+        "Invalid date '{0}'. Use YYYY-MM-DD, YYYY-MM-DD HH:MM, a Unix timestamp, \
+        today/yesterday, or an age like 30d or 2w."
+    )]
+    InvalidDate(String),
+
+    /// `gcpx config` was given a key it doesn't know (key, valid keys)
+    #[error("Unknown setting '{0}'. Valid keys: {1}")]
+    UnknownSetting(String, String),
+
+    /// A dotted setting's table is some other kind of value in the settings file
+    #[error("'{0}' in the settings file is not a table")]
+    SettingNotTable(String),
+
+    /// A file mode is not octal or out of range
+    #[error("Invalid mode '{0}'. Expected octal, e.g. 600 or 0640.")]
+    InvalidMode(String),
+
+    /// `store_backend = "command"` without both store commands
+    #[error(
+        "store_backend = \"command\" requires a [store_command] table \
+        with get_cmd and put_cmd in the settings file."
+    )]
+    StoreCommandMissing,
+
+    /// A credential store command failed (context, its stderr)
+    #[error("Credential command failed for context '{0}': {1}")]
+    CredentialCommandFailed(String, String),
+
+    /// The directory for temporary credential copies is not private
+    #[error(
+        "Refusing to use {0:?} for temporary credentials: it must be a directory \
+        owned by you with mode 0700."
+    )]
+    UnsafeTempDir(PathBuf),
+
+    /// `repair` without `--force` on a context whose metadata is fine
+    #[error("Context '{0}' already has valid metadata. Pass --force to rebuild it.")]
+    MetadataValid(String),
+
+    /// `rollback` on a context without a kept ADC version
+    #[error(
+        "No previous ADC version saved for '{0}'. \
+        Versions are kept with 'gcpx save --keep-versions <N>'."
+    )]
+    NoPreviousVersion(String),
+
+    /// The trash entry `undo` would restore is gone (entry path, context)
+    #[error("Trash entry {0:?} is missing; '{1}' cannot be restored.")]
+    TrashEntryMissing(PathBuf, String),
+
+    /// A typed confirmation for a protected context didn't match
+    #[error("Confirmation did not match; not switching to '{0}'.")]
+    ConfirmationMismatch(String),
+
+    /// `switch --by-project` matched no context
+    #[error("No context has project '{0}'.")]
+    NoContextForProject(String),
+
+    /// `switch --by-project` matched several contexts (project, their names)
+    #[error(
+        "{count} contexts have project '{0}': {names}. Switch to one by name.",
+        count = .1.len(),
+        names = .1.join(", ")
+    )]
+    AmbiguousProject(String, Vec<String>),
+
+    /// `delete --gcloud-config` on the active gcloud configuration
+    #[error(
+        "gcloud configuration '{0}' is currently active; not deleting it. \
+        Activate another configuration first, or delete the context without --gcloud-config."
+    )]
+    GcloudConfigActive(String),
+
+    /// `delete --gcloud-config` on a configuration other contexts use (config, contexts)
+    #[error(
+        "gcloud configuration '{0}' is also used by: {names}. \
+        Delete the context without --gcloud-config to keep it.",
+        names = .1.join(", ")
+    )]
+    GcloudConfigShared(String, Vec<String>),
+
+    /// `prune --empty-configs` couldn't delete some configurations (failed, total)
+    #[error("{0} of {1} configurations could not be deleted")]
+    PruneFailed(usize, usize),
+
+    /// `adopt --all` couldn't adopt some configurations
+    #[error("{0} configuration(s) could not be adopted")]
+    AdoptFailed(usize),
+
+    /// `list --check-config` with the isolated_configs setting
+    #[error(
+        "--check-config checks the shared gcloud configurations, which contexts don't use \
+        with the isolated_configs setting"
+    )]
+    CheckConfigIsolated,
+
+    /// `list --check-config --strict` found contexts without their configuration
+    #[error("{0} context(s) reference a missing gcloud configuration")]
+    MissingGcloudConfigs(usize),
+
+    /// A `list --filter` key is not one gcpx knows
+    #[error("Unknown filter key '{0}'. Use tag, account, project, config or org.")]
+    UnknownFilterKey(String),
+
+    /// A `list --filter` is not `key=value`
+    #[error("Invalid filter '{0}'. Expected key=value.")]
+    InvalidFilter(String),
+
+    /// `list --broken` found broken contexts
+    #[error("{0} broken context(s). Fix them with 'gcpx repair <name>' or delete them.")]
+    BrokenContexts(usize),
+
+    /// `doctor` left problems unfixed (remaining, found, whether `--fix` was used)
+    #[error(
+        "{0} of {1} problem(s) remain{hint}",
+        hint = if *.2 { "" } else { "; run 'gcpx doctor --fix' to fix what can be fixed" }
+    )]
+    ProblemsRemain(usize, usize, bool),
+
+    /// `doctor --fix` can't set file modes on this platform
+    #[error("file modes are not supported on this platform")]
+    ModesUnsupported,
+
+    /// `show --copy-field adc-path` with the command backend
+    #[error("Credentials for '{0}' are kept by the store command; there is no file to copy.")]
+    NoCredentialFile(String),
+
+    /// `show --copy` of a field the context doesn't have (context, field)
+    #[error("Context '{0}' has no {1} recorded.")]
+    FieldNotRecorded(String, String),
+
+    /// `show --copy` couldn't use the clipboard (reason, context, field)
+    #[error("{0} Use 'gcpx show {1}' and copy the {2} by hand.")]
+    Clipboard(String, String, String),
+
+    /// `export --all` couldn't read some contexts
+    #[error(
+        "{0} context(s) couldn't be read, so no backup was written. Fix them, or pass \
+        --skip-unreadable to back up the rest."
+    )]
+    UnreadableContexts(usize),
+
+    /// An archive entry would be written outside the store
+    #[error("Refusing unsafe path in archive: {0:?}")]
+    UnsafeArchivePath(PathBuf),
+
+    /// An export archive has no `adc.json` for a context (archive, context)
+    #[error("Archive {0:?} has no credentials for '{1}'")]
+    ArchiveMissingCredentials(PathBuf, String),
+
+    /// A full store backup was given to a single-context import
+    #[error("Archive {0:?} is a full store backup; restore it with 'gcpx import --all'")]
+    ArchiveIsBackup(PathBuf),
+
+    /// A single-context archive was given to `import --all`
+    #[error("Archive {0:?} is not a full store backup; import single contexts without --all")]
+    ArchiveNotBackup(PathBuf),
+
+    /// An export archive holds no context
+    #[error("Archive {0:?} contains no context")]
+    EmptyArchive(PathBuf),
+
+    /// A single-context export archive holds several contexts
+    #[error("Archive {0:?} contains more than one context")]
+    ArchiveHasSeveralContexts(PathBuf),
+
+    /// `import-store` from a directory that doesn't exist
+    #[error("Store directory not found: {0:?}")]
+    StoreNotFound(PathBuf),
+
+    /// `import-store` from the store in use
+    #[error("{0:?} is the current store; nothing to import.")]
+    SameStore(PathBuf),
+
+    /// An import couldn't restore some contexts (failed, total)
+    #[error("{0} of {1} contexts could not be imported")]
+    ImportFailed(usize, usize),
+
+    /// The editor exited unsuccessfully
+    #[error("Editor exited with an error; nothing was changed.")]
+    EditorFailed,
+
+    /// Completions are requested for a shell gcpx has no dynamic script for
+    #[error("Dynamic completions are not supported for {0}. Use 'gcpx completions {0}' instead.")]
+    DynamicCompletionsUnsupported(String),
+
+    /// `completions install` for a shell without a standard directory
+    #[error(
+        "No standard completion directory for {0}. Run 'gcpx completions {0}' and load \
+        the output from your profile."
+    )]
+    NoCompletionDir(String),
+
+    /// A `self-test` step produced the wrong result
+    #[error("{0}")]
+    SelfTestCheck(String),
+
+    /// `self-test` had failing steps (failed, total)
+    #[error("Self-test failed: {0} of {1} steps")]
+    SelfTestFailed(usize, usize),
+}
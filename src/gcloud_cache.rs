@@ -0,0 +1,112 @@
+//! Cache of the gcloud account and project that `save` last queried.
+//!
+//! Each `gcloud config get-value` launches a Python process, and setting up
+//! many contexts queries the same configuration over and over. The answers
+//! are kept per gcloud configuration in `<store>/.gcloud-cache.json` and
+//! reused while fresh: younger than [`CACHE_TTL_SECS`], with the
+//! configuration's file unchanged since, and no `CLOUDSDK_CORE_*` override in
+//! the environment. gcpx clears the cache whenever it changes gcloud state
+//! itself (see [`invalidate_gcloud_cache`](crate::config::invalidate_gcloud_cache)).
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::time::UNIX_EPOCH;
+
+use crate::config::{
+    get_gcloud_cache_path, get_gcloud_config_file, get_gcloud_value, now_unix, write_atomic,
+};
+use crate::log::debug;
+
+/// How long a cached answer is reused, in seconds.
+pub const CACHE_TTL_SECS: u64 = 600;
+
+/// Environment variables that override the configuration's account or project.
+const OVERRIDE_VARS: &[&str] = &["CLOUDSDK_CORE_ACCOUNT", "CLOUDSDK_CORE_PROJECT"];
+
+/// The account and project queried for one gcloud configuration.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CacheEntry {
+    account: Option<String>,
+    project: Option<String>,
+    /// When gcloud was queried (Unix seconds)
+    queried_at: u64,
+    /// Modification time of the configuration's file at that point
+    /// (milliseconds since the Unix epoch), if it existed
+    config_modified: Option<u64>,
+}
+
+/// Returns the modification time of a gcloud configuration's file.
+fn config_modified(config: &str) -> Option<u64> {
+    let modified = fs::metadata(get_gcloud_config_file(config).ok()?)
+        .ok()?
+        .modified()
+        .ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_millis() as u64)
+}
+
+fn load_cache() -> BTreeMap<String, CacheEntry> {
+    get_gcloud_cache_path()
+        .ok()
+        .and_then(|path| fs::read(path).ok())
+        .and_then(|content| serde_json::from_slice(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Returns the cached account and project for `config`, if still valid.
+fn cached(config: &str) -> Option<(Option<String>, Option<String>)> {
+    if OVERRIDE_VARS.iter().any(|var| env::var_os(var).is_some()) {
+        return None;
+    }
+    let entry = load_cache().remove(config)?;
+    let fresh = now_unix().saturating_sub(entry.queried_at) < CACHE_TTL_SECS;
+    if !fresh || entry.config_modified != config_modified(config) {
+        return None;
+    }
+    Some((entry.account, entry.project))
+}
+
+/// Returns the account and project of gcloud configuration `config`, from
+/// the cache when `use_cache` is set and the cached answer is still valid,
+/// otherwise by asking gcloud (and caching the answer).
+///
+/// With `active`, `config` is the active configuration and gcloud is asked
+/// about that; otherwise it is selected with `--configuration`.
+pub fn gcloud_account_and_project(
+    config: &str,
+    active: bool,
+    use_cache: bool,
+) -> Result<(Option<String>, Option<String>)> {
+    if use_cache {
+        if let Some(answer) = cached(config) {
+            debug(format_args!(
+                "using cached account and project of gcloud config '{}'",
+                config
+            ));
+            return Ok(answer);
+        }
+    }
+    let configuration = (!active).then_some(config);
+    let account = get_gcloud_value("account", configuration)?;
+    let project = get_gcloud_value("project", configuration)?;
+
+    // The cache only saves time, so failing to write it is not an error
+    let mut cache = load_cache();
+    cache.insert(
+        config.to_string(),
+        CacheEntry {
+            account: account.clone(),
+            project: project.clone(),
+            queried_at: now_unix(),
+            config_modified: config_modified(config),
+        },
+    );
+    if let (Ok(path), Ok(content)) = (get_gcloud_cache_path(), serde_json::to_vec_pretty(&cache)) {
+        if path.parent().is_some_and(|dir| dir.exists()) {
+            let _ = write_atomic(&path, &content);
+        }
+    }
+    Ok((account, project))
+}
@@ -0,0 +1,49 @@
+//! Context groups (workspaces).
+//!
+//! A group names a primary context plus overrides applied on top of it when
+//! switching with `gcpx switch-group`: a kubectl context and environment
+//! variables. Groups live in `<store>/groups.json`, separate from contexts,
+//! and refer to contexts by name.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::{get_store_dir, write_atomic};
+
+/// A named group, as stored in `groups.json`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Group {
+    /// The context to switch to
+    pub context: String,
+    /// kubectl context to use instead of the one saved with the context (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kubectl_context: Option<String>,
+    /// Environment variables to export in the shell (see `gcpx groups env`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
+
+/// Returns the path to the groups file.
+pub fn get_groups_path() -> Result<PathBuf> {
+    Ok(get_store_dir()?.join("groups.json"))
+}
+
+/// Loads all groups. Returns an empty map if the groups file doesn't exist.
+pub fn load_groups() -> Result<BTreeMap<String, Group>> {
+    let path = get_groups_path()?;
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?;
+    serde_json::from_str(&content).with_context(|| format!("Invalid groups file: {:?}", path))
+}
+
+/// Writes all groups atomically.
+pub fn save_groups(groups: &BTreeMap<String, Group>) -> Result<()> {
+    let json = serde_json::to_string_pretty(groups)?;
+    write_atomic(&get_groups_path()?, json.as_bytes())
+}
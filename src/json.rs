@@ -0,0 +1,59 @@
+//! Versioned JSON output.
+//!
+//! Every `--json` output is a [`JsonEnvelope`]: a top-level object carrying the
+//! schema version and the current context next to the command's own fields,
+//! so tools can detect schema changes and read the active context from any
+//! command. Bump [`JSON_SCHEMA_VERSION`] when a field changes meaning or is
+//! removed; adding fields is not a breaking change.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::path::PathBuf;
+
+use crate::config::{ContextMetadata, get_current_context_checked};
+
+/// Version of the JSON schema emitted by `--json` outputs.
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+/// Top-level object of every `--json` output.
+#[derive(Debug, Serialize)]
+pub struct JsonEnvelope<T: Serialize> {
+    pub version: u32,
+    /// The active context, or null if none
+    pub current: Option<String>,
+    #[serde(flatten)]
+    pub data: T,
+}
+
+impl<T: Serialize> JsonEnvelope<T> {
+    /// Wraps command output, filling in the version and current context.
+    pub fn new(data: T) -> Self {
+        JsonEnvelope {
+            version: JSON_SCHEMA_VERSION,
+            current: get_current_context_checked(),
+            data,
+        }
+    }
+
+    /// Prints the envelope as pretty JSON on stdout.
+    pub fn print(&self) -> Result<()> {
+        println!("{}", serde_json::to_string_pretty(self)?);
+        Ok(())
+    }
+}
+
+/// A single context: its name, whether it is active, and its metadata fields.
+#[derive(Debug, Serialize)]
+pub struct ContextEntry<'a> {
+    pub name: &'a str,
+    pub active: bool,
+    /// Path of the context's `adc.json`, when requested (`list --paths`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub adc_path: Option<PathBuf>,
+    /// Whether the context's gcloud configuration is missing, when checked
+    /// (`list --check-config`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gcloud_config_missing: Option<bool>,
+    #[serde(flatten)]
+    pub metadata: Option<&'a ContextMetadata>,
+}
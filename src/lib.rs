@@ -17,10 +17,25 @@
 
 pub mod commands;
 pub mod config;
+pub mod credstore;
+pub mod editor;
+pub mod error;
+pub mod gcloud_cache;
+pub mod groups;
+pub mod json;
+pub mod log;
+pub mod prompt;
+pub mod settings;
+pub mod style;
+pub mod trash;
+pub mod verify;
 
 // Re-export commonly used items
 pub use commands::{
-    delete_context, interactive_switch, login_context, run_with_context, save_context,
-    switch_context,
+    LoginOptions, SaveOptions, SwitchOptions, delete_context, interactive_switch, login_context,
+    login_context_with_options, rename_context, run_with_context, save_context,
+    save_context_with_options, switch_context, switch_context_with_options, touch_context,
+    undo_last_op,
 };
 pub use config::{ContextMetadata, get_current_tracking, list_contexts, validate_context_name};
+pub use error::GcpxError;
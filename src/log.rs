@@ -0,0 +1,21 @@
+//! Opt-in diagnostic logging.
+//!
+//! Set `GCPX_LOG` to any value other than empty or `0` to have gcpx explain
+//! what it is doing (skipped steps, commands run) on stderr.
+
+use std::env;
+use std::fmt;
+
+/// Returns true if diagnostic logging is enabled via `GCPX_LOG`.
+pub fn log_enabled() -> bool {
+    env::var("GCPX_LOG").is_ok_and(|v| !v.is_empty() && v != "0")
+}
+
+/// Prints a diagnostic message to stderr if logging is enabled.
+///
+/// Call with `format_args!` so nothing is formatted when logging is off.
+pub fn debug(message: fmt::Arguments) {
+    if log_enabled() {
+        eprintln!("gcpx: {}", message);
+    }
+}
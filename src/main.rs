@@ -1,24 +1,144 @@
 //! gcpx CLI entry point.
 
 use anyhow::Result;
+use clap::error::ErrorKind;
 use clap::{CommandFactory, Parser, Subcommand};
 use clap_complete::{Shell, generate};
-use std::io;
+use std::io::{self, Read};
+use std::path::PathBuf;
 
+use gcpx::commands::complete::{
+    complete_flag_value, complete_names, dynamic_completion_script, install_completions,
+};
 use gcpx::commands::{
-    delete_context, interactive_switch, login_context, run_with_context, save_context,
-    switch_context,
+    AdoptAdcOptions, CopyField, DoctorOptions, ExportOptions, ExportShell, GroupBy, ImportOptions,
+    ImportStoreOptions, InteractiveOptions, ListOptions, LoginOptions, OutputFormat, PruneOptions,
+    RepairOptions, RunEachOptions, RunOptions, SaveOptions, SortOrder, SwitchOptions, add_group,
+    adopt_adc_file, adopt_all_gcloud_configs, adopt_gcloud_config, config_get, config_list,
+    config_set, config_unset, context_for_project, copy_context_field, delete_context, doctor,
+    export_context, export_store, import_backup, import_context, import_store,
+    interactive_switch_with_options, list_contexts_cmd, list_groups, login_context_with_options,
+    print_env_exports, print_group_env, print_run_env, prune_gcloud_configs, reauth_if_needed,
+    remove_group, rename_context, rename_gcloud_config, repair_context, rollback_context, run_each,
+    run_with_context, run_with_context_file, run_with_context_options, save_context_with_options,
+    self_test, show_context, show_status, show_tree, switch_context_with_options, switch_group,
+    touch_context, undo_last_op, whoami,
+};
+use gcpx::config::{
+    get_current_context_checked, get_previous_tracking, parse_time_spec, set_store_dir_override,
 };
-use gcpx::config::{get_current_tracking, list_contexts};
+use gcpx::credstore::{CredentialMode, parse_mode};
+use gcpx::prompt::set_no_input;
 
 #[derive(Parser)]
 #[command(name = "gcpx")]
 #[command(author, version, about = "GCP Context Switcher - manage multiple gcloud accounts", long_about = None)]
 struct Cli {
+    /// Use an alternate context store for this invocation (overrides GCPX_HOME)
+    #[arg(long, global = true, value_name = "PATH")]
+    store_dir: Option<PathBuf>,
+
+    /// Never prompt; fail instead (implied when stdin is not a terminal, or
+    /// in CI: CI or GCPX_CI set)
+    #[arg(long, global = true)]
+    no_input: bool,
+
+    /// Only offer contexts whose account contains this text (interactive mode)
+    #[arg(long, value_name = "SUBSTR")]
+    account: Option<String>,
+
+    /// Offer only the N most recently used contexts, plus "show all" (interactive mode)
+    #[arg(long, value_name = "N", conflicts_with = "context")]
+    limit: Option<usize>,
+
+    /// Run the command after '--' with this context, like 'gcpx run'
+    /// (e.g. 'gcpx -c work -- terraform plan')
+    #[arg(
+        short = 'c',
+        long,
+        value_name = "NAME",
+        requires = "cmd",
+        conflicts_with = "account"
+    )]
+    context: Option<String>,
+
+    /// Command to run with --context
+    #[arg(last = true, value_name = "COMMAND", requires = "context")]
+    cmd: Vec<String>,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
 
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print a setting's value
+    Get {
+        /// Setting name (see 'gcpx config list')
+        key: String,
+    },
+    /// Change a setting (lists are comma-separated)
+    Set {
+        /// Setting name (see 'gcpx config list')
+        key: String,
+        /// New value
+        value: String,
+    },
+    /// Remove a setting, reverting it to its default
+    Unset {
+        /// Setting name (see 'gcpx config list')
+        key: String,
+    },
+    /// Show the settings that are set and every available key
+    List,
+}
+
+#[derive(Subcommand)]
+enum GroupsAction {
+    /// Create or replace a group
+    Add {
+        /// Group name
+        name: String,
+        /// Context the group switches to
+        context: String,
+        /// kubectl context to use instead of the context's own
+        #[arg(long, value_name = "KUBE_CONTEXT")]
+        kubectl: Option<String>,
+        /// Environment variable to export (repeatable)
+        #[arg(long, value_name = "KEY=VALUE")]
+        env: Vec<String>,
+    },
+    /// Delete a group
+    Remove {
+        /// Group name
+        name: String,
+    },
+    /// Print export statements for a group's environment variables
+    Env {
+        /// Group name
+        name: String,
+        /// Shell syntax to print
+        #[arg(long, value_enum, default_value_t = ExportShell::Bash)]
+        shell: ExportShell,
+    },
+}
+
+#[derive(Subcommand)]
+enum CompletionsAction {
+    /// Write the completion script where the shell loads per-user completions from
+    Install {
+        /// Shell to install completions for (bash, zsh or fish)
+        #[arg(value_enum)]
+        shell: Shell,
+        /// Complete context names at completion time by calling back into gcpx
+        #[arg(long)]
+        dynamic: bool,
+        /// Print the script instead of writing it (like 'gcpx completions <shell>')
+        #[arg(long)]
+        print: bool,
+    },
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Save current gcloud state as a named context
@@ -28,23 +148,322 @@ enum Commands {
         /// Quiet mode - hide sensitive details (account, project, etc.)
         #[arg(short, long)]
         quiet: bool,
+        /// Description to store with the context
+        #[arg(long, conflicts_with = "edit_description")]
+        description: Option<String>,
+        /// Compose the description in $EDITOR
+        #[arg(long)]
+        edit_description: bool,
+        /// Also capture gcloud properties (region, zone, plus the
+        /// capture_properties setting) and replay them on switch
+        #[arg(long)]
+        all_properties: bool,
+        /// Tag to attach to the context (repeatable)
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+        /// Service account to impersonate when using this context; a
+        /// comma-separated list is a delegation chain ending at the target
+        #[arg(long, value_name = "SERVICE_ACCOUNT", value_delimiter = ',')]
+        impersonate: Vec<String>,
+        /// Save credentials from this file instead of the gcloud ADC path
+        #[arg(long, value_name = "PATH")]
+        adc_path: Option<PathBuf>,
+        /// Save the credentials named by GOOGLE_APPLICATION_CREDENTIALS, even if
+        /// gcloud has written an ADC file
+        #[arg(long, conflicts_with_all = ["adc_path", "stdin"])]
+        from_env: bool,
+        /// Flag as a production context: switching shows a warning and asks for
+        /// confirmation (also implied by the 'prod' tag)
+        #[arg(long)]
+        danger: bool,
+        /// Keep up to N previous credential versions for 'gcpx rollback'
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        keep_versions: usize,
+        /// Leave the credential files' permissions alone (for filesystems that
+        /// don't support Unix modes)
+        #[arg(long, conflicts_with = "mode")]
+        no_permissions: bool,
+        /// Octal mode for the credential files (default 600, or the
+        /// credential_mode setting)
+        #[arg(long, value_name = "OCTAL", value_parser = parse_mode)]
+        mode: Option<u32>,
+        /// Read the credential JSON from stdin instead of gcloud's ADC file
+        /// (gcloud and kubectl are not queried)
+        #[arg(long, conflicts_with_all = ["adc_path", "all_properties"])]
+        stdin: bool,
+        /// Account to record (instead of the live gcloud account)
+        #[arg(long)]
+        account: Option<String>,
+        /// Project to record (instead of the live gcloud project)
+        #[arg(long)]
+        project: Option<String>,
+        /// Check that the active gcloud account can see --project (network
+        /// call); warn if it can't
+        #[arg(long, requires = "project")]
+        check_project: bool,
+        /// With --check-project, refuse to save an inaccessible project
+        #[arg(long, requires = "check_project")]
+        strict: bool,
+        /// gcloud configuration to record (instead of the active one); the
+        /// account and project are read from it
+        #[arg(long = "config", value_name = "CONFIG")]
+        gcloud_config: Option<String>,
+        /// Copy the active gcloud configuration to a new one with this name and
+        /// record that instead
+        #[arg(long, value_name = "NEW_CONFIG", conflicts_with_all = ["gcloud_config", "stdin"])]
+        copy_current_config_as: Option<String>,
+        /// Keep the existing description, tags, impersonation, danger flag and
+        /// run policy unless given again; only captured state is replaced
+        #[arg(long)]
+        merge_metadata: bool,
+        /// Show what would be captured and where it would be stored, without
+        /// writing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Fail if gcloud has no active configuration instead of recording 'default'
+        #[arg(long)]
+        require_active_config: bool,
+        /// For external_account credentials, keep a copy of the credential
+        /// source file with the context
+        #[arg(long)]
+        bundle_credential_source: bool,
+        /// Keep a verbatim copy of the gcloud configuration file and write it
+        /// back over the configuration on every switch
+        #[arg(long)]
+        snapshot_config: bool,
+        /// Skip the save (printing "no changes") if the credentials and
+        /// metadata match what is already stored
+        #[arg(long, conflicts_with = "copy_current_config_as")]
+        if_changed: bool,
+        /// Ask gcloud for the account and project even if a recent answer is
+        /// cached (gcpx's own gcloud changes already clear the cache)
+        #[arg(long, conflicts_with = "stdin")]
+        force_account_query: bool,
+        /// Refuse this command in 'gcpx run' without --override-policy: a
+        /// program, optionally with words its arguments contain in order,
+        /// e.g. 'terraform apply' (repeatable)
+        #[arg(long, value_name = "COMMAND")]
+        run_deny: Vec<String>,
+        /// Only allow these commands in 'gcpx run' without --override-policy
+        /// (same form as --run-deny; repeatable)
+        #[arg(long, value_name = "COMMAND")]
+        run_allow: Vec<String>,
+        /// kubectl context to pair with this context (instead of the active one)
+        #[arg(long, value_name = "KUBE_CONTEXT", conflicts_with = "no_kube")]
+        kube_context: Option<String>,
+        /// Don't record a kubectl context
+        #[arg(long)]
+        no_kube: bool,
     },
     /// Switch to a saved context
     Switch {
-        /// Context name (interactive if omitted)
+        /// Context name (interactive if omitted, '-' for the previous context)
+        #[arg(group = "target")]
         name: Option<String>,
+        /// Switch to the one context whose saved project is ID
+        #[arg(long, value_name = "ID", group = "target")]
+        by_project: Option<String>,
+        /// Only offer contexts whose account contains this text (interactive mode)
+        #[arg(long, value_name = "SUBSTR", conflicts_with = "target")]
+        account: Option<String>,
+        /// Offer only the N most recently used contexts, plus "show all" (interactive mode)
+        #[arg(long, value_name = "N", conflicts_with = "target")]
+        limit: Option<usize>,
         /// Quiet mode - hide sensitive details (account, project, etc.)
         #[arg(short, long)]
         quiet: bool,
+        /// Switch the gcloud configuration but leave the current ADC in place
+        #[arg(long)]
+        no_adc: bool,
+        /// Don't update tracking, so 'current' and 'switch -' still refer to the
+        /// previously tracked context (live state will diverge by design)
+        #[arg(long)]
+        no_track: bool,
+        /// Skip the confirmation prompt for production contexts
+        #[arg(long)]
+        force: bool,
+        /// Print a single log-safe line (previous -> new context, gcloud
+        /// config) instead of the usual output; never shows account or project
+        #[arg(long, requires = "target", conflicts_with = "quiet")]
+        print: bool,
+        /// Write the credentials to FILE instead of the standard ADC location
+        #[arg(
+            long,
+            value_name = "FILE",
+            requires = "target",
+            conflicts_with = "no_adc"
+        )]
+        adc_dest: Option<PathBuf>,
+        /// Only activate the gcloud configuration and update tracking; leave
+        /// ADC and kubectl untouched
+        #[arg(long, requires = "target", conflicts_with_all = ["no_adc", "adc_dest"])]
+        activate_only: bool,
+        /// If the context doesn't exist, warn on stderr and exit 0 instead of
+        /// failing (other errors still fail)
+        #[arg(long, requires = "name")]
+        if_exists: bool,
+        /// Overwrite the gcloud configuration with the context's config
+        /// snapshot (see 'save --snapshot-config'), discarding changes made
+        /// since it was saved
+        #[arg(long)]
+        restore_config: bool,
     },
     /// Print the currently active context (for shell prompts)
-    Current,
+    Current {
+        /// Print the previous context instead (the one 'switch -' goes back to)
+        #[arg(long)]
+        previous: bool,
+    },
+    /// Show the tracked context and whether live gcloud/ADC state matches it
+    Status {
+        /// Print a versioned JSON object
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show the account and project actually in effect (live gcloud and ADC)
+    Whoami {
+        /// Print a versioned JSON object
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show the saved details of a context
+    Show {
+        /// Context name
+        name: String,
+        /// Print a versioned JSON object
+        #[arg(long)]
+        json: bool,
+        /// Copy the account to the clipboard instead of printing details
+        #[arg(long, conflicts_with = "json")]
+        copy: bool,
+        /// Copy this field to the clipboard instead of printing details
+        #[arg(long, value_enum, value_name = "FIELD", conflicts_with = "json")]
+        copy_field: Option<CopyField>,
+    },
+    /// Print the store directory layout, marking incomplete contexts
+    Tree,
     /// List all saved contexts
-    List,
+    List {
+        /// Output format
+        #[arg(long, value_enum, default_value_t)]
+        format: OutputFormat,
+        /// Sort order
+        #[arg(long, value_enum, default_value_t)]
+        sort: SortOrder,
+        /// Only show contexts matching key=value (tag, account, project, config, org; repeatable)
+        #[arg(long = "filter", value_name = "KEY=VALUE")]
+        filters: Vec<String>,
+        /// Check saved user credentials against Google and group contexts into
+        /// valid, revoked and couldn't-check (makes network calls)
+        #[arg(long)]
+        stale: bool,
+        /// Print only the number of contexts
+        #[arg(long, conflicts_with_all = ["stale", "summary"])]
+        count: bool,
+        /// Append a footer with the total and the active context (plain and
+        /// table formats)
+        #[arg(long)]
+        summary: bool,
+        /// Print a versioned JSON object ({"version", "current", "contexts"})
+        #[arg(long, conflicts_with_all = ["format", "count", "stale", "summary"])]
+        json: bool,
+        /// Cluster contexts under a heading per group (plain and table formats)
+        #[arg(long, value_enum, conflicts_with_all = ["json", "count", "stale"])]
+        group_by: Option<GroupBy>,
+        /// Show only contexts with missing or invalid files, with a reason each;
+        /// exits nonzero if there are any
+        #[arg(
+            long,
+            conflicts_with_all = ["format", "sort", "filters", "stale", "count", "summary", "json", "group_by", "since", "before", "active_only", "inactive_only", "paths", "check_config"]
+        )]
+        broken: bool,
+        /// Only show contexts last used at or after DATE (YYYY-MM-DD[ HH:MM],
+        /// Unix time, today, yesterday, or an age like 30d)
+        #[arg(long, value_name = "DATE", value_parser = parse_time_spec)]
+        since: Option<u64>,
+        /// Only show contexts last used before DATE, or never
+        #[arg(long, value_name = "DATE", value_parser = parse_time_spec)]
+        before: Option<u64>,
+        /// Only show the active context, if it still exists (bare name in plain format)
+        #[arg(long, conflicts_with = "inactive_only")]
+        active_only: bool,
+        /// Show every context except the active one (bare names in plain format)
+        #[arg(long)]
+        inactive_only: bool,
+        /// Print each context's adc.json path (name<TAB>path in plain format,
+        /// an adc_path field in JSON; '-' or no field when the credential
+        /// store keeps no file)
+        #[arg(long, conflicts_with_all = ["count", "stale", "summary", "group_by"])]
+        paths: bool,
+        /// Mark contexts whose gcloud configuration no longer exists (one
+        /// 'gcloud config configurations list' call)
+        #[arg(long, conflicts_with_all = ["count", "stale"])]
+        check_config: bool,
+        /// With --check-config, exit nonzero if any configuration is missing
+        #[arg(long, requires = "check_config")]
+        strict: bool,
+    },
     /// Run a command with a specific context (isolated)
     Run {
         /// Context name to use
-        name: String,
+        #[arg(required_unless_present = "context_file")]
+        name: Option<String>,
+        /// Use this credential file instead of a saved context; only
+        /// GOOGLE_APPLICATION_CREDENTIALS is set. Takes no context name, and
+        /// the command goes after '--'
+        #[arg(long, value_name = "ADC_JSON", conflicts_with = "print_env")]
+        context_file: Option<PathBuf>,
+        /// Print the environment that would be set, then exit without running
+        #[arg(long)]
+        print_env: bool,
+        /// Write a JSON summary (context, exit_code, duration_ms) to FILE after the command exits
+        #[arg(long, value_name = "FILE", conflicts_with = "print_env")]
+        report: Option<PathBuf>,
+        /// Start the command with an empty environment (plus the context's
+        /// variables, --env-passthrough and --env)
+        #[arg(long, conflicts_with = "print_env")]
+        clean_env: bool,
+        /// With --clean-env, keep this variable from the current environment;
+        /// '*' and '?' globs allowed, e.g. 'AWS_*' (repeatable)
+        #[arg(long, value_name = "PATTERN", requires = "clean_env")]
+        env_passthrough: Vec<String>,
+        /// Set a variable for the command, overriding inherited and context
+        /// variables (repeatable)
+        #[arg(long, value_name = "KEY=VALUE", conflicts_with = "print_env")]
+        env: Vec<String>,
+        /// Run the command even if the context's run policy (--run-deny /
+        /// --run-allow on save) forbids it
+        #[arg(long, conflicts_with_all = ["print_env", "context_file"])]
+        override_policy: bool,
+        /// Command and arguments to run
+        #[arg(
+            trailing_var_arg = true,
+            required_unless_present_any = ["print_env", "context_file"]
+        )]
+        cmd: Vec<String>,
+    },
+    /// Print export statements for a context (use with: eval "$(gcpx env)")
+    Env {
+        /// Context name (defaults to the current context)
+        name: Option<String>,
+        /// Shell syntax to print
+        #[arg(long, value_enum, default_value_t = ExportShell::Bash)]
+        shell: ExportShell,
+    },
+    /// Run a command in several contexts, labeling output by context
+    RunEach {
+        /// Comma-separated contexts to run in
+        #[arg(long, value_delimiter = ',', required_unless_present = "tag")]
+        contexts: Vec<String>,
+        /// Also run in every context with this tag
+        #[arg(long)]
+        tag: Option<String>,
+        /// Run in up to N contexts at once
+        #[arg(long, value_name = "N", default_value_t = 1)]
+        parallel: usize,
+        /// Run the command even in contexts whose run policy forbids it
+        #[arg(long)]
+        override_policy: bool,
         /// Command and arguments to run
         #[arg(trailing_var_arg = true, required = true)]
         cmd: Vec<String>,
@@ -57,6 +476,176 @@ enum Commands {
         #[arg(long)]
         gcloud_config: bool,
     },
+    /// Restore the context removed or overwritten by the last delete/save
+    Undo,
+    /// View or change gcpx's own settings (~/.config/gcpx/config.toml)
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// List context groups, or manage them
+    Groups {
+        #[command(subcommand)]
+        action: Option<GroupsAction>,
+    },
+    /// Switch to a group's context and apply its kubectl override
+    SwitchGroup {
+        /// Group name
+        name: String,
+        /// Quiet mode - hide sensitive details (account, project, etc.)
+        #[arg(short, long)]
+        quiet: bool,
+    },
+    /// Delete gcloud configurations that no context uses (never the active one)
+    Prune {
+        /// Select gcloud configurations not referenced by any context
+        #[arg(long, required = true)]
+        empty_configs: bool,
+        /// Only list what would be deleted
+        #[arg(long)]
+        dry_run: bool,
+        /// Delete without asking for confirmation
+        #[arg(short, long, conflicts_with = "dry_run")]
+        yes: bool,
+    },
+    /// Check that gcpx works here: save, list, switch and delete in a temporary store
+    SelfTest,
+    /// Check the store for broken contexts, stale tracking and leftover temp files
+    Doctor {
+        /// Fix what can be fixed without new credentials (replaced files go to .trash)
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Write a context to a .tar.gz archive for `gcpx import`
+    Export {
+        /// Context to export
+        #[arg(required_unless_present = "all")]
+        name: Option<String>,
+        /// Archive to create
+        #[arg(required_unless_present = "all")]
+        file: Option<PathBuf>,
+        /// Replace machine-specific paths in the credentials with placeholders
+        /// that `import` resolves against the new store
+        #[arg(long)]
+        portable: bool,
+        /// Back up the whole store to FILE: every context, the tracking files,
+        /// groups.json and the settings file
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["name", "file"])]
+        all: Option<PathBuf>,
+        /// With --all, leave out contexts whose credentials can't be read
+        /// instead of failing
+        #[arg(long, requires = "all")]
+        skip_unreadable: bool,
+    },
+    /// Restore a context from an archive written by `gcpx export`
+    Import {
+        /// Archive to import
+        file: PathBuf,
+        /// Import the context under a different name
+        #[arg(long = "as", value_name = "NAME", conflicts_with = "all")]
+        name: Option<String>,
+        /// Replace an existing context of the same name (with --all, also the
+        /// tracking files, groups.json and settings)
+        #[arg(long)]
+        overwrite: bool,
+        /// Restore a full store backup written by 'gcpx export --all'
+        #[arg(long)]
+        all: bool,
+    },
+    /// Copy contexts from another gcpx store directory (e.g. an old machine's ~/.gcpx)
+    ImportStore {
+        /// Store directory to import from
+        path: PathBuf,
+        /// Contexts to import
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        names: Vec<String>,
+        /// Import every context in the store
+        #[arg(long)]
+        all: bool,
+        /// Replace contexts that already exist instead of skipping them
+        #[arg(long)]
+        overwrite: bool,
+    },
+    /// Register an existing gcloud configuration as a context of the same name
+    Adopt {
+        /// gcloud configuration to adopt
+        #[arg(required_unless_present = "all")]
+        config: Option<String>,
+        /// Adopt every gcloud configuration that isn't a context yet
+        #[arg(long, conflicts_with = "config")]
+        all: bool,
+        /// Quiet mode - hide sensitive details (account, project, etc.)
+        #[arg(short, long)]
+        quiet: bool,
+    },
+    /// Create a context from an existing credential file (e.g. a hand-managed adc-work.json)
+    AdoptAdc {
+        /// Credential file to adopt
+        file: PathBuf,
+        /// Name of the new context
+        name: String,
+        /// Account to record (defaults to the one named in the credentials)
+        #[arg(long)]
+        account: Option<String>,
+        /// Project to record (defaults to the credentials' quota or key project)
+        #[arg(long)]
+        project: Option<String>,
+        /// gcloud configuration to record (defaults to 'default')
+        #[arg(long = "config", value_name = "CONFIG")]
+        gcloud_config: Option<String>,
+        /// Quiet mode - hide sensitive details (account, project, etc.)
+        #[arg(short, long)]
+        quiet: bool,
+    },
+    /// Rename a gcloud configuration and update the contexts that use it
+    RenameConfig {
+        /// Current gcloud configuration name
+        old: String,
+        /// New gcloud configuration name
+        new: String,
+    },
+    /// Switch to a context and run 'login' only if its credentials were revoked or expired
+    ReauthIfNeeded {
+        /// Context name
+        name: String,
+        /// Quiet mode - hide sensitive details (account, project, etc.)
+        #[arg(short, long)]
+        quiet: bool,
+    },
+    /// Restore a context's previous credentials (see 'save --keep-versions')
+    Rollback {
+        /// Context name to roll back
+        name: String,
+    },
+    /// Rename a saved context
+    Rename {
+        /// Current context name
+        old: String,
+        /// New context name
+        new: String,
+    },
+    /// Mark a context as recently used without switching to it
+    Touch {
+        /// Context name to touch
+        name: String,
+    },
+    /// Rebuild missing or broken metadata for a context from its credentials
+    Repair {
+        /// Context name to repair
+        name: String,
+        /// Rebuild the metadata even if it is valid
+        #[arg(long)]
+        force: bool,
+        /// Account to record (otherwise read from the credentials or prompted for)
+        #[arg(long)]
+        account: Option<String>,
+        /// Project to record (otherwise read from the credentials or prompted for)
+        #[arg(long)]
+        project: Option<String>,
+        /// gcloud configuration to record (defaults to the context name)
+        #[arg(long = "config", value_name = "CONFIG")]
+        gcloud_config: Option<String>,
+    },
     /// Re-authenticate and save credentials for a context
     Login {
         /// Context name to authenticate
@@ -64,63 +653,563 @@ enum Commands {
         /// Quiet mode - hide sensitive details (account, project, etc.)
         #[arg(short, long)]
         quiet: bool,
+        /// Service account to impersonate, or a comma-separated delegation
+        /// chain (keeps the saved one if omitted)
+        #[arg(long, value_name = "SERVICE_ACCOUNT", value_delimiter = ',')]
+        impersonate: Vec<String>,
+        /// Keep up to N previous credential versions for 'gcpx rollback'
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        keep_versions: usize,
+        /// Copy the active gcloud configuration to a new one with this name and
+        /// log in there
+        #[arg(long, value_name = "NEW_CONFIG")]
+        copy_current_config_as: Option<String>,
+        /// Don't launch a browser: open the URL gcloud prints on any machine and
+        /// paste the code back (for SSH sessions and headless servers)
+        #[arg(long)]
+        no_browser: bool,
     },
     /// Generate shell completions
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
     Completions {
+        #[command(subcommand)]
+        action: Option<CompletionsAction>,
         /// Shell to generate completions for
-        #[arg(value_enum)]
-        shell: Shell,
+        #[arg(value_enum, required = true)]
+        shell: Option<Shell>,
+        /// Complete context names at completion time by calling back into gcpx
+        #[arg(long)]
+        dynamic: bool,
     },
 }
 
+/// Arguments of `gcpx __complete`, which prints completion candidates for the
+/// dynamic completion scripts.
+///
+/// It is parsed apart from [`Cli`] so the static completion generators never
+/// see it: clap_complete's bash generator can't handle the `__complete` name.
+#[derive(Parser)]
+#[command(name = "gcpx __complete")]
+struct CompleteArgs {
+    /// Subcommand being completed
+    #[arg(allow_hyphen_values = true)]
+    subcommand: String,
+    /// Partial word typed so far
+    #[arg(default_value = "", allow_hyphen_values = true)]
+    prefix: String,
+    /// Complete the value of this flag instead of a positional argument
+    #[arg(long, value_name = "FLAG", allow_hyphen_values = true)]
+    after: Option<String>,
+}
+
+/// Generates the completion script for a shell, completing context names at
+/// completion time with `dynamic`.
+fn completion_script(shell: Shell, dynamic: bool) -> Result<String> {
+    let mut cmd = Cli::command();
+    if dynamic {
+        let subcommands: Vec<String> = cmd
+            .get_subcommands()
+            .filter(|sub| !sub.is_hide_set())
+            .map(|sub| sub.get_name().to_string())
+            .collect();
+        return dynamic_completion_script(shell, &subcommands);
+    }
+    let mut script = Vec::new();
+    generate(shell, &mut cmd, "gcpx", &mut script);
+    Ok(String::from_utf8(script)?)
+}
+
+/// Prints the completion candidates for `gcpx __complete`, one per line.
+fn print_completion_candidates(args: CompleteArgs) -> Result<()> {
+    let candidates = match args.after {
+        Some(flag) => complete_flag_value(&Cli::command(), &args.subcommand, &flag, &args.prefix)?,
+        None => complete_names(&args.subcommand, &args.prefix)?,
+    };
+    for name in candidates {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+/// Reads all of standard input.
+fn read_stdin() -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    io::stdin().read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
 fn main() -> Result<()> {
+    if std::env::args_os()
+        .nth(1)
+        .is_some_and(|arg| arg == "__complete")
+    {
+        return print_completion_candidates(CompleteArgs::parse_from(std::env::args_os().skip(1)));
+    }
+    // Background helper `show --copy` starts to keep the X11 clipboard filled
+    #[cfg(all(
+        unix,
+        not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+    ))]
+    if std::env::args_os()
+        .nth(1)
+        .is_some_and(|arg| arg == "__hold-clipboard")
+    {
+        return gcpx::commands::hold_clipboard();
+    }
     let cli = Cli::parse();
 
+    if cli.account.is_some() && cli.command.is_some() {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--account only applies to interactive mode; use 'gcpx switch --account' instead",
+            )
+            .exit();
+    }
+
+    if cli.limit.is_some() && cli.command.is_some() {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--limit only applies to interactive mode; use 'gcpx switch --limit' instead",
+            )
+            .exit();
+    }
+
+    if cli.context.is_some() && cli.command.is_some() {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--context runs the command after '--'; it can't be combined with a subcommand",
+            )
+            .exit();
+    }
+
+    set_no_input(cli.no_input);
+
+    if let Some(dir) = &cli.store_dir {
+        set_store_dir_override(Some(dir));
+    }
+
     match cli.command {
-        Some(Commands::Save { name, quiet }) => save_context(&name, quiet)?,
-        Some(Commands::Switch { name, quiet }) => {
+        Some(Commands::Save {
+            name,
+            quiet,
+            description,
+            edit_description,
+            all_properties,
+            tags,
+            impersonate,
+            adc_path,
+            from_env,
+            danger,
+            keep_versions,
+            no_permissions,
+            mode,
+            stdin,
+            account,
+            project,
+            check_project,
+            strict,
+            gcloud_config,
+            copy_current_config_as,
+            merge_metadata,
+            dry_run,
+            require_active_config,
+            bundle_credential_source,
+            snapshot_config,
+            if_changed,
+            force_account_query,
+            run_deny,
+            run_allow,
+            kube_context,
+            no_kube,
+        }) => save_context_with_options(
+            &name,
+            &SaveOptions {
+                quiet,
+                description,
+                edit_description,
+                all_properties,
+                tags,
+                impersonate,
+                adc_path,
+                from_env,
+                danger,
+                keep_versions,
+                credential_mode: if no_permissions {
+                    Some(CredentialMode::Skip)
+                } else {
+                    mode.map(CredentialMode::Mode)
+                },
+                adc_json: if stdin { Some(read_stdin()?) } else { None },
+                account,
+                project,
+                check_project,
+                strict,
+                gcloud_config,
+                copy_current_config_as,
+                merge_metadata,
+                dry_run,
+                require_active_config,
+                bundle_credential_source,
+                snapshot_config,
+                if_changed,
+                force_account_query,
+                run_deny,
+                run_allow,
+                kube_context,
+                no_kube,
+                no_track: false,
+            },
+        )?,
+        Some(Commands::Switch {
+            name,
+            by_project,
+            account,
+            limit,
+            quiet,
+            no_adc,
+            no_track,
+            force,
+            print,
+            adc_dest,
+            activate_only,
+            if_exists,
+            restore_config,
+        }) => {
+            let name = match by_project {
+                Some(project) => Some(context_for_project(&project)?),
+                None => name,
+            };
             if let Some(n) = name {
-                switch_context(&n, quiet)?
+                let options = SwitchOptions {
+                    quiet,
+                    no_adc,
+                    no_track,
+                    force,
+                    print,
+                    adc_dest,
+                    activate_only,
+                    if_exists,
+                    restore_config,
+                };
+                switch_context_with_options(&n, &options)?
             } else {
-                interactive_switch(quiet)?
+                interactive_switch_with_options(&InteractiveOptions {
+                    quiet,
+                    account,
+                    limit,
+                    switch: SwitchOptions {
+                        no_adc,
+                        no_track,
+                        restore_config,
+                        ..Default::default()
+                    },
+                })?
             }
         }
-        Some(Commands::List) => {
-            let current = get_current_tracking();
-            let ctxs = list_contexts()?;
-            if ctxs.is_empty() {
-                println!("No contexts found. Create one with 'gcpx save <name>'");
-            } else {
-                for ctx in ctxs {
-                    if ctx == current {
-                        println!("* {} (active)", ctx);
-                    } else {
-                        println!("  {}", ctx);
-                    }
-                }
+        Some(Commands::List {
+            format,
+            sort,
+            filters,
+            stale,
+            count,
+            summary,
+            json,
+            group_by,
+            broken,
+            since,
+            before,
+            active_only,
+            inactive_only,
+            paths,
+            check_config,
+            strict,
+        }) => {
+            if paths && format == OutputFormat::Table {
+                Cli::command()
+                    .error(
+                        ErrorKind::ArgumentConflict,
+                        "--paths can't be combined with '--format table'; \
+                        use the plain or JSON format",
+                    )
+                    .exit();
+            }
+            if summary && format == OutputFormat::Json {
+                Cli::command()
+                    .error(
+                        ErrorKind::ArgumentConflict,
+                        "--summary can't be combined with '--format json'; \
+                        use --json for an object that names the current context",
+                    )
+                    .exit();
             }
+            list_contexts_cmd(&ListOptions {
+                format,
+                sort,
+                filters,
+                stale,
+                count,
+                summary,
+                json,
+                group_by,
+                broken,
+                since,
+                before,
+                active_only,
+                inactive_only,
+                paths,
+                check_config,
+                strict,
+            })?
         }
-        Some(Commands::Current) => {
-            print!("{}", get_current_tracking());
+        Some(Commands::Current { previous: false }) => {
+            let current = get_current_context_checked().unwrap_or_else(|| "none".to_string());
+            print!("{}", current);
         }
-        Some(Commands::Run { name, cmd }) => {
-            run_with_context(&name, &cmd)?;
+        Some(Commands::Current { previous: true }) => print!("{}", get_previous_tracking()),
+        Some(Commands::Status { json }) => show_status(json)?,
+        Some(Commands::Whoami { json }) => whoami(json)?,
+        Some(Commands::Show {
+            name,
+            json,
+            copy,
+            copy_field,
+        }) => match copy_field {
+            Some(field) => copy_context_field(&name, field)?,
+            None if copy => copy_context_field(&name, CopyField::default())?,
+            None => show_context(&name, json)?,
+        },
+        Some(Commands::Tree) => show_tree()?,
+        Some(Commands::Run {
+            name,
+            context_file,
+            print_env,
+            report,
+            clean_env,
+            env_passthrough,
+            env,
+            override_policy,
+            cmd,
+        }) => {
+            let options = RunOptions {
+                report,
+                clean_env,
+                env_passthrough,
+                env,
+                override_policy,
+            };
+            match (context_file, name) {
+                (Some(file), name) => {
+                    // clap fills the name first even after '--', so it's the
+                    // start of the command only if everything after '--' is
+                    let cmd: Vec<String> = name.into_iter().chain(cmd).collect();
+                    let after_dashes = std::env::args_os()
+                        .skip_while(|arg| arg != "--")
+                        .skip(1)
+                        .count();
+                    if after_dashes != cmd.len() {
+                        Cli::command()
+                            .error(
+                                ErrorKind::ArgumentConflict,
+                                "--context-file can't be combined with a context name; \
+                                put the command after '--'",
+                            )
+                            .exit();
+                    }
+                    run_with_context_file(&file, &cmd, &options)?;
+                }
+                (None, Some(name)) if print_env => print_run_env(&name)?,
+                (None, Some(name)) => run_with_context_options(&name, &cmd, &options)?,
+                (None, None) => unreachable!("clap requires a name without --context-file"),
+            }
         }
+        Some(Commands::Env { name, shell }) => print_env_exports(name.as_deref(), shell)?,
+        Some(Commands::RunEach {
+            contexts,
+            tag,
+            parallel,
+            override_policy,
+            cmd,
+        }) => run_each(
+            &RunEachOptions {
+                contexts,
+                tag,
+                parallel,
+                override_policy,
+            },
+            &cmd,
+        )?,
         Some(Commands::Delete {
             name,
             gcloud_config,
         }) => {
             delete_context(&name, gcloud_config)?;
         }
-        Some(Commands::Login { name, quiet }) => {
-            login_context(&name, quiet)?;
+        Some(Commands::Undo) => undo_last_op()?,
+        Some(Commands::ReauthIfNeeded { name, quiet }) => reauth_if_needed(&name, quiet)?,
+        Some(Commands::Rollback { name }) => rollback_context(&name)?,
+        Some(Commands::Config { action }) => match action {
+            ConfigAction::Get { key } => config_get(&key)?,
+            ConfigAction::Set { key, value } => config_set(&key, &value)?,
+            ConfigAction::Unset { key } => config_unset(&key)?,
+            ConfigAction::List => config_list()?,
+        },
+        Some(Commands::Groups { action }) => match action {
+            None => list_groups()?,
+            Some(GroupsAction::Add {
+                name,
+                context,
+                kubectl,
+                env,
+            }) => add_group(&name, &context, kubectl, &env)?,
+            Some(GroupsAction::Remove { name }) => remove_group(&name)?,
+            Some(GroupsAction::Env { name, shell }) => print_group_env(&name, shell)?,
+        },
+        Some(Commands::SwitchGroup { name, quiet }) => switch_group(&name, quiet)?,
+        Some(Commands::Prune {
+            empty_configs: _,
+            dry_run,
+            yes,
+        }) => prune_gcloud_configs(&PruneOptions { dry_run, yes })?,
+        Some(Commands::SelfTest) => self_test()?,
+        Some(Commands::Doctor { fix }) => doctor(&DoctorOptions { fix })?,
+        Some(Commands::Export {
+            name,
+            file,
+            portable,
+            all,
+            skip_unreadable,
+        }) => {
+            let options = ExportOptions {
+                portable,
+                skip_unreadable,
+            };
+            match (all, name, file) {
+                (Some(archive), _, _) => export_store(&archive, &options)?,
+                (None, Some(name), Some(file)) => export_context(&name, &file, &options)?,
+                _ => unreachable!("clap requires a name and file without --all"),
+            }
+        }
+        Some(Commands::Import {
+            file,
+            name,
+            overwrite,
+            all,
+        }) => {
+            let options = ImportOptions { name, overwrite };
+            if all {
+                import_backup(&file, &options)?;
+            } else {
+                import_context(&file, &options)?;
+            }
+        }
+        Some(Commands::ImportStore {
+            path,
+            names,
+            all,
+            overwrite,
+        }) => import_store(
+            &path,
+            &ImportStoreOptions {
+                names,
+                all,
+                overwrite,
+            },
+        )?,
+        Some(Commands::Adopt { config, all, quiet }) => match config {
+            Some(config) if !all => adopt_gcloud_config(&config, quiet)?,
+            _ => adopt_all_gcloud_configs(quiet)?,
+        },
+        Some(Commands::AdoptAdc {
+            file,
+            name,
+            account,
+            project,
+            gcloud_config,
+            quiet,
+        }) => adopt_adc_file(
+            &file,
+            &name,
+            &AdoptAdcOptions {
+                account,
+                project,
+                gcloud_config,
+                quiet,
+            },
+        )?,
+        Some(Commands::RenameConfig { old, new }) => rename_gcloud_config(&old, &new)?,
+        Some(Commands::Rename { old, new }) => {
+            rename_context(&old, &new)?;
         }
-        Some(Commands::Completions { shell }) => {
-            let mut cmd = Cli::command();
-            let name = cmd.get_name().to_string();
-            generate(shell, &mut cmd, name, &mut io::stdout());
+        Some(Commands::Touch { name }) => touch_context(&name)?,
+        Some(Commands::Repair {
+            name,
+            force,
+            account,
+            project,
+            gcloud_config,
+        }) => repair_context(
+            &name,
+            &RepairOptions {
+                force,
+                account,
+                project,
+                gcloud_config,
+                ..Default::default()
+            },
+        )?,
+        Some(Commands::Login {
+            name,
+            quiet,
+            impersonate,
+            keep_versions,
+            copy_current_config_as,
+            no_browser,
+        }) => {
+            login_context_with_options(
+                &name,
+                &LoginOptions {
+                    quiet,
+                    impersonate,
+                    keep_versions,
+                    copy_current_config_as,
+                    no_browser,
+                },
+            )?;
         }
-        None => interactive_switch(false)?,
+        Some(Commands::Completions {
+            action,
+            shell,
+            dynamic,
+        }) => match action {
+            Some(CompletionsAction::Install {
+                shell,
+                dynamic,
+                print,
+            }) => {
+                let script = completion_script(shell, dynamic)?;
+                if print {
+                    print!("{}", script);
+                } else {
+                    install_completions(shell, &script)?;
+                }
+            }
+            None => {
+                let shell = shell.expect("clap requires a shell without a subcommand");
+                print!("{}", completion_script(shell, dynamic)?);
+            }
+        },
+        None => match cli.context {
+            Some(context) => run_with_context(&context, &cli.cmd)?,
+            None => interactive_switch_with_options(&InteractiveOptions {
+                quiet: false,
+                account: cli.account,
+                limit: cli.limit,
+                ..Default::default()
+            })?,
+        },
     }
 
     Ok(())
@@ -0,0 +1,54 @@
+//! Gatekeeping for interactive prompts.
+//!
+//! Anything that would prompt the user (menus, confirmations, the editor, the
+//! browser login) calls [`require_input`] first, so scripted use fails fast
+//! instead of hanging. Input is disallowed with the global `--no-input` flag,
+//! in CI mode (see [`ci_mode`]) or when stdin is not a terminal.
+
+use anyhow::{Result, bail};
+use std::env;
+use std::io::{IsTerminal, stdin};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::error::GcpxError;
+
+static NO_INPUT: AtomicBool = AtomicBool::new(false);
+
+/// Disables (or re-enables) interactive prompts for the rest of the process.
+pub fn set_no_input(no_input: bool) {
+    NO_INPUT.store(no_input, Ordering::SeqCst);
+}
+
+/// Reads a boolean environment variable: `None` if it is unset, otherwise
+/// whether it is set to something other than empty, `0` or `false`.
+fn env_flag(var: &str) -> Option<bool> {
+    let value = env::var(var).ok()?;
+    Some(!matches!(
+        value.trim().to_ascii_lowercase().as_str(),
+        "" | "0" | "false"
+    ))
+}
+
+/// Returns true when running in CI: `GCPX_CI` is set to a true value, or it is
+/// unset and `CI` is (as most CI systems do). CI mode implies `--no-input` and
+/// disables colors. `GCPX_CI=0` turns it off even where `CI` is set.
+pub fn ci_mode() -> bool {
+    env_flag("GCPX_CI")
+        .or_else(|| env_flag("CI"))
+        .unwrap_or(false)
+}
+
+/// Returns true if gcpx may prompt the user.
+pub fn input_allowed() -> bool {
+    !NO_INPUT.load(Ordering::SeqCst) && !ci_mode() && stdin().is_terminal()
+}
+
+/// Fails with [`GcpxError::InputRequired`] if prompting is not allowed.
+///
+/// `hint` tells the user what to pass instead, e.g. "pass --force".
+pub fn require_input(hint: &str) -> Result<()> {
+    if !input_allowed() {
+        bail!(GcpxError::InputRequired(hint.to_string()));
+    }
+    Ok(())
+}
@@ -0,0 +1,354 @@
+//! User settings for gcpx.
+//!
+//! Settings are read from `~/.config/gcpx/config.toml`. Every key is optional;
+//! a missing file is equivalent to an empty one.
+//!
+//! ## Testing
+//!
+//! Set the `GCPX_CONFIG` environment variable to override the settings file location.
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::config::{get_home, write_atomic};
+use crate::credstore::{StoreBackend, StoreCommandSettings};
+use crate::error::GcpxError;
+
+/// gcpx's own settings, as stored in the settings file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Directory where contexts are stored (overridden by `GCPX_HOME` and `--store-dir`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub store_dir: Option<PathBuf>,
+    /// Extra gcloud properties (`section/key`) captured by `save --all-properties`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub capture_properties: Vec<String>,
+    /// Suggest re-login on switch when user credentials are older than this many days
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub adc_max_age_days: Option<u64>,
+    /// Give each context its own gcloud config directory (`CLOUDSDK_CONFIG`)
+    /// instead of activating named configurations in the shared one
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub isolated_configs: bool,
+    /// ADC file name inside the gcloud config directory, for nonstandard
+    /// layouts (overridden by `GCPX_ADC_FILENAME`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub adc_filename: Option<String>,
+    /// Where context credentials are kept (`file` or `command`)
+    #[serde(skip_serializing_if = "is_default_backend")]
+    pub store_backend: StoreBackend,
+    /// `get_cmd`/`put_cmd` templates for `store_backend = "command"`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub store_command: Option<StoreCommandSettings>,
+    /// Octal mode for saved credential files (default `600`), or `none` to
+    /// leave permissions alone on filesystems that don't support them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credential_mode: Option<String>,
+    /// Template for each context in the interactive menu, with `{name}`,
+    /// `{account}`, `{project}` and `{active}` placeholders (default: the name)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub menu_format: Option<String>,
+    /// How many trash entries to keep besides the one `gcpx undo` restores
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trash_keep: Option<usize>,
+}
+
+fn is_default_backend(backend: &StoreBackend) -> bool {
+    *backend == StoreBackend::File
+}
+
+/// Default for [`Settings::adc_max_age_days`].
+pub const DEFAULT_ADC_MAX_AGE_DAYS: u64 = 90;
+
+/// Default for [`Settings::trash_keep`].
+pub const DEFAULT_TRASH_KEEP: usize = 20;
+
+/// Returns the path to the settings file (~/.config/gcpx/config.toml).
+/// Can be overridden with GCPX_CONFIG environment variable for testing.
+pub fn get_settings_path() -> Result<PathBuf> {
+    if let Ok(path) = env::var("GCPX_CONFIG") {
+        return Ok(PathBuf::from(path));
+    }
+    Ok(get_home()?.join(".config").join("gcpx").join("config.toml"))
+}
+
+/// Settings parsed earlier in this process, with the file they came from.
+struct CachedSettings {
+    path: PathBuf,
+    /// Modification time and size of the file when it was parsed (None if
+    /// it didn't exist)
+    stamp: Option<(SystemTime, u64)>,
+    settings: Settings,
+}
+
+static SETTINGS_CACHE: Mutex<Option<CachedSettings>> = Mutex::new(None);
+
+/// Returns the modification time and size of the settings file, if it exists.
+fn file_stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Loads the settings file. Returns default settings if the file doesn't exist.
+///
+/// Path helpers call this constantly, so the file is parsed once per process
+/// and reused for as long as its modification time and size stay the same.
+pub fn load_settings() -> Result<Settings> {
+    let path = get_settings_path()?;
+    let stamp = file_stamp(&path);
+    let mut cache = SETTINGS_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(cached) = cache
+        .as_ref()
+        .filter(|c| c.path == path && c.stamp == stamp)
+    {
+        return Ok(cached.settings.clone());
+    }
+
+    let settings = if stamp.is_none() {
+        Settings::default()
+    } else {
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read settings file: {:?}", path))?;
+        toml::from_str(&content).with_context(|| format!("Invalid settings file: {:?}", path))?
+    };
+    *cache = Some(CachedSettings {
+        path,
+        stamp,
+        settings: settings.clone(),
+    });
+    Ok(settings)
+}
+
+/// Forgets the parsed settings, so the next [`load_settings`] reads the file.
+fn invalidate_settings_cache() {
+    *SETTINGS_CACHE.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Kind of value a setting holds, used to parse `gcpx config set` input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingKind {
+    String,
+    Path,
+    Bool,
+    Integer,
+    /// Comma-separated on the command line, an array in the file
+    List,
+}
+
+/// Every setting `gcpx config` knows, with its kind and a short description.
+/// Nested keys use dots (`store_command.get_cmd`).
+pub const SETTING_KEYS: &[(&str, SettingKind, &str)] = &[
+    (
+        "store_dir",
+        SettingKind::Path,
+        "Directory where contexts are stored",
+    ),
+    (
+        "capture_properties",
+        SettingKind::List,
+        "Extra gcloud properties captured by 'save --all-properties'",
+    ),
+    (
+        "adc_max_age_days",
+        SettingKind::Integer,
+        "Suggest re-login for user credentials older than this",
+    ),
+    (
+        "isolated_configs",
+        SettingKind::Bool,
+        "Give each context its own gcloud config directory",
+    ),
+    (
+        "adc_filename",
+        SettingKind::String,
+        "ADC file name inside the gcloud config directory",
+    ),
+    (
+        "store_backend",
+        SettingKind::String,
+        "Where credentials are kept: file or command",
+    ),
+    (
+        "store_command.get_cmd",
+        SettingKind::String,
+        "Command printing a context's credentials",
+    ),
+    (
+        "store_command.put_cmd",
+        SettingKind::String,
+        "Command storing a context's credentials from stdin",
+    ),
+    (
+        "credential_mode",
+        SettingKind::String,
+        "Octal mode for saved credentials (default 600), or none",
+    ),
+    (
+        "menu_format",
+        SettingKind::String,
+        "Interactive menu item template: {name}, {account}, {project}, {active}",
+    ),
+    (
+        "trash_keep",
+        SettingKind::Integer,
+        "Trash entries kept besides the one 'gcpx undo' restores (default 20)",
+    ),
+];
+
+/// Returns the kind of a known setting, or an error listing the valid keys.
+fn setting_kind(key: &str) -> Result<SettingKind> {
+    match SETTING_KEYS.iter().find(|(k, _, _)| *k == key) {
+        Some((_, kind, _)) => Ok(*kind),
+        None => {
+            let keys: Vec<&str> = SETTING_KEYS.iter().map(|(k, _, _)| *k).collect();
+            bail!(GcpxError::UnknownSetting(key.to_string(), keys.join(", ")))
+        }
+    }
+}
+
+/// Reads the settings file as a raw TOML table, keeping keys gcpx doesn't know.
+fn load_settings_table() -> Result<toml::Table> {
+    let path = get_settings_path()?;
+    if !path.exists() {
+        return Ok(toml::Table::new());
+    }
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read settings file: {:?}", path))?;
+    content
+        .parse()
+        .with_context(|| format!("Invalid settings file: {:?}", path))
+}
+
+/// Validates and atomically writes a raw settings table.
+fn save_settings_table(table: &toml::Table) -> Result<()> {
+    toml::Value::Table(table.clone())
+        .try_into::<Settings>()
+        .context("Invalid setting value")?;
+    let path = get_settings_path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_atomic(&path, toml::to_string(table)?.as_bytes())?;
+    invalidate_settings_cache();
+    Ok(())
+}
+
+/// Splits a dotted key into its table and leaf (`a.b` -> (Some("a"), "b")).
+fn split_key(key: &str) -> (Option<&str>, &str) {
+    match key.split_once('.') {
+        Some((table, leaf)) => (Some(table), leaf),
+        None => (None, key),
+    }
+}
+
+/// Renders a setting value the way `gcpx config set` accepts it.
+fn format_value(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(s) => s.clone(),
+        toml::Value::Array(items) => items.iter().map(format_value).collect::<Vec<_>>().join(","),
+        other => other.to_string(),
+    }
+}
+
+/// Returns a setting's value as set in the settings file, or `None` if unset.
+pub fn get_setting(key: &str) -> Result<Option<String>> {
+    setting_kind(key)?;
+    let table = load_settings_table()?;
+    let value = match split_key(key) {
+        (Some(outer), leaf) => table
+            .get(outer)
+            .and_then(|t| t.as_table())
+            .and_then(|t| t.get(leaf)),
+        (None, leaf) => table.get(leaf),
+    };
+    Ok(value.map(format_value))
+}
+
+/// Returns every known setting that is set, in [`SETTING_KEYS`] order.
+pub fn list_settings() -> Result<Vec<(&'static str, String)>> {
+    let mut set = Vec::new();
+    for (key, _, _) in SETTING_KEYS {
+        if let Some(value) = get_setting(key)? {
+            set.push((*key, value));
+        }
+    }
+    Ok(set)
+}
+
+/// Sets a setting in the settings file, validating the key and value.
+///
+/// Other keys (including unknown ones) are kept; comments are not.
+pub fn set_setting(key: &str, value: &str) -> Result<()> {
+    let parsed = match setting_kind(key)? {
+        SettingKind::String | SettingKind::Path => toml::Value::String(value.to_string()),
+        SettingKind::Bool => toml::Value::Boolean(
+            value
+                .parse()
+                .with_context(|| format!("'{}' expects true or false", key))?,
+        ),
+        SettingKind::Integer => toml::Value::Integer(
+            value
+                .parse()
+                .with_context(|| format!("'{}' expects a whole number", key))?,
+        ),
+        SettingKind::List => toml::Value::Array(
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| toml::Value::String(item.to_string()))
+                .collect(),
+        ),
+    };
+
+    let mut table = load_settings_table()?;
+    match split_key(key) {
+        (Some(outer), leaf) => {
+            let inner = table
+                .entry(outer)
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            let Some(inner) = inner.as_table_mut() else {
+                bail!(GcpxError::SettingNotTable(outer.to_string()));
+            };
+            inner.insert(leaf.to_string(), parsed);
+        }
+        (None, leaf) => {
+            table.insert(leaf.to_string(), parsed);
+        }
+    }
+    save_settings_table(&table)
+}
+
+/// Removes a setting from the settings file. Returns false if it wasn't set.
+pub fn unset_setting(key: &str) -> Result<bool> {
+    setting_kind(key)?;
+    let mut table = load_settings_table()?;
+    let removed = match split_key(key) {
+        (Some(outer), leaf) => {
+            let removed = table
+                .get_mut(outer)
+                .and_then(|t| t.as_table_mut())
+                .and_then(|t| t.remove(leaf))
+                .is_some();
+            if table
+                .get(outer)
+                .and_then(|t| t.as_table())
+                .is_some_and(|t| t.is_empty())
+            {
+                table.remove(outer);
+            }
+            removed
+        }
+        (None, leaf) => table.remove(leaf).is_some(),
+    };
+    if removed {
+        save_settings_table(&table)?;
+    }
+    Ok(removed)
+}
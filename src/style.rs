@@ -0,0 +1,43 @@
+//! Terminal styling helpers.
+//!
+//! Colors are only emitted when stderr is a terminal, `NO_COLOR` is unset
+//! (see <https://no-color.org>) and gcpx is not in CI mode.
+
+use std::env;
+use std::io::{IsTerminal, stderr};
+
+use crate::prompt::ci_mode;
+
+/// Returns true if ANSI colors should be used for stderr output.
+pub fn color_enabled() -> bool {
+    env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()) && !ci_mode() && stderr().is_terminal()
+}
+
+/// Wraps `text` in bold red ANSI codes when colors are enabled.
+pub fn red_bold(text: &str) -> String {
+    if color_enabled() {
+        format!("\x1b[1;31m{}\x1b[0m", text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Prints a warning banner to stderr for a context flagged as dangerous.
+pub fn print_danger_banner(name: &str) {
+    let line = "!".repeat(60);
+    eprintln!("{}", red_bold(&line));
+    eprintln!(
+        "{}",
+        red_bold(&format!("  WARNING: '{}' is a production context", name))
+    );
+    eprintln!("{}", red_bold(&line));
+}
+
+/// Prints a one-line notice to stderr when switching away from a context
+/// flagged as dangerous.
+pub fn print_leaving_danger_notice(name: &str) {
+    eprintln!(
+        "{}",
+        red_bold(&format!("Leaving production context '{}'.", name))
+    );
+}
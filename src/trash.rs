@@ -0,0 +1,200 @@
+//! Trash and last-operation tracking backing `gcpx undo`.
+//!
+//! Destructive operations move (or copy) the affected context directory to
+//! `<store>/.trash/<name>-<timestamp>/` and record themselves in
+//! `<store>/.last-op`. Only the most recent operation can be undone; the
+//! newest older entries (`trash_keep` in the settings, default 20) are kept
+//! for manual recovery and the rest are removed whenever an operation is
+//! recorded.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::{get_context_dir, get_store_dir, now_unix};
+use crate::settings::{DEFAULT_TRASH_KEEP, load_settings};
+
+/// The kind of destructive operation recorded in `.last-op`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OpKind {
+    /// A context was deleted
+    Delete,
+    /// An existing context was overwritten by `save`
+    Save,
+    /// An existing context was replaced by `import --overwrite` or
+    /// `import-store --overwrite`
+    Import,
+}
+
+/// The most recent destructive operation, as stored in `.last-op`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastOp {
+    /// What kind of operation this was
+    pub op: OpKind,
+    /// The context the operation affected
+    pub name: String,
+    /// Name of the entry under `.trash` holding the previous contents
+    pub trash_entry: String,
+    /// The tracked current context before the operation
+    pub current: String,
+    /// The tracked previous context before the operation
+    pub previous: String,
+    /// The gcloud configuration `delete --gcloud-config` removed, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gcloud_config: Option<String>,
+    /// Name of the entry under `.trash` holding that configuration's file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gcloud_config_entry: Option<String>,
+}
+
+/// Returns the path to the trash directory.
+pub fn get_trash_dir() -> Result<PathBuf> {
+    Ok(get_store_dir()?.join(".trash"))
+}
+
+fn get_last_op_path() -> Result<PathBuf> {
+    Ok(get_store_dir()?.join(".last-op"))
+}
+
+/// Returns a fresh `.trash/<name>-<timestamp>` path that does not exist yet.
+fn new_trash_path(name: &str) -> Result<PathBuf> {
+    let trash_dir = get_trash_dir()?;
+    fs::create_dir_all(&trash_dir)?;
+    let stamp = now_unix();
+    let mut path = trash_dir.join(format!("{}-{}", name, stamp));
+    let mut n = 1;
+    while path.exists() {
+        path = trash_dir.join(format!("{}-{}-{}", name, stamp, n));
+        n += 1;
+    }
+    Ok(path)
+}
+
+/// Recursively copies a directory.
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Moves a context directory into the trash, returning the trash entry name.
+pub fn move_to_trash(name: &str) -> Result<String> {
+    let context_dir = get_context_dir(name)?;
+    let dest = new_trash_path(name)?;
+    fs::rename(&context_dir, &dest)
+        .with_context(|| format!("Failed to move {:?} to trash", context_dir))?;
+    Ok(trash_entry_name(&dest))
+}
+
+/// Copies a context directory into the trash, returning the trash entry name.
+pub fn copy_to_trash(name: &str) -> Result<String> {
+    let context_dir = get_context_dir(name)?;
+    let dest = new_trash_path(name)?;
+    copy_dir(&context_dir, &dest)
+        .with_context(|| format!("Failed to copy {:?} to trash", context_dir))?;
+    Ok(trash_entry_name(&dest))
+}
+
+/// Moves a single file into the trash as `<label>-<timestamp>`, returning
+/// the trash entry name.
+pub fn move_file_to_trash(path: &Path, label: &str) -> Result<String> {
+    let dest = new_trash_path(label)?;
+    fs::rename(path, &dest).with_context(|| format!("Failed to move {:?} to trash", path))?;
+    Ok(trash_entry_name(&dest))
+}
+
+/// Copies a single file into the trash as `<label>-<timestamp>`, returning
+/// the trash entry name.
+pub fn copy_file_to_trash(path: &Path, label: &str) -> Result<String> {
+    let dest = new_trash_path(label)?;
+    fs::copy(path, &dest).with_context(|| format!("Failed to copy {:?} to trash", path))?;
+    Ok(trash_entry_name(&dest))
+}
+
+fn trash_entry_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Returns the `(timestamp, counter)` a trash entry name ends with, used to
+/// order entries from oldest to newest.
+///
+/// Entries are named `<label>-<timestamp>` or `<label>-<timestamp>-<n>`; the
+/// counter is always smaller than the timestamp, which tells the two apart
+/// when the label itself ends in a number.
+fn entry_stamp(entry: &str) -> (u64, u64) {
+    let mut parts = entry.rsplit('-').map(|p| p.parse::<u64>().ok());
+    match (parts.next().flatten(), parts.next().flatten()) {
+        (Some(n), Some(stamp)) if n < stamp => (stamp, n),
+        (Some(stamp), _) => (stamp, 0),
+        _ => (0, 0),
+    }
+}
+
+/// Removes the oldest trash entries beyond the `trash_keep` setting. Entries
+/// referenced by `op` (the operation `gcpx undo` would reverse) are never
+/// removed and don't count towards the limit.
+fn prune_trash(op: &LastOp) -> Result<()> {
+    let keep = load_settings()?.trash_keep.unwrap_or(DEFAULT_TRASH_KEEP);
+    let trash_dir = get_trash_dir()?;
+    let Ok(entries) = fs::read_dir(&trash_dir) else {
+        return Ok(());
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .filter(|n| *n != op.trash_entry && op.gcloud_config_entry.as_ref() != Some(n))
+        .collect();
+    if names.len() <= keep {
+        return Ok(());
+    }
+    names.sort_by_key(|n| entry_stamp(n));
+    for name in &names[..names.len() - keep] {
+        let path = trash_dir.join(name);
+        let removed = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        removed.with_context(|| format!("Failed to remove old trash entry {:?}", path))?;
+    }
+    Ok(())
+}
+
+/// Records the most recent destructive operation, replacing any earlier one,
+/// and prunes the trash down to the `trash_keep` setting.
+pub fn record_last_op(op: &LastOp) -> Result<()> {
+    let content = serde_json::to_string_pretty(op)?;
+    fs::write(get_last_op_path()?, content)?;
+    prune_trash(op)
+}
+
+/// Loads the most recent destructive operation, if any.
+pub fn load_last_op() -> Result<Option<LastOp>> {
+    let path = get_last_op_path()?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path)?;
+    Ok(Some(serde_json::from_str(&content)?))
+}
+
+/// Forgets the most recent destructive operation.
+pub fn clear_last_op() -> Result<()> {
+    let path = get_last_op_path()?;
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,146 @@
+//! Live credential checks.
+//!
+//! Checking a context asks gcloud to mint an access token from its saved ADC,
+//! which hits the network. Each check runs gcloud against a throwaway
+//! `CLOUDSDK_CONFIG` so the user's own gcloud state is never touched.
+
+use anyhow::Result;
+use std::fs;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::{DEFAULT_ADC_FILENAME, adc_type_expires, adc_type_of, gcloud_command};
+use crate::credstore::credential_store;
+
+/// How many contexts are checked at once.
+pub const DEFAULT_CHECK_CONCURRENCY: usize = 8;
+
+/// How long a single check may take before it is abandoned.
+pub const DEFAULT_CHECK_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// The outcome of checking a context's saved credentials.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenStatus {
+    /// gcloud minted an access token
+    Valid,
+    /// The refresh token was rejected (revoked or expired)
+    Revoked,
+    /// The credentials could not be checked, with the reason
+    Unchecked(String),
+}
+
+/// Checks whether a context's saved user credentials can still mint a token.
+///
+/// Only refresh-token based credentials (see [`adc_type_expires`]) are checked;
+/// anything else is reported as [`TokenStatus::Unchecked`].
+pub fn check_context_token(name: &str, timeout: Duration) -> TokenStatus {
+    match try_check_context_token(name, timeout) {
+        Ok(status) => status,
+        Err(e) => TokenStatus::Unchecked(e.to_string()),
+    }
+}
+
+fn try_check_context_token(name: &str, timeout: Duration) -> Result<TokenStatus> {
+    let adc = credential_store()?.read(name).unwrap_or_default();
+    match adc_type_of(&adc) {
+        Some(t) if adc_type_expires(&t) => {}
+        Some(t) => return Ok(TokenStatus::Unchecked(format!("{} credentials", t))),
+        None => return Ok(TokenStatus::Unchecked("unreadable credentials".to_string())),
+    }
+
+    // A private (0700) directory with an unpredictable name, removed on drop
+    let config_dir = tempfile::Builder::new().prefix("gcpx-check-").tempdir()?;
+    fs::write(config_dir.path().join(DEFAULT_ADC_FILENAME), &adc)?;
+
+    let result = run_with_timeout(
+        gcloud_command()
+            .args(["auth", "application-default", "print-access-token"])
+            .env("CLOUDSDK_CONFIG", config_dir.path())
+            .env_remove("GOOGLE_APPLICATION_CREDENTIALS"),
+        timeout,
+    );
+    drop(config_dir);
+
+    Ok(match result? {
+        None => TokenStatus::Unchecked(format!("timed out after {}s", timeout.as_secs())),
+        Some((true, _)) => TokenStatus::Valid,
+        Some((false, stderr)) => {
+            if stderr.contains("invalid_grant") || stderr.contains("expired or revoked") {
+                TokenStatus::Revoked
+            } else {
+                let reason = stderr.lines().last().unwrap_or("gcloud failed").trim();
+                TokenStatus::Unchecked(reason.to_string())
+            }
+        }
+    })
+}
+
+/// Runs a command, killing it if it outlives `timeout`.
+///
+/// Returns `None` on timeout, otherwise whether it succeeded and its stderr.
+/// Stderr is drained on a separate thread so a chatty child can't fill the
+/// pipe and stall until the timeout.
+fn run_with_timeout(cmd: &mut Command, timeout: Duration) -> Result<Option<(bool, String)>> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let mut pipe = child.stderr.take();
+    let reader = thread::spawn(move || {
+        let mut stderr = Vec::new();
+        if let Some(pipe) = pipe.as_mut() {
+            let _ = pipe.read_to_end(&mut stderr);
+        }
+        String::from_utf8_lossy(&stderr).into_owned()
+    });
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            let stderr = reader.join().unwrap_or_default();
+            return Ok(Some((status.success(), stderr)));
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Checks many contexts in parallel, at most `concurrency` at a time.
+///
+/// Results are returned in the same order as `names`.
+pub fn check_context_tokens(
+    names: &[String],
+    concurrency: usize,
+    timeout: Duration,
+) -> Vec<TokenStatus> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(vec![None; names.len()]);
+    thread::scope(|scope| {
+        for _ in 0..concurrency.clamp(1, names.len().max(1)) {
+            scope.spawn(|| {
+                loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(name) = names.get(i) else {
+                        break;
+                    };
+                    let status = check_context_token(name, timeout);
+                    results.lock().unwrap_or_else(|e| e.into_inner())[i] = Some(status);
+                }
+            });
+        }
+    });
+    results
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner())
+        .into_iter()
+        .map(|s| s.unwrap_or_else(|| TokenStatus::Unchecked("not checked".to_string())))
+        .collect()
+}
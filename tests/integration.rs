@@ -4,29 +4,104 @@
 
 use std::env;
 use std::fs;
+use std::sync::{Mutex, MutexGuard};
 use tempfile::TempDir;
 
+use gcpx::GcpxError;
+
+/// Serializes tests that mutate process-wide environment variables.
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+/// Minimal stand-in for `gcloud`, so tests are fast and never touch the real
+/// gcloud state. Configurations are files under `$bin/configs`, the active one
+/// is recorded in `$bin/active`, projects the account can see are files under
+/// `$bin/projects`, `config list` prints `$bin/properties.json` if present,
+/// and every invocation is appended to `$bin/gcloud.log`.
+#[cfg(unix)]
+const FAKE_GCLOUD: &str = r#"#!/bin/sh
+state="$(dirname "$0")"
+echo "$*" >> "$state/gcloud.log"
+case "$1 $2 $3" in
+  "config configurations list")
+    case "$*" in
+      *is_active*) cat "$state/active" ;;
+      *) ls "$state/configs" ;;
+    esac ;;
+  "config configurations activate")
+    [ -e "$state/configs/$4" ] || { echo "ERROR: configuration [$4] does not exist" >&2; exit 1; }
+    echo "$4" > "$state/active" ;;
+  "config configurations describe")
+    [ -e "$state/configs/$4" ] || exit 1 ;;
+  "config configurations create")
+    touch "$state/configs/$4"
+    case "$*" in *--no-activate*) ;; *) echo "$4" > "$state/active" ;; esac ;;
+  "config configurations delete")
+    rm -f "$state/configs/$4" ;;
+  "config get-value account"|"config get-value project")
+    echo "(unset)" ;;
+  "config list --format=json"*)
+    cat "$state/properties.json" 2>/dev/null || echo "{}" ;;
+  "projects describe "*)
+    [ -e "$state/projects/$3" ] || { echo "ERROR: project [$3] not found or permission denied" >&2; exit 1; } ;;
+esac
+exit 0
+"#;
+
+/// Stand-in for `kubectl` with no contexts configured.
+#[cfg(unix)]
+const FAKE_KUBECTL: &str = "#!/bin/sh\nexit 1\n";
+
 /// Helper to set up a test environment with temporary directories.
 struct TestEnv {
     _gcpx_dir: TempDir,
     _gcloud_dir: TempDir,
+    _bin_dir: TempDir,
+    _old_path: Option<std::ffi::OsString>,
+    _lock: MutexGuard<'static, ()>,
 }
 
 impl TestEnv {
     fn new() -> Self {
+        let lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
         let gcpx_dir = TempDir::new().expect("Failed to create temp gcpx dir");
         let gcloud_dir = TempDir::new().expect("Failed to create temp gcloud dir");
+        let bin_dir = TempDir::new().expect("Failed to create temp bin dir");
+        let old_path = env::var_os("PATH");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            for (name, script) in [("gcloud", FAKE_GCLOUD), ("kubectl", FAKE_KUBECTL)] {
+                let path = bin_dir.path().join(name);
+                fs::write(&path, script).expect("Failed to write fake binary");
+                fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+            }
+            fs::create_dir(bin_dir.path().join("configs")).unwrap();
+            fs::write(bin_dir.path().join("configs").join("default"), "").unwrap();
+            fs::write(bin_dir.path().join("active"), "default\n").unwrap();
+        }
 
-        // SAFETY: Tests run serially (cargo test runs single-threaded by default for
-        // integration tests), so setting env vars is safe here.
+        // SAFETY: ENV_LOCK is held for the lifetime of the TestEnv, so no other
+        // test reads or writes the environment concurrently.
         unsafe {
             env::set_var("GCPX_HOME", gcpx_dir.path());
             env::set_var("GCPX_GCLOUD_DIR", gcloud_dir.path());
+            env::set_var("GCPX_CONFIG", gcpx_dir.path().join("config.toml"));
+            env::remove_var("GOOGLE_APPLICATION_CREDENTIALS");
+            env::remove_var("GCPX_ADC_FILENAME");
+            if cfg!(unix) {
+                let mut paths = vec![bin_dir.path().to_path_buf()];
+                paths.extend(env::split_paths(&old_path.clone().unwrap_or_default()));
+                env::set_var("PATH", env::join_paths(paths).unwrap());
+            }
         }
 
         TestEnv {
             _gcpx_dir: gcpx_dir,
             _gcloud_dir: gcloud_dir,
+            _bin_dir: bin_dir,
+            _old_path: old_path,
+            _lock: lock,
         }
     }
 
@@ -34,10 +109,38 @@ impl TestEnv {
         self._gcpx_dir.path()
     }
 
+    /// Returns a command that runs the gcpx binary under test.
+    fn gcpx_command(&self) -> std::process::Command {
+        std::process::Command::new(env!("CARGO_BIN_EXE_gcpx"))
+    }
+
+    /// Runs the gcpx binary under test with `args` and waits for it.
+    fn gcpx(&self, args: &[&str]) -> std::process::Output {
+        self.gcpx_command().args(args).output().unwrap()
+    }
+
     fn gcloud_path(&self) -> &std::path::Path {
         self._gcloud_dir.path()
     }
 
+    /// Creates a gcloud configuration known to the fake gcloud.
+    #[cfg(unix)]
+    fn create_gcloud_config(&self, name: &str) {
+        fs::write(self._bin_dir.path().join("configs").join(name), "").unwrap();
+    }
+
+    /// Returns every command line the fake gcloud was invoked with.
+    #[cfg(unix)]
+    fn gcloud_log(&self) -> String {
+        fs::read_to_string(self._bin_dir.path().join("gcloud.log")).unwrap_or_default()
+    }
+
+    /// Returns whether the fake gcloud knows a configuration.
+    #[cfg(unix)]
+    fn gcloud_config_exists(&self, name: &str) -> bool {
+        self._bin_dir.path().join("configs").join(name).exists()
+    }
+
     /// Creates a fake ADC file in the gcloud directory.
     fn create_fake_adc(&self) {
         let adc_path = self
@@ -55,10 +158,16 @@ impl TestEnv {
 
 impl Drop for TestEnv {
     fn drop(&mut self) {
-        // SAFETY: Tests run serially, so removing env vars is safe here.
+        // SAFETY: ENV_LOCK is still held (fields drop after this), so removing
+        // env vars is safe here.
         unsafe {
             env::remove_var("GCPX_HOME");
             env::remove_var("GCPX_GCLOUD_DIR");
+            env::remove_var("GCPX_CONFIG");
+            env::remove_var("GCPX_ADC_FILENAME");
+            if let Some(path) = &self._old_path {
+                env::set_var("PATH", path);
+            }
         }
     }
 }
@@ -79,18 +188,37 @@ fn test_get_current_tracking_default() {
     assert_eq!(current, "none");
 }
 
+#[test]
+fn test_get_current_tracking_trims_and_handles_empty() {
+    let env = TestEnv::new();
+
+    fs::write(env.gcpx_path().join(".current"), "work\n").unwrap();
+    assert_eq!(gcpx::get_current_tracking(), "work");
+
+    fs::write(env.gcpx_path().join(".current"), "").unwrap();
+    assert_eq!(gcpx::get_current_tracking(), "none");
+
+    gcpx::config::set_current_tracking("personal").unwrap();
+    assert_eq!(gcpx::get_current_tracking(), "personal");
+    let leftovers: Vec<_> = fs::read_dir(env.gcpx_path())
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().to_string_lossy().contains(".tmp"))
+        .collect();
+    assert!(leftovers.is_empty());
+}
+
 #[test]
 fn test_save_context_without_adc_fails() {
     let _env = TestEnv::new();
 
     let result = gcpx::save_context("test-context", false);
-    assert!(result.is_err());
-    assert!(
-        result
-            .unwrap_err()
-            .to_string()
-            .contains("No credentials found")
-    );
+    let err = result.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<GcpxError>(),
+        Some(GcpxError::NoCredentials(_))
+    ));
+    assert!(err.to_string().contains("No credentials found"));
 }
 
 #[test]
@@ -149,8 +277,11 @@ fn test_switch_nonexistent_context_fails() {
     let _env = TestEnv::new();
 
     let result = gcpx::switch_context("nonexistent", false);
-    assert!(result.is_err());
-    assert!(result.unwrap_err().to_string().contains("not found"));
+    let err = result.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<GcpxError>(),
+        Some(GcpxError::ContextNotFound(name)) if name == "nonexistent"
+    ));
 }
 
 #[test]
@@ -171,8 +302,11 @@ fn test_delete_nonexistent_context_fails() {
     let _env = TestEnv::new();
 
     let result = gcpx::delete_context("nonexistent", false);
-    assert!(result.is_err());
-    assert!(result.unwrap_err().to_string().contains("not found"));
+    let err = result.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<GcpxError>(),
+        Some(GcpxError::ContextNotFound(_))
+    ));
 }
 
 #[test]
@@ -180,8 +314,15 @@ fn test_run_with_nonexistent_context_fails() {
     let _env = TestEnv::new();
 
     let result = gcpx::run_with_context("nonexistent", &["echo".to_string(), "hello".to_string()]);
-    assert!(result.is_err());
-    assert!(result.unwrap_err().to_string().contains("not found"));
+    let err = result.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<GcpxError>(),
+        Some(GcpxError::ContextNotFound(name)) if name == "nonexistent"
+    ));
+    assert_eq!(
+        err.to_string(),
+        "Context 'nonexistent' not found. Run 'gcpx save nonexistent' first."
+    );
 }
 
 #[test]
@@ -191,8 +332,28 @@ fn test_run_with_empty_command_fails() {
     gcpx::save_context("test-ctx", false).expect("Failed to save context");
 
     let result = gcpx::run_with_context("test-ctx", &[]);
-    assert!(result.is_err());
-    assert!(result.unwrap_err().to_string().contains("No command"));
+    let err = result.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<GcpxError>(),
+        Some(GcpxError::NoCommand)
+    ));
+}
+
+#[test]
+fn test_run_missing_program_error() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("test-ctx", true).unwrap();
+
+    let err = gcpx::run_with_context("test-ctx", &["gcpx-no-such-tool".to_string()]).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<GcpxError>(),
+        Some(GcpxError::ProgramNotFound(_))
+    ));
+    assert_eq!(err.to_string(), "'gcpx-no-such-tool' not found on PATH");
+
+    let err = gcpx::run_with_context("test-ctx", &["./no/such/tool".to_string()]).unwrap_err();
+    assert_eq!(err.to_string(), "'./no/such/tool' does not exist");
 }
 
 #[test]
@@ -293,3 +454,3907 @@ fn test_adc_file_permissions() {
 
     assert_eq!(mode, 0o600, "ADC file should have 0600 permissions");
 }
+
+#[test]
+fn test_rename_context() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+
+    gcpx::save_context("old-name", false).expect("Failed to save context");
+    gcpx::rename_context("old-name", "new-name").expect("Failed to rename context");
+
+    let contexts = gcpx::list_contexts().expect("Failed to list contexts");
+    assert_eq!(contexts, vec!["new-name"]);
+
+    // Current tracking follows the rename
+    assert_eq!(gcpx::get_current_tracking(), "new-name");
+}
+
+#[test]
+fn test_rename_to_existing_context_fails() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+
+    gcpx::save_context("first", false).expect("Failed to save context");
+    gcpx::save_context("second", false).expect("Failed to save context");
+
+    let result = gcpx::rename_context("first", "second");
+    assert!(result.is_err());
+    assert!(result.unwrap_err().to_string().contains("already exists"));
+}
+
+#[test]
+fn test_rename_updates_previous_pointer() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+
+    gcpx::save_context("work", false).expect("Failed to save context");
+    gcpx::save_context("personal", false).expect("Failed to save context");
+    gcpx::config::set_previous_tracking("work").expect("Failed to set previous");
+
+    gcpx::rename_context("work", "office").expect("Failed to rename context");
+    assert_eq!(gcpx::config::get_previous_tracking(), "office");
+}
+
+#[test]
+fn test_delete_clears_previous_pointer() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+
+    gcpx::save_context("work", false).expect("Failed to save context");
+    gcpx::save_context("personal", false).expect("Failed to save context");
+    gcpx::config::set_previous_tracking("work").expect("Failed to set previous");
+
+    gcpx::delete_context("work", false).expect("Failed to delete context");
+    assert_eq!(gcpx::config::get_previous_tracking(), "none");
+}
+
+#[test]
+fn test_switch_previous_without_history_fails() {
+    let _env = TestEnv::new();
+
+    let result = gcpx::switch_context("-", false);
+    assert!(result.is_err());
+    assert!(
+        result
+            .unwrap_err()
+            .to_string()
+            .contains("No previous context")
+    );
+}
+
+#[test]
+fn test_complete_names_filters_by_prefix() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+
+    gcpx::save_context("work-eu", false).expect("Failed to save context");
+    gcpx::save_context("work-us", false).expect("Failed to save context");
+    gcpx::save_context("personal", false).expect("Failed to save context");
+
+    let names = gcpx::commands::complete::complete_names("switch", "work").unwrap();
+    assert_eq!(names, vec!["work-eu", "work-us"]);
+
+    // Subcommands that don't take a context name complete nothing
+    let names = gcpx::commands::complete::complete_names("list", "").unwrap();
+    assert!(names.is_empty());
+}
+
+#[test]
+fn test_store_dir_precedence() {
+    let env = TestEnv::new();
+
+    // GCPX_HOME wins over the settings file
+    let settings_dir = TempDir::new().unwrap();
+    fs::write(
+        env.gcpx_path().join("config.toml"),
+        format!("store_dir = {:?}\n", settings_dir.path()),
+    )
+    .unwrap();
+    assert_eq!(gcpx::config::get_store_dir().unwrap(), env.gcpx_path());
+
+    // The settings file is used when GCPX_HOME is unset
+    // SAFETY: ENV_LOCK is held by the TestEnv.
+    unsafe {
+        env::remove_var("GCPX_HOME");
+    }
+    assert_eq!(gcpx::config::get_store_dir().unwrap(), settings_dir.path());
+
+    // The --store-dir override wins over everything
+    let flag_dir = TempDir::new().unwrap();
+    gcpx::config::set_store_dir_override(Some(flag_dir.path()));
+    let resolved = gcpx::config::get_store_dir();
+    gcpx::config::set_store_dir_override(None);
+    assert_eq!(resolved.unwrap(), flag_dir.path());
+}
+
+#[test]
+fn test_save_context_with_description() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+
+    let options = gcpx::SaveOptions {
+        description: Some("Staging for the EU team".to_string()),
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("staging", &options).expect("Failed to save context");
+
+    let metadata = gcpx::config::load_context_metadata("staging")
+        .unwrap()
+        .expect("Metadata should exist");
+    assert_eq!(
+        metadata.description.as_deref(),
+        Some("Staging for the EU team")
+    );
+}
+
+#[test]
+fn test_save_merge_metadata_keeps_user_fields() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        description: Some("EU staging".to_string()),
+        tags: vec!["eu".to_string()],
+        danger: true,
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("staging", &options).unwrap();
+
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        merge_metadata: true,
+        project: Some("new-project".to_string()),
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("staging", &options).unwrap();
+    let metadata = gcpx::config::load_context_metadata("staging")
+        .unwrap()
+        .unwrap();
+    assert_eq!(metadata.description.as_deref(), Some("EU staging"));
+    assert_eq!(metadata.tags, vec!["eu".to_string()]);
+    assert!(metadata.danger);
+    assert_eq!(metadata.project.as_deref(), Some("new-project"));
+
+    // Explicit values still replace the stored ones
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        merge_metadata: true,
+        tags: vec!["billing".to_string()],
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("staging", &options).unwrap();
+    let metadata = gcpx::config::load_context_metadata("staging")
+        .unwrap()
+        .unwrap();
+    assert_eq!(metadata.tags, vec!["billing".to_string()]);
+    assert_eq!(metadata.description.as_deref(), Some("EU staging"));
+}
+
+#[test]
+fn test_invalid_name_error_kind() {
+    let err = gcpx::validate_context_name("foo/bar").unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<GcpxError>(),
+        Some(GcpxError::InvalidName(_))
+    ));
+}
+
+#[test]
+fn test_switch_no_adc_leaves_live_adc() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+
+    gcpx::save_context("first", true).expect("Failed to save context");
+    gcpx::save_context("second", true).expect("Failed to save context");
+
+    let live_adc = env
+        .gcloud_path()
+        .join("application_default_credentials.json");
+    fs::write(
+        &live_adc,
+        r#"{"type": "authorized_user", "client_id": "live"}"#,
+    )
+    .unwrap();
+
+    let options = gcpx::SwitchOptions {
+        quiet: true,
+        no_adc: true,
+        ..Default::default()
+    };
+    gcpx::switch_context_with_options("first", &options).expect("Failed to switch");
+
+    assert_eq!(gcpx::get_current_tracking(), "first");
+    let content = fs::read_to_string(&live_adc).unwrap();
+    assert!(content.contains("live"));
+}
+
+#[test]
+fn test_switch_if_exists_softens_not_found() {
+    let env = TestEnv::new();
+    let switch = |args: &[&str]| env.gcpx(&[&["switch"], args].concat());
+
+    let output = switch(&["missing", "--if-exists"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("'missing' not found"));
+    assert_eq!(gcpx::get_current_tracking(), "none");
+
+    assert!(!switch(&["missing"]).status.success());
+    // Only "not found" is softened
+    assert!(!switch(&["bad/name", "--if-exists"]).status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_switch_by_project() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    for (name, project) in [("app", "shared"), ("app-ci", "shared"), ("data", "lake")] {
+        let options = gcpx::SaveOptions {
+            quiet: true,
+            project: Some(project.to_string()),
+            adc_json: Some(br#"{"type": "authorized_user"}"#.to_vec()),
+            ..Default::default()
+        };
+        gcpx::save_context_with_options(name, &options).unwrap();
+    }
+
+    assert_eq!(gcpx::commands::context_for_project("lake").unwrap(), "data");
+    let err = gcpx::commands::context_for_project("shared")
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("app, app-ci"), "{}", err);
+    assert!(gcpx::commands::context_for_project("nowhere").is_err());
+
+    let switch = |project: &str| env.gcpx(&["switch", "--by-project", project, "--print"]);
+    assert!(switch("lake").status.success());
+    assert_eq!(gcpx::get_current_tracking(), "data");
+    let output = switch("shared");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("app, app-ci"));
+    assert_eq!(gcpx::get_current_tracking(), "data");
+}
+
+#[test]
+fn test_save_if_changed_skips_unchanged() {
+    let _env = TestEnv::new();
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        project: Some("one".to_string()),
+        adc_json: Some(br#"{"type": "authorized_user", "client_id": "a"}"#.to_vec()),
+        if_changed: true,
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("work", &options).unwrap();
+    let mut metadata = gcpx::config::load_context_metadata("work")
+        .unwrap()
+        .unwrap();
+    metadata.last_used = Some(1);
+    gcpx::config::save_context_metadata("work", &metadata).unwrap();
+    let last_used = || {
+        gcpx::config::load_context_metadata("work")
+            .unwrap()
+            .unwrap()
+            .last_used
+    };
+
+    // Same credentials, only formatted differently: nothing is written
+    let options = gcpx::SaveOptions {
+        adc_json: Some(b"{ \"client_id\": \"a\",\n  \"type\": \"authorized_user\" }".to_vec()),
+        ..options
+    };
+    gcpx::save_context_with_options("work", &options).unwrap();
+    assert_eq!(last_used(), Some(1));
+
+    let options = gcpx::SaveOptions {
+        project: Some("two".to_string()),
+        ..options
+    };
+    gcpx::save_context_with_options("work", &options).unwrap();
+    assert_ne!(last_used(), Some(1));
+    let metadata = gcpx::config::load_context_metadata("work")
+        .unwrap()
+        .unwrap();
+    assert_eq!(metadata.project.as_deref(), Some("two"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_snapshot_config_restored_on_switch() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    env.create_gcloud_config("beta");
+    let configurations = env.gcloud_path().join("configurations");
+    fs::create_dir_all(&configurations).unwrap();
+    let snapshot = "[core]\nproject = beta-project\n\n[custom]\nunmodelled = yes\n";
+    fs::write(configurations.join("config_beta"), snapshot).unwrap();
+
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        gcloud_config: Some("beta".to_string()),
+        adc_json: Some(br#"{"type": "authorized_user"}"#.to_vec()),
+        snapshot_config: true,
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("beta", &options).unwrap();
+    let saved = env.gcpx_path().join("beta").join("config-snapshot.ini");
+    assert_eq!(fs::read_to_string(&saved).unwrap(), snapshot);
+
+    // The live configuration drifts; a plain switch keeps the user's changes
+    let live = configurations.join("config_beta");
+    fs::write(&live, "[core]\n").unwrap();
+    gcpx::config::set_current_tracking("none").unwrap();
+    gcpx::switch_context("beta", true).unwrap();
+    assert_eq!(fs::read_to_string(&live).unwrap(), "[core]\n");
+
+    // ...and --restore-config puts the snapshot back verbatim, unless the
+    // switch fails
+    let restore = gcpx::SwitchOptions {
+        quiet: true,
+        restore_config: true,
+        ..Default::default()
+    };
+    let live_adc = env
+        .gcloud_path()
+        .join("application_default_credentials.json");
+    fs::remove_file(&live_adc).unwrap();
+    fs::create_dir(&live_adc).unwrap();
+    gcpx::config::set_current_tracking("none").unwrap();
+    assert!(gcpx::switch_context_with_options("beta", &restore).is_err());
+    assert_eq!(fs::read_to_string(&live).unwrap(), "[core]\n");
+    fs::remove_dir(&live_adc).unwrap();
+    gcpx::switch_context_with_options("beta", &restore).unwrap();
+    assert_eq!(fs::read_to_string(&live).unwrap(), snapshot);
+
+    // A deleted configuration is recreated from the snapshot
+    fs::remove_file(&live).unwrap();
+    gcpx::config::set_current_tracking("none").unwrap();
+    gcpx::switch_context("beta", true).unwrap();
+    assert_eq!(fs::read_to_string(&live).unwrap(), snapshot);
+
+    // Re-saving without the flag drops the snapshot
+    let options = gcpx::SaveOptions {
+        snapshot_config: false,
+        ..options
+    };
+    gcpx::save_context_with_options("beta", &options).unwrap();
+    assert!(!saved.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_kubectl_switch_outcomes() {
+    use gcpx::config::{KubeSwitch, switch_kubectl_context};
+    let env = TestEnv::new();
+    let kubectl = env._bin_dir.path().join("kubectl");
+    let attempts = env._bin_dir.path().join("attempts");
+    // Knows "dev"; "flaky" fails on the first attempt only
+    fs::write(
+        &kubectl,
+        format!(
+            r#"#!/bin/sh
+echo x >> '{attempts}'
+case "$3" in
+  dev) exit 0 ;;
+  flaky) [ "$(wc -l < '{attempts}')" -gt 1 ] && exit 0; echo "connection refused" >&2; exit 1 ;;
+  *) echo "error: no context exists with the name: \"$3\"" >&2; exit 1 ;;
+esac
+"#,
+            attempts = attempts.display()
+        ),
+    )
+    .unwrap();
+
+    assert_eq!(switch_kubectl_context("dev"), KubeSwitch::Switched);
+    assert_eq!(switch_kubectl_context("gone"), KubeSwitch::ContextNotFound);
+    assert!(
+        KubeSwitch::ContextNotFound
+            .failure_message("gone")
+            .unwrap()
+            .contains("no longer exists")
+    );
+    fs::remove_file(&attempts).unwrap();
+    assert_eq!(switch_kubectl_context("flaky"), KubeSwitch::Switched);
+    assert_eq!(fs::read_to_string(&attempts).unwrap().lines().count(), 2);
+
+    // SAFETY: ENV_LOCK is held through TestEnv, which restores PATH on drop
+    unsafe { env::set_var("PATH", env.gcloud_path()) };
+    assert_eq!(switch_kubectl_context("dev"), KubeSwitch::KubectlMissing);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_gcloud_runs_without_prompts_or_update_check() {
+    let env = TestEnv::new();
+    let env_log = env._bin_dir.path().join("env.log");
+    fs::write(
+        env._bin_dir.path().join("gcloud"),
+        format!(
+            "#!/bin/sh\necho \"$CLOUDSDK_CORE_DISABLE_PROMPTS $CLOUDSDK_COMPONENT_MANAGER_DISABLE_UPDATE_CHECK\" >> '{}'\necho work\n",
+            env_log.display()
+        ),
+    )
+    .unwrap();
+
+    assert_eq!(gcpx::config::get_current_gcloud_config().unwrap(), "work");
+    assert_eq!(fs::read_to_string(&env_log).unwrap(), "1 1\n");
+}
+
+#[test]
+fn test_switch_activate_only_skips_adc_and_kubectl() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        kube_context: Some("gke_prod".to_string()),
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("first", &options).unwrap();
+    gcpx::save_context("second", true).unwrap();
+
+    // Record kubectl invocations from here on
+    let kubectl_log = env._bin_dir.path().join("kubectl.log");
+    let kubectl = env._bin_dir.path().join("kubectl");
+    fs::write(
+        &kubectl,
+        format!(
+            "#!/bin/sh\necho \"$*\" >> '{}'\nexit 1\n",
+            kubectl_log.display()
+        ),
+    )
+    .unwrap();
+
+    let live_adc = env
+        .gcloud_path()
+        .join("application_default_credentials.json");
+    fs::write(
+        &live_adc,
+        r#"{"type": "authorized_user", "client_id": "live"}"#,
+    )
+    .unwrap();
+
+    let options = gcpx::SwitchOptions {
+        quiet: true,
+        activate_only: true,
+        ..Default::default()
+    };
+    gcpx::switch_context_with_options("first", &options).unwrap();
+    assert_eq!(gcpx::get_current_tracking(), "first");
+    assert!(fs::read_to_string(&live_adc).unwrap().contains("live"));
+    assert!(!kubectl_log.exists());
+
+    gcpx::switch_context("second", true).unwrap();
+    gcpx::switch_context("first", true).unwrap();
+    assert!(
+        fs::read_to_string(&kubectl_log)
+            .unwrap()
+            .contains("use-context gke_prod")
+    );
+}
+
+#[test]
+fn test_switch_reports_previous_context() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("first", true).unwrap();
+    gcpx::save_context("second", true).unwrap();
+
+    let switch = |args: &[&str]| {
+        let output = env.gcpx(&[&["switch"], args].concat());
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    assert!(switch(&["first"]).contains("Switched from 'second' to 'first'."));
+    let quiet = switch(&["second", "-q"]);
+    assert!(quiet.contains("Switched to 'second' successfully!"));
+    assert!(!quiet.contains("Switched from"));
+
+    // Nothing tracked yet: only the destination
+    fs::remove_file(env.gcpx_path().join(".current")).unwrap();
+    assert!(switch(&["first"]).contains("Switched to 'first' successfully!"));
+}
+
+#[test]
+fn test_switch_no_track_keeps_tracking() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+
+    gcpx::save_context("first", true).expect("Failed to save context");
+    gcpx::save_context("second", true).expect("Failed to save context");
+
+    let options = gcpx::SwitchOptions {
+        quiet: true,
+        no_track: true,
+        ..Default::default()
+    };
+    gcpx::switch_context_with_options("first", &options).expect("Failed to switch");
+
+    assert_eq!(gcpx::get_current_tracking(), "second");
+    assert_eq!(gcpx::config::get_previous_tracking(), "none");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_save_all_properties_captures_allowlisted_and_switch_replays() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    fs::write(
+        env._bin_dir.path().join("properties.json"),
+        r#"{"compute": {"zone": "europe-west1-b"}, "core": {"disable_prompts": "True"}}"#,
+    )
+    .unwrap();
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        all_properties: true,
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("zoned", &options).unwrap();
+
+    let metadata = gcpx::config::load_context_metadata("zoned")
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        metadata.gcloud_properties.into_iter().collect::<Vec<_>>(),
+        vec![("compute/zone".to_string(), "europe-west1-b".to_string())]
+    );
+
+    gcpx::save_context("other", true).unwrap();
+    gcpx::switch_context("zoned", true).unwrap();
+    let log = env.gcloud_log();
+    assert!(
+        log.contains("config set compute/zone europe-west1-b"),
+        "{}",
+        log
+    );
+    assert!(!log.contains("disable_prompts"), "{}", log);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_switch_back_after_no_track_realigns_live_state() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    let live_adc = env
+        .gcloud_path()
+        .join("application_default_credentials.json");
+    for (name, config) in [("a", "config-a"), ("b", "config-b")] {
+        env.create_gcloud_config(config);
+        let options = gcpx::SaveOptions {
+            quiet: true,
+            gcloud_config: Some(config.to_string()),
+            adc_json: Some(
+                format!(r#"{{"type": "authorized_user", "client_id": "{}"}}"#, name).into_bytes(),
+            ),
+            ..Default::default()
+        };
+        gcpx::save_context_with_options(name, &options).unwrap();
+    }
+
+    assert!(env.gcpx(&["switch", "a"]).status.success());
+    assert!(env.gcpx(&["switch", "b", "--no-track"]).status.success());
+    assert_eq!(gcpx::get_current_tracking(), "a");
+    assert_eq!(
+        gcpx::config::get_current_gcloud_config().unwrap(),
+        "config-b"
+    );
+
+    let output = env.gcpx(&["switch", "a"]);
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("Already on"));
+    assert_eq!(
+        gcpx::config::get_current_gcloud_config().unwrap(),
+        "config-a"
+    );
+    assert!(
+        fs::read_to_string(&live_adc)
+            .unwrap()
+            .contains(r#""client_id": "a""#)
+    );
+    assert_eq!(gcpx::get_current_tracking(), "a");
+    // Tracking didn't change, so neither does the previous context
+    assert_eq!(gcpx::config::get_previous_tracking(), "b");
+
+    // Now in sync, so switching again is a no-op
+    let output = env.gcpx(&["switch", "a"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Already on context 'a'."));
+}
+
+#[test]
+fn test_touch_context_updates_last_used() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+
+    gcpx::save_context("recent", true).expect("Failed to save context");
+    let mut metadata = gcpx::config::load_context_metadata("recent")
+        .unwrap()
+        .unwrap();
+    metadata.last_used = Some(0);
+    gcpx::config::save_context_metadata("recent", &metadata).unwrap();
+
+    gcpx::touch_context("recent").expect("Failed to touch context");
+    let metadata = gcpx::config::load_context_metadata("recent")
+        .unwrap()
+        .unwrap();
+    assert!(metadata.last_used.unwrap() > 0);
+}
+
+#[test]
+fn test_repair_rebuilds_broken_metadata() {
+    let env = TestEnv::new();
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        adc_json: Some(
+            br#"{"type": "service_account", "client_email": "ci@pipeline.iam.gserviceaccount.com", "project_id": "pipeline"}"#
+                .to_vec(),
+        ),
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("legacy", &options).unwrap();
+    let metadata_path = env.gcpx_path().join("legacy").join("metadata.json");
+
+    // Valid metadata is left alone without --force
+    assert!(gcpx::commands::repair_context("legacy", &Default::default()).is_err());
+
+    fs::write(&metadata_path, "{ not json").unwrap();
+    gcpx::commands::repair_context("legacy", &Default::default()).unwrap();
+    let metadata = gcpx::config::load_context_metadata("legacy")
+        .unwrap()
+        .unwrap();
+    assert_eq!(metadata.gcloud_config, "legacy");
+    assert_eq!(
+        metadata.account.as_deref(),
+        Some("ci@pipeline.iam.gserviceaccount.com")
+    );
+    assert_eq!(metadata.project.as_deref(), Some("pipeline"));
+    assert_eq!(metadata.adc_type.as_deref(), Some("service_account"));
+
+    fs::remove_file(&metadata_path).unwrap();
+    let options = gcpx::commands::RepairOptions {
+        project: Some("other".to_string()),
+        gcloud_config: Some("ci".to_string()),
+        ..Default::default()
+    };
+    gcpx::commands::repair_context("legacy", &options).unwrap();
+    let metadata = gcpx::config::load_context_metadata("legacy")
+        .unwrap()
+        .unwrap();
+    assert_eq!(metadata.gcloud_config, "ci");
+    assert_eq!(metadata.project.as_deref(), Some("other"));
+
+    // --force re-derives the org from a new account, but keeps one set by hand
+    let force = |account: &str| gcpx::commands::RepairOptions {
+        account: Some(account.to_string()),
+        force: true,
+        ..Default::default()
+    };
+    let org = || {
+        gcpx::config::load_context_metadata("legacy")
+            .unwrap()
+            .unwrap()
+            .org
+    };
+    gcpx::commands::repair_context("legacy", &force("me@example.com")).unwrap();
+    assert_eq!(org().as_deref(), Some("example.com"));
+    gcpx::commands::repair_context("legacy", &force("me@other.org")).unwrap();
+    assert_eq!(org().as_deref(), Some("other.org"));
+    let mut metadata = gcpx::config::load_context_metadata("legacy")
+        .unwrap()
+        .unwrap();
+    metadata.org = Some("Platform Team".to_string());
+    gcpx::config::save_context_metadata("legacy", &metadata).unwrap();
+    gcpx::commands::repair_context("legacy", &force("me@example.com")).unwrap();
+    assert_eq!(org().as_deref(), Some("Platform Team"));
+
+    let err = gcpx::commands::repair_context("missing", &Default::default()).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<gcpx::GcpxError>(),
+        Some(gcpx::GcpxError::ContextNotFound(_))
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_save_credential_mode() {
+    use std::os::unix::fs::PermissionsExt;
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    let mode = |name: &str| {
+        fs::metadata(env.gcpx_path().join(name).join("adc.json"))
+            .unwrap()
+            .permissions()
+            .mode()
+            & 0o777
+    };
+    let save = |args: &[&str]| env.gcpx(&[&["save"], args].concat());
+
+    assert!(save(&["default-mode", "-q"]).status.success());
+    assert_eq!(mode("default-mode"), 0o600);
+    assert!(save(&["custom", "-q", "--mode", "0640"]).status.success());
+    assert_eq!(mode("custom"), 0o640);
+    assert!(!save(&["bad", "-q", "--mode", "999"]).status.success());
+
+    fs::write(
+        env.gcpx_path().join("config.toml"),
+        "credential_mode = \"644\"\n",
+    )
+    .unwrap();
+    assert!(save(&["setting", "-q"]).status.success());
+    assert_eq!(mode("setting"), 0o644);
+    fs::set_permissions(
+        env.gcpx_path().join("setting").join("adc.json"),
+        fs::Permissions::from_mode(0o604),
+    )
+    .unwrap();
+    assert!(
+        save(&["setting", "-q", "--no-permissions"])
+            .status
+            .success()
+    );
+    assert_eq!(mode("setting"), 0o604);
+}
+
+#[test]
+fn test_ci_mode_from_environment() {
+    let _env = TestEnv::new();
+    let saved: Vec<_> = ["CI", "GCPX_CI"]
+        .iter()
+        .map(|var| (*var, env::var_os(var)))
+        .collect();
+    let ci_mode = |ci: Option<&str>, gcpx_ci: Option<&str>| {
+        for (var, value) in [("CI", ci), ("GCPX_CI", gcpx_ci)] {
+            // SAFETY: ENV_LOCK is held through TestEnv
+            unsafe {
+                match value {
+                    Some(value) => env::set_var(var, value),
+                    None => env::remove_var(var),
+                }
+            }
+        }
+        gcpx::prompt::ci_mode()
+    };
+
+    assert!(!ci_mode(None, None));
+    assert!(ci_mode(Some("true"), None));
+    assert!(ci_mode(None, Some("1")));
+    assert!(!ci_mode(Some("false"), None));
+    // GCPX_CI wins over CI either way
+    assert!(!ci_mode(Some("true"), Some("0")));
+    assert!(ci_mode(Some("0"), Some("yes")));
+    assert!(!gcpx::prompt::input_allowed());
+    assert!(!gcpx::style::color_enabled());
+
+    for (var, value) in saved {
+        // SAFETY: ENV_LOCK is held through TestEnv
+        unsafe {
+            match value {
+                Some(value) => env::set_var(var, value),
+                None => env::remove_var(var),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_touch_nonexistent_context_fails() {
+    let _env = TestEnv::new();
+
+    let err = gcpx::touch_context("missing").unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<GcpxError>(),
+        Some(GcpxError::ContextNotFound(_))
+    ));
+}
+
+#[test]
+fn test_current_context_checked_after_out_of_band_delete() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+
+    gcpx::save_context("ephemeral", true).expect("Failed to save context");
+    assert_eq!(
+        gcpx::config::get_current_context_checked().as_deref(),
+        Some("ephemeral")
+    );
+
+    // Remove the context directory behind gcpx's back
+    fs::remove_dir_all(env.gcpx_path().join("ephemeral")).unwrap();
+
+    // Raw tracking still reports the stale name; the checked variant doesn't
+    assert_eq!(gcpx::get_current_tracking(), "ephemeral");
+    assert_eq!(gcpx::config::get_current_context_checked(), None);
+}
+
+#[test]
+fn test_current_previous() {
+    let env = TestEnv::new();
+    let current = |args: &[&str]| {
+        let output = env.gcpx(&[&["current"], args].concat());
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    assert_eq!(current(&["--previous"]), "none");
+    fs::write(env.gcpx_path().join(".previous"), "  work\n").unwrap();
+    assert_eq!(current(&["--previous"]), "work");
+    fs::write(env.gcpx_path().join(".previous"), "\n").unwrap();
+    assert_eq!(current(&["--previous"]), "none");
+}
+
+#[test]
+fn test_save_records_adc_type() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+
+    gcpx::save_context("typed", true).expect("Failed to save context");
+    let metadata = gcpx::config::load_context_metadata("typed")
+        .unwrap()
+        .unwrap();
+    assert_eq!(metadata.adc_type.as_deref(), Some("authorized_user"));
+}
+
+#[test]
+fn test_list_contexts_with_metadata() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        tags: vec!["prod".to_string()],
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("tagged", &options).expect("Failed to save context");
+    fs::create_dir_all(env.gcpx_path().join("legacy")).unwrap();
+    fs::write(env.gcpx_path().join("legacy").join("adc.json"), "{}").unwrap();
+
+    let contexts = gcpx::config::list_contexts_with_metadata().unwrap();
+    assert_eq!(contexts.len(), 2);
+    assert_eq!(contexts[0].0, "legacy");
+    assert!(contexts[0].1.is_none());
+    assert_eq!(contexts[1].0, "tagged");
+    assert_eq!(contexts[1].1.as_ref().unwrap().tags, vec!["prod"]);
+}
+
+#[test]
+fn test_print_env_matches_run_env() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        project: Some("env-project".to_string()),
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("envctx", &options).expect("Failed to save context");
+    let metadata_path = env.gcpx_path().join("envctx").join("metadata.json");
+    let mut metadata: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&metadata_path).unwrap()).unwrap();
+    metadata["gcloud_properties"] = serde_json::json!({"billing/quota_project": "billing-project"});
+    fs::write(&metadata_path, metadata.to_string()).unwrap();
+
+    let vars = gcpx::commands::resolve_run_env("envctx").unwrap();
+    let adc = env.gcpx_path().join("envctx").join("adc.json");
+    assert!(vars.contains(&(
+        "GOOGLE_APPLICATION_CREDENTIALS".to_string(),
+        adc.to_string_lossy().into_owned()
+    )));
+    assert!(vars.contains(&(
+        "CLOUDSDK_ACTIVE_CONFIG_NAME".to_string(),
+        "default".to_string()
+    )));
+    assert!(vars.contains(&(
+        "GOOGLE_CLOUD_PROJECT".to_string(),
+        "env-project".to_string()
+    )));
+    assert!(vars.contains(&(
+        "GOOGLE_CLOUD_QUOTA_PROJECT".to_string(),
+        "billing-project".to_string()
+    )));
+
+    // The command sees the same variables
+    let output = env.gcpx(&[
+        "run",
+        "envctx",
+        "--",
+        "sh",
+        "-c",
+        "echo \"$GOOGLE_CLOUD_PROJECT $GOOGLE_CLOUD_QUOTA_PROJECT\"",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        "env-project billing-project"
+    );
+}
+
+#[test]
+fn test_context_is_complete() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+
+    gcpx::save_context("complete", true).expect("Failed to save context");
+    assert!(gcpx::config::context_is_complete("complete").unwrap());
+
+    // Corrupt metadata: still exists, but is no longer complete
+    fs::write(
+        env.gcpx_path().join("complete").join("metadata.json"),
+        "{not json",
+    )
+    .unwrap();
+    assert!(gcpx::config::context_exists("complete").unwrap());
+    assert!(!gcpx::config::context_is_complete("complete").unwrap());
+
+    // Missing metadata
+    fs::remove_file(env.gcpx_path().join("complete").join("metadata.json")).unwrap();
+    assert!(!gcpx::config::context_is_complete("complete").unwrap());
+}
+
+#[test]
+fn test_impersonate_exported_by_run_env() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        impersonate: vec!["deployer@proj.iam.gserviceaccount.com".to_string()],
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("imp", &options).expect("Failed to save context");
+
+    let metadata = gcpx::config::load_context_metadata("imp").unwrap().unwrap();
+    assert_eq!(
+        metadata.impersonate,
+        ["deployer@proj.iam.gserviceaccount.com"]
+    );
+    // A single account is still stored as a plain string
+    let raw: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(env.gcpx_path().join("imp").join("metadata.json")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(raw["impersonate"], "deployer@proj.iam.gserviceaccount.com");
+
+    let run_env = gcpx::commands::resolve_run_env("imp").unwrap();
+    assert!(run_env.contains(&(
+        "CLOUDSDK_AUTH_IMPERSONATE_SERVICE_ACCOUNT".to_string(),
+        "deployer@proj.iam.gserviceaccount.com".to_string()
+    )));
+}
+
+#[test]
+fn test_impersonation_chain() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+
+    let chain = vec![
+        "hop@proj.iam.gserviceaccount.com".to_string(),
+        "target@other.iam.gserviceaccount.com".to_string(),
+    ];
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        impersonate: chain.clone(),
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("chain", &options).unwrap();
+    let metadata = gcpx::config::load_context_metadata("chain")
+        .unwrap()
+        .unwrap();
+    assert_eq!(metadata.impersonate, chain);
+
+    let joined = "hop@proj.iam.gserviceaccount.com,target@other.iam.gserviceaccount.com";
+    let run_env = gcpx::commands::resolve_run_env("chain").unwrap();
+    assert!(run_env.contains(&(
+        "CLOUDSDK_AUTH_IMPERSONATE_SERVICE_ACCOUNT".to_string(),
+        joined.to_string()
+    )));
+
+    gcpx::save_context("other", true).unwrap();
+    gcpx::switch_context("chain", true).unwrap();
+    assert!(env.gcloud_log().contains(&format!(
+        "config set auth/impersonate_service_account {}",
+        joined
+    )));
+
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        impersonate: vec!["alice@example.com".to_string()],
+        ..Default::default()
+    };
+    let err = gcpx::save_context_with_options("bad", &options).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("doesn't look like a service account")
+    );
+}
+
+#[test]
+fn test_switch_unsets_impersonation_for_plain_context() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("plain", true).unwrap();
+    gcpx::save_context("other", true).unwrap();
+
+    // Nothing impersonated in the live config: no extra gcloud call
+    gcpx::switch_context("plain", true).unwrap();
+    assert!(!env.gcloud_log().contains("config unset"));
+
+    let configurations = env.gcloud_path().join("configurations");
+    fs::create_dir_all(&configurations).unwrap();
+    fs::write(
+        configurations.join("config_default"),
+        "[auth]\nimpersonate_service_account = deploy@p.iam.gserviceaccount.com\n",
+    )
+    .unwrap();
+    gcpx::switch_context("other", true).unwrap();
+    assert!(
+        env.gcloud_log()
+            .contains("config unset auth/impersonate_service_account")
+    );
+}
+
+#[test]
+fn test_undo_delete_restores_context() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+
+    gcpx::save_context("first", true).unwrap();
+    gcpx::save_context("second", true).unwrap();
+    gcpx::config::set_previous_tracking("first").unwrap();
+
+    gcpx::delete_context("first", false).unwrap();
+    assert!(!gcpx::config::context_exists("first").unwrap());
+    assert_eq!(gcpx::config::get_previous_tracking(), "none");
+
+    gcpx::undo_last_op().unwrap();
+    assert!(gcpx::config::context_exists("first").unwrap());
+    assert_eq!(gcpx::config::get_previous_tracking(), "first");
+
+    // Only the last operation is undoable
+    let err = gcpx::undo_last_op().unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<gcpx::GcpxError>(),
+        Some(gcpx::GcpxError::NothingToUndo)
+    ));
+}
+
+#[test]
+fn test_undo_overwriting_save() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        description: Some("original".to_string()),
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("work", &options).unwrap();
+    gcpx::save_context("work", true).unwrap();
+    let metadata = gcpx::config::load_context_metadata("work")
+        .unwrap()
+        .unwrap();
+    assert_eq!(metadata.description, None);
+
+    gcpx::undo_last_op().unwrap();
+    let metadata = gcpx::config::load_context_metadata("work")
+        .unwrap()
+        .unwrap();
+    assert_eq!(metadata.description.as_deref(), Some("original"));
+    assert!(
+        !gcpx::list_contexts()
+            .unwrap()
+            .contains(&".trash".to_string())
+    );
+}
+
+#[test]
+#[cfg(unix)]
+fn test_undo_delete_restores_gcloud_config() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    env.create_gcloud_config("first");
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        gcloud_config: Some("first".to_string()),
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("first", &options).unwrap();
+    let config_file = env
+        .gcloud_path()
+        .join("configurations")
+        .join("config_first");
+    fs::create_dir_all(config_file.parent().unwrap()).unwrap();
+    fs::write(&config_file, "[core]\nproject = first-project\n").unwrap();
+
+    gcpx::delete_context("first", true).unwrap();
+    assert!(!env.gcloud_config_exists("first"));
+    // The fake gcloud only forgets the configuration; real gcloud removes the file
+    fs::remove_file(&config_file).unwrap();
+
+    gcpx::undo_last_op().unwrap();
+    assert!(gcpx::config::context_exists("first").unwrap());
+    assert_eq!(
+        fs::read_to_string(&config_file).unwrap(),
+        "[core]\nproject = first-project\n"
+    );
+}
+
+#[test]
+fn test_trash_keeps_newest_entries() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::settings::set_setting("trash_keep", "2").unwrap();
+
+    gcpx::save_context("work", true).unwrap();
+    for _ in 0..5 {
+        gcpx::save_context("work", true).unwrap();
+    }
+    // Two kept entries plus the one undo restores
+    let trash = env.gcpx_path().join(".trash");
+    assert_eq!(fs::read_dir(&trash).unwrap().count(), 3);
+    gcpx::undo_last_op().unwrap();
+    assert_eq!(fs::read_dir(&trash).unwrap().count(), 2);
+}
+
+#[test]
+fn test_save_falls_back_to_google_application_credentials() {
+    let env = TestEnv::new();
+    let key_path = env.gcpx_path().join("key.json");
+    fs::write(&key_path, r#"{"type": "service_account"}"#).unwrap();
+
+    // SAFETY: ENV_LOCK is held by `env`.
+    unsafe { env::set_var("GOOGLE_APPLICATION_CREDENTIALS", &key_path) };
+    let result = gcpx::save_context("sa", true);
+    unsafe { env::remove_var("GOOGLE_APPLICATION_CREDENTIALS") };
+    result.expect("Failed to save from GOOGLE_APPLICATION_CREDENTIALS");
+
+    let metadata = gcpx::config::load_context_metadata("sa").unwrap().unwrap();
+    assert_eq!(
+        metadata.adc_source.as_deref(),
+        Some(gcpx::config::ADC_SOURCE_ENV)
+    );
+    assert_eq!(metadata.adc_type.as_deref(), Some("service_account"));
+
+    // The gcloud ADC file wins when both exist
+    env.create_fake_adc();
+    gcpx::save_context("user", true).unwrap();
+    let metadata = gcpx::config::load_context_metadata("user")
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        metadata.adc_source.as_deref(),
+        Some(gcpx::config::ADC_SOURCE_GCLOUD)
+    );
+}
+
+#[test]
+fn test_save_from_env_prefers_google_application_credentials() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        from_env: true,
+        ..Default::default()
+    };
+    let err = gcpx::save_context_with_options("sa", &options).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("GOOGLE_APPLICATION_CREDENTIALS is not set")
+    );
+
+    let key_path = env.gcpx_path().join("key.json");
+    fs::write(&key_path, "not json").unwrap();
+    // SAFETY: ENV_LOCK is held by `env`.
+    unsafe { env::set_var("GOOGLE_APPLICATION_CREDENTIALS", &key_path) };
+    let invalid = gcpx::save_context_with_options("sa", &options);
+    fs::write(&key_path, r#"{"type": "service_account"}"#).unwrap();
+    let result = gcpx::save_context_with_options("sa", &options);
+    unsafe { env::remove_var("GOOGLE_APPLICATION_CREDENTIALS") };
+    assert!(invalid.is_err());
+    result.expect("Failed to save with --from-env");
+
+    // Saved from the variable even though the gcloud ADC file exists
+    let metadata = gcpx::config::load_context_metadata("sa").unwrap().unwrap();
+    assert_eq!(
+        metadata.adc_source.as_deref(),
+        Some(gcpx::config::ADC_SOURCE_ENV)
+    );
+    assert_eq!(metadata.adc_type.as_deref(), Some("service_account"));
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let adc = env.gcpx_path().join("sa").join("adc.json");
+        assert_eq!(
+            fs::metadata(adc).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+    }
+}
+
+#[test]
+fn test_switch_to_prod_requires_force() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        tags: vec!["prod".to_string()],
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("prod", &options).unwrap();
+    gcpx::save_context("dev", true).unwrap();
+    assert!(
+        gcpx::config::load_context_metadata("prod")
+            .unwrap()
+            .unwrap()
+            .is_dangerous()
+    );
+
+    // With input disabled, confirmation can't be given
+    gcpx::prompt::set_no_input(true);
+    let err = gcpx::switch_context("prod", true).unwrap_err();
+    gcpx::prompt::set_no_input(false);
+    assert!(matches!(
+        err.downcast_ref::<GcpxError>(),
+        Some(GcpxError::InputRequired(_))
+    ));
+    assert_eq!(gcpx::get_current_tracking(), "dev");
+
+    let options = gcpx::SwitchOptions {
+        quiet: true,
+        force: true,
+        ..Default::default()
+    };
+    gcpx::switch_context_with_options("prod", &options).unwrap();
+    assert_eq!(gcpx::get_current_tracking(), "prod");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_check_context_tokens() {
+    use gcpx::verify::{TokenStatus, check_context_tokens};
+    use std::time::Duration;
+
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("user", true).unwrap();
+
+    let key_path = env.gcpx_path().join("key.json");
+    fs::write(&key_path, r#"{"type": "service_account"}"#).unwrap();
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        adc_path: Some(key_path),
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("robot", &options).unwrap();
+
+    let names = vec!["user".to_string(), "robot".to_string()];
+    let statuses = check_context_tokens(&names, 2, Duration::from_secs(10));
+    assert_eq!(statuses[0], TokenStatus::Valid);
+    assert!(matches!(statuses[1], TokenStatus::Unchecked(_)));
+}
+
+#[test]
+fn test_tree_marks_incomplete_contexts() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("good", true).unwrap();
+    fs::create_dir_all(env.gcpx_path().join("orphan")).unwrap();
+
+    let tree = gcpx::commands::tree::render_tree().unwrap();
+    assert!(tree.contains("good/\n"));
+    assert!(tree.contains("adc.json ("));
+    assert!(tree.contains(".current ("));
+    assert!(tree.contains("orphan/  [missing adc.json]  [missing metadata.json]"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_run_each_reports_failures() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("a", true).unwrap();
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        tags: vec!["batch".to_string()],
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("b", &options).unwrap();
+
+    let options = gcpx::commands::RunEachOptions {
+        contexts: vec!["a".to_string()],
+        tag: Some("batch".to_string()),
+        parallel: 2,
+        ..Default::default()
+    };
+    gcpx::commands::run_each(&options, &["true".to_string()]).unwrap();
+    assert!(gcpx::commands::run_each(&options, &["false".to_string()]).is_err());
+
+    let options = gcpx::commands::RunEachOptions {
+        contexts: vec!["a".to_string(), "missing".to_string()],
+        ..Default::default()
+    };
+    assert!(gcpx::commands::run_each(&options, &["true".to_string()]).is_err());
+}
+
+#[test]
+fn test_interactive_candidates_account_filter() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("alice-work", true).unwrap();
+    gcpx::save_context("bob-work", true).unwrap();
+    fs::create_dir_all(env.gcpx_path().join("legacy")).unwrap();
+    fs::write(env.gcpx_path().join("legacy").join("adc.json"), "{}").unwrap();
+
+    for (name, account) in [
+        ("alice-work", "alice@corp.com"),
+        ("bob-work", "bob@corp.com"),
+    ] {
+        let mut m = gcpx::config::load_context_metadata(name).unwrap().unwrap();
+        m.account = Some(account.to_string());
+        gcpx::config::save_context_metadata(name, &m).unwrap();
+    }
+
+    let options = gcpx::commands::InteractiveOptions {
+        account: Some("alice".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(
+        gcpx::commands::interactive_candidates(&options).unwrap(),
+        vec!["alice-work"]
+    );
+
+    // Without a filter, contexts lacking metadata are still offered
+    let all = gcpx::commands::interactive_candidates(&Default::default()).unwrap();
+    assert_eq!(all, vec!["alice-work", "bob-work", "legacy"]);
+}
+
+#[test]
+fn test_keep_versions_and_rollback() {
+    let env = TestEnv::new();
+    let live_adc = env
+        .gcloud_path()
+        .join("application_default_credentials.json");
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        keep_versions: 2,
+        ..Default::default()
+    };
+
+    for version in ["v1", "v2", "v3", "v4"] {
+        fs::write(
+            &live_adc,
+            format!(r#"{{"type": "authorized_user", "v": "{}"}}"#, version),
+        )
+        .unwrap();
+        gcpx::save_context_with_options("work", &options).unwrap();
+    }
+
+    let dir = env.gcpx_path().join("work");
+    let read = |file: &str| fs::read_to_string(dir.join(file)).unwrap();
+    assert!(read("adc.json").contains("v4"));
+    assert!(read("adc.json.1").contains("v3"));
+    assert!(read("adc.json.2").contains("v2"));
+    assert!(!dir.join("adc.json.3").exists());
+    assert!(!dir.join("adc.json.0").exists());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(dir.join("adc.json.1"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    gcpx::commands::rollback_context("work").unwrap();
+    assert!(read("adc.json").contains("v3"));
+    assert!(read("adc.json.1").contains("v4"));
+    assert!(read("adc.json.2").contains("v2"));
+    // "work" is the active context, so the live ADC follows
+    assert!(fs::read_to_string(&live_adc).unwrap().contains("v3"));
+
+    // Rolling back again undoes the rollback
+    gcpx::commands::rollback_context("work").unwrap();
+    assert!(read("adc.json").contains("v4"));
+    assert!(read("adc.json.1").contains("v3"));
+    assert!(fs::read_to_string(&live_adc).unwrap().contains("v4"));
+}
+
+#[test]
+fn test_save_from_supplied_adc_json() {
+    let env = TestEnv::new();
+
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        adc_json: Some(b"not json".to_vec()),
+        ..Default::default()
+    };
+    assert!(gcpx::save_context_with_options("ci", &options).is_err());
+
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        adc_json: Some(br#"{"type": "service_account", "client_email": "ci@p.iam"}"#.to_vec()),
+        account: Some("ci@p.iam".to_string()),
+        project: Some("pipeline".to_string()),
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("ci", &options).unwrap();
+
+    let metadata = gcpx::config::load_context_metadata("ci").unwrap().unwrap();
+    assert_eq!(metadata.account.as_deref(), Some("ci@p.iam"));
+    assert_eq!(metadata.project.as_deref(), Some("pipeline"));
+    assert_eq!(metadata.gcloud_config, "default");
+    assert_eq!(
+        metadata.adc_source.as_deref(),
+        Some(gcpx::config::ADC_SOURCE_STDIN)
+    );
+    assert!(
+        fs::read_to_string(env.gcpx_path().join("ci").join("adc.json"))
+            .unwrap()
+            .contains("service_account")
+    );
+}
+
+#[test]
+fn test_isolated_configs() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    fs::write(
+        env.gcpx_path().join("config.toml"),
+        "isolated_configs = true\n",
+    )
+    .unwrap();
+
+    gcpx::save_context("iso", true).unwrap();
+    let gcloud_dir = env.gcpx_path().join("iso").join("gcloud");
+    assert!(
+        gcloud_dir
+            .join("application_default_credentials.json")
+            .exists()
+    );
+    assert!(
+        gcloud_dir
+            .join("configurations")
+            .join("config_default")
+            .exists()
+    );
+
+    gcpx::save_context("other", true).unwrap();
+    gcpx::switch_context("iso", true).unwrap();
+    assert_eq!(gcpx::get_current_tracking(), "iso");
+
+    let run_env = gcpx::commands::resolve_run_env("iso").unwrap();
+    assert!(run_env.contains(&(
+        "CLOUDSDK_CONFIG".to_string(),
+        gcloud_dir.to_string_lossy().into_owned()
+    )));
+    assert!(
+        !run_env
+            .iter()
+            .any(|(k, _)| k == "CLOUDSDK_ACTIVE_CONFIG_NAME")
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_isolated_configs_seed_gcloud() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    fs::write(
+        env.gcpx_path().join("config.toml"),
+        "isolated_configs = true\n",
+    )
+    .unwrap();
+    gcpx::save_context("iso", true).unwrap();
+    gcpx::save_context("other", true).unwrap();
+
+    let mut metadata = gcpx::config::load_context_metadata("iso").unwrap().unwrap();
+    metadata.project = Some("iso-project".to_string());
+    metadata
+        .gcloud_properties
+        .insert("compute/region".to_string(), "europe-west1".to_string());
+    metadata.impersonate = vec!["sa@iso-project.iam.gserviceaccount.com".to_string()];
+    gcpx::config::save_context_metadata("iso", &metadata).unwrap();
+
+    // Switching seeds a missing config directory: gcloud gets the credentials
+    // and the saved properties, and no shared configuration is activated
+    let gcloud_dir = env.gcpx_path().join("iso").join("gcloud");
+    fs::remove_dir_all(&gcloud_dir).unwrap();
+    fs::remove_file(env._bin_dir.path().join("gcloud.log")).unwrap();
+    gcpx::switch_context("iso", true).unwrap();
+    let log = env.gcloud_log();
+    let adc = gcloud_dir.join("application_default_credentials.json");
+    assert!(log.contains(&format!("auth login --cred-file={} --quiet", adc.display())));
+    assert!(log.contains("config set core/project iso-project"));
+    assert!(log.contains("config set compute/region europe-west1"));
+    assert!(log.contains(
+        "config set auth/impersonate_service_account sa@iso-project.iam.gserviceaccount.com"
+    ));
+    assert!(!log.contains("configurations activate"));
+
+    // --no-adc leaves the credentials out
+    fs::remove_dir_all(&gcloud_dir).unwrap();
+    gcpx::config::set_current_tracking("other").unwrap();
+    let options = gcpx::SwitchOptions {
+        quiet: true,
+        no_adc: true,
+        ..Default::default()
+    };
+    gcpx::switch_context_with_options("iso", &options).unwrap();
+    assert!(
+        gcloud_dir
+            .join("configurations")
+            .join("config_default")
+            .exists()
+    );
+    assert!(!adc.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_failed_save_restores_command_store_credentials() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    let vault = TempDir::new().unwrap();
+    let write_config = |put_cmd: &str| {
+        fs::write(
+            env.gcpx_path().join("config.toml"),
+            format!(
+                "store_backend = \"command\"\n\n[store_command]\n\
+                get_cmd = \"cat {vault}/{{name}}\"\n\
+                put_cmd = \"{put_cmd}\"\n",
+                vault = vault.path().display(),
+                put_cmd = put_cmd.replace("{vault}", &vault.path().display().to_string())
+            ),
+        )
+        .unwrap();
+    };
+    write_config("cat > {vault}/$GCPX_CONTEXT");
+    gcpx::save_context("vaulted", true).unwrap();
+    let before = fs::read(vault.path().join("vaulted")).unwrap();
+
+    // The helper stores the new credentials, then reports failure
+    write_config("cat > {vault}/$GCPX_CONTEXT; exit 1");
+    fs::write(
+        env.gcloud_path()
+            .join("application_default_credentials.json"),
+        r#"{"type": "authorized_user", "refresh_token": "replaced"}"#,
+    )
+    .unwrap();
+    assert!(gcpx::save_context("vaulted", true).is_err());
+
+    assert_eq!(fs::read(vault.path().join("vaulted")).unwrap(), before);
+    assert!(gcpx::config::context_exists("vaulted").unwrap());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_rename_with_command_credential_store() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    let vault = TempDir::new().unwrap();
+    fs::write(
+        env.gcpx_path().join("config.toml"),
+        format!(
+            "store_backend = \"command\"\n\n[store_command]\n\
+            get_cmd = \"cat {vault}/{{name}}\"\n\
+            put_cmd = \"cat > {vault}/$GCPX_CONTEXT\"\n",
+            vault = vault.path().display()
+        ),
+    )
+    .unwrap();
+    gcpx::save_context("old", true).unwrap();
+
+    gcpx::commands::rename_context("old", "new").unwrap();
+
+    assert_eq!(
+        fs::read(vault.path().join("new")).unwrap(),
+        fs::read(vault.path().join("old")).unwrap()
+    );
+    let run_env = gcpx::commands::resolve_run_env("new").unwrap();
+    let (_, adc_path) = run_env
+        .iter()
+        .find(|(k, _)| k == "GOOGLE_APPLICATION_CREDENTIALS")
+        .unwrap();
+    assert!(
+        fs::read_to_string(adc_path)
+            .unwrap()
+            .contains("test-refresh-token")
+    );
+    fs::remove_file(adc_path).unwrap();
+
+    // A failed write under the new name leaves the context where it was
+    fs::create_dir(vault.path().join("newer")).unwrap();
+    assert!(gcpx::commands::rename_context("new", "newer").is_err());
+    assert!(gcpx::config::context_exists("new").unwrap());
+    assert!(!env.gcpx_path().join("newer").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_command_credential_store() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    let vault = TempDir::new().unwrap();
+    fs::write(
+        env.gcpx_path().join("config.toml"),
+        format!(
+            "store_backend = \"command\"\n\n[store_command]\n\
+            get_cmd = \"cat {vault}/{{name}}\"\n\
+            put_cmd = \"cat > {vault}/$GCPX_CONTEXT\"\n",
+            vault = vault.path().display()
+        ),
+    )
+    .unwrap();
+
+    gcpx::save_context("vaulted", true).unwrap();
+    assert!(vault.path().join("vaulted").exists());
+    assert!(!env.gcpx_path().join("vaulted").join("adc.json").exists());
+    assert!(gcpx::config::context_exists("vaulted").unwrap());
+
+    // Switching restores the ADC from the secret store
+    gcpx::save_context("other", true).unwrap();
+    let live_adc = env
+        .gcloud_path()
+        .join("application_default_credentials.json");
+    fs::remove_file(&live_adc).unwrap();
+    gcpx::switch_context("vaulted", true).unwrap();
+    assert!(
+        fs::read_to_string(&live_adc)
+            .unwrap()
+            .contains("test-refresh-token")
+    );
+
+    // Commands get a temporary copy of the credentials
+    let run_env = gcpx::commands::resolve_run_env("vaulted").unwrap();
+    let (_, adc_path) = run_env
+        .iter()
+        .find(|(k, _)| k == "GOOGLE_APPLICATION_CREDENTIALS")
+        .unwrap();
+    assert!(
+        fs::read_to_string(adc_path)
+            .unwrap()
+            .contains("test-refresh-token")
+    );
+    // ...in a directory only we can access
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::path::Path::new(adc_path).parent().unwrap();
+        assert_eq!(
+            fs::metadata(dir).unwrap().permissions().mode() & 0o777,
+            0o700
+        );
+    }
+    fs::remove_file(adc_path).unwrap();
+
+    // Nothing on disk doesn't make the context look broken
+    assert!(gcpx::config::context_is_complete("vaulted").unwrap());
+    assert!(
+        gcpx::config::context_problems("vaulted")
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_command_credential_store_large_payload() {
+    let env = TestEnv::new();
+    let vault = TempDir::new().unwrap();
+    // The helper fills its stdout before it reads any input
+    fs::write(
+        env.gcpx_path().join("config.toml"),
+        format!(
+            "store_backend = \"command\"\n\n[store_command]\n\
+            get_cmd = \"cat {vault}/{{name}}\"\n\
+            put_cmd = \"head -c 200000 /dev/zero; cat > {vault}/$GCPX_CONTEXT\"\n",
+            vault = vault.path().display()
+        ),
+    )
+    .unwrap();
+
+    let payload = vec![b'x'; 200_000];
+    let store = gcpx::credstore::credential_store().unwrap();
+    store.write("big", &payload).unwrap();
+    assert_eq!(fs::read(vault.path().join("big")).unwrap(), payload);
+}
+
+#[test]
+fn test_list_count_and_summary() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("one", true).unwrap();
+    gcpx::save_context("two", true).unwrap();
+    let output = env.gcpx(&["list", "--count"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "2");
+
+    let output = env.gcpx(&["list", "--summary"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.ends_with("\n2 contexts, active: two\n"),
+        "{}",
+        stdout
+    );
+
+    // The JSON array has nowhere to put the footer
+    let output = env.gcpx(&["list", "--summary", "--format", "json"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--summary"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_delete_gcloud_config_uses_metadata_config() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    env.create_gcloud_config("alpha");
+    env.create_gcloud_config("alpha-config");
+    env.create_gcloud_config("team");
+
+    let save_with_config = |name: &str, config: &str| {
+        let options = gcpx::SaveOptions {
+            quiet: true,
+            gcloud_config: Some(config.to_string()),
+            ..Default::default()
+        };
+        gcpx::save_context_with_options(name, &options).unwrap();
+    };
+    save_with_config("alpha", "alpha-config");
+    save_with_config("team-a", "team");
+    save_with_config("team-b", "team");
+    save_with_config("live", "default");
+
+    gcpx::delete_context("alpha", true).unwrap();
+    assert!(!env.gcloud_config_exists("alpha-config"));
+    assert!(env.gcloud_config_exists("alpha"));
+
+    // Shared with another context: refused, and nothing is deleted
+    assert!(gcpx::delete_context("team-a", true).is_err());
+    assert!(gcpx::config::context_exists("team-a").unwrap());
+    assert!(env.gcloud_config_exists("team"));
+
+    // Currently active: refused
+    assert!(gcpx::delete_context("live", true).is_err());
+    assert!(gcpx::config::context_exists("live").unwrap());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_rename_gcloud_config_updates_contexts() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    env.create_gcloud_config("old-cfg");
+
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        gcloud_config: Some("old-cfg".to_string()),
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("work", &options).unwrap();
+    gcpx::save_context("other", true).unwrap();
+
+    gcpx::commands::rename_gcloud_config("old-cfg", "new-cfg").unwrap();
+    assert!(env.gcloud_config_exists("new-cfg"));
+    assert!(!env.gcloud_config_exists("old-cfg"));
+
+    let work = gcpx::config::load_context_metadata("work")
+        .unwrap()
+        .unwrap();
+    assert_eq!(work.gcloud_config, "new-cfg");
+    let other = gcpx::config::load_context_metadata("other")
+        .unwrap()
+        .unwrap();
+    assert_eq!(other.gcloud_config, "default");
+
+    // Renaming onto an existing configuration is refused
+    assert!(gcpx::commands::rename_gcloud_config("new-cfg", "default").is_err());
+}
+
+#[test]
+fn test_no_input_refuses_prompts() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("work", true).unwrap();
+    // Bare `gcpx` would open the interactive switcher
+    let output = env.gcpx(&["--no-input"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("non-interactively"));
+
+    let output = env.gcpx(&["save", "notes", "--edit-description", "--no-input"]);
+    assert!(!output.status.success());
+    assert!(!env.gcpx_path().join("notes").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_save_copy_current_config_as() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        copy_current_config_as: Some("forked".to_string()),
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("newproj", &options).unwrap();
+    assert!(env.gcloud_config_exists("forked"));
+    assert!(env.gcloud_config_exists("default"));
+
+    let metadata = gcpx::config::load_context_metadata("newproj")
+        .unwrap()
+        .unwrap();
+    assert_eq!(metadata.gcloud_config, "forked");
+
+    // Forking onto an existing configuration fails before anything is saved
+    assert!(gcpx::save_context_with_options("again", &options).is_err());
+    assert!(!gcpx::config::context_exists("again").unwrap());
+    assert!(env.gcloud_config_exists("forked"));
+
+    // A save that fails after forking removes the copy again
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        copy_current_config_as: Some("doomed".to_string()),
+        project: Some("inaccessible".to_string()),
+        check_project: true,
+        strict: true,
+        ..Default::default()
+    };
+    assert!(gcpx::save_context_with_options("doomed", &options).is_err());
+    assert!(!gcpx::config::context_exists("doomed").unwrap());
+    assert!(!env.gcloud_config_exists("doomed"));
+}
+
+#[test]
+fn test_failed_metadata_write_leaves_context_unchanged() {
+    let env = TestEnv::new();
+    let live_adc = env
+        .gcloud_path()
+        .join("application_default_credentials.json");
+    fs::write(&live_adc, r#"{"type": "authorized_user", "v": "old"}"#).unwrap();
+    gcpx::save_context("work", true).unwrap();
+
+    // A directory where metadata.json should be makes the metadata write fail
+    let context_dir = env.gcpx_path().join("work");
+    fs::remove_file(context_dir.join("metadata.json")).unwrap();
+    fs::create_dir(context_dir.join("metadata.json")).unwrap();
+
+    fs::write(&live_adc, r#"{"type": "authorized_user", "v": "new"}"#).unwrap();
+    assert!(gcpx::save_context("work", true).is_err());
+    let adc = fs::read_to_string(context_dir.join("adc.json")).unwrap();
+    assert!(adc.contains("old"), "ADC was overwritten: {}", adc);
+
+    // A stray directory without credentials doesn't gain any
+    fs::create_dir_all(env.gcpx_path().join("fresh").join("metadata.json")).unwrap();
+    assert!(gcpx::save_context("fresh", true).is_err());
+    assert!(!env.gcpx_path().join("fresh").join("adc.json").exists());
+}
+
+#[test]
+fn test_current_generation_changes_on_switch() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    assert_eq!(gcpx::config::current_generation(), None);
+
+    gcpx::save_context("a", true).unwrap();
+    gcpx::save_context("b", true).unwrap();
+    let before = gcpx::config::current_generation().expect("signal written on save");
+
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    gcpx::switch_context("a", true).unwrap();
+    let after = gcpx::config::current_generation().unwrap();
+    assert!(after > before);
+}
+
+#[test]
+fn test_switch_print_is_log_safe() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        account: Some("secret@example.com".to_string()),
+        project: Some("secret-project".to_string()),
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("dev", &options).unwrap();
+    gcpx::save_context_with_options("prod", &options).unwrap();
+    let output = env.gcpx(&["switch", "dev", "--print"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.lines().count(), 1);
+    assert!(stdout.starts_with("switch: prod -> dev (gcloud config '"));
+    assert!(!stdout.contains("secret"));
+
+    let output = env.gcpx(&["switch", "dev", "--print"]);
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("switch: dev (unchanged"));
+}
+
+#[test]
+fn test_switch_away_from_dangerous_context_notice() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("dev", true).unwrap();
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        danger: true,
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("prod", &options).unwrap();
+
+    // Leaving prod needs no confirmation, even without input
+    let output = env.gcpx(&["--no-input", "switch", "dev"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Leaving production context 'prod'"));
+
+    let output = env.gcpx(&["switch", "prod", "--force"]);
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("Leaving"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_adopt_gcloud_configs() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    env.create_gcloud_config("alpha");
+    env.create_gcloud_config("beta");
+
+    gcpx::commands::adopt_gcloud_config("alpha", true).unwrap();
+    let metadata = gcpx::config::load_context_metadata("alpha")
+        .unwrap()
+        .unwrap();
+    assert_eq!(metadata.gcloud_config, "alpha");
+    // Adopting registers the context without switching to it
+    assert_eq!(
+        gcpx::config::get_current_gcloud_config().unwrap(),
+        "default"
+    );
+    assert_eq!(gcpx::get_current_tracking(), "none");
+    assert!(!env.gcpx_path().join(".current").exists());
+
+    let err = gcpx::commands::adopt_gcloud_config("alpha", true).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<gcpx::GcpxError>(),
+        Some(gcpx::GcpxError::ContextExists(_))
+    ));
+
+    gcpx::commands::adopt_all_gcloud_configs(true).unwrap();
+    assert_eq!(
+        gcpx::list_contexts().unwrap(),
+        vec![
+            "alpha".to_string(),
+            "beta".to_string(),
+            "default".to_string()
+        ]
+    );
+}
+
+#[test]
+fn test_adopt_adc_file() {
+    let env = TestEnv::new();
+    let key = env.gcpx_path().join("adc-work.json");
+    fs::write(
+        &key,
+        r#"{"type": "service_account", "client_email": "deployer@work-prod.iam.gserviceaccount.com", "project_id": "work-prod"}"#,
+    )
+    .unwrap();
+
+    gcpx::commands::adopt_adc_file(&key, "work", &Default::default()).unwrap();
+    let metadata = gcpx::config::load_context_metadata("work")
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        metadata.account.as_deref(),
+        Some("deployer@work-prod.iam.gserviceaccount.com")
+    );
+    assert_eq!(metadata.project.as_deref(), Some("work-prod"));
+    assert_eq!(metadata.gcloud_config, "default");
+    assert_eq!(
+        metadata.adc_source.as_deref(),
+        Some(gcpx::config::ADC_SOURCE_PATH)
+    );
+    assert_eq!(gcpx::get_current_tracking(), "none");
+    assert!(!env.gcpx_path().join(".current").exists());
+
+    // Flags win over what the JSON says
+    let options = gcpx::commands::AdoptAdcOptions {
+        project: Some("work-dev".to_string()),
+        quiet: true,
+        ..Default::default()
+    };
+    gcpx::commands::adopt_adc_file(&key, "work-dev", &options).unwrap();
+    let metadata = gcpx::config::load_context_metadata("work-dev")
+        .unwrap()
+        .unwrap();
+    assert_eq!(metadata.project.as_deref(), Some("work-dev"));
+
+    let err = gcpx::commands::adopt_adc_file(&key, "work", &options).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<gcpx::GcpxError>(),
+        Some(gcpx::GcpxError::ContextExists(_))
+    ));
+
+    fs::write(&key, "not json").unwrap();
+    assert!(gcpx::commands::adopt_adc_file(&key, "broken", &options).is_err());
+    assert!(!gcpx::config::context_exists("broken").unwrap());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_failed_switch_restores_previous_state() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    env.create_gcloud_config("beta");
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        gcloud_config: Some("beta".to_string()),
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("b", &options).unwrap();
+    gcpx::save_context("a", true).unwrap();
+
+    // Make the live ADC unwritable by putting a directory in its place
+    let live_adc = env
+        .gcloud_path()
+        .join("application_default_credentials.json");
+    fs::remove_file(&live_adc).unwrap();
+    fs::create_dir(&live_adc).unwrap();
+
+    let previous = gcpx::config::get_previous_tracking();
+    let err = gcpx::switch_context("b", true).unwrap_err();
+    assert!(format!("{:#}", err).contains("restored"));
+    assert_eq!(
+        gcpx::config::get_current_gcloud_config().unwrap(),
+        "default"
+    );
+    assert_eq!(gcpx::get_current_tracking(), "a");
+    assert_eq!(gcpx::config::get_previous_tracking(), previous);
+
+    // A failure while updating tracking puts `.current` and `.previous` back too
+    fs::remove_dir(&live_adc).unwrap();
+    gcpx::switch_context("b", true).unwrap();
+    gcpx::switch_context("a", true).unwrap();
+    assert_eq!(gcpx::config::get_previous_tracking(), "b");
+    let blocker = env.gcpx_path().join(".switch-signal");
+    fs::remove_file(&blocker).unwrap();
+    fs::create_dir(&blocker).unwrap();
+    assert!(gcpx::switch_context("b", true).is_err());
+    fs::remove_dir(&blocker).unwrap();
+    assert_eq!(gcpx::get_current_tracking(), "a");
+    assert_eq!(gcpx::config::get_previous_tracking(), "b");
+}
+
+#[test]
+fn test_json_envelope() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("one", true).unwrap();
+    gcpx::save_context("two", true).unwrap();
+    let run = |args: &[&str]| -> serde_json::Value {
+        let output = env.gcpx(args);
+        assert!(output.status.success());
+        serde_json::from_slice(&output.stdout).unwrap()
+    };
+
+    let list = run(&["list", "--json"]);
+    assert_eq!(list["version"], gcpx::json::JSON_SCHEMA_VERSION);
+    assert_eq!(list["current"], "two");
+    assert_eq!(list["contexts"][0]["name"], "one");
+    assert_eq!(list["contexts"][1]["active"], true);
+
+    let show = run(&["show", "one", "--json"]);
+    assert_eq!(show["current"], "two");
+    assert_eq!(show["context"]["name"], "one");
+    assert_eq!(show["context"]["active"], false);
+
+    let status = run(&["status", "--json"]);
+    assert_eq!(status["version"], gcpx::json::JSON_SCHEMA_VERSION);
+    assert_eq!(status["tracked"], "two");
+    assert_eq!(status["in_sync"], true);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_reauth_if_needed_skips_login_for_valid_credentials() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("work", true).unwrap();
+    gcpx::save_context("other", true).unwrap();
+
+    gcpx::commands::reauth_if_needed("work", true).unwrap();
+    assert_eq!(gcpx::get_current_tracking(), "work");
+    let log = env.gcloud_log();
+    assert!(log.contains("auth application-default print-access-token"));
+    assert!(!log.contains("auth login"));
+}
+
+#[test]
+fn test_adc_filename_override() {
+    let env = TestEnv::new();
+    assert_eq!(
+        gcpx::config::get_adc_path().unwrap(),
+        env.gcloud_path().join(gcpx::config::DEFAULT_ADC_FILENAME)
+    );
+
+    fs::write(
+        env.gcpx_path().join("config.toml"),
+        "adc_filename = \"adc-from-settings.json\"\n",
+    )
+    .unwrap();
+    assert_eq!(
+        gcpx::config::get_adc_path().unwrap(),
+        env.gcloud_path().join("adc-from-settings.json")
+    );
+
+    // SAFETY: the TestEnv holds ENV_LOCK and clears the variable on drop.
+    unsafe { env::set_var("GCPX_ADC_FILENAME", "adc-from-env.json") };
+    assert_eq!(
+        gcpx::config::get_adc_path().unwrap(),
+        env.gcloud_path().join("adc-from-env.json")
+    );
+
+    for bad in ["../adc.json", "sub/adc.json", "..", "/tmp/adc.json"] {
+        // SAFETY: the TestEnv holds ENV_LOCK and clears the variable on drop.
+        unsafe { env::set_var("GCPX_ADC_FILENAME", bad) };
+        assert!(gcpx::config::get_adc_path().is_err(), "{bad} accepted");
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_switch_skips_activating_active_config() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("a", true).unwrap();
+    gcpx::save_context("b", true).unwrap();
+
+    // Both contexts use the already-active 'default' configuration
+    gcpx::switch_context("a", true).unwrap();
+    assert_eq!(gcpx::get_current_tracking(), "a");
+    assert!(!env.gcloud_log().contains("configurations activate"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_global_context_flag_runs_command() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("work", true).unwrap();
+    let output = env.gcpx(&[
+        "-c",
+        "work",
+        "--",
+        "sh",
+        "-c",
+        "echo \"$GOOGLE_APPLICATION_CREDENTIALS\"",
+    ]);
+    assert!(output.status.success());
+    let adc = env.gcpx_path().join("work").join("adc.json");
+    assert!(String::from_utf8_lossy(&output.stdout).contains(&*adc.to_string_lossy()));
+
+    // Without '--' the command is not accepted
+    let output = env.gcpx(&["-c", "work", "echo", "hi"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_org_captured_and_grouped() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    for (name, account) in [
+        ("acme-dev", Some("dev@Acme.com")),
+        ("acme-prod", Some("ops@acme.com")),
+        ("globex", Some("me@globex.io")),
+        ("anon", None),
+    ] {
+        let options = gcpx::SaveOptions {
+            quiet: true,
+            account: account.map(str::to_string),
+            ..Default::default()
+        };
+        gcpx::save_context_with_options(name, &options).unwrap();
+    }
+    let metadata = gcpx::config::load_context_metadata("acme-dev")
+        .unwrap()
+        .unwrap();
+    assert_eq!(metadata.org.as_deref(), Some("acme.com"));
+    let output = env.gcpx(&["list", "--group-by", "org"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "acme.com:\n  acme-dev\n  acme-prod\n\nglobex.io:\n  globex\n\n(none):\n* anon (active)\n"
+    );
+}
+
+#[test]
+fn test_config_get_set_unset() {
+    let env = TestEnv::new();
+    let settings_path = env.gcpx_path().join("config.toml");
+    fs::write(&settings_path, "# mine\nfuture_key = 1\n").unwrap();
+
+    gcpx::settings::set_setting("adc_max_age_days", "30").unwrap();
+    gcpx::settings::set_setting("capture_properties", "run/region, core/disable_prompts").unwrap();
+    gcpx::settings::set_setting("store_command.get_cmd", "vault get {name}").unwrap();
+    assert_eq!(
+        gcpx::settings::get_setting("adc_max_age_days")
+            .unwrap()
+            .as_deref(),
+        Some("30")
+    );
+    assert_eq!(
+        gcpx::settings::get_setting("store_command.get_cmd")
+            .unwrap()
+            .as_deref(),
+        Some("vault get {name}")
+    );
+
+    let settings = gcpx::settings::load_settings().unwrap();
+    assert_eq!(settings.adc_max_age_days, Some(30));
+    assert_eq!(
+        settings.capture_properties,
+        vec!["run/region".to_string(), "core/disable_prompts".to_string()]
+    );
+    // Keys gcpx doesn't know survive a write
+    assert!(
+        fs::read_to_string(&settings_path)
+            .unwrap()
+            .contains("future_key = 1")
+    );
+
+    // Bad keys and values are rejected without touching the file
+    assert!(gcpx::settings::set_setting("no_such_key", "1").is_err());
+    assert!(gcpx::settings::set_setting("isolated_configs", "maybe").is_err());
+    assert!(gcpx::settings::set_setting("store_backend", "s3").is_err());
+    assert_eq!(gcpx::settings::get_setting("store_backend").unwrap(), None);
+
+    assert!(gcpx::settings::unset_setting("adc_max_age_days").unwrap());
+    assert!(!gcpx::settings::unset_setting("adc_max_age_days").unwrap());
+    assert_eq!(
+        gcpx::settings::load_settings().unwrap().adc_max_age_days,
+        None
+    );
+}
+
+#[test]
+fn test_save_dry_run_writes_nothing() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+
+    let options = gcpx::SaveOptions {
+        dry_run: true,
+        description: Some("preview".to_string()),
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("preview", &options).unwrap();
+    assert!(!env.gcpx_path().join("preview").exists());
+    assert_eq!(gcpx::get_current_tracking(), "none");
+    let output = env.gcpx(&["save", "preview", "--dry-run"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("would create context 'preview'"));
+    assert!(stdout.contains("\"adc_type\": \"authorized_user\""));
+    assert!(!env.gcpx_path().join("preview").exists());
+}
+
+#[test]
+fn test_groups_switch_and_env() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("alpha", true).unwrap();
+    gcpx::save_context("beta", true).unwrap();
+
+    gcpx::commands::add_group(
+        "platform",
+        "alpha",
+        None,
+        &["TF_WORKSPACE=it's".to_string()],
+    )
+    .unwrap();
+    assert!(gcpx::commands::add_group("bad", "missing", None, &[]).is_err());
+    assert!(gcpx::commands::add_group("bad", "alpha", None, &["NOEQ".to_string()]).is_err());
+    for key in ["A;rm -rf ~;B", "1ST", "", "WITH SPACE", "DASH-ED"] {
+        let err =
+            gcpx::commands::add_group("bad", "alpha", None, &[format!("{}=x", key)]).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<gcpx::GcpxError>(),
+            Some(gcpx::GcpxError::InvalidEnvKey(_))
+        ));
+    }
+
+    gcpx::commands::switch_group("platform", true).unwrap();
+    assert_eq!(gcpx::get_current_tracking(), "alpha");
+    let output = env.gcpx(&["groups", "env", "platform"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "export TF_WORKSPACE='it'\\''s'\n"
+    );
+
+    gcpx::commands::remove_group("platform").unwrap();
+    assert!(gcpx::commands::switch_group("platform", true).is_err());
+}
+
+#[test]
+fn test_run_report_written_on_failure() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("work", true).unwrap();
+    let report = env.gcpx_path().join("report.json");
+    let output = env.gcpx(&[
+        "run",
+        "work",
+        "--report",
+        report.to_str().unwrap(),
+        "--",
+        "sh",
+        "-c",
+        "echo streamed; exit 3",
+    ]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("streamed"));
+
+    let summary: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    assert_eq!(summary["context"], "work");
+    assert_eq!(summary["exit_code"], 3);
+    assert!(summary["duration_ms"].is_u64());
+}
+
+#[test]
+fn test_run_passes_stdio_through() {
+    use std::io::Write;
+
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("work", true).unwrap();
+
+    let mut child = env
+        .gcpx_command()
+        .args(["run", "work", "--", "cat"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"line one\nline two\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    // stdout is the command's alone
+    assert_eq!(output.stdout, b"line one\nline two\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Running with context 'work'"));
+}
+
+#[test]
+fn test_run_with_context_file() {
+    let env = TestEnv::new();
+    let key = env.gcpx_path().join("throwaway.json");
+    fs::create_dir_all(env.gcpx_path()).unwrap();
+    fs::write(&key, r#"{"type": "service_account"}"#).unwrap();
+    let report = env.gcpx_path().join("report.json");
+    let output = env.gcpx(&[
+        "run",
+        "--context-file",
+        key.to_str().unwrap(),
+        "--report",
+        report.to_str().unwrap(),
+        "--",
+        "sh",
+        "-c",
+        "echo \"[$GOOGLE_APPLICATION_CREDENTIALS]\"",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let key = key.canonicalize().unwrap();
+    assert!(stdout.contains(&format!("[{}]", key.display())));
+    let summary: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    assert_eq!(summary["context"], key.to_string_lossy().as_ref());
+    // Nothing was saved
+    assert!(gcpx::list_contexts().unwrap().is_empty());
+
+    fs::write(&key, "not json").unwrap();
+    let output = env.gcpx(&["run", "--context-file", key.to_str().unwrap(), "--", "true"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid credentials"));
+}
+
+#[test]
+fn test_run_context_file_rejects_context_name() {
+    let env = TestEnv::new();
+    let key = env.gcpx_path().join("throwaway.json");
+    fs::create_dir_all(env.gcpx_path()).unwrap();
+    fs::write(&key, r#"{"type": "service_account"}"#).unwrap();
+    let key = key.to_str().unwrap();
+
+    for args in [
+        ["run", "--context-file", key, "work", "--", "true"].as_slice(),
+        &["run", "--context-file", key, "true"],
+    ] {
+        let output = env.gcpx(args);
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("after '--'"));
+    }
+    assert!(
+        env.gcpx(&["run", "--context-file", key, "--", "true"])
+            .status
+            .success()
+    );
+}
+
+#[test]
+fn test_run_policy() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        run_deny: vec!["sh -c".to_string()],
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("prod", &options).unwrap();
+    let cmd = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+
+    // Matched by program file name and denied words anywhere in the arguments
+    let err = gcpx::run_with_context("prod", &cmd(&["/bin/sh", "-c", "true"])).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<GcpxError>(),
+        Some(GcpxError::RunPolicy(..))
+    ));
+    assert!(gcpx::run_with_context("prod", &cmd(&["sh", "-e", "-c", "true"])).is_err());
+    gcpx::run_with_context("prod", &cmd(&["sh", "-e", "/dev/null"])).unwrap();
+
+    let options = gcpx::commands::RunOptions {
+        override_policy: true,
+        ..Default::default()
+    };
+    gcpx::commands::run_with_context_options("prod", &cmd(&["sh", "-c", "true"]), &options)
+        .unwrap();
+
+    // A merge keeps the policy; an allowlist forbids everything else
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        merge_metadata: true,
+        run_allow: vec!["true".to_string()],
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("prod", &options).unwrap();
+    let metadata = gcpx::config::load_context_metadata("prod")
+        .unwrap()
+        .unwrap();
+    assert_eq!(metadata.run_denylist, ["sh -c"]);
+    gcpx::run_with_context("prod", &cmd(&["true"])).unwrap();
+    assert!(gcpx::run_with_context("prod", &cmd(&["false"])).is_err());
+    assert!(gcpx::commands::run_policy_violation(&metadata, &cmd(&["false"])).is_some());
+
+    // Global flags don't get around a denied subcommand, nor block an allowed one
+    let terraform = gcpx::config::ContextMetadata {
+        run_denylist: vec!["terraform apply".to_string()],
+        run_allowlist: vec!["terraform plan".to_string()],
+        ..metadata.clone()
+    };
+    let violation = |args: &[&str]| gcpx::commands::run_policy_violation(&terraform, &cmd(args));
+    assert!(violation(&["terraform", "-chdir=infra", "apply"]).is_some());
+    assert!(violation(&["terraform", "-chdir=infra", "plan"]).is_none());
+    assert!(violation(&["terraform", "-chdir=infra", "destroy"]).is_some());
+    let output = env.gcpx(&["run", "prod", "--", "sh", "-c", "true"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--override-policy"));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_run_policy_checked_before_credentials() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    let vault = TempDir::new().unwrap();
+    let fetched = vault.path().join("fetched");
+    fs::write(
+        env.gcpx_path().join("config.toml"),
+        format!(
+            "store_backend = \"command\"\n\n[store_command]\n\
+            get_cmd = \"touch {fetched}; cat {vault}/{{name}}\"\n\
+            put_cmd = \"cat > {vault}/$GCPX_CONTEXT\"\n",
+            fetched = fetched.display(),
+            vault = vault.path().display()
+        ),
+    )
+    .unwrap();
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        run_deny: vec!["sh".to_string()],
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("prod", &options).unwrap();
+
+    let cmd = ["sh".to_string(), "-c".to_string(), "true".to_string()];
+    assert!(gcpx::run_with_context("prod", &cmd).is_err());
+    assert!(!fetched.exists());
+}
+
+#[test]
+fn test_save_warns_when_credentials_belong_to_another_account() {
+    let env = TestEnv::new();
+    let key = env.gcpx_path().join("key.json");
+    fs::create_dir_all(env.gcpx_path()).unwrap();
+    fs::write(
+        &key,
+        r#"{"type": "service_account", "client_email": "ci@p.iam.gserviceaccount.com"}"#,
+    )
+    .unwrap();
+
+    let save = |account: &str| {
+        let output = env.gcpx(&[
+            "save",
+            "ci",
+            "--account",
+            account,
+            "--adc-path",
+            key.to_str().unwrap(),
+        ]);
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    };
+    let stderr = save("me@example.com");
+    assert!(stderr.contains(
+        "credentials belong to ci@p.iam.gserviceaccount.com, but the gcloud account is me@example.com"
+    ));
+    let metadata = gcpx::config::load_context_metadata("ci").unwrap().unwrap();
+    assert_eq!(metadata.account.as_deref(), Some("me@example.com"));
+    assert_eq!(
+        metadata.adc_identity.as_deref(),
+        Some("ci@p.iam.gserviceaccount.com")
+    );
+
+    assert!(!save("ci@p.iam.gserviceaccount.com").contains("Warning"));
+}
+
+#[test]
+fn test_whoami_flags_account_mismatch() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        account: Some("me@example.com".to_string()),
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("work", &options).unwrap();
+    let output = env.gcpx(&["whoami", "--json"]);
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["current"], "work");
+    assert_eq!(report["account"], serde_json::Value::Null);
+    assert_eq!(report["adc_type"], "authorized_user");
+    let mismatches = report["mismatches"].as_array().unwrap();
+    assert_eq!(mismatches.len(), 1);
+    assert!(mismatches[0].as_str().unwrap().contains("me@example.com"));
+}
+
+#[test]
+fn test_export_line_evals_to_original_value() {
+    use gcpx::commands::{ExportShell, export_line};
+
+    let values = [
+        "/path/with spaces/adc.json",
+        "it's \"quoted\"",
+        "$HOME `id` \\n ; echo pwned",
+        "",
+    ];
+    for value in values {
+        let line = export_line(ExportShell::Bash, "GCPX_TEST_VALUE", value).unwrap();
+        let output = std::process::Command::new("sh")
+            .args(["-c", &format!("{}; printf %s \"$GCPX_TEST_VALUE\"", line)])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", line);
+        assert_eq!(String::from_utf8_lossy(&output.stdout), value, "{}", line);
+    }
+
+    assert_eq!(
+        export_line(ExportShell::Fish, "K", r"it's a\path").unwrap(),
+        r"set -gx K 'it\'s a\\path'"
+    );
+    assert_eq!(
+        export_line(ExportShell::Zsh, "K", "it's").unwrap(),
+        export_line(ExportShell::Bash, "K", "it's").unwrap()
+    );
+    for key in ["A=B", "X;id", "1A", ""] {
+        assert!(export_line(ExportShell::Bash, key, "v").is_err(), "{key:?}");
+        assert!(export_line(ExportShell::Fish, key, "v").is_err(), "{key:?}");
+    }
+}
+
+#[test]
+fn test_env_fish_output() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("work", true).unwrap();
+    let output = env.gcpx(&["env", "work", "--shell", "fish"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.lines().all(|line| line.starts_with("set -gx ")),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("set -gx GOOGLE_APPLICATION_CREDENTIALS '"));
+}
+
+#[test]
+fn test_most_recent_candidates() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    for name in ["a", "b", "c", "d"] {
+        gcpx::save_context(name, true).unwrap();
+    }
+    for (name, last_used) in [
+        ("a", None),
+        ("b", Some(100)),
+        ("d", Some(300)),
+        ("c", Some(200)),
+    ] {
+        let mut m = gcpx::config::load_context_metadata(name).unwrap().unwrap();
+        m.last_used = last_used;
+        gcpx::config::save_context_metadata(name, &m).unwrap();
+    }
+
+    let all = gcpx::commands::interactive_candidates(&Default::default()).unwrap();
+    assert_eq!(
+        gcpx::commands::most_recent(&all, 2).unwrap(),
+        vec!["d", "c"]
+    );
+    assert_eq!(
+        gcpx::commands::most_recent(&all, 10).unwrap(),
+        vec!["d", "c", "b", "a"]
+    );
+    assert_eq!(
+        gcpx::commands::most_recent(&["a".to_string(), "b".to_string()], 1).unwrap(),
+        vec!["b"]
+    );
+}
+
+#[test]
+fn test_save_require_active_config() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    fs::write(env._bin_dir.path().join("active"), "").unwrap();
+
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        require_active_config: true,
+        ..Default::default()
+    };
+    let err = gcpx::save_context_with_options("work", &options).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<gcpx::GcpxError>(),
+        Some(gcpx::GcpxError::NoActiveGcloudConfig)
+    ));
+    assert!(!gcpx::config::context_exists("work").unwrap());
+
+    // Without the flag, 'default' is still assumed
+    gcpx::save_context("work", true).unwrap();
+    let metadata = gcpx::config::load_context_metadata("work")
+        .unwrap()
+        .unwrap();
+    assert_eq!(metadata.gcloud_config, "default");
+}
+
+#[test]
+fn test_save_kube_context_override() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        kube_context: Some("gke_prod".to_string()),
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("work", &options).unwrap();
+    let metadata = gcpx::config::load_context_metadata("work")
+        .unwrap()
+        .unwrap();
+    assert_eq!(metadata.kubectl_context.as_deref(), Some("gke_prod"));
+
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        no_kube: true,
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("work", &options).unwrap();
+    let metadata = gcpx::config::load_context_metadata("work")
+        .unwrap()
+        .unwrap();
+    assert_eq!(metadata.kubectl_context, None);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_login_no_browser_reaches_both_logins() {
+    use std::os::fd::{FromRawFd, OwnedFd};
+
+    let env = TestEnv::new();
+    env.create_fake_adc();
+
+    // login refuses to run without a terminal, so give it a pseudo-terminal
+    let (mut master, mut slave) = (0, 0);
+    // SAFETY: openpty only writes the two descriptors it returns
+    let rc = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    assert_eq!(rc, 0, "openpty failed");
+    // SAFETY: both descriptors were just opened and are owned here only
+    let (master, slave) = unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+
+    let output = env
+        .gcpx_command()
+        .args(["login", "work", "--no-browser", "-q"])
+        .env_remove("CI")
+        .env_remove("GCPX_CI")
+        .stdin(slave)
+        .output()
+        .unwrap();
+    drop(master);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let log = env.gcloud_log();
+    assert!(log.contains("auth login --no-launch-browser"));
+    assert!(log.contains("auth application-default login --no-launch-browser"));
+    assert!(gcpx::config::context_exists("work").unwrap());
+}
+
+#[test]
+fn test_save_check_project() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    let projects = env._bin_dir.path().join("projects");
+    fs::create_dir_all(&projects).unwrap();
+    fs::write(projects.join("my-project"), "").unwrap();
+
+    let options = |project: &str, strict: bool| gcpx::SaveOptions {
+        quiet: true,
+        project: Some(project.to_string()),
+        check_project: true,
+        strict,
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("ok", &options("my-project", true)).unwrap();
+
+    // Inaccessible: a warning by default, an error with strict
+    gcpx::save_context_with_options("typo", &options("my-projcet", false)).unwrap();
+    let err = gcpx::save_context_with_options("strict", &options("my-projcet", true)).unwrap_err();
+    assert!(err.to_string().contains("isn't accessible"));
+    assert!(!gcpx::config::context_exists("strict").unwrap());
+
+    // Without the flag, gcloud isn't asked
+    let _ = fs::remove_file(env._bin_dir.path().join("gcloud.log"));
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        project: Some("anything".to_string()),
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("fast", &options).unwrap();
+    assert!(!env.gcloud_log().contains("projects describe"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_save_check_project_uses_context_account() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    let options = |strict: bool| gcpx::SaveOptions {
+        quiet: true,
+        account: Some("me@example.com".to_string()),
+        project: Some("my-project".to_string()),
+        check_project: true,
+        strict,
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("work", &options(false)).unwrap();
+    assert!(env.gcloud_log().contains(
+        "projects describe my-project --format=value(projectId) \
+        --account=me@example.com --configuration=default"
+    ));
+
+    // Without gcloud the check can't run: a warning, or an error with strict
+    fs::remove_file(env._bin_dir.path().join("gcloud")).unwrap();
+    // SAFETY: the TestEnv holds ENV_LOCK and restores PATH on drop.
+    unsafe { env::set_var("PATH", env._bin_dir.path()) };
+    let stdin_options = |strict: bool| gcpx::SaveOptions {
+        adc_json: Some(br#"{"type": "authorized_user"}"#.to_vec()),
+        ..options(strict)
+    };
+    gcpx::save_context_with_options("offline", &stdin_options(false)).unwrap();
+    let err = gcpx::save_context_with_options("strict", &stdin_options(true)).unwrap_err();
+    assert!(err.to_string().contains("couldn't check project"));
+    assert!(!gcpx::config::context_exists("strict").unwrap());
+}
+
+#[test]
+fn test_show_copy_fails_gracefully() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        account: Some("me@example.com".to_string()),
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("work", &options).unwrap();
+
+    let err =
+        gcpx::commands::copy_context_field("work", gcpx::commands::CopyField::Project).unwrap_err();
+    assert!(err.to_string().contains("no project recorded"), "{}", err);
+
+    if cfg!(target_os = "linux") {
+        let output = env
+            .gcpx_command()
+            .args(["show", "work", "--copy"])
+            .env_remove("DISPLAY")
+            .env_remove("WAYLAND_DISPLAY")
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("No clipboard available"));
+    }
+}
+
+#[test]
+fn test_import_store() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    let old_store = TempDir::new().unwrap();
+    for name in ["alpha", "beta", "broken"] {
+        let dir = old_store.path().join(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("adc.json"), r#"{"type":"authorized_user"}"#).unwrap();
+    }
+    fs::write(
+        old_store.path().join("alpha").join("metadata.json"),
+        r#"{"gcloud_config":"alpha-cfg","account":"a@example.com","project":null}"#,
+    )
+    .unwrap();
+    fs::write(
+        old_store.path().join("broken").join("metadata.json"),
+        "{not json",
+    )
+    .unwrap();
+    fs::write(old_store.path().join(".current"), "alpha").unwrap();
+
+    gcpx::save_context("beta", true).unwrap();
+    let local_beta = fs::read(env.gcpx_path().join("beta").join("adc.json")).unwrap();
+
+    let options = gcpx::commands::ImportStoreOptions {
+        all: true,
+        ..Default::default()
+    };
+    let err = gcpx::commands::import_store(old_store.path(), &options).unwrap_err();
+    assert!(err.to_string().contains("1 of 3"), "{}", err);
+
+    let alpha = gcpx::config::load_context_metadata("alpha")
+        .unwrap()
+        .unwrap();
+    assert_eq!(alpha.gcloud_config, "alpha-cfg");
+    assert!(!env.gcpx_path().join("broken").exists());
+    // Existing contexts are kept without --overwrite
+    assert_eq!(
+        fs::read(env.gcpx_path().join("beta").join("adc.json")).unwrap(),
+        local_beta
+    );
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(env.gcpx_path().join("alpha").join("adc.json"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    // Overwriting replaces the whole directory, and can be undone
+    let local_version = env.gcpx_path().join("beta").join("adc.json.1");
+    fs::write(&local_version, "kept version").unwrap();
+    let options = gcpx::commands::ImportStoreOptions {
+        names: vec!["beta".to_string()],
+        overwrite: true,
+        ..Default::default()
+    };
+    gcpx::commands::import_store(old_store.path(), &options).unwrap();
+    assert_eq!(
+        fs::read_to_string(env.gcpx_path().join("beta").join("adc.json")).unwrap(),
+        r#"{"type":"authorized_user"}"#
+    );
+    assert!(!local_version.exists());
+    assert_eq!(gcpx::list_contexts().unwrap(), ["alpha", "beta"]);
+    assert!(fs::read_dir(env.gcpx_path()).unwrap().all(|e| {
+        !e.unwrap()
+            .file_name()
+            .to_string_lossy()
+            .starts_with(".import-")
+    }));
+
+    gcpx::undo_last_op().unwrap();
+    assert_eq!(
+        fs::read(env.gcpx_path().join("beta").join("adc.json")).unwrap(),
+        local_beta
+    );
+    assert_eq!(fs::read_to_string(&local_version).unwrap(), "kept version");
+}
+
+#[test]
+fn test_complete_command_names_and_flag_values() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("work", true).unwrap();
+    gcpx::save_context("personal", true).unwrap();
+    let complete = |args: &[&str]| {
+        let output = env.gcpx(&[&["__complete"], args].concat());
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    assert_eq!(complete(&["switch", ""]), "personal\nwork\n");
+    assert_eq!(complete(&["delete", "w"]), "work\n");
+    assert_eq!(
+        complete(&["list", "", "--after", "--format"]),
+        "json\nplain\ntable\n"
+    );
+    assert_eq!(complete(&["save", "", "--after", "--config"]), "default\n");
+    assert_eq!(complete(&["-c", "p", "--after", "-c"]), "personal\n");
+    // Boolean flags have no values to complete
+    assert_eq!(complete(&["delete", "", "--after", "--gcloud-config"]), "");
+}
+
+#[test]
+fn test_switch_adc_dest() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("b", true).unwrap();
+    fs::write(
+        env.gcpx_path().join("b").join("adc.json"),
+        r#"{"type":"service_account"}"#,
+    )
+    .unwrap();
+    gcpx::save_context("a", true).unwrap();
+    let live_adc = env
+        .gcloud_path()
+        .join("application_default_credentials.json");
+    let live_before = fs::read(&live_adc).unwrap();
+
+    let dest = env.gcpx_path().join("container-adc.json");
+    let options = gcpx::SwitchOptions {
+        quiet: true,
+        adc_dest: Some(dest.clone()),
+        ..Default::default()
+    };
+    gcpx::switch_context_with_options("b", &options).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(&dest).unwrap(),
+        r#"{"type":"service_account"}"#
+    );
+    assert_eq!(fs::read(&live_adc).unwrap(), live_before);
+    assert_eq!(gcpx::get_current_tracking(), "b");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&dest).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
+
+#[test]
+fn test_switch_adc_dest_on_tracked_context() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("a", true).unwrap();
+    gcpx::switch_context("a", true).unwrap();
+
+    let dest = env.gcpx_path().join("container-adc.json");
+    let options = gcpx::SwitchOptions {
+        quiet: true,
+        adc_dest: Some(dest.clone()),
+        ..Default::default()
+    };
+    gcpx::switch_context_with_options("a", &options).unwrap();
+
+    assert_eq!(
+        fs::read(&dest).unwrap(),
+        fs::read(env.gcpx_path().join("a").join("adc.json")).unwrap()
+    );
+    assert_eq!(gcpx::get_current_tracking(), "a");
+}
+
+#[test]
+fn test_external_account_credential_source_bundled() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("other", true).unwrap();
+
+    let token_dir = TempDir::new().unwrap();
+    let token = token_dir.path().join("oidc-token");
+    fs::write(&token, "header.payload.signature").unwrap();
+    let adc = serde_json::json!({
+        "type": "external_account",
+        "audience": "//iam.googleapis.com/projects/1/locations/global/workloadIdentityPools/p/providers/q",
+        "subject_token_type": "urn:ietf:params:oauth:token-type:jwt",
+        "token_url": "https://sts.googleapis.com/v1/token",
+        "credential_source": { "file": token.to_string_lossy() },
+    });
+    let adc_file = token_dir.path().join("wif.json");
+    fs::write(&adc_file, adc.to_string()).unwrap();
+
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        adc_path: Some(adc_file),
+        bundle_credential_source: true,
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("wif", &options).unwrap();
+    fs::remove_file(&token).unwrap();
+
+    gcpx::switch_context("other", true).unwrap();
+    gcpx::switch_context("wif", true).unwrap();
+    let live: serde_json::Value = serde_json::from_slice(
+        &fs::read(
+            env.gcloud_path()
+                .join("application_default_credentials.json"),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let bundled = env.gcpx_path().join("wif").join("credential_source");
+    assert_eq!(
+        live["credential_source"]["file"],
+        bundled.to_string_lossy().as_ref()
+    );
+    assert_eq!(
+        fs::read_to_string(&bundled).unwrap(),
+        "header.payload.signature"
+    );
+
+    // Commands get the rewritten credentials from the context directory
+    let run_env = gcpx::commands::resolve_run_env("wif").unwrap();
+    let (_, run_adc) = run_env
+        .iter()
+        .find(|(k, _)| k == "GOOGLE_APPLICATION_CREDENTIALS")
+        .unwrap();
+    let run_adc = std::path::Path::new(run_adc);
+    assert_eq!(run_adc.parent().unwrap(), env.gcpx_path().join("wif"));
+    let run_json: serde_json::Value = serde_json::from_slice(&fs::read(run_adc).unwrap()).unwrap();
+    assert_eq!(
+        run_json["credential_source"]["file"],
+        bundled.to_string_lossy().as_ref()
+    );
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(run_adc).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let mode = fs::metadata(&bundled).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    // Versions keep their own token file, and rollback brings it back
+    fs::write(&token, "second.token.value").unwrap();
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        adc_path: Some(token_dir.path().join("wif.json")),
+        bundle_credential_source: true,
+        keep_versions: 1,
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("wif", &options).unwrap();
+    assert_eq!(fs::read_to_string(&bundled).unwrap(), "second.token.value");
+    assert_eq!(
+        fs::read_to_string(env.gcpx_path().join("wif").join("credential_source.1")).unwrap(),
+        "header.payload.signature"
+    );
+    gcpx::commands::rollback_context("wif").unwrap();
+    assert_eq!(
+        fs::read_to_string(&bundled).unwrap(),
+        "header.payload.signature"
+    );
+
+    // Bundling requires an external account with a source file
+    env.create_fake_adc();
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        bundle_credential_source: true,
+        ..Default::default()
+    };
+    assert!(gcpx::save_context_with_options("plain", &options).is_err());
+}
+
+#[test]
+fn test_list_broken() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("good", true).unwrap();
+    gcpx::save_context("bad-json", true).unwrap();
+    fs::write(env.gcpx_path().join("bad-json").join("adc.json"), "{oops").unwrap();
+    gcpx::save_context("no-meta", true).unwrap();
+    fs::remove_file(env.gcpx_path().join("no-meta").join("metadata.json")).unwrap();
+    let output = env.gcpx(&["list", "--broken"]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(
+        stdout,
+        "bad-json: invalid adc.json\nno-meta: missing metadata.json\n"
+    );
+
+    fs::remove_dir_all(env.gcpx_path().join("bad-json")).unwrap();
+    fs::remove_dir_all(env.gcpx_path().join("no-meta")).unwrap();
+    let output = env.gcpx(&["list", "--broken"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "No broken contexts.\n"
+    );
+}
+
+#[test]
+fn test_portable_export_import_resolves_paths() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("plain", true).unwrap();
+
+    let token_dir = TempDir::new().unwrap();
+    let token = token_dir.path().join("oidc-token");
+    fs::write(&token, "header.payload.signature").unwrap();
+    let adc = serde_json::json!({
+        "type": "external_account",
+        "audience": "//iam.googleapis.com/projects/1/locations/global/workloadIdentityPools/p/providers/q",
+        "subject_token_type": "urn:ietf:params:oauth:token-type:jwt",
+        "token_url": "https://sts.googleapis.com/v1/token",
+        "credential_source": { "file": token.to_string_lossy() },
+    });
+    let adc_file = token_dir.path().join("wif.json");
+    fs::write(&adc_file, adc.to_string()).unwrap();
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        adc_path: Some(adc_file),
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("wif", &options).unwrap();
+
+    let archive = token_dir.path().join("wif.tar.gz");
+    let portable = gcpx::commands::ExportOptions {
+        portable: true,
+        ..Default::default()
+    };
+    gcpx::commands::export_context("wif", &archive, &portable).unwrap();
+    fs::remove_file(&token).unwrap();
+
+    let import = gcpx::commands::ImportOptions {
+        name: Some("wif2".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(
+        gcpx::commands::import_context(&archive, &import).unwrap(),
+        "wif2"
+    );
+    let dir = env.gcpx_path().join("wif2");
+    let imported: serde_json::Value =
+        serde_json::from_slice(&fs::read(dir.join("adc.json")).unwrap()).unwrap();
+    assert_eq!(
+        imported["credential_source"]["file"],
+        dir.join("credential_source").to_string_lossy().as_ref()
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("credential_source")).unwrap(),
+        "header.payload.signature"
+    );
+
+    // Importing over an existing context needs --overwrite
+    let err = gcpx::commands::import_context(&archive, &import).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<gcpx::GcpxError>(),
+        Some(gcpx::GcpxError::ContextExists(_))
+    ));
+
+    // Overwriting can be undone
+    let marker = dir.join("local-note");
+    fs::write(&marker, "before").unwrap();
+    let overwrite = gcpx::commands::ImportOptions {
+        overwrite: true,
+        ..import
+    };
+    gcpx::commands::import_context(&archive, &overwrite).unwrap();
+    assert!(!marker.exists());
+    gcpx::undo_last_op().unwrap();
+    assert_eq!(fs::read_to_string(&marker).unwrap(), "before");
+
+    // authorized_user credentials pass through unchanged
+    let archive = token_dir.path().join("plain.tar.gz");
+    gcpx::commands::export_context("plain", &archive, &portable).unwrap();
+    let import = gcpx::commands::ImportOptions {
+        name: Some("plain2".to_string()),
+        ..Default::default()
+    };
+    gcpx::commands::import_context(&archive, &import).unwrap();
+    assert_eq!(
+        fs::read(env.gcpx_path().join("plain2").join("adc.json")).unwrap(),
+        fs::read(env.gcpx_path().join("plain").join("adc.json")).unwrap()
+    );
+}
+
+#[test]
+fn test_export_import_all() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("dev", true).unwrap();
+    gcpx::save_context("prod", true).unwrap();
+    gcpx::switch_context("dev", true).unwrap();
+    fs::write(
+        env.gcpx_path().join("config.toml"),
+        "isolated_configs = false\n",
+    )
+    .unwrap();
+    let backup_dir = TempDir::new().unwrap();
+    let archive = backup_dir.path().join("backup.tar.gz");
+    let export = |extra: &[&str]| {
+        env.gcpx(&[&["export", "--all", archive.to_str().unwrap()], extra].concat())
+    };
+    let output = export(&[]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Backed up 2 context(s)"));
+
+    // A context without readable credentials fails the backup unless skipped
+    fs::remove_file(&archive).unwrap();
+    let broken = env.gcpx_path().join("broken");
+    fs::create_dir(&broken).unwrap();
+    fs::write(
+        broken.join("metadata.json"),
+        r#"{"gcloud_config":"broken"}"#,
+    )
+    .unwrap();
+    let output = export(&[]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--skip-unreadable"));
+    assert!(!archive.exists());
+    let output = export(&["--skip-unreadable"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("(1 skipped)"));
+
+    // --all takes the archive; a context name alongside it is a usage error
+    let output = env.gcpx(&["export", "dev", "--all", archive.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(2));
+
+    // A single-context import points at --all
+    let err = gcpx::commands::import_context(&archive, &Default::default()).unwrap_err();
+    assert!(err.to_string().contains("import --all"));
+
+    // Restore into an empty store
+    for entry in fs::read_dir(env.gcpx_path()).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            fs::remove_dir_all(path).unwrap();
+        } else {
+            fs::remove_file(path).unwrap();
+        }
+    }
+    let output = env.gcpx(&["import", "--all", archive.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(gcpx::list_contexts().unwrap(), ["dev", "prod"]);
+    assert_eq!(gcpx::get_current_tracking(), "dev");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("run 'gcpx switch dev'"));
+    assert_eq!(
+        fs::read_to_string(env.gcpx_path().join("config.toml")).unwrap(),
+        "isolated_configs = false\n"
+    );
+
+    // Existing contexts are kept unless --overwrite
+    let mut m = gcpx::config::load_context_metadata("dev").unwrap().unwrap();
+    m.description = Some("local".to_string());
+    gcpx::config::save_context_metadata("dev", &m).unwrap();
+    let options = gcpx::commands::ImportOptions::default();
+    fs::remove_file(env.gcpx_path().join(".current")).unwrap();
+    gcpx::commands::import_backup(&archive, &options).unwrap();
+    let m = gcpx::config::load_context_metadata("dev").unwrap().unwrap();
+    assert_eq!(m.description.as_deref(), Some("local"));
+    // ...and tracking isn't pointed at a context the import didn't restore
+    assert_eq!(gcpx::get_current_tracking(), "none");
+    let options = gcpx::commands::ImportOptions {
+        overwrite: true,
+        ..Default::default()
+    };
+    gcpx::commands::import_backup(&archive, &options).unwrap();
+    let m = gcpx::config::load_context_metadata("dev").unwrap().unwrap();
+    assert_eq!(m.description, None);
+}
+
+#[test]
+fn test_list_since_before_last_used() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    for (name, last_used) in [
+        ("never", None),
+        ("old", Some(1_600_000_000)),
+        ("new", Some(1_700_000_000)),
+    ] {
+        gcpx::save_context(name, true).unwrap();
+        let mut m = gcpx::config::load_context_metadata(name).unwrap().unwrap();
+        m.last_used = last_used;
+        gcpx::config::save_context_metadata(name, &m).unwrap();
+    }
+
+    let list = |args: &[&str]| {
+        let output = env.gcpx(&[&["list"], args].concat());
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|l| l.split_whitespace().find(|w| *w != "*"))
+            .map(String::from)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(list(&["--since", "2023-01-01"]), ["new"]);
+    assert_eq!(list(&["--before", "2023-01-01"]), ["never", "old"]);
+    assert_eq!(
+        list(&["--since", "2020-01-01", "--before", "2023-01-01T00:00"]),
+        ["old"]
+    );
+
+    let output = env.gcpx(&["list", "--since", "someday"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid date 'someday'"));
+}
+
+#[test]
+fn test_list_sort_by_project_and_account() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    for (name, project, account) in [
+        ("a", None, Some("zed@example.com")),
+        ("b", Some("prj-y"), None),
+        ("c", Some("prj-x"), Some("amy@example.com")),
+        ("d", Some("prj-y"), Some("amy@example.com")),
+    ] {
+        let options = gcpx::SaveOptions {
+            quiet: true,
+            project: project.map(String::from),
+            account: account.map(String::from),
+            ..Default::default()
+        };
+        gcpx::save_context_with_options(name, &options).unwrap();
+    }
+
+    let list = |sort: &str| {
+        let output = env.gcpx(&["list", "--sort", sort]);
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|l| l.split_whitespace().find(|w| *w != "*"))
+            .map(String::from)
+            .collect::<Vec<_>>()
+    };
+    // Ties keep name order; contexts without the field come last
+    assert_eq!(list("project"), ["c", "b", "d", "a"]);
+    assert_eq!(list("account"), ["c", "d", "a", "b"]);
+    assert_eq!(list("name"), ["a", "b", "c", "d"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_list_stale_honours_format() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("dev", true).unwrap();
+
+    let list = |format: &str| {
+        let output = env.gcpx(&["list", "--stale", "--format", format]);
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    // The fake gcloud mints a token for anything
+    let json: serde_json::Value = serde_json::from_str(&list("json")).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!([{"name": "dev", "status": "valid"}])
+    );
+    let table = list("table");
+    assert!(table.starts_with("NAME"));
+    assert!(table.contains("dev   valid"));
+    assert_eq!(list("plain"), "Valid:\n  dev\n");
+    assert!(env.gcloud_log().contains("print-access-token"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_list_check_config() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("dev", true).unwrap();
+    gcpx::save_context("gone", true).unwrap();
+    let mut m = gcpx::config::load_context_metadata("gone")
+        .unwrap()
+        .unwrap();
+    m.gcloud_config = "deleted".to_string();
+    gcpx::config::save_context_metadata("gone", &m).unwrap();
+
+    let list = |args: &[&str]| env.gcpx(&[&["list"], args].concat());
+    let lists = || env.gcloud_log().matches("configurations list").count();
+    let before = lists();
+    let output = list(&["--check-config"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("gone (active) (gcloud config 'deleted' missing)"));
+    assert!(stdout.contains("  dev\n"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("1 context(s)"));
+    // gcloud is asked once, not per context
+    assert_eq!(lists(), before + 1);
+
+    let output = list(&["--check-config", "--json"]);
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let missing: Vec<bool> = json["contexts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["gcloud_config_missing"].as_bool().unwrap())
+        .collect();
+    assert_eq!(missing, [false, true]);
+
+    assert!(!list(&["--check-config", "--strict"]).status.success());
+    env.create_gcloud_config("deleted");
+    assert!(list(&["--check-config", "--strict"]).status.success());
+}
+
+#[test]
+fn test_list_active_and_inactive_only() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    for name in ["personal", "work"] {
+        gcpx::save_context(name, true).unwrap();
+    }
+    gcpx::config::set_current_tracking("work").unwrap();
+
+    let list = |args: &[&str]| {
+        let output = env.gcpx(&[&["list"], args].concat());
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    assert_eq!(list(&["--active-only"]), "work\n");
+    assert_eq!(list(&["--inactive-only"]), "personal\n");
+    let json: serde_json::Value =
+        serde_json::from_str(&list(&["--active-only", "--json"])).unwrap();
+    assert_eq!(json["contexts"].as_array().unwrap().len(), 1);
+    assert_eq!(json["contexts"][0]["name"], "work");
+
+    // A tracked context that no longer exists prints nothing
+    gcpx::config::set_current_tracking("gone").unwrap();
+    assert_eq!(list(&["--active-only"]), "");
+
+    let output = env.gcpx(&["list", "--active-only", "--inactive-only"]);
+    assert!(!output.status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_list_paths() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    for name in ["personal", "work"] {
+        gcpx::save_context(name, true).unwrap();
+    }
+
+    let list = |args: &[&str]| env.gcpx(&[&["list"], args].concat());
+    let stdout = |args: &[&str]| {
+        let output = list(args);
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    let adc = |name: &str| env.gcpx_path().join(name).join("adc.json");
+    assert_eq!(
+        stdout(&["--active-only", "--paths"]),
+        format!("work\t{}\n", adc("work").display())
+    );
+    let json: serde_json::Value = serde_json::from_str(&stdout(&["--paths", "--json"])).unwrap();
+    assert_eq!(
+        json["contexts"][0]["adc_path"],
+        adc("personal").to_str().unwrap()
+    );
+    assert_eq!(
+        json["contexts"][1]["adc_path"],
+        adc("work").to_str().unwrap()
+    );
+
+    // The table has no column for paths
+    assert_eq!(
+        list(&["--paths", "--format", "table"]).status.code(),
+        Some(2)
+    );
+
+    // Credentials kept by a command backend have no file to point at
+    let vault = TempDir::new().unwrap();
+    fs::write(
+        env.gcpx_path().join("config.toml"),
+        format!(
+            "store_backend = \"command\"\n\n[store_command]\n\
+            get_cmd = \"cat {vault}/{{name}}\"\n\
+            put_cmd = \"cat > {vault}/$GCPX_CONTEXT\"\n",
+            vault = vault.path().display()
+        ),
+    )
+    .unwrap();
+    gcpx::save_context("vaulted", true).unwrap();
+    assert_eq!(stdout(&["--active-only", "--paths"]), "vaulted\t-\n");
+    let json: serde_json::Value =
+        serde_json::from_str(&stdout(&["--active-only", "--paths", "--json"])).unwrap();
+    assert!(json["contexts"][0].get("adc_path").is_none());
+}
+
+#[test]
+fn test_self_test_leaves_store_untouched() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("work", true).unwrap();
+    let log = env._bin_dir.path().join("gcloud.log");
+    let _ = fs::remove_file(&log);
+
+    let output = env.gcpx(&["self-test"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Self-test passed (6 steps)."));
+
+    assert_eq!(gcpx::list_contexts().unwrap(), ["work"]);
+    assert_eq!(gcpx::get_current_tracking(), "work");
+    assert!(!log.exists(), "self-test should not call gcloud");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_prune_empty_gcloud_configs() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    for config in ["work", "orphan", "active-orphan"] {
+        env.create_gcloud_config(config);
+    }
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        gcloud_config: Some("work".to_string()),
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("work", &options).unwrap();
+    fs::write(env._bin_dir.path().join("active"), "active-orphan\n").unwrap();
+
+    // `default` is never offered, even when unused
+    assert_eq!(
+        gcpx::commands::orphaned_gcloud_configs().unwrap(),
+        ["orphan"]
+    );
+
+    // Unreadable metadata doesn't stop the scan
+    gcpx::save_context("broken", true).unwrap();
+    fs::write(env.gcpx_path().join("broken").join("metadata.json"), "{").unwrap();
+    assert_eq!(
+        gcpx::commands::orphaned_gcloud_configs().unwrap(),
+        ["orphan"]
+    );
+
+    let dry_run = gcpx::commands::PruneOptions {
+        dry_run: true,
+        ..Default::default()
+    };
+    gcpx::commands::prune_gcloud_configs(&dry_run).unwrap();
+    assert!(env.gcloud_config_exists("orphan"));
+
+    let yes = gcpx::commands::PruneOptions {
+        yes: true,
+        ..Default::default()
+    };
+    gcpx::commands::prune_gcloud_configs(&yes).unwrap();
+    assert!(!env.gcloud_config_exists("orphan"));
+    assert!(env.gcloud_config_exists("work"));
+    assert!(env.gcloud_config_exists("active-orphan"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_run_clean_env_passthrough() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("work", true).unwrap();
+
+    let output = env
+        .gcpx_command()
+        .args([
+            "run",
+            "work",
+            "--clean-env",
+            "--env-passthrough",
+            "KEEP_?E_*",
+            "--env",
+            "KEEP_ME_TOO=override",
+            "--env",
+            "EXTRA=1",
+            "--",
+            "/usr/bin/env",
+        ])
+        .env("KEEP_ME_TOO", "inherited")
+        .env("KEEP_ME_ALSO", "yes")
+        .env("DROP_ME", "no")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let vars: Vec<&str> = stdout.lines().filter(|l| l.contains('=')).collect();
+    assert!(vars.contains(&"KEEP_ME_ALSO=yes"));
+    assert!(vars.contains(&"KEEP_ME_TOO=override"));
+    assert!(vars.contains(&"EXTRA=1"));
+    assert!(
+        vars.iter()
+            .any(|v| v.starts_with("GOOGLE_APPLICATION_CREDENTIALS="))
+    );
+    assert!(!vars.iter().any(|v| v.starts_with("DROP_ME=")));
+    assert!(!vars.iter().any(|v| v.starts_with("PATH=")));
+
+    let err = gcpx::commands::run_with_context_options(
+        "work",
+        &["true".to_string()],
+        &gcpx::commands::RunOptions {
+            env: vec!["NOEQUALS".to_string()],
+            ..Default::default()
+        },
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("Expected KEY=VALUE"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_doctor_fix() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    gcpx::save_context("loose", true).unwrap();
+    let loose_adc = env.gcpx_path().join("loose").join("adc.json");
+    fs::set_permissions(&loose_adc, fs::Permissions::from_mode(0o644)).unwrap();
+    gcpx::save_context("no-meta", true).unwrap();
+    fs::write(
+        env.gcpx_path().join("no-meta").join("metadata.json"),
+        "{ not json",
+    )
+    .unwrap();
+    gcpx::save_context("bad-json", true).unwrap();
+    fs::write(env.gcpx_path().join("bad-json").join("adc.json"), "{oops").unwrap();
+    gcpx::config::set_current_tracking("deleted").unwrap();
+    let temp = env.gcpx_path().join("loose").join("adc.json.tmp4242");
+    fs::write(&temp, "{}").unwrap();
+    let old = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+    fs::File::options()
+        .write(true)
+        .open(&temp)
+        .unwrap()
+        .set_modified(old)
+        .unwrap();
+    // A kept version is a credential file too
+    let loose_version = env.gcpx_path().join("loose").join("adc.json.1");
+    fs::write(&loose_version, "{}").unwrap();
+    fs::set_permissions(&loose_version, fs::Permissions::from_mode(0o644)).unwrap();
+    // Leftovers of killed gcpx commands in the temp directory
+    let tmp = TempDir::new().unwrap();
+    let stray_edit = tmp.path().join("gcpx-edit-abc123.txt");
+    fs::write(&stray_edit, "notes").unwrap();
+    let stray_check = tmp.path().join("gcpx-check-abc123");
+    fs::create_dir(&stray_check).unwrap();
+    let day_old = std::time::SystemTime::now() - std::time::Duration::from_secs(2 * 86400);
+    for path in [&stray_edit, &stray_check] {
+        fs::File::open(path).unwrap().set_modified(day_old).unwrap();
+    }
+    let fresh_adc = tmp.path().join("gcpx-adc-work-xyz.json");
+    fs::write(&fresh_adc, "{}").unwrap();
+    let doctor = |args: &[&str]| {
+        env.gcpx_command()
+            .args(args)
+            .env("TMPDIR", tmp.path())
+            .env_remove("XDG_RUNTIME_DIR")
+            .output()
+            .unwrap()
+    };
+
+    let output = doctor(&["doctor"]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("context 'loose': adc.json readable by others - fixable with --fix"));
+    assert!(stdout.contains("current context 'deleted' no longer exists"));
+    assert!(stdout.contains("adc.json.tmp4242"));
+    assert!(stdout.contains("context 'loose': adc.json.1 readable by others"));
+    assert!(stdout.contains("gcpx-edit-abc123.txt"));
+    assert!(stdout.contains("gcpx-check-abc123"));
+    // Recent ones may belong to a command still running
+    assert!(!stdout.contains("gcpx-adc-work"));
+    // Diagnosing alone changes nothing
+    assert!(temp.exists());
+
+    let output = doctor(&["doctor", "--fix"]);
+    // The invalid credentials need a new login, so a problem remains
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("mode 0644 -> 0600"));
+    assert!(stdout.contains("run 'gcpx login bad-json'"));
+    assert_eq!(
+        fs::metadata(&loose_adc).unwrap().permissions().mode() & 0o777,
+        0o600
+    );
+    assert!(!temp.exists());
+    assert_eq!(
+        fs::metadata(&loose_version).unwrap().permissions().mode() & 0o777,
+        0o600
+    );
+    assert!(!stray_edit.exists());
+    assert!(!stray_check.exists());
+    assert!(fresh_adc.exists());
+    assert_eq!(gcpx::get_current_tracking(), "none");
+    let metadata = gcpx::config::load_context_metadata("no-meta")
+        .unwrap()
+        .unwrap();
+    assert_eq!(metadata.gcloud_config, "no-meta");
+    let trashed: Vec<String> = fs::read_dir(env.gcpx_path().join(".trash"))
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    assert!(trashed.iter().any(|n| n.starts_with("adc.json.tmp4242-")));
+    assert!(
+        trashed
+            .iter()
+            .any(|n| n.starts_with("no-meta-metadata.json-"))
+    );
+
+    fs::remove_dir_all(env.gcpx_path().join("bad-json")).unwrap();
+    let output = doctor(&["doctor"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "No problems found.\n"
+    );
+}
+
+#[test]
+fn test_render_menu_item() {
+    use gcpx::commands::switch::render_menu_item;
+
+    let metadata = gcpx::ContextMetadata {
+        gcloud_config: "work".to_string(),
+        account: Some("me@example.com".to_string()),
+        project: Some("work-project".to_string()),
+        ..Default::default()
+    };
+    let template = "{active}{name} — {project} ({account})";
+    assert_eq!(
+        render_menu_item(template, "work", Some(&metadata), false),
+        "work — work-project (me@example.com)"
+    );
+    assert_eq!(
+        render_menu_item("{name} {active}", "work", Some(&metadata), true),
+        "work *"
+    );
+    assert_eq!(
+        render_menu_item("{name} {active}", "work", Some(&metadata), false),
+        "work"
+    );
+    // Without metadata, only the name is shown
+    assert_eq!(render_menu_item(template, "legacy", None, false), "legacy");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_save_caches_gcloud_account_query() {
+    let env = TestEnv::new();
+    env.create_fake_adc();
+    let queries = |env: &TestEnv| env.gcloud_log().matches("get-value account").count();
+
+    gcpx::save_context("a", true).unwrap();
+    gcpx::save_context("b", true).unwrap();
+    assert_eq!(queries(&env), 1);
+
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        force_account_query: true,
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("c", &options).unwrap();
+    assert_eq!(queries(&env), 2);
+
+    // Setting a property (like any gcloud change gcpx makes) clears the cache
+    gcpx::config::set_gcloud_property("core/project", "other", None).unwrap();
+    gcpx::save_context("d", true).unwrap();
+    assert_eq!(queries(&env), 3);
+    gcpx::save_context("e", true).unwrap();
+    assert_eq!(queries(&env), 3);
+
+    // An explicit configuration is asked about and cached by name, without
+    // looking up the active one
+    env.create_gcloud_config("team");
+    let lookups = |env: &TestEnv| env.gcloud_log().matches("configurations list").count();
+    let before = lookups(&env);
+    let options = gcpx::SaveOptions {
+        quiet: true,
+        gcloud_config: Some("team".to_string()),
+        ..Default::default()
+    };
+    gcpx::save_context_with_options("f", &options).unwrap();
+    gcpx::save_context_with_options("g", &options).unwrap();
+    assert_eq!(queries(&env), 4);
+    assert!(
+        env.gcloud_log()
+            .contains("config get-value account --configuration=team")
+    );
+    assert_eq!(lookups(&env), before);
+}
+
+#[test]
+fn test_completions_install() {
+    let env = TestEnv::new();
+    let home = env.gcpx_path().join("home");
+    let completions = |args: &[&str]| {
+        env.gcpx_command()
+            .arg("completions")
+            .args(args)
+            .env("HOME", &home)
+            .env_remove("XDG_DATA_HOME")
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("ZDOTDIR")
+            .output()
+            .unwrap()
+    };
+
+    let printed = completions(&["bash"]);
+    assert!(printed.status.success());
+    assert!(completions(&["install", "bash", "--print"]).stdout == printed.stdout);
+
+    let output = completions(&["install", "bash"]);
+    assert!(output.status.success());
+    let path = home.join(".local/share/bash-completion/completions/gcpx");
+    assert_eq!(fs::read(&path).unwrap(), printed.stdout);
+    assert!(String::from_utf8_lossy(&output.stdout).contains(&path.display().to_string()));
+
+    let output = completions(&["install", "fish", "--dynamic"]);
+    assert!(output.status.success());
+    let script = fs::read_to_string(home.join(".config/fish/completions/gcpx.fish")).unwrap();
+    assert!(script.contains("__complete"));
+
+    let output = completions(&["install", "zsh"]);
+    assert!(output.status.success());
+    assert!(home.join(".zfunc/_gcpx").is_file());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("fpath=("));
+
+    assert!(!completions(&["install", "powershell"]).status.success());
+}
+
+#[test]
+fn test_store_refuses_gcloud_dir() {
+    let env = TestEnv::new();
+    let is_guard = |err: anyhow::Error| {
+        matches!(
+            err.downcast_ref::<gcpx::GcpxError>(),
+            Some(gcpx::GcpxError::StoreIsGcloudDir(_))
+        )
+    };
+
+    // SAFETY: ENV_LOCK is held by `env`, and dropping it resets GCPX_HOME.
+    unsafe { env::set_var("GCPX_HOME", env.gcloud_path()) };
+    assert!(is_guard(gcpx::list_contexts().unwrap_err()));
+
+    // A directory holding gcloud's ADC file is refused as well
+    let elsewhere = env.gcpx_path().join("elsewhere");
+    fs::create_dir_all(&elsewhere).unwrap();
+    fs::write(elsewhere.join("application_default_credentials.json"), "{}").unwrap();
+    unsafe { env::set_var("GCPX_HOME", &elsewhere) };
+    assert!(is_guard(gcpx::list_contexts().unwrap_err()));
+
+    // So is a parent of the gcloud directory, which would list it as a context
+    unsafe { env::set_var("GCPX_HOME", env.gcloud_path().parent().unwrap()) };
+    assert!(is_guard(gcpx::list_contexts().unwrap_err()));
+
+    unsafe { env::set_var("GCPX_HOME", env.gcpx_path()) };
+    assert!(gcpx::list_contexts().is_ok());
+}